@@ -1,23 +1,10 @@
-fn start_midi(tx: Box<dyn Fn(MidiEvent) + Send>) {
-    let mut midi_in = midir::MidiInput::new("MIDI input").unwrap();
-    midi_in.ignore(midir::Ignore::ActiveSense);
+fn start_midi(tx: Box<dyn Fn(MidiEvent) + Send>) -> Option<MidiConnection> {
+    let midi_in = midir::MidiInput::new("MIDI input").unwrap();
     let in_ports = midi_in.ports();
 
     if !in_ports.is_empty() {
-        // Create a callback to handle incoming MIDI messages
-        let callback = move |_, message: &[u8], _: &mut ()| {
-            let event = MidiEvent::from_raw(message);
-            if event.is_invalid() {
-                return;
-            }
-            tx(event);
-        };
-
-        // Connect to the selected MIDI input port
-        let c = midi_in
-            .connect(&in_ports[0], "midi-read-connection", callback, ())
-            .unwrap();
-        Box::leak(Box::new(c));
+        // Connect to the selected MIDI input port, which stays connected until the connection is dropped
+        Some(MidiConnection::connect(&in_ports[0], move |event| tx(event)).unwrap())
     } else {
         println!("No MIDI input ports available.");
         std::thread::spawn(move || {
@@ -41,5 +28,6 @@ fn start_midi(tx: Box<dyn Fn(MidiEvent) + Send>) {
                 }
             }
         });
+        None
     }
 }
@@ -1,6 +1,12 @@
 pub mod adapter;
 pub mod buffer;
 pub mod delay_line;
+pub mod envelope_follower;
+pub mod fir;
+pub mod lfo;
+pub mod lookahead;
+pub mod loudness;
 pub mod resample;
 pub mod ring;
 pub mod sample;
+pub mod weighting;
@@ -1,4 +1,4 @@
-use std::{ops::Range, slice::SliceIndex};
+use std::slice::SliceIndex;
 
 pub trait AudioBuffer<'a>: Sized {
     fn samples(self) -> &'a [f32];
@@ -83,6 +83,7 @@ impl<'a> AudioBufferMut<'a> for &'a mut [f32] {
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct MonoBuffer<'a> {
     channel: &'a [f32],
 }
@@ -119,6 +120,24 @@ impl<'a> MonoBufferMut<'a> {
     }
 }
 
+impl<'a> AudioBuffer<'a> for MonoBuffer<'a> {
+    fn samples(self) -> &'a [f32] {
+        self.channel
+    }
+}
+
+impl<'a> AudioBuffer<'a> for MonoBufferMut<'a> {
+    fn samples(self) -> &'a [f32] {
+        self.channel
+    }
+}
+
+impl<'a> AudioBufferMut<'a> for MonoBufferMut<'a> {
+    fn samples_mut(self) -> &'a mut [f32] {
+        self.channel
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct StereoBuffer<'a> {
     pub left: &'a [f32],
@@ -191,8 +210,8 @@ impl<'a> StereoBufferMut<'a> {
 
     /// Fills all buffers with silence.
     pub fn clear(&mut self) {
-        self.left.clear();
-        self.right.clear();
+        AudioBufferMut::clear(&mut *self.left);
+        AudioBufferMut::clear(&mut *self.right);
     }
 
     pub fn copy(&mut self, other: StereoBuffer) {
@@ -233,3 +252,27 @@ impl StereoChannel {
         [StereoChannel::Left, StereoChannel::Right]
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stereo_clear() {
+        let mut left = [0.5; 16];
+        let mut right = [-0.25; 16];
+        let mut buffer = StereoBufferMut::new(&mut left, &mut right);
+        buffer.clear();
+        assert_eq!(buffer.len(), 16);
+        assert!(buffer.left.iter().chain(buffer.right.iter()).all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_mono_scale() {
+        let mut samples = [0.5, -1.0, 0.25, 0.0];
+        let buffer = MonoBufferMut::new(&mut samples);
+        assert_eq!(buffer.len(), 4);
+        buffer.scale(0.5);
+        assert_eq!(samples, [0.25, -0.5, 0.125, 0.0]);
+    }
+}
@@ -21,10 +21,14 @@ pub struct DelayLine {
     /// A positive value causes faster than normal playback, and a negative value slower than normal playback.
     /// A `None` value indicates that there is no warping as the delay has reached its target value.
     warp: Option<f32>,
+    /// Controls how quickly the delay line repitches to the target delay value, in inverse seconds.
+    omega: f32,
     /// The resampler.
     resampler: Resampler<CubicInterpolator>,
     /// A small buffer for holding output.
     output_adapter: FixedOutputAdapter<OUTPUT_SIZE>,
+    /// A buffer for holding the samples read from the ring buffer, which grows as needed.
+    read_buffer: Vec<f32>,
 }
 
 impl DelayLine {
@@ -37,8 +41,10 @@ impl DelayLine {
             sample_rate: 0.0,
             target_delay: 0,
             warp: None,
+            omega: 6.0,
             resampler: Resampler::new(),
             output_adapter: FixedOutputAdapter::new(),
+            read_buffer: Vec::with_capacity(2 * OUTPUT_SIZE),
         }
     }
 
@@ -49,10 +55,19 @@ impl DelayLine {
         self.ring = RingBuffer::new(size);
     }
 
-    /// Sets the delay of the read head to be the given number of seconds behind the write head.
+    /// Silences the delay line, and jumps straight to the target delay if it was gliding towards it.
+    /// Unlike setting the sample rate, this doesn't reallocate the ring buffer.
+    pub fn clear(&mut self) {
+        self.ring.clear();
+        self.output_adapter = FixedOutputAdapter::new();
+        self.seek_samples(self.target_delay);
+    }
+
+    /// Sets the delay of the read head to be the given number of seconds behind the write head,
+    /// rounded to the nearest sample at the sample rate given to `set_sample_rate`.
     /// This takes effect instantaneously so may result in clicking/popping in the audio output.
     pub fn seek_seconds(&mut self, delay: f32) {
-        self.seek_samples((delay * self.sample_rate) as usize);
+        self.seek_samples((delay * self.sample_rate).round() as usize);
     }
 
     /// Sets the delay of the read head to be the given number of samples behind the write head.
@@ -66,14 +81,22 @@ impl DelayLine {
 
     /// Sets the delay of the read head to be the given number of seconds behind the write head,
     /// which will be smoothly transitioned to be speeding up or slowing down playback of the delayed signal.
+    /// The delay is rounded to the nearest sample, as in `seek_seconds`.
     pub fn set_target_delay(&mut self, target_delay: f32) {
-        let new_target = (target_delay * self.sample_rate) as usize;
+        let new_target = (target_delay * self.sample_rate).round() as usize;
         if self.target_delay != new_target {
             self.target_delay = new_target;
             self.warp = self.warp.or(Some(0.0));
         }
     }
 
+    /// Sets how quickly the delay line repitches towards a new target delay, in inverse seconds.
+    /// Lower values give slower and more dramatic pitch slides,
+    /// while very high values retarget almost instantly, which may result in clicking/popping.
+    pub fn set_warp_stiffness(&mut self, omega: f32) {
+        self.omega = omega.max(0.01);
+    }
+
     /// Reads samples from the delay line.
     pub fn read(&mut self, audio_out: &mut [f32]) {
         let mut output = std::mem::take(&mut self.output_adapter);
@@ -101,12 +124,12 @@ impl DelayLine {
             return;
         }
 
-        // Read samples from the ring buffer into the stack
-        let read_buffer = &mut [0.0; 1024][..input_size];
-        self.ring.read(read_buffer, false);
+        // Read samples from the ring buffer
+        self.read_buffer.resize(input_size, 0.0);
+        self.ring.read(&mut self.read_buffer, false);
 
         // Perform the resampling directly into the output buffer
-        let offset = self.resampler.resample(read_buffer, samples, ratio);
+        let offset = self.resampler.resample(&self.read_buffer, samples, ratio);
         self.ring.seek_relative(offset as isize);
     }
 
@@ -122,14 +145,14 @@ impl DelayLine {
             return;
         };
 
-        // Controls how quickly the delay line repitches to the target delay value
-        let omega = 6.0f32;
+        let omega = self.omega;
+        let dt = num_samples as f32 / self.sample_rate;
 
         // Compute the current delay error, in samples
         let error = self.delay_samples() - self.target_delay as f32;
 
-        // If the error is very small, snap the delay and warp
-        if error.abs() < 0.001 {
+        // If the error is very small, or the stiffness is too high to integrate stably, snap the delay and warp
+        if error.abs() < 0.001 || omega * dt >= 1.0 {
             self.seek_samples(self.target_delay);
             return;
         }
@@ -138,7 +161,7 @@ impl DelayLine {
         let warp_acc = omega.powf(2.0) * error - 2.0 * omega * warp;
 
         // Apply the acceleration
-        warp += warp_acc * (num_samples as f32 / self.sample_rate);
+        warp += warp_acc * dt;
 
         // Update the warp value
         self.warp = Some(warp);
@@ -154,3 +177,92 @@ impl DelayLine {
         self.delay_samples() / self.sample_rate
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Counts the number of samples it takes for the delay line to reach a new target delay.
+    fn samples_to_converge(omega: f32) -> usize {
+        let mut line = DelayLine::new(1.0);
+        line.set_sample_rate(48000);
+        line.set_warp_stiffness(omega);
+        line.seek_seconds(0.1);
+        line.set_target_delay(0.2);
+
+        let mut buffer = [0.0; 32];
+        let mut samples = 0;
+        while (line.delay_seconds() - 0.2).abs() > 0.0001 && samples < 48000 * 10 {
+            line.read(&mut buffer);
+            line.write(&buffer);
+            samples += buffer.len();
+        }
+        samples
+    }
+
+    #[test]
+    fn test_warp_stiffness() {
+        let fast = samples_to_converge(12.0);
+        let slow = samples_to_converge(3.0);
+        assert!(fast < slow);
+        assert!(slow < 48000 * 10);
+
+        // A very high stiffness retargets immediately
+        assert!(samples_to_converge(10_000.0) <= 32);
+    }
+
+    #[test]
+    fn test_seek_seconds() {
+        for sample_rate in [44100, 48000, 96000] {
+            for delay in [0.0, 0.01, 0.1, 0.3, 0.75] {
+                let mut line = DelayLine::new(1.0);
+                line.set_sample_rate(sample_rate);
+                line.seek_seconds(delay);
+                assert_eq!(line.delay_samples(), (delay * sample_rate as f32).round());
+                assert!((line.delay_seconds() - delay).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_seek_then_target() {
+        // A target delay equal to the delay just seeked to doesn't start a glide, even where truncating differs
+        let mut line = DelayLine::new(1.0);
+        line.set_sample_rate(48000);
+        line.seek_seconds(0.0101);
+        let delay = line.delay_samples();
+        line.set_target_delay(0.0101);
+        assert!(line.warp.is_none());
+
+        let mut buffer = [0.0; 100];
+        for _ in 0..10 {
+            line.read(&mut buffer);
+            line.write(&buffer);
+        }
+        assert_eq!(line.delay_samples(), delay);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut line = DelayLine::new(1.0);
+        line.set_sample_rate(48000);
+        line.seek_seconds(0.01);
+        line.set_target_delay(0.02);
+
+        let mut buffer = [0.0; 100];
+        for _ in 0..10 {
+            line.read(&mut buffer);
+            line.write(&[1.0; 100]);
+        }
+        assert!(buffer.iter().any(|&s| s != 0.0));
+
+        // Once cleared, the delay line is silent and no longer gliding
+        line.clear();
+        assert!((line.delay_samples() - 960.0).abs() < 1e-3);
+        for _ in 0..9 {
+            line.read(&mut buffer);
+            assert!(buffer.iter().all(|&s| s == 0.0));
+            line.write(&[0.0; 100]);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use super::weighting::{Weighting, WeightingFilter};
+
+/// Tracks the peak level of a signal, rising and falling with separate attack and release times.
+/// The signal can be weighted before it is measured, so that the level follows its perceived loudness.
+#[derive(Clone, Copy)]
+pub struct EnvelopeFollower {
+    /// The sample rate in `Hz`.
+    sample_rate: f32,
+    /// Attack time in seconds.
+    attack: f32,
+    /// Release time in seconds.
+    release: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    /// Filter applying the frequency weighting to the signal.
+    weighting: WeightingFilter,
+    /// The current level, as a linear amplitude.
+    level: f32,
+}
+
+impl Default for EnvelopeFollower {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.0,
+            attack: 0.0,
+            release: 0.01,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            weighting: WeightingFilter::new(),
+            level: 0.0,
+        }
+    }
+}
+
+impl EnvelopeFollower {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.weighting.set_sample_rate(sample_rate);
+        self.calc_coefficients();
+    }
+
+    /// Sets the time in seconds taken to rise towards a louder level, where zero follows peaks instantly.
+    pub fn set_attack(&mut self, attack: f32) {
+        self.attack = attack.max(0.0);
+        self.calc_coefficients();
+    }
+
+    /// Sets the time in seconds taken to fall towards a quieter level.
+    pub fn set_release(&mut self, release: f32) {
+        self.release = release.max(0.0);
+        self.calc_coefficients();
+    }
+
+    /// Sets the frequency weighting applied to the signal before its level is measured.
+    pub fn set_weighting(&mut self, weighting: Weighting) {
+        self.weighting.set_weighting(weighting);
+    }
+
+    /// Gets the current level, as a linear amplitude.
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    pub fn reset(&mut self) {
+        self.weighting.reset();
+        self.level = 0.0;
+    }
+
+    /// Updates the level with the next sample of the signal, and returns the new level.
+    pub fn process_sample(&mut self, sample: f32) -> f32 {
+        let magnitude = self.weighting.process_sample(sample).abs();
+        let coeff = if magnitude > self.level {
+            self.attack_coeff
+        } else {
+            self.release_coeff
+        };
+        self.level = magnitude + coeff * (self.level - magnitude);
+        self.level
+    }
+
+    fn calc_coefficients(&mut self) {
+        let coeff = |time: f32| {
+            if time > 0.0 && self.sample_rate > 0.0 {
+                (-1.0 / (time * self.sample_rate)).exp()
+            } else {
+                0.0
+            }
+        };
+        self.attack_coeff = coeff(self.attack);
+        self.release_coeff = coeff(self.release);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::PI;
+
+    /// Follows a sine wave for one second, and returns the level it settles at.
+    fn settled_level(follower: &mut EnvelopeFollower, frequency: f32) -> f32 {
+        follower.reset();
+        for i in 0..48000 {
+            follower.process_sample((2.0 * PI * frequency * i as f32 / 48000.0).sin());
+        }
+        follower.level()
+    }
+
+    #[test]
+    fn test_weighting() {
+        let mut follower = EnvelopeFollower::new();
+        follower.set_sample_rate(48000);
+        follower.set_release(1.0);
+        let unweighted = [1000.0, 50.0].map(|f| settled_level(&mut follower, f));
+
+        // The A-weighted level of a 1kHz tone matches its unweighted level, while the lows are attenuated
+        follower.set_weighting(Weighting::A);
+        let weighted = [1000.0, 50.0].map(|f| settled_level(&mut follower, f));
+        assert!((weighted[0] / unweighted[0] - 1.0).abs() < 0.01);
+        assert!(weighted[1] < 0.05 * unweighted[1]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::f32::consts::PI;
+
+/// A finite impulse response filter kernel, which convolves a signal with a fixed set of coefficients.
+///
+/// The history of input samples is kept in a ring buffer, so processing never allocates.
+#[derive(Clone)]
+pub struct FirKernel {
+    /// The coefficients, where the first is applied to the most recent input sample.
+    coeffs: Box<[f32]>,
+    /// The most recent input samples, stored twice over so that every window of them is contiguous.
+    history: Box<[f32]>,
+    /// The position in `history` of the next input sample.
+    pos: usize,
+}
+
+impl FirKernel {
+    /// Creates a kernel with the given coefficients, where the first is applied to the most recent input sample.
+    pub fn new(coeffs: impl Into<Box<[f32]>>) -> Self {
+        let coeffs = coeffs.into();
+        assert!(!coeffs.is_empty(), "Expected at least one coefficient");
+        let history = vec![0.0; 2 * coeffs.len()].into_boxed_slice();
+        Self {
+            coeffs,
+            history,
+            pos: 0,
+        }
+    }
+
+    /// Creates a Blackman-windowed sinc lowpass kernel, where `cutoff` is a proportion of the sample rate
+    /// between `0.0` and `0.5`. The number of taps is rounded up to the nearest odd number.
+    pub fn lowpass(taps: usize, cutoff: f32) -> Self {
+        let mut kernel = Self::new(vec![0.0; taps | 1]);
+        kernel.set_lowpass(cutoff);
+        kernel
+    }
+
+    /// Creates a Blackman-windowed sinc highpass kernel, where `cutoff` is a proportion of the sample rate
+    /// between `0.0` and `0.5`. The number of taps is rounded up to the nearest odd number.
+    pub fn highpass(taps: usize, cutoff: f32) -> Self {
+        let mut kernel = Self::new(vec![0.0; taps | 1]);
+        kernel.set_highpass(cutoff);
+        kernel
+    }
+
+    /// Recomputes the coefficients as a Blackman-windowed sinc lowpass kernel with the same number of taps,
+    /// where `cutoff` is a proportion of the sample rate between `0.0` and `0.5`. The history is kept, and
+    /// nothing is allocated, so the cutoff can be changed while processing.
+    pub fn set_lowpass(&mut self, cutoff: f32) {
+        windowed_sinc(&mut self.coeffs, cutoff.clamp(0.0, 0.5));
+    }
+
+    /// Recomputes the coefficients as a Blackman-windowed sinc highpass kernel with the same number of taps,
+    /// where `cutoff` is a proportion of the sample rate between `0.0` and `0.5`. The history is kept, and
+    /// nothing is allocated, so the cutoff can be changed while processing.
+    pub fn set_highpass(&mut self, cutoff: f32) {
+        self.set_lowpass(cutoff);
+        spectral_inversion(&mut self.coeffs);
+    }
+
+    /// Gets the coefficients, where the first is applied to the most recent input sample.
+    pub fn coefficients(&self) -> &[f32] {
+        &self.coeffs
+    }
+
+    /// Gets the number of taps.
+    pub fn taps(&self) -> usize {
+        self.coeffs.len()
+    }
+
+    /// Gets the delay introduced by a symmetric kernel in samples.
+    pub fn latency_samples(&self) -> usize {
+        (self.coeffs.len() - 1) / 2
+    }
+
+    /// Clears the history of input samples.
+    pub fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.pos = 0;
+    }
+
+    /// Filters a single sample.
+    pub fn process_sample(&mut self, sample: f32) -> f32 {
+        let len = self.coeffs.len();
+        self.history[self.pos] = sample;
+        self.history[self.pos + len] = sample;
+
+        // The window holds the input samples from newest to oldest
+        let window = &self.history[(self.pos + 1)..=(self.pos + len)];
+        let output = self.coeffs.iter().zip(window.iter().rev()).map(|(c, s)| c * s).sum();
+
+        self.pos = (self.pos + 1) % len;
+        output
+    }
+
+    /// Filters `input` into `output`, which must be the same length.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        assert!(input.len() == output.len());
+        for (s_out, &s_in) in output.iter_mut().zip(input.iter()) {
+            *s_out = self.process_sample(s_in);
+        }
+    }
+}
+
+/// Fills `coeffs` with a Blackman-windowed sinc lowpass kernel with unity gain at DC.
+fn windowed_sinc(coeffs: &mut [f32], cutoff: f32) {
+    let taps = coeffs.len();
+    let mid = (taps / 2) as f32;
+    for (n, coeff) in coeffs.iter_mut().enumerate() {
+        let x = n as f32 - mid;
+        let sinc = if x == 0.0 {
+            2.0 * cutoff
+        } else {
+            (2.0 * PI * cutoff * x).sin() / (PI * x)
+        };
+        let phase = 2.0 * PI * n as f32 / (taps - 1).max(1) as f32;
+        *coeff = sinc * (0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos());
+    }
+
+    let sum: f32 = coeffs.iter().sum();
+    if sum != 0.0 {
+        coeffs.iter_mut().for_each(|c| *c /= sum);
+    }
+}
+
+/// Converts a symmetric lowpass kernel to a highpass kernel.
+fn spectral_inversion(coeffs: &mut [f32]) {
+    coeffs.iter_mut().for_each(|c| *c = -*c);
+    let mid = coeffs.len() / 2;
+    coeffs[mid] += 1.0;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_moving_average() {
+        let mut kernel = FirKernel::new([1.0 / 3.0; 3]);
+        let input = [0.0, 0.0, 3.0, 3.0, 3.0, 3.0];
+        let mut output = [0.0; 6];
+        kernel.process(&input[..4], &mut output[..4]);
+        kernel.process(&input[4..], &mut output[4..]);
+
+        // The step rises over three samples, including across blocks
+        let expected = [0.0, 0.0, 1.0, 2.0, 3.0, 3.0];
+        assert!(output.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_lowpass_and_highpass() {
+        let lowpass = FirKernel::lowpass(30, 0.1);
+        let highpass = FirKernel::highpass(30, 0.1);
+        assert_eq!(lowpass.taps(), 31);
+        assert_eq!(lowpass.latency_samples(), 15);
+
+        // The lowpass kernel passes DC and the highpass kernel blocks it
+        assert!((lowpass.coefficients().iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!(highpass.coefficients().iter().sum::<f32>().abs() < 1e-6);
+
+        // The lowpass kernel blocks the Nyquist frequency and the highpass kernel passes it
+        let nyquist = |kernel: &FirKernel| {
+            let coeffs = kernel.coefficients().iter().enumerate();
+            coeffs.map(|(i, c)| if i % 2 == 0 { *c } else { -c }).sum::<f32>().abs()
+        };
+        assert!(nyquist(&lowpass) < 1e-3);
+        assert!((nyquist(&highpass) - 1.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,169 @@
+/// Number of beats in a bar, which is assumed to be in 4/4 time.
+const BEATS_PER_BAR: f32 = 4.0;
+
+/// A note length, used to sync rates to tempo.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NoteDivision {
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+}
+
+impl NoteDivision {
+    /// Gets the length of the note in beats, where a beat is a quarter note.
+    pub fn beats(self) -> f32 {
+        match self {
+            NoteDivision::Whole => 4.0,
+            NoteDivision::Half => 2.0,
+            NoteDivision::Quarter => 1.0,
+            NoteDivision::Eighth => 0.5,
+            NoteDivision::Sixteenth => 0.25,
+            NoteDivision::ThirtySecond => 0.125,
+        }
+    }
+}
+
+/// A low frequency oscillator, which can either run freely or be synced to tempo.
+#[derive(Clone, Copy)]
+pub struct Lfo {
+    /// Duration of a sample in seconds.
+    inv_sample_rate: f32,
+    /// The rate in `Hz` when not synced to tempo.
+    frequency: f32,
+    /// The length of a cycle when synced to tempo.
+    division: NoteDivision,
+    /// The tempo in beats per minute.
+    bpm: f32,
+    /// Whether the rate is synced to tempo.
+    sync: bool,
+    /// Whether the phase is reset at the start of each bar, when synced to tempo.
+    reset_on_bar: bool,
+    /// The current phase between 0 and 1.
+    phase: f32,
+    /// The current position within the bar between 0 and 1.
+    bar_position: f32,
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Self {
+            inv_sample_rate: 0.0,
+            frequency: 0.0,
+            division: NoteDivision::Quarter,
+            bpm: 120.0,
+            sync: false,
+            reset_on_bar: false,
+            phase: 0.0,
+            bar_position: 0.0,
+        }
+    }
+}
+
+impl Lfo {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.inv_sample_rate = (sample_rate as f32).recip();
+    }
+
+    /// Sets the rate in `Hz` used when not synced to tempo.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+    }
+
+    /// Syncs the rate to tempo, so that each cycle lasts for the given note division.
+    pub fn set_rate_sync(&mut self, division: NoteDivision, bpm: f32) {
+        self.division = division;
+        self.bpm = bpm.max(1.0);
+        self.sync = true;
+    }
+
+    /// Toggles between the tempo synced rate and the free-running frequency.
+    pub fn set_sync(&mut self, sync: bool) {
+        self.sync = sync;
+    }
+
+    /// Sets whether the phase is reset at the start of each bar, when synced to tempo.
+    pub fn set_reset_on_bar(&mut self, reset_on_bar: bool) {
+        self.reset_on_bar = reset_on_bar;
+    }
+
+    /// Resets the phase and bar position, such as when the transport starts.
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+        self.bar_position = 0.0;
+    }
+
+    /// Gets the current rate in `Hz`.
+    pub fn rate(&self) -> f32 {
+        if self.sync {
+            self.bpm / (60.0 * self.division.beats())
+        } else {
+            self.frequency
+        }
+    }
+
+    /// Gets the phase between 0 and 1, `n` samples after the current position.
+    pub fn phase_at(&self, n: usize) -> f32 {
+        let elapsed = n as f32 * self.inv_sample_rate;
+        if self.sync && self.reset_on_bar {
+            let cycles_per_bar = BEATS_PER_BAR / self.division.beats();
+            ((self.bar_position + elapsed * self.bar_rate()).fract() * cycles_per_bar).fract()
+        } else {
+            (self.phase + elapsed * self.rate()).fract()
+        }
+    }
+
+    /// Advances the oscillator by `n` samples.
+    pub fn advance(&mut self, n: usize) {
+        self.phase = self.phase_at(n);
+        self.bar_position = (self.bar_position + n as f32 * self.inv_sample_rate * self.bar_rate()).fract();
+    }
+
+    /// Gets the number of bars per second.
+    fn bar_rate(&self) -> f32 {
+        self.bpm / (60.0 * BEATS_PER_BAR)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_sync() {
+        let mut lfo = Lfo::new();
+        lfo.set_sample_rate(48000);
+        lfo.set_frequency(3.0);
+        lfo.set_rate_sync(NoteDivision::Eighth, 120.0);
+        assert_eq!(lfo.rate(), 4.0);
+
+        // A cycle completes every 0.25 seconds
+        lfo.advance(6000);
+        assert!((lfo.phase_at(0) - 0.5).abs() < 1e-4);
+        lfo.advance(6000);
+        assert!(lfo.phase_at(0) < 1e-4 || lfo.phase_at(0) > 1.0 - 1e-4);
+
+        lfo.set_sync(false);
+        assert_eq!(lfo.rate(), 3.0);
+    }
+
+    #[test]
+    fn test_reset_on_bar() {
+        let mut lfo = Lfo::new();
+        lfo.set_sample_rate(48000);
+        lfo.set_rate_sync(NoteDivision::Half, 90.0);
+        lfo.set_reset_on_bar(true);
+
+        // Three quarters of the way through a bar of half notes, the phase is half way through a cycle
+        let bar = 48000 * 4 * 60 / 90;
+        lfo.advance(bar * 3 / 4);
+        assert!((lfo.phase_at(0) - 0.5).abs() < 1e-3);
+        assert!(lfo.phase_at(bar / 4 + 1) < 1e-3);
+    }
+}
@@ -0,0 +1,105 @@
+use super::ring::RingBuffer;
+use std::collections::VecDeque;
+
+/// Delays a signal by a fixed number of samples, while keeping track of the peak of the samples
+/// that are yet to be output, so that effects can react to transients before they arrive.
+#[derive(Clone)]
+pub struct Lookahead {
+    /// Holds the delayed samples.
+    ring: RingBuffer,
+    /// The delay in samples.
+    len: usize,
+    /// Candidates for the peak of the upcoming samples, as pairs of sample index and magnitude.
+    /// The indices are increasing and the magnitudes are decreasing, so the peak is always at the front.
+    peaks: VecDeque<(usize, f32)>,
+    /// Index of the next sample to be pushed.
+    idx: usize,
+}
+
+impl Lookahead {
+    /// Creates a lookahead buffer which delays the signal by `len` samples.
+    pub fn new(len: usize) -> Self {
+        Self {
+            ring: RingBuffer::new(len + 1),
+            len,
+            peaks: VecDeque::with_capacity(len + 1),
+            idx: 0,
+        }
+    }
+
+    /// Gets the delay in samples.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the signal isn't delayed at all.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Pushes a sample into the buffer, and returns the sample that was pushed `len` samples ago.
+    pub fn push(&mut self, sample: f32) -> f32 {
+        // Discard the candidate peaks which can no longer be the maximum
+        let magnitude = sample.abs();
+        while self.peaks.back().is_some_and(|&(_, m)| m <= magnitude) {
+            self.peaks.pop_back();
+        }
+        self.peaks.push_back((self.idx, magnitude));
+
+        // Discard the peaks which have now been output
+        while self.peaks.front().is_some_and(|&(i, _)| i + self.len <= self.idx) {
+            self.peaks.pop_front();
+        }
+        self.idx += 1;
+
+        // The oldest sample in the ring buffer is the one which is about to be overwritten
+        let mut delayed = [0.0];
+        self.ring.write(&[sample]);
+        self.ring.seek(self.ring.size());
+        self.ring.read(&mut delayed, false);
+        delayed[0]
+    }
+
+    /// Gets the peak magnitude of the samples that have been pushed but not yet output.
+    pub fn max_upcoming(&self) -> f32 {
+        self.peaks.front().map(|&(_, m)| m).unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let mut lookahead = Lookahead::new(5);
+        let input: Vec<f32> = (1..=20).map(|i| i as f32).collect();
+        let output: Vec<f32> = input.iter().map(|&s| lookahead.push(s)).collect();
+        assert_eq!(output[..5], [0.0; 5]);
+        assert_eq!(output[5..], input[..15]);
+    }
+
+    #[test]
+    fn test_peak_is_upcoming() {
+        let mut lookahead = Lookahead::new(4);
+        let mut input = [0.1; 16];
+        input[6] = -0.9;
+
+        for (i, &s) in input.iter().enumerate() {
+            let output = lookahead.push(s);
+            // The peak is visible for the whole window before it is output
+            let expected = if (6..10).contains(&i) { 0.9 } else { 0.1 };
+            assert_eq!(lookahead.max_upcoming(), expected);
+            assert_eq!(
+                output,
+                if i == 10 {
+                    -0.9
+                } else if i < 4 {
+                    0.0
+                } else {
+                    0.1
+                }
+            );
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use super::{
+    buffer::StereoBuffer,
+    weighting::{Weighting, WeightingFilter},
+};
+use std::collections::VecDeque;
+
+/// Length in seconds of the steps in which the signal is measured, which is the overlap between gating blocks.
+const STEP: f32 = 0.1;
+/// Number of steps in the window of the momentary loudness, which are also the gating blocks.
+const MOMENTARY_STEPS: usize = 4;
+/// Number of steps in the window of the short-term loudness.
+const SHORT_TERM_STEPS: usize = 30;
+/// Loudness in LUFS below which gating blocks are ignored by the integrated loudness.
+const ABSOLUTE_GATE: f32 = -70.0;
+/// Loudness in LU relative to the ungated loudness below which gating blocks are ignored.
+const RELATIVE_GATE: f32 = -10.0;
+
+/// Measures the loudness of a stereo signal in LUFS, following ITU-R BS.1770 and EBU R128.
+///
+/// The momentary and short-term loudness are measured over the last 400ms and 3s of the signal, while the
+/// integrated loudness is measured over the whole signal, ignoring silent and much quieter passages.
+/// Each loudness is `f32::NEG_INFINITY` until enough of the signal has been measured.
+pub struct LoudnessMeter {
+    /// Filters applying the K-weighting to the left and right channels.
+    filters: [WeightingFilter; 2],
+    /// Number of samples in each step.
+    step_len: usize,
+    /// Sum of the squared samples of both channels in the current step.
+    sum: f64,
+    /// Number of samples in the current step.
+    count: usize,
+    /// The mean square of each step in the short-term window, oldest first.
+    steps: VecDeque<f64>,
+    /// The mean square of every gating block measured so far.
+    blocks: Vec<f64>,
+}
+
+impl Default for LoudnessMeter {
+    fn default() -> Self {
+        let mut filter = WeightingFilter::new();
+        filter.set_weighting(Weighting::K);
+        Self {
+            filters: [filter; 2],
+            step_len: 0,
+            sum: 0.0,
+            count: 0,
+            steps: VecDeque::with_capacity(SHORT_TERM_STEPS),
+            blocks: vec![],
+        }
+    }
+}
+
+impl LoudnessMeter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the sample rate, which resets the meter.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        for filter in self.filters.iter_mut() {
+            filter.set_sample_rate(sample_rate);
+        }
+        self.step_len = (STEP * sample_rate as f32).round() as usize;
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        for filter in self.filters.iter_mut() {
+            filter.reset();
+        }
+        self.sum = 0.0;
+        self.count = 0;
+        self.steps.clear();
+        self.blocks.clear();
+    }
+
+    /// Measures the next block of the signal.
+    pub fn process(&mut self, audio_in: StereoBuffer) {
+        assert!(self.step_len > 0, "Sample rate has not been set");
+        for (&l, &r) in audio_in.left.iter().zip(audio_in.right.iter()) {
+            let [left, right] = &mut self.filters;
+            let (l, r) = (left.process_sample(l), right.process_sample(r));
+            self.sum += (l * l + r * r) as f64;
+            self.count += 1;
+            if self.count == self.step_len {
+                self.end_step();
+            }
+        }
+    }
+
+    fn end_step(&mut self) {
+        if self.steps.len() == SHORT_TERM_STEPS {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(self.sum / self.count as f64);
+        self.sum = 0.0;
+        self.count = 0;
+
+        // The gating blocks overlap by all but one step
+        if let Some(power) = self.mean_power(MOMENTARY_STEPS) {
+            self.blocks.push(power);
+        }
+    }
+
+    /// Gets the mean square of the last `steps` steps, if that many have been measured.
+    fn mean_power(&self, steps: usize) -> Option<f64> {
+        let start = self.steps.len().checked_sub(steps)?;
+        Some(self.steps.range(start..).sum::<f64>() / steps as f64)
+    }
+
+    /// Gets the loudness in LUFS of the last 400ms.
+    pub fn momentary(&self) -> f32 {
+        self.mean_power(MOMENTARY_STEPS).map_or(f32::NEG_INFINITY, loudness)
+    }
+
+    /// Gets the loudness in LUFS of the last 3 seconds.
+    pub fn short_term(&self) -> f32 {
+        self.mean_power(SHORT_TERM_STEPS).map_or(f32::NEG_INFINITY, loudness)
+    }
+
+    /// Gets the loudness in LUFS of the whole signal, excluding gating blocks below the absolute gate of -70 LUFS
+    /// and those more than 10 LU below the loudness of the remaining blocks.
+    pub fn integrated(&self) -> f32 {
+        let gated_mean = |gate: f32| {
+            let blocks = self.blocks.iter().filter(|&&power| loudness(power) > gate);
+            let (sum, count) = blocks.fold((0.0, 0), |(sum, count), &power| (sum + power, count + 1));
+            (count > 0).then(|| sum / count as f64)
+        };
+        let Some(ungated) = gated_mean(ABSOLUTE_GATE) else {
+            return f32::NEG_INFINITY;
+        };
+        let gate = loudness(ungated) + RELATIVE_GATE;
+        gated_mean(gate.max(ABSOLUTE_GATE)).map_or(f32::NEG_INFINITY, loudness)
+    }
+}
+
+/// Converts the summed mean square of the K-weighted channels to a loudness in LUFS.
+fn loudness(power: f64) -> f32 {
+    (-0.691 + 10.0 * power.log10()) as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::scale_from_gain;
+    use std::f32::consts::PI;
+
+    /// Measures a 1kHz sine wave in both channels, with a peak level in dBFS for each of the given durations.
+    fn measure(meter: &mut LoudnessMeter, levels: &[(f32, f32)]) {
+        let mut phase = 0.0f32;
+        for &(level, seconds) in levels {
+            let amplitude = scale_from_gain(level);
+            let sine: Vec<_> = (0..(seconds * 48000.0) as usize)
+                .map(|_| {
+                    phase = (phase + 1000.0 / 48000.0).fract();
+                    amplitude * (2.0 * PI * phase).sin()
+                })
+                .collect();
+            for block in sine.chunks(512) {
+                meter.process(StereoBuffer::new(block, block));
+            }
+        }
+    }
+
+    #[test]
+    fn test_calibration_tone() {
+        // A 1kHz sine wave at -23dBFS in both channels is -23 LUFS, per EBU Tech 3341
+        let mut meter = LoudnessMeter::new();
+        meter.set_sample_rate(48000);
+        assert_eq!(meter.integrated(), f32::NEG_INFINITY);
+        measure(&mut meter, &[(-23.0, 5.0)]);
+        assert!((meter.momentary() - -23.0).abs() < 0.1);
+        assert!((meter.short_term() - -23.0).abs() < 0.1);
+        assert!((meter.integrated() - -23.0).abs() < 0.1);
+
+        meter.reset();
+        measure(&mut meter, &[(-33.0, 5.0)]);
+        assert!((meter.integrated() - -33.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_gating() {
+        // The quiet passages are more than 10 LU below the rest, so are ignored
+        let mut meter = LoudnessMeter::new();
+        meter.set_sample_rate(48000);
+        measure(&mut meter, &[(-36.0, 2.0), (-23.0, 20.0), (-36.0, 2.0)]);
+        assert!((meter.integrated() - -23.0).abs() < 0.1);
+        assert!((meter.momentary() - -36.0).abs() < 0.1);
+
+        // Silence is below the absolute gate
+        measure(&mut meter, &[(-200.0, 5.0)]);
+        assert!((meter.integrated() - -23.0).abs() < 0.1);
+    }
+}
@@ -9,6 +9,7 @@ pub trait Interpolator {
     fn interpolate(t: f32, samples: &[f32]) -> f32;
 }
 
+#[derive(Clone)]
 pub struct Resampler<I: Interpolator> {
     x1: f32,
     _interpolator: PhantomData<I>,
@@ -48,9 +49,10 @@ impl<I: Interpolator> Resampler<I> {
     /// * `sampled_out` - The output sample buffer.
     /// * `ratio` - The ratio of input samples to output samples.
     pub fn resample(&mut self, samples_in: &[f32], samples_out: &mut [f32], ratio: f32) -> usize {
-        // Fast path for when no actual resampling is occuring
-        if self.x1.fract() == 0.0 && ratio == 1.0 {
-            let x1 = self.x1 as usize;
+        // Fast path for when no actual resampling is occuring, which skips ahead to the next whole sample if an
+        // earlier ratio left the position between samples
+        if ratio == 1.0 {
+            let x1 = self.x1.ceil() as usize;
             samples_out.copy_from_slice(&samples_in[x1..(x1 + samples_out.len())]);
             let offset = samples_out.len() + x1 - I::window();
             self.x1 = I::window() as f32;
@@ -80,6 +82,7 @@ impl<I: Interpolator> Resampler<I> {
     }
 }
 
+#[derive(Clone)]
 pub struct CubicInterpolator;
 
 impl Interpolator for CubicInterpolator {
@@ -100,6 +103,49 @@ impl Interpolator for CubicInterpolator {
     }
 }
 
+#[derive(Clone)]
+pub struct LinearInterpolator;
+
+impl Interpolator for LinearInterpolator {
+    #[inline]
+    fn window() -> usize {
+        0
+    }
+
+    #[inline]
+    fn interpolate(t: f32, samples: &[f32]) -> f32 {
+        samples[0] + t * (samples[1] - samples[0])
+    }
+}
+
+/// A 6-point, 5th-order Lagrange interpolator, which is more accurate than [`CubicInterpolator`]
+/// at high frequencies but costs more to compute.
+#[derive(Clone)]
+pub struct LagrangeInterpolator;
+
+impl Interpolator for LagrangeInterpolator {
+    #[inline]
+    fn window() -> usize {
+        2
+    }
+
+    #[inline]
+    fn interpolate(t: f32, samples: &[f32]) -> f32 {
+        // The samples are at positions -2 to 3 relative to the interpolated pair
+        let x = t + 2.0;
+        let mut output = 0.0;
+        for (k, &sample) in samples[..6].iter().enumerate() {
+            let mut weight = 1.0;
+            for j in (0..6).filter(|&j| j != k) {
+                weight *= (x - j as f32) / (k as f32 - j as f32);
+            }
+            output += weight * sample;
+        }
+        output
+    }
+}
+
+#[derive(Clone)]
 pub struct FloorInterpolator;
 
 impl Interpolator for FloorInterpolator {
@@ -113,3 +159,31 @@ impl Interpolator for FloorInterpolator {
         samples[0]
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_realign_at_unity() {
+        let samples_in: Vec<_> = (0..64).map(|i| i as f32).collect();
+        let mut resampler = Resampler::<CubicInterpolator>::new();
+        let mut read_idx = 0;
+        let mut resample = |resampler: &mut Resampler<_>, ratio| {
+            let mut samples_out = [0.0; 5];
+            assert!(resampler.next_input_size(samples_out.len(), ratio) <= samples_in.len() - read_idx);
+            read_idx += resampler.resample(&samples_in[read_idx..], &mut samples_out, ratio);
+            samples_out
+        };
+
+        // A fractional ratio leaves the position between samples, with the next output at 8.5
+        assert_eq!(resample(&mut resampler, 1.5), [1.0, 2.5, 4.0, 5.5, 7.0]);
+        assert_eq!(resampler.position().fract(), 0.5);
+
+        // Returning to unity skips ahead to the next whole sample, and copies the input from there
+        let samples_out = resample(&mut resampler, 1.0);
+        assert_eq!(samples_out, [9.0, 10.0, 11.0, 12.0, 13.0]);
+        assert_eq!(resampler.position(), 1.0);
+        assert_eq!(resample(&mut resampler, 1.0), [14.0, 15.0, 16.0, 17.0, 18.0]);
+    }
+}
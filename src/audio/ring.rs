@@ -20,6 +20,29 @@ impl RingBuffer {
         self.buffer.len()
     }
 
+    /// Grows the ring buffer to hold at least `size` samples, keeping its contents and the delay of the read head.
+    pub fn grow(&mut self, size: usize) {
+        let len = self.buffer.len();
+        if size <= len {
+            return;
+        }
+
+        // Copy the contents from oldest to newest, so that they end just before the write head
+        let delay = self.delay();
+        let mut buffer = vec![0.0; size].into_boxed_slice();
+        let (newest, oldest) = self.buffer.split_at(self.write_idx);
+        buffer[(size - len)..(size - newest.len())].copy_from_slice(oldest);
+        buffer[(size - newest.len())..].copy_from_slice(newest);
+        self.buffer = buffer;
+        self.write_idx = 0;
+        self.seek(delay);
+    }
+
+    /// Fills the ring buffer with silence, without moving the read or write positions.
+    pub fn clear(&mut self) {
+        self.buffer.fill(0.0);
+    }
+
     /// Gets the delay of the read head relative to the write head in samples.
     pub fn delay(&self) -> usize {
         let offset = self.write_idx as isize - self.read_idx as isize;
@@ -1,10 +1,23 @@
-use super::buffer::{AudioBufferMut, MonoBuffer, StereoBuffer};
-use crate::convert::uninterleave_stereo;
-use std::io::Read;
+use super::{
+    buffer::{AudioBufferMut, MonoBuffer, StereoBuffer},
+    loudness::LoudnessMeter,
+};
+use crate::{
+    convert::{downmix_to_stereo, uninterleave_stereo},
+    note::Note,
+    util::scale_from_gain,
+};
+use std::io::{Read, Seek, SeekFrom};
 use thiserror::Error;
 
 /// A callback function for reporting progress of a long-running process.
-type ProgressFn = Box<dyn FnMut(f64)>;
+pub type ProgressFn = Box<dyn FnMut(f64)>;
+
+/// Number of samples decoded between each report of progress.
+const PROGRESS_INTERVAL: usize = 16384;
+/// Largest `smpl` chunk that is read, which leaves room for many loops, so that a corrupt size can't
+/// cause a huge allocation.
+const MAX_SMPL_CHUNK_SIZE: u32 = 4096;
 
 #[derive(Clone)]
 pub struct AudioSample {
@@ -13,6 +26,22 @@ pub struct AudioSample {
     length: usize,
     data: Box<[f32]>,
     peaks: Option<(f32, f32)>,
+    /// The start and end of the sustain loop in samples, where the end is exclusive.
+    loop_points: Option<(usize, usize)>,
+    /// The note the sample was recorded at.
+    root_note: Option<Note>,
+}
+
+/// The outcome of normalizing the loudness of a sample with [`AudioSample::normalize_loudness`].
+#[derive(Clone, Copy, Debug)]
+pub struct LoudnessNormalization {
+    /// The integrated loudness of the sample in LUFS before it was normalized.
+    pub loudness: f32,
+    /// The gain in dB applied to the sample.
+    pub gain: f32,
+    /// Whether the gain was reduced so that the peaks don't exceed full scale, leaving the sample quieter than
+    /// the target. To reach the target, the sample should instead be passed through a true-peak limiter.
+    pub clamped: bool,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
@@ -29,6 +58,8 @@ impl AudioSample {
             length: audio.len(),
             data: audio.channel().to_vec().into_boxed_slice(),
             peaks: None,
+            loop_points: None,
+            root_note: None,
         }
     }
 
@@ -43,11 +74,39 @@ impl AudioSample {
             length: audio.len(),
             data: data.into_boxed_slice(),
             peaks: None,
+            loop_points: None,
+            root_note: None,
         }
     }
 
-    pub fn read_wav(reader: impl Read, progress: Option<ProgressFn>) -> Result<Self, ReadAudioClipError> {
-        let mut wav = hound::WavReader::new(reader)?;
+    /// Reads a mono or stereo WAV file, rejecting files with any other number of channels.
+    /// Loop points and the root note are read from the `smpl` chunk, if the file has one.
+    pub fn read_wav(reader: impl Read + Seek, progress: Option<ProgressFn>) -> Result<Self, ReadAudioClipError> {
+        Self::read_wav_inner(reader, progress, false)
+    }
+
+    /// Reads a WAV file, mixing files with more than two channels down to stereo.
+    /// See [`downmix_to_stereo`] for how the channels are mixed.
+    pub fn read_wav_downmix(
+        reader: impl Read + Seek,
+        progress: Option<ProgressFn>,
+    ) -> Result<Self, ReadAudioClipError> {
+        Self::read_wav_inner(reader, progress, true)
+    }
+
+    fn read_wav_inner(
+        mut reader: impl Read + Seek,
+        mut progress: Option<ProgressFn>,
+        downmix: bool,
+    ) -> Result<Self, ReadAudioClipError> {
+        // Look for the `smpl` chunk before decoding, as `hound` doesn't expose it, then rewind for `hound`
+        let start = reader.stream_position().map_err(ReadAudioClipError::IoError)?;
+        let metadata =
+            find_riff_chunk(&mut reader, b"smpl", MAX_SMPL_CHUNK_SIZE).and_then(|chunk| parse_smpl_chunk(&chunk));
+        reader
+            .seek(SeekFrom::Start(start))
+            .map_err(ReadAudioClipError::IoError)?;
+        let wav = hound::WavReader::new(reader)?;
 
         // Extract information from the file header
         let spec = wav.spec();
@@ -67,14 +126,16 @@ impl AudioSample {
         let scale = (max_value as f32).recip();
 
         // Read the interlaced samples into a buffer, normalized into `f32` values between -1.0 and 1.0
-        let samples: Vec<f32> = match format {
-            hound::SampleFormat::Int => wav
-                .into_samples::<i32>()
-                .map(|s| s.map(|s| s as f32 * scale))
-                .collect::<Result<_, _>>(),
-            hound::SampleFormat::Float => wav.into_samples::<f32>().collect(),
+        let total = channels as usize * length;
+        let mut samples = Vec::with_capacity(total);
+        match format {
+            hound::SampleFormat::Int => {
+                let iter = wav.into_samples::<i32>().map(|s| s.map(|s| s as f32 * scale));
+                read_samples(iter, &mut samples, total, &mut progress)
+            }
+            hound::SampleFormat::Float => read_samples(wav.into_samples::<f32>(), &mut samples, total, &mut progress),
         }?;
-        if samples.len() != channels as usize * length {
+        if samples.len() != total {
             return Err(ReadAudioClipError::UnexpectedError);
         }
 
@@ -87,9 +148,20 @@ impl AudioSample {
                 uninterleave_stereo(&samples, left, right);
                 (ChannelFormat::Stereo, data.into_boxed_slice())
             }
+            _ if downmix => {
+                let mut data = vec![0.0; 2 * length];
+                let (left, right) = data.split_at_mut(length);
+                downmix_to_stereo(&samples, channels as usize, left, right);
+                (ChannelFormat::Stereo, data.into_boxed_slice())
+            }
             _ => return Err(ReadAudioClipError::BadFormat("Unsupported number of channels")),
         };
 
+        // Only keep loop points which lie within the sample
+        let loop_points = metadata
+            .and_then(|m| m.loop_points)
+            .filter(|&(start, end)| start < end && end <= length);
+
         // Construct the clip
         Ok(Self {
             channel_format,
@@ -97,6 +169,8 @@ impl AudioSample {
             length,
             data,
             peaks: None,
+            loop_points,
+            root_note: metadata.and_then(|m| m.root_note),
         })
     }
 
@@ -108,6 +182,16 @@ impl AudioSample {
         self.length
     }
 
+    /// Gets the start and end of the sustain loop in samples, where the end is exclusive.
+    pub fn loop_points(&self) -> Option<(usize, usize)> {
+        self.loop_points
+    }
+
+    /// Gets the note the sample was recorded at, if known.
+    pub fn root_note(&self) -> Option<Note> {
+        self.root_note
+    }
+
     pub fn channels(&self) -> usize {
         match self.channel_format {
             ChannelFormat::Mono => 1,
@@ -147,7 +231,18 @@ impl AudioSample {
         }
         let data = data.into_boxed_slice();
 
-        Self { data, length, ..*self }
+        // Move the loop to match the trimmed data, dropping it if it has been cut
+        let loop_points = self
+            .loop_points
+            .filter(|&(loop_start, loop_end)| loop_start >= start && loop_end <= end)
+            .map(|(loop_start, loop_end)| (loop_start - start, loop_end - start));
+
+        Self {
+            data,
+            length,
+            loop_points,
+            ..*self
+        }
     }
 
     /// Calculates the extreme values (minimum and maximum) of the samples across all channels.
@@ -171,6 +266,117 @@ impl AudioSample {
 
         self.data.scale(scale);
     }
+
+    /// Scales the audio clip such that its integrated loudness reaches `target_lufs`, as measured by a
+    /// [`LoudnessMeter`], where mono clips are measured as if played through both channels.
+    /// The gain is limited so that the peaks don't exceed full scale, and silent clips are left unchanged.
+    pub fn normalize_loudness(&mut self, target_lufs: f32) -> LoudnessNormalization {
+        let mut meter = LoudnessMeter::new();
+        meter.set_sample_rate(self.sample_rate);
+        meter.process(self.stereo_data());
+        let loudness = meter.integrated();
+        if !loudness.is_finite() {
+            return LoudnessNormalization {
+                loudness,
+                gain: 0.0,
+                clamped: false,
+            };
+        }
+
+        // Limit the gain to that which brings the peaks to full scale
+        let (min, max) = self.analyze_peaks();
+        let headroom = -20.0 * f32::max(-min, max).log10();
+        let gain = target_lufs - loudness;
+        let clamped = gain > headroom;
+        let gain = gain.min(headroom);
+
+        let scale = scale_from_gain(gain);
+        self.data.scale(scale);
+        self.peaks = Some((scale * min, scale * max));
+        LoudnessNormalization {
+            loudness,
+            gain,
+            clamped,
+        }
+    }
+}
+
+/// Metadata read from the `smpl` chunk of a WAV file.
+#[derive(Clone, Copy)]
+struct SamplerMetadata {
+    loop_points: Option<(usize, usize)>,
+    root_note: Option<Note>,
+}
+
+/// Reads the contents of the chunk with the given ID in a RIFF WAVE file, starting from the current position of
+/// `reader`, by skipping over the contents of the chunks before it. Chunks larger than `max_size` are ignored.
+fn find_riff_chunk(reader: &mut (impl Read + Seek), id: &[u8; 4], max_size: u32) -> Option<Vec<u8>> {
+    let mut header = [0; 12];
+    reader.read_exact(&mut header).ok()?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return None;
+    }
+
+    let mut header = [0; 8];
+    while reader.read_exact(&mut header).is_ok() {
+        let size = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if &header[0..4] == id {
+            if size > max_size {
+                return None;
+            }
+            let mut chunk = vec![0; size as usize];
+            reader.read_exact(&mut chunk).ok()?;
+            return Some(chunk);
+        }
+        // Chunks are padded to an even number of bytes
+        reader.seek(SeekFrom::Current(size as i64 + size as i64 % 2)).ok()?;
+    }
+    None
+}
+
+/// Parses the root note and the first loop from the contents of a `smpl` chunk.
+fn parse_smpl_chunk(chunk: &[u8]) -> Option<SamplerMetadata> {
+    let field = |offset: usize| Some(u32::from_le_bytes(chunk.get(offset..(offset + 4))?.try_into().unwrap()));
+
+    let unity_note = field(12)?;
+    let num_loops = field(28)?;
+    // Each loop is 24 bytes following the 36 byte header, with an inclusive end
+    let loop_points = if num_loops > 0 {
+        let start = field(36 + 8)? as usize;
+        let end = field(36 + 12)? as usize;
+        end.checked_add(1).map(|end| (start, end))
+    } else {
+        None
+    };
+
+    Some(SamplerMetadata {
+        loop_points,
+        root_note: (unity_note < 128).then_some(Note(unity_note as u8)),
+    })
+}
+
+/// Collects decoded samples into `samples`, reporting the fraction of the `total` samples decoded so far.
+fn read_samples(
+    iter: impl Iterator<Item = Result<f32, hound::Error>>,
+    samples: &mut Vec<f32>,
+    total: usize,
+    progress: &mut Option<ProgressFn>,
+) -> Result<(), hound::Error> {
+    let mut report = |count: usize| {
+        if let Some(progress) = progress {
+            progress(count as f64 / total.max(1) as f64);
+        }
+    };
+
+    report(0);
+    for sample in iter {
+        samples.push(sample?);
+        if samples.len().is_multiple_of(PROGRESS_INTERVAL) {
+            report(samples.len());
+        }
+    }
+    report(total);
+    Ok(())
 }
 
 #[derive(Error, Debug)]
@@ -195,3 +401,166 @@ impl From<hound::Error> for ReadAudioClipError {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+    /// Writes a 16-bit WAV file in memory, where each frame holds the given samples.
+    fn write_wav(frames: &[Vec<i16>]) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels: frames[0].len() as u16,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut bytes = vec![];
+        let mut writer = hound::WavWriter::new(Cursor::new(&mut bytes), spec).unwrap();
+        for &sample in frames.iter().flatten() {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        bytes
+    }
+
+    /// Appends a `smpl` chunk with a root note and a single loop to a WAV file.
+    fn add_smpl_chunk(bytes: &mut Vec<u8>, root_note: u32, loop_start: u32, loop_end: u32) {
+        let header = [0, 0, 0, root_note, 0, 0, 0, 1, 0];
+        let sample_loop = [0, 0, loop_start, loop_end, 0, 0];
+        bytes.extend_from_slice(b"smpl");
+        bytes.extend_from_slice(&60u32.to_le_bytes());
+        for value in header.iter().chain(sample_loop.iter()) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let riff_size = bytes.len() as u32 - 8;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    }
+
+    #[test]
+    fn test_smpl_chunk() {
+        let frames: Vec<_> = (0..1000).map(|i| vec![i as i16]).collect();
+        let mut bytes = write_wav(&frames);
+        let sample = AudioSample::read_wav(Cursor::new(&bytes), None).unwrap();
+        assert_eq!(sample.loop_points(), None);
+        assert_eq!(sample.root_note(), None);
+
+        add_smpl_chunk(&mut bytes, 57, 100, 899);
+        let sample = AudioSample::read_wav(Cursor::new(&bytes), None).unwrap();
+        assert_eq!(sample.length(), 1000);
+        assert_eq!(sample.loop_points(), Some((100, 900)));
+        assert_eq!(sample.root_note(), Some(Note(57)));
+
+        // Trimming the sample moves the loop
+        assert_eq!(sample.trim(50, 950).loop_points(), Some((50, 850)));
+        assert_eq!(sample.trim(200, 950).loop_points(), None);
+    }
+
+    #[test]
+    fn test_bad_smpl_chunk() {
+        let frames: Vec<_> = (0..1000).map(|i| vec![i as i16]).collect();
+        let mut bytes = write_wav(&frames);
+
+        // A `smpl` chunk claiming to be far larger than the file is ignored
+        bytes.extend_from_slice(b"smpl");
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0; 60]);
+        let riff_size = bytes.len() as u32 - 8;
+        bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+        let sample = AudioSample::read_wav(Cursor::new(&bytes), None).unwrap();
+        assert_eq!(sample.length(), 1000);
+        assert_eq!(sample.loop_points(), None);
+        assert_eq!(sample.root_note(), None);
+    }
+
+    /// Creates a stereo sample of a 1kHz sine wave with a peak level in dBFS, lasting for five seconds.
+    fn sine_sample(level: f32) -> AudioSample {
+        let amplitude = scale_from_gain(level);
+        let sine: Vec<_> = (0..240_000)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 48000.0).sin())
+            .collect();
+        AudioSample::new_stereo(48000, StereoBuffer::new(&sine, &sine))
+    }
+
+    #[test]
+    fn test_normalize_loudness() {
+        // A 1kHz tone in both channels has the same loudness in LUFS as its peak level in dBFS
+        let mut sample = sine_sample(-30.0);
+        let original = sample.data(0)[12];
+        let result = sample.normalize_loudness(-16.0);
+        assert!((result.loudness - -30.0).abs() < 0.1);
+        assert!((result.gain - 14.0).abs() < 0.1);
+        assert!(!result.clamped);
+
+        // The level is raised by the measured difference
+        assert!((sample.data(0)[12] / original - scale_from_gain(result.gain)).abs() < 1e-4);
+        let mut meter = LoudnessMeter::new();
+        meter.set_sample_rate(48000);
+        meter.process(sample.stereo_data());
+        assert!((meter.integrated() - -16.0).abs() < 0.01);
+
+        // Reaching the target would clip the peaks, so the gain is limited
+        let mut sample = sine_sample(-12.0);
+        let result = sample.normalize_loudness(3.0);
+        assert!(result.clamped);
+        assert!((result.gain - 12.0).abs() < 1e-3);
+        let (min, max) = sample.analyze_peaks();
+        assert!((f32::max(-min, max) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_progress() {
+        let frames: Vec<_> = (0..100_000)
+            .map(|i| vec![(i % 1000) as i16, -((i % 1000) as i16)])
+            .collect();
+        let bytes = write_wav(&frames);
+
+        let values = Rc::new(RefCell::new(vec![]));
+        let recorded = values.clone();
+        let progress: ProgressFn = Box::new(move |p| recorded.borrow_mut().push(p));
+        AudioSample::read_wav(Cursor::new(&bytes), Some(progress)).unwrap();
+
+        // Progress is reported periodically, increasing from zero to one
+        let values = values.borrow();
+        assert!(values.len() > 10);
+        assert_eq!(values.first(), Some(&0.0));
+        assert_eq!(values.last(), Some(&1.0));
+        assert!(values.windows(2).all(|w| w[1] > w[0]));
+    }
+
+    #[test]
+    fn test_downmix() {
+        let frames: Vec<_> = (0..100).map(|i| vec![i * 100, -i * 100, 1000, 3000]).collect();
+        let bytes = write_wav(&frames);
+
+        // Only mono and stereo files are accepted without downmixing
+        assert!(AudioSample::read_wav(Cursor::new(&bytes), None).is_err());
+
+        // The front and rear channels of each side are averaged
+        let sample = AudioSample::read_wav_downmix(Cursor::new(&bytes), None).unwrap();
+        assert_eq!(sample.channels(), 2);
+        assert_eq!(sample.length(), 100);
+        let scale = (0x7fff as f32).recip();
+        for (i, frame) in frames.iter().enumerate() {
+            let left = (frame[0] + frame[2]) as f32 / 2.0 * scale;
+            let right = (frame[1] + frame[3]) as f32 / 2.0 * scale;
+            assert!((sample.data(0)[i] - left).abs() < 1e-6);
+            assert!((sample.data(1)[i] - right).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_surround_downmix() {
+        let bytes = write_wav(&[vec![1000, 2000, 3000, 4000, 5000, 6000]]);
+        let sample = AudioSample::read_wav_downmix(Cursor::new(&bytes), None).unwrap();
+
+        // The centre and surround channels are mixed in at -3dB, and the LFE channel is dropped
+        let gain = 0.5f32.sqrt();
+        let scale = (1.0 + 2.0 * gain).recip() / 0x7fff as f32;
+        let left = scale * (1000.0 + gain * (3000.0 + 5000.0));
+        let right = scale * (2000.0 + gain * (3000.0 + 6000.0));
+        assert!((sample.data(0)[0] - left).abs() < 1e-6);
+        assert!((sample.data(1)[0] - right).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::processor::IIRFilter;
+use std::f64::consts::PI;
+
+/// Maximum number of biquad sections in a weighting filter.
+const MAX_SECTIONS: usize = 3;
+
+/// A frequency weighting applied to a signal before its level is measured, so that the level correlates better
+/// with its perceived loudness.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Weighting {
+    /// The signal is measured as it is.
+    #[default]
+    None,
+    /// The A-weighting curve of IEC 61672, which is 0dB at 1kHz and rolls off the low and very high frequencies.
+    A,
+    /// The K-weighting of ITU-R BS.1770 used by LUFS meters, which is a high shelf boosting the highs by 4dB
+    /// followed by a highpass filter at around 38Hz.
+    K,
+}
+
+/// A filter implementing a [`Weighting`] curve, as a cascade of biquads.
+#[derive(Clone, Copy)]
+pub struct WeightingFilter {
+    weighting: Weighting,
+    sample_rate: f32,
+    sections: [IIRFilter; MAX_SECTIONS],
+    /// Number of sections in use.
+    num_sections: usize,
+}
+
+/// The coefficients of a second-order section, `b0` to `b2` and `a1` and `a2`, normalized so that `a0` is one.
+/// The filters are designed in double precision, as the poles of the low frequency sections are close to one.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+}
+
+impl Default for WeightingFilter {
+    fn default() -> Self {
+        Self {
+            weighting: Weighting::None,
+            sample_rate: 0.0,
+            sections: Default::default(),
+            num_sections: 0,
+        }
+    }
+}
+
+impl WeightingFilter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.calc_sections();
+    }
+
+    pub fn weighting(&self) -> Weighting {
+        self.weighting
+    }
+
+    /// Sets the weighting curve, which resets the filter.
+    pub fn set_weighting(&mut self, weighting: Weighting) {
+        self.weighting = weighting;
+        self.calc_sections();
+    }
+
+    pub fn reset(&mut self) {
+        for section in self.sections.iter_mut() {
+            section.reset();
+        }
+    }
+
+    /// Filters the next sample of the signal.
+    #[inline]
+    pub fn process_sample(&mut self, sample: f32) -> f32 {
+        let sections = self.sections[..self.num_sections].iter_mut();
+        sections.fold(sample, |s, section| section.process_sample(s))
+    }
+
+    fn calc_sections(&mut self) {
+        let fs = self.sample_rate as f64;
+        let sections: &[Biquad] = if fs <= 0.0 {
+            &[]
+        } else {
+            match self.weighting {
+                Weighting::None => &[],
+                Weighting::A => &a_weighting(fs),
+                Weighting::K => &k_weighting(fs),
+            }
+        };
+        for (section, biquad) in self.sections.iter_mut().zip(sections) {
+            *section = IIRFilter::from_biquad(biquad.b.map(|b| b as f32), biquad.a.map(|a| a as f32));
+        }
+        self.num_sections = sections.len();
+    }
+}
+
+/// Designs the A-weighting filter, from the poles of its analog transfer function.
+fn a_weighting(fs: f64) -> [Biquad; 3] {
+    // Prewarps each pole frequency, so that it lands in the same place after the bilinear transform
+    let w = |f: f64| 2.0 * fs * (PI * f / fs).tan();
+    let (w1, w2, w3, w4) = (w(20.598997), w(107.65265), w(737.86223), w(12194.217));
+    let mut sections = [
+        Biquad::from_analog([1.0, 0.0, 0.0], [1.0, 2.0 * w1, w1 * w1], fs),
+        Biquad::from_analog([1.0, 0.0, 0.0], [1.0, w2 + w3, w2 * w3], fs),
+        Biquad::from_analog([0.0, 0.0, w4 * w4], [1.0, 2.0 * w4, w4 * w4], fs),
+    ];
+
+    // Normalize the gain to 0dB at 1kHz
+    let gain: f64 = sections.iter().map(|s| s.magnitude(1000.0, fs)).product();
+    sections[0].b = sections[0].b.map(|b| b / gain);
+    sections
+}
+
+/// Designs the K-weighting filter, with the coefficients of BS.1770 recalculated for the sample rate.
+fn k_weighting(fs: f64) -> [Biquad; 2] {
+    // The high shelf, modelling the acoustic effect of the head
+    let k = (PI * 1681.974450955533 / fs).tan();
+    let q = 0.7071752369554196;
+    let vh = 10f64.powf(3.999843853973347 / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    // The highpass filter, known as the revised low-frequency B-weighting curve
+    let k = (PI * 38.13547087602444 / fs).tan();
+    let q = 0.5003270373238773;
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = Biquad::new([1.0, -2.0, 1.0], [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]);
+
+    [shelf, highpass]
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a }
+    }
+
+    /// Converts the analog transfer function `(b0 s^2 + b1 s + b2) / (a0 s^2 + a1 s + a2)` to a biquad with the
+    /// bilinear transform.
+    fn from_analog(b: [f64; 3], a: [f64; 3], fs: f64) -> Self {
+        let k = 2.0 * fs;
+        let transform = |[c0, c1, c2]: [f64; 3]| {
+            [
+                c0 * k * k + c1 * k + c2,
+                2.0 * (c2 - c0 * k * k),
+                c0 * k * k - c1 * k + c2,
+            ]
+        };
+        let (b, a) = (transform(b), transform(a));
+        Self::new(b.map(|b| b / a[0]), [a[1] / a[0], a[2] / a[0]])
+    }
+
+    /// Gets the magnitude of the frequency response at a frequency in Hz.
+    fn magnitude(&self, frequency: f64, fs: f64) -> f64 {
+        let w = 2.0 * PI * frequency / fs;
+        let response = |c: [f64; 3]| {
+            let re = c[0] + c[1] * w.cos() + c[2] * (2.0 * w).cos();
+            let im = -c[1] * w.sin() - c[2] * (2.0 * w).sin();
+            (re * re + im * im).sqrt()
+        };
+        response(self.b) / response([1.0, self.a[0], self.a[1]])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Filters a sine wave, and returns the ratio of its peak once the filter has settled to that of the input.
+    fn gain(weighting: Weighting, frequency: f32) -> f32 {
+        let mut filter = WeightingFilter::new();
+        filter.set_sample_rate(48000);
+        filter.set_weighting(weighting);
+        let output: Vec<_> = (0..48000)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / 48000.0).sin())
+            .map(|s| filter.process_sample(s))
+            .collect();
+        output[24000..].iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn test_a_weighting() {
+        // The curve passes through 0dB at 1kHz, and matches the standard at other frequencies
+        let db = |frequency| 20.0 * gain(Weighting::A, frequency).log10();
+        assert!(db(1000.0).abs() < 0.05);
+        assert!((db(100.0) - -19.1).abs() < 0.2);
+        assert!((db(50.0) - -30.2).abs() < 0.2);
+        assert!((db(4000.0) - 1.0).abs() < 0.2);
+
+        assert!((gain(Weighting::None, 50.0) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_k_weighting() {
+        let db = |frequency| 20.0 * gain(Weighting::K, frequency).log10();
+        assert!((db(10_000.0) - 4.0).abs() < 0.2);
+        assert!(db(1000.0).abs() < 1.0);
+        assert!(db(20.0) < -6.0);
+    }
+}
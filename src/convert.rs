@@ -15,6 +15,64 @@ pub fn uninterleave_stereo(input: &[f32], left: &mut [f32], right: &mut [f32]) {
     }
 }
 
+/// Interleaves a signal with any number of channels, with one input buffer per channel.
+pub fn interleave(inputs: &[&[f32]], output: &mut [f32]) {
+    let channels = inputs.len();
+    for (c, input) in inputs.iter().enumerate() {
+        for (i, &sample) in input.iter().enumerate() {
+            output[channels * i + c] = sample;
+        }
+    }
+}
+
+/// Uninterleaves a signal with any number of channels, with one output buffer per channel.
+pub fn uninterleave(input: &[f32], outputs: &mut [&mut [f32]]) {
+    let channels = outputs.len();
+    for (i, frame) in input.chunks_exact(channels).enumerate() {
+        for (output, &sample) in outputs.iter_mut().zip(frame) {
+            output[i] = sample;
+        }
+    }
+}
+
+/// Converts a sample between -1.0 and 1.0 to a signed integer with the given number of bits, rounding to the
+/// nearest value. Samples beyond -1.0 and 1.0 are clamped, rather than wrapping around.
+pub fn float_to_int(sample: f32, bits_per_sample: u16) -> i32 {
+    let max_value = ((1i64 << (bits_per_sample - 1)) - 1) as f64;
+    (sample as f64 * max_value).round().clamp(-max_value, max_value) as i32
+}
+
+/// Mixes an interleaved signal with any number of channels down to stereo.
+///
+/// Six channels are treated as 5.1 surround in WAV order (L, R, C, LFE, Ls, Rs),
+/// where the centre and surround channels are mixed in at -3dB and the LFE channel is dropped.
+/// Otherwise, even channels are averaged into the left channel and odd channels into the right,
+/// with a single channel being copied to both.
+pub fn downmix_to_stereo(input: &[f32], channels: usize, left: &mut [f32], right: &mut [f32]) {
+    const SURROUND_GAIN: f32 = std::f32::consts::FRAC_1_SQRT_2;
+    // Scales the surround downmix so that it can't clip
+    const SURROUND_SCALE: f32 = 1.0 / (1.0 + 2.0 * SURROUND_GAIN);
+
+    let lr = left.iter_mut().zip(right.iter_mut());
+    for (frame, (l, r)) in input.chunks_exact(channels).zip(lr) {
+        (*l, *r) = match *frame {
+            [mono] => (mono, mono),
+            [fl, fr, c, _lfe, sl, sr] => (
+                SURROUND_SCALE * (fl + SURROUND_GAIN * (c + sl)),
+                SURROUND_SCALE * (fr + SURROUND_GAIN * (c + sr)),
+            ),
+            _ => {
+                let left = frame.iter().step_by(2);
+                let right = frame.iter().skip(1).step_by(2);
+                (
+                    left.clone().sum::<f32>() / left.count() as f32,
+                    right.clone().sum::<f32>() / right.count() as f32,
+                )
+            }
+        };
+    }
+}
+
 /// Converts a LR signal to a MS signal
 pub fn leftright_to_midside(left: &[f32], right: &[f32], mid: &mut [f32], side: &mut [f32]) {
     let lr = left.iter().zip(right.iter());
@@ -1,6 +1,7 @@
 use crate::{
+    audio::{buffer::StereoBuffer, sample::AudioSample},
     midi::TimedMidiEvent,
-    processor::{Processor, ProcessorData},
+    processor::{ParamEvent, Processor, ProcessorData},
 };
 use bumpalo::Bump;
 use slotmap::{new_key_type, Key, SecondaryMap, SlotMap};
@@ -8,12 +9,32 @@ use std::{
     collections::HashMap,
     hash::Hash,
     slice::{from_raw_parts, from_raw_parts_mut},
+    time::{Duration, Instant},
 };
+use thiserror::Error;
 
 new_key_type! {
     pub struct DeviceId;
 }
 
+/// Weight of the most recent block in the rolling average of the processing time of each device.
+const LOAD_SMOOTHING: f32 = 0.1;
+
+/// Longest tail in seconds rendered by [`render_offline`], for devices which never fall silent.
+const MAX_TAIL_SECONDS: usize = 30;
+
+/// A callback which receives the MIDI output of each device after it is processed.
+pub type MidiObserver = Box<dyn FnMut(DeviceId, &[TimedMidiEvent])>;
+
+/// An error in the routing of the graph, which causes a device to be skipped.
+#[derive(Error, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphError {
+    #[error("Buffer index {index} is out of bounds; max is {max}")]
+    OutOfBounds { index: usize, max: usize },
+    #[error("Buffer at index {0} is already borrowed")]
+    AlreadyBorrowed(usize),
+}
+
 pub struct AudioEngine {
     sample_rate: u32,
     devices: SlotMap<DeviceId, Box<dyn Processor>>,
@@ -22,10 +43,41 @@ pub struct AudioEngine {
     audio_buffers: Vec<f32>,
     audio_map: HashMap<(DeviceId, usize), usize>, // FIXME
     midi_inputs: SecondaryMap<DeviceId, DeviceId>,
-    midi_buffer_cnt: usize,                 // FIXME
-    midi_buffers: Vec<Vec<TimedMidiEvent>>, // FIXME
-    midi_map: HashMap<DeviceId, usize>,     // FIXME
-    device_order: Vec<DeviceId>,            // FIXME
+    /// Number of devices whose MIDI output is routed to another device.
+    midi_buffer_cnt: usize,
+    /// Buffers holding the MIDI output of each device in `midi_map`, which are reused across blocks.
+    midi_buffers: Vec<Vec<TimedMidiEvent>>,
+    /// The index of the MIDI buffer of each device whose MIDI output is routed to another device.
+    midi_map: HashMap<DeviceId, usize>,
+    device_order: Vec<DeviceId>, // FIXME
+    param_events: SecondaryMap<DeviceId, Vec<ParamEvent>>,
+    /// MIDI events queued to be sent to each device, in order of time.
+    midi_events: SecondaryMap<DeviceId, Vec<TimedMidiEvent>>,
+    midi_observer: Option<MidiObserver>,
+    /// Allocator for the slices passed to each device, which is reused across blocks to avoid allocating.
+    bump: Bump,
+    /// Buffer for the MIDI output of the device being processed.
+    midi_out: Vec<TimedMidiEvent>,
+    /// Buffer for the MIDI input of the device being processed, when it is merged with queued events.
+    midi_in: Vec<TimedMidiEvent>,
+    /// Output channels of each device whose buffers hold the output of an earlier device which is still to be read,
+    /// so that the output of the device is summed with it rather than replacing it.
+    summed_outputs: SecondaryMap<DeviceId, u64>,
+    /// Buffer holding the contents of the outputs of the device being processed, when they are summed with those of
+    /// earlier devices and the device can't add to them itself.
+    summed: Vec<f32>,
+    /// The longest block which is expected to be processed, which `summed` is sized for.
+    max_block_size: usize,
+    /// Whether the processing time of each device is measured.
+    profiling: bool,
+    /// Rolling average of the time taken to process a block for each device, when profiling is enabled.
+    device_loads: SecondaryMap<DeviceId, Duration>,
+    /// The most recent error in the routing of the graph, and the device which was skipped because of it.
+    graph_error: Option<(DeviceId, GraphError)>,
+    /// Time in milliseconds over which the output fades in when processing starts.
+    fade_in_ms: f32,
+    /// Number of samples processed since the fade in started.
+    fade_in_position: usize,
 }
 
 impl AudioEngine {
@@ -42,6 +94,20 @@ impl AudioEngine {
             midi_buffers: vec![],
             midi_map: HashMap::new(),
             device_order: vec![],
+            param_events: SecondaryMap::new(),
+            midi_events: SecondaryMap::new(),
+            midi_observer: None,
+            bump: Bump::new(),
+            midi_out: vec![],
+            midi_in: vec![],
+            summed_outputs: SecondaryMap::new(),
+            summed: vec![],
+            max_block_size: 0,
+            profiling: false,
+            device_loads: SecondaryMap::new(),
+            graph_error: None,
+            fade_in_ms: 0.0,
+            fade_in_position: 0,
         }
     }
 
@@ -61,6 +127,9 @@ impl AudioEngine {
 
     pub fn remove_device(&mut self, device_id: DeviceId) {
         self.devices.remove(device_id);
+        self.param_events.remove(device_id);
+        self.midi_events.remove(device_id);
+        self.device_loads.remove(device_id);
 
         self.reconcile_graph();
     }
@@ -69,6 +138,73 @@ impl AudioEngine {
         self.devices.get_mut(device_id).unwrap().as_mut()
     }
 
+    /// Queues a parameter change to be applied to a device at a sample offset within the next processed block.
+    pub fn queue_parameter(&mut self, device_id: DeviceId, event: ParamEvent) {
+        let Some(events) = self.param_events.entry(device_id) else {
+            return;
+        };
+        let events = events.or_insert(vec![]);
+        let idx = events.partition_point(|e| e.time <= event.time);
+        events.insert(idx, event);
+    }
+
+    /// Queues a MIDI event to be sent to a device within the next processed block,
+    /// in addition to the events it receives from its MIDI input.
+    /// As with any MIDI stream, the time of the event is relative to the previous event queued for the device.
+    pub fn queue_midi(&mut self, device_id: DeviceId, event: TimedMidiEvent) {
+        let Some(events) = self.midi_events.entry(device_id) else {
+            return;
+        };
+        events.or_insert(vec![]).push(event);
+    }
+
+    /// Enables or disables measuring the time taken by each device to process a block.
+    /// Disabling profiling clears the measurements.
+    pub fn enable_profiling(&mut self, enabled: bool) {
+        self.profiling = enabled;
+        if !enabled {
+            self.device_loads.clear();
+        }
+    }
+
+    /// Gets a rolling average of the time taken by a device to process a block,
+    /// or `None` if profiling is disabled or the device hasn't been processed.
+    pub fn device_load(&self, device_id: DeviceId) -> Option<Duration> {
+        self.device_loads.get(device_id).copied()
+    }
+
+    /// Gets the number of samples for which the devices keep producing output after their inputs fall silent,
+    /// assuming that each device feeds the next.
+    pub fn tail_samples(&self) -> usize {
+        let devices = self.device_order.iter().filter_map(|&id| self.devices.get(id));
+        devices.fold(0, |tail, device| tail.saturating_add(device.tail_samples()))
+    }
+
+    /// Gets the most recent error in the routing of the graph, along with the device which was skipped because of
+    /// it, and clears it. Errors are recorded rather than raised, so that a misconfigured graph can't abort the
+    /// audio thread.
+    pub fn take_graph_error(&mut self) -> Option<(DeviceId, GraphError)> {
+        self.graph_error.take()
+    }
+
+    /// Sets the time in milliseconds over which the output fades in from silence when processing starts, to avoid
+    /// a pop while the devices settle. The fade is applied to the inputs of the devices with no audio outputs,
+    /// where the audio leaves the graph. Defaults to zero, which disables the fade.
+    pub fn set_fade_in(&mut self, fade_in_ms: f32) {
+        self.fade_in_ms = fade_in_ms.max(0.0);
+    }
+
+    /// Fades the output in again from the start of the next block, such as when playback restarts.
+    pub fn restart_fade_in(&mut self) {
+        self.fade_in_position = 0;
+    }
+
+    /// Sets a callback which receives the MIDI output of each device that produces any, such as to visualise it.
+    /// The callback is called on the audio thread, so it must not block.
+    pub fn set_midi_observer(&mut self, observer: Option<MidiObserver>) {
+        self.midi_observer = observer;
+    }
+
     pub fn set_audio_input(
         &mut self,
         src_device: DeviceId,
@@ -103,7 +239,7 @@ impl AudioEngine {
     }
 
     pub fn set_midi_input(&mut self, src_device: DeviceId, dst_device: DeviceId) {
-        let input_map = self.midi_inputs.insert(dst_device, src_device);
+        self.midi_inputs.insert(dst_device, src_device);
 
         self.reconcile_graph();
     }
@@ -114,42 +250,116 @@ impl AudioEngine {
         self.reconcile_graph();
     }
 
+    /// Sets the longest block which is expected to be processed, so that the buffers needed to process it are
+    /// allocated up front rather than on the audio thread.
+    pub fn set_max_block_size(&mut self, max_block_size: usize) {
+        self.max_block_size = max_block_size;
+        self.reconcile_graph();
+    }
+
+    /// Processes a block of `len` samples. The block size may change between calls, and empty blocks are ignored.
     pub fn process(&mut self, len: usize) {
         if self.sample_rate == 0 {
             panic!("Sample rate has not been set.");
         }
+        if len == 0 {
+            return;
+        }
 
-        let mut bump = Bump::new();
-
-        self.midi_buffers.resize_with(16, Vec::new); // FIXME
+        self.bump.reset();
 
         let num_buffers = 16; // FIXME
 
-        self.audio_buffers.resize(num_buffers * len, 0.0);
-        self.audio_buffers[..len].fill(0.0);
+        // The last buffer is kept silent, and is read by inputs which aren't connected
+        let silent_buffer = num_buffers;
+        self.audio_buffers.resize((num_buffers + 1) * len, 0.0);
+        self.audio_buffers[(silent_buffer * len)..].fill(0.0);
 
-        let mut midi_out = vec![];
+        let mut midi_out = std::mem::take(&mut self.midi_out);
+        let mut midi_merged = std::mem::take(&mut self.midi_in);
 
-        for &device_id in self.device_order.iter() {
-            bump.reset();
+        // Buffers which have been faded in this block, so that those read by several devices are only faded once
+        let fade_in_samples = (0.001 * self.fade_in_ms * self.sample_rate as f32).round() as usize;
+        let fading = self.fade_in_position < fade_in_samples;
+        let mut faded_mask = 0u64;
 
+        for &device_id in self.device_order.iter() {
             let Some(device) = self.devices.get_mut(device_id) else {
                 // FIXME: Fill outputs with silence?
                 continue;
             };
             let descr = device.description();
 
-            // Prepare audio buffers
+            // Prepare audio buffers, where required inputs which aren't connected are silent
             let inputs = self.audio_inputs.get(device_id).map(|i| &i[..]).unwrap_or(&[]);
             let num_inputs = inputs.len().clamp(descr.min_audio_ins, descr.max_audio_ins);
             let num_outputs = descr.num_audio_outs;
-            let (audio_in, audio_out) = borrow_buffers(
+
+            // Fade in the audio leaving the graph
+            if fading && num_outputs == 0 {
+                for ch in 0..num_inputs {
+                    let Some(&idx) = inputs.get(ch).and_then(|i| self.audio_map.get(i)) else {
+                        continue;
+                    };
+                    if idx >= silent_buffer || faded_mask & (1 << idx) != 0 {
+                        continue;
+                    }
+                    faded_mask |= 1 << idx;
+                    let buffer = &mut self.audio_buffers[(idx * len)..][..len];
+                    for (i, sample) in buffer.iter_mut().enumerate() {
+                        let gain = (self.fade_in_position + i) as f32 / fade_in_samples as f32;
+                        *sample *= gain.min(1.0);
+                    }
+                }
+            }
+
+            let output_buffer = |ch| self.audio_map.get(&(device_id, ch)).copied().unwrap_or(0);
+            let summed_mask = self.summed_outputs.get(device_id).copied().unwrap_or(0);
+            let is_summed = |ch: usize| summed_mask & (1 << ch) != 0;
+            let buffers = borrow_buffers(
                 &mut self.audio_buffers,
                 len,
-                (0..num_inputs).map(|ch| inputs.get(ch).and_then(|i| self.audio_map.get(i)).copied().unwrap_or(0)),
-                (0..num_outputs).map(|ch| self.audio_map.get(&(device_id, ch)).copied().unwrap_or(0)),
-                &bump,
+                (0..num_inputs).map(|ch| {
+                    let buffer = inputs.get(ch).and_then(|i| self.audio_map.get(i));
+                    buffer.copied().unwrap_or(silent_buffer)
+                }),
+                (0..num_outputs).map(output_buffer),
+                &self.bump,
             );
+            let (audio_in, audio_out) = match buffers {
+                Ok(buffers) => buffers,
+                Err(err) => {
+                    // Silence the outputs of the skipped device, rather than leaving the last block in them, but keep
+                    // those which hold the output of earlier devices
+                    for ch in (0..num_outputs).filter(|&ch| !is_summed(ch)) {
+                        let idx = output_buffer(ch);
+                        if idx < silent_buffer {
+                            self.audio_buffers[(idx * len)..][..len].fill(0.0);
+                        }
+                    }
+                    self.graph_error = Some((device_id, err));
+                    continue;
+                }
+            };
+
+            // Sum the outputs with those of earlier devices which share their buffers, by having the device add to
+            // them if it can, or by adding their contents back after it has overwritten them
+            let shared = summed_mask != 0;
+            let accumulate = shared && device.supports_accumulate();
+            if shared && !accumulate && self.summed.len() < num_outputs * len {
+                // Only reached when the block is longer than the maximum block size
+                self.summed.resize(num_outputs * len, 0.0);
+            }
+            if shared {
+                for (ch, buffer) in audio_out.iter_mut().enumerate() {
+                    match (is_summed(ch), accumulate) {
+                        (true, true) => {}
+                        (false, true) => buffer.fill(0.0),
+                        (true, false) => self.summed[(ch * len)..][..len].copy_from_slice(buffer),
+                        (false, false) => self.summed[(ch * len)..][..len].fill(0.0),
+                    }
+                }
+            }
 
             // Prepare MIDI buffers
             let midi_in = self
@@ -158,25 +368,102 @@ impl AudioEngine {
                 .and_then(|i| self.midi_map.get(i))
                 .map(|idx| &self.midi_buffers[*idx][..])
                 .unwrap_or(&[]);
+            let midi_in = match self.midi_events.get_mut(device_id) {
+                Some(queued) if !queued.is_empty() => {
+                    // Merge the queued events after any routed events at the same time, comparing the times since
+                    // the start of the block and then converting them back to the time since the previous event
+                    midi_merged.clear();
+                    let mut routed = midi_in.iter().peekable();
+                    let (mut routed_time, mut queued_time, mut merged_time) = (0, 0, 0);
+                    for event in queued.drain(..) {
+                        queued_time += event.time;
+                        while let Some(&e) = routed.next_if(|e| routed_time + e.time <= queued_time) {
+                            routed_time += e.time;
+                            midi_merged.push(TimedMidiEvent {
+                                time: routed_time - merged_time,
+                                ..e
+                            });
+                            merged_time = routed_time;
+                        }
+                        midi_merged.push(TimedMidiEvent {
+                            time: queued_time - merged_time,
+                            ..event
+                        });
+                        merged_time = queued_time;
+                    }
+                    if let Some(&e) = routed.next() {
+                        // Only the first remaining routed event follows a queued event
+                        midi_merged.push(TimedMidiEvent {
+                            time: routed_time + e.time - merged_time,
+                            ..e
+                        });
+                        midi_merged.extend(routed);
+                    }
+                    &midi_merged[..]
+                }
+                _ => midi_in,
+            };
             midi_out.clear();
 
+            // Prepare parameter events
+            let param_events = self.param_events.get(device_id).map(|e| &e[..]).unwrap_or(&[]);
+
+            let start = self.profiling.then(Instant::now);
             device.process(ProcessorData {
                 midi_in,
                 midi_out: &mut midi_out,
                 samples: len,
                 audio_in,
                 audio_out,
+                param_events,
+                accumulate,
             });
+            if let Some(start) = start {
+                let elapsed = start.elapsed();
+                let load = match self.device_loads.get(device_id) {
+                    Some(&load) => load.mul_f32(1.0 - LOAD_SMOOTHING) + elapsed.mul_f32(LOAD_SMOOTHING),
+                    None => elapsed,
+                };
+                self.device_loads.insert(device_id, load);
+            }
+
+            if shared && !accumulate {
+                for ch in 0..num_outputs {
+                    let summed = &self.summed[(ch * len)..][..len];
+                    let buffer = &mut self.audio_buffers[(output_buffer(ch) * len)..][..len];
+                    for (sample, summed) in buffer.iter_mut().zip(summed) {
+                        *sample += summed;
+                    }
+                }
+            }
 
+            if let Some(events) = self.param_events.get_mut(device_id) {
+                events.clear();
+            }
+
+            if let (Some(observer), false) = (&mut self.midi_observer, midi_out.is_empty()) {
+                observer(device_id, &midi_out);
+            }
+
+            // Copy rather than swap, so that each buffer keeps the capacity its own source needs
             if let Some(idx) = self.midi_map.get(&device_id) {
-                std::mem::swap(&mut self.midi_buffers[*idx], &mut midi_out);
+                let buffer = &mut self.midi_buffers[*idx];
+                buffer.clear();
+                buffer.extend_from_slice(&midi_out);
             }
         }
+
+        self.midi_out = midi_out;
+        self.midi_in = midi_merged;
+        if fading {
+            self.fade_in_position += len;
+        }
     }
 
     pub fn test_connect(&mut self, devices: &[DeviceId]) {
         self.device_order.clear();
         self.audio_map.clear();
+        self.midi_map.clear();
 
         self.device_order.extend(devices);
 
@@ -193,10 +480,52 @@ impl AudioEngine {
             };
             self.set_audio_input(a, 0, b, 0);
             self.set_audio_input(a, 1, b, 1);
+            self.set_midi_input(a, b);
         }
     }
 
     fn reconcile_graph(&mut self) {
+        // Give each device whose MIDI output is routed to another device its own buffer
+        self.midi_map.clear();
+        for (dst_device, &src_device) in self.midi_inputs.iter() {
+            if self.devices.contains_key(dst_device) && self.devices.contains_key(src_device) {
+                let idx = self.midi_map.len();
+                self.midi_map.entry(src_device).or_insert(idx);
+            }
+        }
+        self.midi_buffer_cnt = self.midi_map.len();
+        self.midi_buffers.resize_with(self.midi_buffer_cnt, Vec::new);
+        for buffer in self.midi_buffers.iter_mut() {
+            buffer.clear();
+        }
+
+        // Sum each output with the output of an earlier device which shares its buffer, when a later device is still
+        // to read it, rather than when the buffer is merely reused
+        self.summed_outputs.clear();
+        let mut summed_channels = 0;
+        for (pos, &device_id) in self.device_order.iter().enumerate() {
+            let Some(device) = self.devices.get(device_id) else {
+                continue;
+            };
+            let (earlier, later) = (&self.device_order[..pos], &self.device_order[(pos + 1)..]);
+            let num_outputs = device.description().num_audio_outs;
+            let mut mask = 0u64;
+            for ch in 0..num_outputs.min(64) {
+                let Some(&idx) = self.audio_map.get(&(device_id, ch)) else {
+                    continue;
+                };
+                let mut inputs = later.iter().filter_map(|&id| self.audio_inputs.get(id)).flatten();
+                if inputs.any(|src| earlier.contains(&src.0) && self.audio_map.get(src) == Some(&idx)) {
+                    mask |= 1 << ch;
+                }
+            }
+            if mask != 0 {
+                self.summed_outputs.insert(device_id, mask);
+                summed_channels = summed_channels.max(num_outputs);
+            }
+        }
+        self.summed.resize(summed_channels * self.max_block_size, 0.0);
+
         // self.device_order.clear();
         // self.audio_map.clear();
 
@@ -237,6 +566,60 @@ impl AudioEngine {
     }
 }
 
+/// Deterministically runs the engine for a number of blocks without any audio hardware, such as for testing.
+///
+/// Each entry of `midi_schedule` is sent to the `input` device during the block with the given index,
+/// and the audio received by the stereo inputs of the `output` device is returned.
+/// The `output` device should be the last device processed, so that its inputs aren't overwritten.
+pub fn run_for(
+    engine: &mut AudioEngine,
+    input: DeviceId,
+    output: DeviceId,
+    blocks: usize,
+    block_size: usize,
+    midi_schedule: Vec<(usize, TimedMidiEvent)>,
+) -> AudioSample {
+    let mut left = Vec::with_capacity(blocks * block_size);
+    let mut right = Vec::with_capacity(blocks * block_size);
+    for block in 0..blocks {
+        let events = midi_schedule.iter().filter(|(idx, _)| *idx == block);
+        for &(_, event) in events {
+            engine.queue_midi(input, event);
+        }
+        engine.process(block_size);
+
+        // Read the buffers which were connected to the inputs of the output device
+        let inputs = engine.audio_inputs.get(output).map(|i| &i[..]).unwrap_or(&[]);
+        for (channel, samples) in [&mut left, &mut right].into_iter().enumerate() {
+            match inputs.get(channel).and_then(|i| engine.audio_map.get(i)) {
+                Some(&idx) => samples.extend_from_slice(&engine.audio_buffers[(idx * block_size)..][..block_size]),
+                None => samples.resize(samples.len() + block_size, 0.0),
+            }
+        }
+    }
+    AudioSample::new_stereo(engine.sample_rate, StereoBuffer::new(&left, &right))
+}
+
+/// Renders the audio received by the stereo inputs of the `output` device, as with [`run_for`], continuing after
+/// the block of the last event in `midi_schedule` until the tails of the devices have decayed.
+pub fn render_offline(
+    engine: &mut AudioEngine,
+    input: DeviceId,
+    output: DeviceId,
+    block_size: usize,
+    midi_schedule: Vec<(usize, TimedMidiEvent)>,
+) -> AudioSample {
+    let blocks = midi_schedule.iter().map(|&(block, _)| block + 1).max().unwrap_or(0);
+    let tail = engine
+        .tail_samples()
+        .min(MAX_TAIL_SECONDS * engine.sample_rate as usize);
+    let blocks = blocks + tail.div_ceil(block_size);
+    run_for(engine, input, output, blocks, block_size, midi_schedule)
+}
+
+/// The input and output slices borrowed by [`borrow_buffers`].
+type BorrowedBuffers<'a> = (&'a [&'a [f32]], &'a mut [&'a mut [f32]]);
+
 /// Borrows slices from a "master" buffer for audio input and output based on specified indices.
 ///
 /// # Parameters
@@ -253,53 +636,61 @@ impl AudioEngine {
 /// - The first element is an array of borrowed slices for audio input. These slices can be overlapping.
 /// - The second element is an array of mutable borrowed slices for audio output. These slices are guaranteed to be non-overlapping.
 ///
-/// # Panics
+/// # Errors
 ///
 /// - If any index is out of bounds based on the master buffer size and `len`.
 /// - If an attempt is made to mutably borrow the same slice more than once.
 fn borrow_buffers<'a>(
     master: &'a mut [f32],
     len: usize,
-    audio_in_indices: impl Iterator<Item = usize> + ExactSizeIterator,
-    audio_out_indices: impl Iterator<Item = usize> + ExactSizeIterator,
+    audio_in_indices: impl Iterator<Item = usize> + ExactSizeIterator + Clone,
+    audio_out_indices: impl Iterator<Item = usize> + ExactSizeIterator + Clone,
     bump: &'a Bump,
-) -> (&'a [&'a [f32]], &'a mut [&'a mut [f32]]) {
+) -> Result<BorrowedBuffers<'a>, GraphError> {
     // Get a mutable pointer to the start of the master buffer
     let base_ptr = master.as_mut_ptr();
 
-    // Calculate the number of possible buffers of given length `len`
-    let max_buffers = (master.len() / len).min(64);
+    // Calculate the number of possible buffers of given length `len`, where any number of empty buffers fit
+    let max_buffers = master.len().checked_div(len).unwrap_or(usize::MAX).min(64);
 
-    // Initialize a bit-mask to keep track of borrowed slices
+    // Validate every index before borrowing any slices, using a bit-mask to keep track of borrowed slices
     let mut borrow_mask = 0u64;
+    for idx in audio_in_indices.clone() {
+        check_index_valid(idx, max_buffers, &mut borrow_mask, false)?;
+    }
+    for idx in audio_out_indices.clone() {
+        check_index_valid(idx, max_buffers, &mut borrow_mask, true)?;
+    }
 
     // Create audio input slices
     let audio_in_slices = bump.alloc_slice_fill_iter(audio_in_indices.map(|idx| {
-        assert_index_valid(idx, max_buffers, &mut borrow_mask, false);
         // Borrow the slice safely, as assured by the mask and index validation
         unsafe { from_raw_parts(base_ptr.add(len * idx), len) }
     }));
 
     // Create audio output slices
     let audio_out_slices = bump.alloc_slice_fill_iter(audio_out_indices.map(|idx| {
-        assert_index_valid(idx, max_buffers, &mut borrow_mask, true);
         // Borrow the slice safely, as assured by the mask and index validation
         unsafe { from_raw_parts_mut(base_ptr.add(len * idx), len) }
     }));
 
-    (audio_in_slices, audio_out_slices)
+    Ok((audio_in_slices, audio_out_slices))
 }
 
-/// Asserts that the given index is valid and updates the borrow mask.
-fn assert_index_valid(idx: usize, max_buffers: usize, borrow_mask: &mut u64, mutable: bool) {
+/// Checks that the given index is valid and updates the borrow mask.
+fn check_index_valid(idx: usize, max_buffers: usize, borrow_mask: &mut u64, mutable: bool) -> Result<(), GraphError> {
     if idx >= max_buffers {
-        panic!("Buffer index {} is out of bounds; max is {}", idx, max_buffers);
+        return Err(GraphError::OutOfBounds {
+            index: idx,
+            max: max_buffers,
+        });
     }
     if mutable && (*borrow_mask & (1 << idx) != 0) {
-        panic!("Buffer at index {} is already borrowed", idx);
+        return Err(GraphError::AlreadyBorrowed(idx));
     }
     // Mark this buffer as borrowed
     *borrow_mask |= 1 << idx;
+    Ok(())
 }
 
 struct BufferAllocator<K: Eq> {
@@ -326,3 +717,615 @@ impl<K: Eq> BufferAllocator<K> {
         self.buffers.iter().any(|(k, _)| *k == key)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        midi::MidiEvent,
+        note::Note,
+        processor::{
+            magnitude_at, Chord, ChordType, ConvolutionReverb, Crossfeed, Delay, Filter, FirFilter, Gain, Pipeline,
+            ProcessorDescription,
+        },
+        synth::SimpleSynth,
+    };
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+        f32::consts::PI,
+        sync::{Arc, Mutex},
+    };
+
+    /// An allocator which counts the allocations made by each thread.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|n| n.set(n.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// A processor which outputs an impulse on each channel at the start of each block.
+    struct Source;
+
+    impl Processor for Source {
+        fn description(&self) -> ProcessorDescription {
+            ProcessorDescription {
+                min_audio_ins: 0,
+                max_audio_ins: 0,
+                num_audio_outs: 2,
+            }
+        }
+
+        fn process(&mut self, data: ProcessorData) {
+            for buffer in data.audio_out.iter_mut() {
+                buffer.fill(0.0);
+                buffer[0] = 1.0;
+            }
+        }
+    }
+
+    /// A processor which outputs full scale DC.
+    struct Constant;
+
+    impl Processor for Constant {
+        fn description(&self) -> ProcessorDescription {
+            ProcessorDescription {
+                min_audio_ins: 0,
+                max_audio_ins: 0,
+                num_audio_outs: 2,
+            }
+        }
+
+        fn process(&mut self, data: ProcessorData) {
+            for buffer in data.audio_out.iter_mut() {
+                buffer.fill(1.0);
+            }
+        }
+    }
+
+    /// A processor which records the length of each block it receives.
+    struct Sink {
+        lens: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Processor for Sink {
+        fn description(&self) -> ProcessorDescription {
+            ProcessorDescription {
+                min_audio_ins: 2,
+                max_audio_ins: 2,
+                num_audio_outs: 0,
+            }
+        }
+
+        fn process(&mut self, data: ProcessorData) {
+            assert!(data.audio_in.iter().all(|b| b.len() == data.samples));
+            self.lens.lock().unwrap().push(data.samples);
+        }
+    }
+
+    /// A processor which records the last block of audio it receives.
+    struct Recorder {
+        blocks: Arc<Mutex<Vec<Vec<f32>>>>,
+    }
+
+    impl Processor for Recorder {
+        fn description(&self) -> ProcessorDescription {
+            ProcessorDescription {
+                min_audio_ins: 2,
+                max_audio_ins: 2,
+                num_audio_outs: 0,
+            }
+        }
+
+        fn process(&mut self, data: ProcessorData) {
+            *self.blocks.lock().unwrap() = data.audio_in.iter().map(|b| b.to_vec()).collect();
+        }
+    }
+
+    #[test]
+    fn test_variable_block_sizes() {
+        let lens = Arc::new(Mutex::new(vec![]));
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+        let devices = [
+            engine.add_device(Box::new(Source)),
+            engine.add_device(Box::new(Gain::new())),
+            engine.add_device(Box::new(Delay::new())),
+            engine.add_device(Box::new(Filter::new())),
+            engine.add_device(Box::new(FirFilter::new())),
+            engine.add_device(Box::new(ConvolutionReverb::new())),
+            engine.add_device(Box::new(Crossfeed::new())),
+            engine.add_device(Box::new(Pipeline::new([
+                Box::new(Gain::new()) as Box<dyn Processor + Send>,
+                Box::new(Delay::new()),
+            ]))),
+            engine.add_device(Box::new(Sink { lens: lens.clone() })),
+        ];
+        engine.test_connect(&devices);
+
+        for len in [0, 64, 1, 512, 0, 37] {
+            engine.process(len);
+        }
+        assert_eq!(*lens.lock().unwrap(), vec![64, 1, 512, 37]);
+    }
+
+    /// A processor which outputs a note on at the start of each block.
+    struct NoteSource(Note);
+
+    impl Processor for NoteSource {
+        fn description(&self) -> ProcessorDescription {
+            ProcessorDescription {
+                min_audio_ins: 0,
+                max_audio_ins: 0,
+                num_audio_outs: 0,
+            }
+        }
+
+        fn process(&mut self, data: ProcessorData) {
+            data.midi_out.push(TimedMidiEvent {
+                time: 0,
+                event: MidiEvent::NoteOn {
+                    channel: 0,
+                    note: self.0,
+                    velocity: 100,
+                },
+            });
+        }
+    }
+
+    /// A processor which records the MIDI events it receives.
+    struct MidiSink {
+        events: Arc<Mutex<Vec<TimedMidiEvent>>>,
+    }
+
+    impl Processor for MidiSink {
+        fn description(&self) -> ProcessorDescription {
+            ProcessorDescription {
+                min_audio_ins: 0,
+                max_audio_ins: 0,
+                num_audio_outs: 0,
+            }
+        }
+
+        fn process(&mut self, data: ProcessorData) {
+            self.events.lock().unwrap().extend_from_slice(data.midi_in);
+        }
+    }
+
+    #[test]
+    fn test_many_midi_sources() {
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+
+        // Process every source before any of the sinks, so that all their outputs are held at once
+        let sinks: Vec<_> = (0..20u8)
+            .map(|i| {
+                let source = engine.add_device(Box::new(NoteSource(Note(40 + i))));
+                let events = Arc::new(Mutex::new(vec![]));
+                let sink = engine.add_device(Box::new(MidiSink { events: events.clone() }));
+                engine.set_midi_input(source, sink);
+                (source, sink, events)
+            })
+            .collect();
+        engine.device_order = sinks.iter().map(|s| s.0).chain(sinks.iter().map(|s| s.1)).collect();
+        assert_eq!(engine.midi_buffers.len(), 20);
+
+        engine.process(64);
+        for (i, (_, _, events)) in sinks.iter().enumerate() {
+            let notes: Vec<_> = events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|e| match e.event {
+                    MidiEvent::NoteOn { note, .. } => Some(note),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(notes, [Note(40 + i as u8)]);
+        }
+    }
+
+    #[test]
+    fn test_merge_queued_midi() {
+        /// A processor which outputs the same MIDI events in each block.
+        struct Sequence(Vec<TimedMidiEvent>);
+
+        impl Processor for Sequence {
+            fn description(&self) -> ProcessorDescription {
+                ProcessorDescription {
+                    min_audio_ins: 0,
+                    max_audio_ins: 0,
+                    num_audio_outs: 0,
+                }
+            }
+
+            fn process(&mut self, data: ProcessorData) {
+                data.midi_out.extend_from_slice(&self.0);
+            }
+        }
+
+        let note_on = |time, note| TimedMidiEvent {
+            time,
+            event: MidiEvent::NoteOn {
+                channel: 0,
+                note: Note(note),
+                velocity: 100,
+            },
+        };
+
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+        let routed = vec![note_on(10, 60), note_on(20, 62), note_on(30, 64)];
+        let source = engine.add_device(Box::new(Sequence(routed)));
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink = engine.add_device(Box::new(MidiSink { events: events.clone() }));
+        engine.test_connect(&[source, sink]);
+
+        // Queued events are placed by their time since the start of the block, after routed events at the same time
+        engine.queue_midi(sink, note_on(20, 61));
+        engine.queue_midi(sink, note_on(10, 63));
+        engine.process(64);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                note_on(10, 60),
+                note_on(10, 61),
+                note_on(10, 62),
+                note_on(0, 63),
+                note_on(30, 64)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_midi_observer() {
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+        let mut chord = Chord::new();
+        chord.set_chord_type(0, ChordType::Major);
+        let source = engine.add_device(Box::new(NoteSource(Note::middle_c())));
+        let chord = engine.add_device(Box::new(chord));
+        engine.test_connect(&[source, chord]);
+
+        let observed = Arc::new(Mutex::new(vec![]));
+        let events = observed.clone();
+        engine.set_midi_observer(Some(Box::new(move |device_id, midi| {
+            let notes = midi.iter().filter_map(|e| match e.event {
+                MidiEvent::NoteOn { note, .. } => Some((device_id, note)),
+                _ => None,
+            });
+            events.lock().unwrap().extend(notes);
+        })));
+        engine.process(64);
+
+        let c_major = ["C4", "E4", "G4"].map(|n| (chord, Note::from_name(n).unwrap()));
+        let observed = observed.lock().unwrap();
+        assert_eq!(observed[0], (source, Note::middle_c()));
+        assert_eq!(observed[1..], c_major);
+    }
+
+    #[test]
+    fn test_no_allocation() {
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+        let mut chord = Chord::new();
+        chord.set_chord_type(0, ChordType::Major);
+        let devices = [
+            engine.add_device(Box::new(NoteSource(Note::middle_c()))),
+            engine.add_device(Box::new(chord)),
+            engine.add_device(Box::new(Source)),
+            engine.add_device(Box::new(Gain::new())),
+            engine.add_device(Box::new(Delay::new())),
+            engine.add_device(Box::new(Filter::new())),
+            engine.add_device(Box::new(Sink {
+                lens: Arc::new(Mutex::new(Vec::with_capacity(32))),
+            })),
+        ];
+        engine.test_connect(&devices);
+
+        // Once the buffers have grown to fit the block size, processing doesn't allocate
+        for _ in 0..4 {
+            engine.process(256);
+        }
+        let before = ALLOCATIONS.with(|n| n.get());
+        for _ in 0..16 {
+            engine.process(256);
+        }
+        assert_eq!(ALLOCATIONS.with(|n| n.get()), before);
+    }
+
+    #[test]
+    fn test_unconnected_input() {
+        let blocks = Arc::new(Mutex::new(vec![]));
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+        let source = engine.add_device(Box::new(Source));
+        let mut filter = Filter::new();
+        filter.set_cutoff(100.0);
+        let filter = engine.add_device(Box::new(filter));
+        let recorder = engine.add_device(Box::new(Recorder { blocks: blocks.clone() }));
+        engine.test_connect(&[source, filter, recorder]);
+
+        // Only the left input of the filter is connected
+        engine.remove_audio_input(filter, 1);
+        engine.process(64);
+
+        let blocks = blocks.lock().unwrap();
+        assert!(blocks[0].iter().any(|&s| s != 0.0));
+        assert!(blocks[1].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_graph_error() {
+        let blocks = Arc::new(Mutex::new(vec![]));
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+        let source = engine.add_device(Box::new(Source));
+        let gain = engine.add_device(Box::new(Gain::new()));
+        let recorder = engine.add_device(Box::new(Recorder { blocks: blocks.clone() }));
+        engine.test_connect(&[source, gain, recorder]);
+        engine.process(64);
+        assert_eq!(engine.take_graph_error(), None);
+
+        // Route both outputs of the gain to the same buffer, so the gain is skipped
+        engine.audio_map.insert((gain, 1), 2);
+        engine.process(64);
+        assert_eq!(engine.take_graph_error(), Some((gain, GraphError::AlreadyBorrowed(2))));
+        assert_eq!(engine.take_graph_error(), None);
+        assert!(blocks.lock().unwrap().iter().flatten().all(|&s| s == 0.0));
+
+        // Route an output of the gain to a buffer which doesn't exist, so the recorder reading it is skipped too
+        engine.audio_map.insert((gain, 1), 100);
+        engine.process(64);
+        assert_eq!(
+            engine.take_graph_error(),
+            Some((recorder, GraphError::OutOfBounds { index: 100, max: 17 }))
+        );
+    }
+
+    #[test]
+    fn test_shared_outputs() {
+        let blocks = Arc::new(Mutex::new(vec![]));
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+        let first = engine.add_device(Box::new(Constant));
+        let second = engine.add_device(Box::new(Constant));
+        let source = engine.add_device(Box::new(Constant));
+        let gain = engine.add_device(Box::new(Gain::new()));
+        let recorder = engine.add_device(Box::new(Recorder { blocks: blocks.clone() }));
+        engine.test_connect(&[first, second, source, gain, recorder]);
+
+        // Route the outputs of both constants and the gain to the same buffers, which the recorder reads
+        for ch in 0..2 {
+            engine.audio_map.insert((first, ch), ch);
+            engine.audio_map.insert((second, ch), ch);
+            engine.audio_map.insert((source, ch), 2 + ch);
+            engine.audio_map.insert((gain, ch), ch);
+            engine.set_audio_input(source, ch, gain, ch);
+            engine.set_audio_input(first, ch, recorder, ch);
+        }
+
+        // The second constant is summed by the engine, and the gain adds to the buffers itself
+        engine.process(64);
+        assert_eq!(engine.take_graph_error(), None);
+        let blocks = blocks.lock().unwrap();
+        for block in blocks.iter() {
+            assert!(block.iter().all(|&s| (s - 3.0).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_reused_buffers() {
+        let blocks = Arc::new(Mutex::new(vec![]));
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+        engine.set_max_block_size(64);
+        let source = engine.add_device(Box::new(Constant));
+        let gains: Vec<_> = (0..3).map(|_| engine.add_device(Box::new(Gain::new()))).collect();
+        let recorder = engine.add_device(Box::new(Recorder { blocks: blocks.clone() }));
+
+        // Every other device in the chain writes to the same buffers, which have already been read
+        engine.test_connect(&[source, gains[0], gains[1], gains[2], recorder]);
+        engine.process(64);
+        assert_eq!(engine.take_graph_error(), None);
+        let blocks = blocks.lock().unwrap();
+        assert_eq!(blocks.len(), 2);
+        for block in blocks.iter() {
+            assert!(block.iter().all(|&s| (s - 1.0).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_fade_in() {
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+        engine.set_fade_in(10.0);
+        let source = engine.add_device(Box::new(Constant));
+        let output = engine.add_device(Box::new(Recorder {
+            blocks: Arc::new(Mutex::new(vec![])),
+        }));
+        engine.test_connect(&[source, output]);
+
+        // The output starts from silence and rises to full scale over 480 samples
+        let audio = run_for(&mut engine, source, output, 8, 128, vec![]);
+        let left = audio.stereo_data().left;
+        assert_eq!(left[0], 0.0);
+        assert!(left[..480].windows(2).all(|w| w[1] > w[0]));
+        assert!((left[240] - 0.5).abs() < 1e-6);
+        assert!(left[480..].iter().all(|&s| s == 1.0));
+        assert_eq!(audio.stereo_data().right, left);
+
+        // Restarting fades in again
+        engine.restart_fade_in();
+        let audio = run_for(&mut engine, source, output, 1, 128, vec![]);
+        assert_eq!(audio.stereo_data().left[0], 0.0);
+    }
+
+    #[test]
+    fn test_run_for() {
+        // Plays the first notes of a C major chord, each 100 samples after the previous one
+        let c_major = ["C4", "E4", "G4"].map(|n| Note::from_name(n).unwrap());
+        let render = |notes: usize| {
+            let mut engine = AudioEngine::new();
+            engine.set_sample_rate(48000);
+            let synth = engine.add_device(Box::new(SimpleSynth::new()));
+            let mut filter = Filter::new();
+            filter.set_cutoff(100.0);
+            let filter = engine.add_device(Box::new(filter));
+            let output = engine.add_device(Box::new(Recorder {
+                blocks: Arc::new(Mutex::new(vec![])),
+            }));
+            engine.test_connect(&[synth, filter, output]);
+
+            let schedule = c_major[..notes]
+                .iter()
+                .map(|&note| {
+                    let event = MidiEvent::NoteOn {
+                        channel: 0,
+                        note,
+                        velocity: 100,
+                    };
+                    (2, TimedMidiEvent { time: 100, event })
+                })
+                .collect();
+            run_for(&mut engine, synth, output, 24, 512, schedule)
+        };
+        let sample = render(3);
+        assert_eq!(sample.length(), 24 * 512);
+
+        // Each note starts 100 samples after the previous one, from 100 samples into the third block,
+        // where the first sample of each note is zero
+        for notes in 1..=3 {
+            let (before, after) = (render(notes - 1), render(notes));
+            let onset = (0..after.length()).find(|&i| after.data(0)[i] != before.data(0)[i]);
+            assert_eq!(onset, Some(2 * 512 + 100 * notes + 1));
+        }
+
+        // Gets the magnitude of a frequency in the last 4800 samples of the output, using a Hann window
+        let signal: Vec<f32> = sample.data(0)[(sample.length() - 4800)..]
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| s * (0.5 - 0.5 * (2.0 * PI * i as f32 / 4800.0).cos()))
+            .collect();
+        let magnitude = |frequency: f32| magnitude_at(&signal, frequency, 48000.0) / 1200.0;
+        // Each note of the chord is present, and other frequencies are not
+        let notes = [261.63, 329.63, 392.0].map(magnitude);
+        assert!(notes.iter().all(|&m| m > 0.5));
+        for frequency in [200.0, 295.0, 360.0, 440.0] {
+            assert!(magnitude(frequency) < 0.02 * notes[0]);
+        }
+    }
+
+    #[test]
+    fn test_render_offline() {
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+        let synth = engine.add_device(Box::new(SimpleSynth::new()));
+        let mut delay = Delay::new();
+        delay.set_delay(0.25);
+        delay.set_feedback(0.5);
+        let delay = engine.add_device(Box::new(delay));
+        let output = engine.add_device(Box::new(Recorder {
+            blocks: Arc::new(Mutex::new(vec![])),
+        }));
+        engine.test_connect(&[synth, delay, output]);
+
+        // The note lasts for two blocks
+        let note = Note::middle_c();
+        let schedule = vec![
+            (
+                0,
+                TimedMidiEvent {
+                    time: 0,
+                    event: MidiEvent::NoteOn {
+                        channel: 0,
+                        note,
+                        velocity: 100,
+                    },
+                },
+            ),
+            (
+                2,
+                TimedMidiEvent {
+                    time: 0,
+                    event: MidiEvent::NoteOff {
+                        channel: 0,
+                        note,
+                        velocity: 0,
+                    },
+                },
+            ),
+        ];
+        let sample = render_offline(&mut engine, synth, output, 512, schedule);
+
+        // Ten echoes decay by 60dB, and the rendering continues for one delay beyond the last of them
+        assert_eq!(engine.tail_samples(), 11 * 12000);
+        assert_eq!(sample.length(), 3 * 512 + (11 * 12000usize).div_ceil(512) * 512);
+
+        // The echoes continue long after the note and its release have ended
+        let peak = |start: f32, end: f32| {
+            let range = (start * 48000.0) as usize..(end * 48000.0) as usize;
+            sample.data(0)[range].iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+        };
+        assert!(peak(1.5, 2.0) > 1e-3);
+        assert!(peak(1.5, 2.0) < 0.5 * peak(0.25, 0.5));
+    }
+
+    #[test]
+    fn test_profiling() {
+        /// A processor which takes a long time to process each block.
+        struct Slow;
+
+        impl Processor for Slow {
+            fn description(&self) -> ProcessorDescription {
+                ProcessorDescription {
+                    min_audio_ins: 0,
+                    max_audio_ins: 0,
+                    num_audio_outs: 0,
+                }
+            }
+
+            fn process(&mut self, _data: ProcessorData) {
+                std::thread::sleep(Duration::from_millis(2));
+            }
+        }
+
+        let mut engine = AudioEngine::new();
+        engine.set_sample_rate(48000);
+        let slow = engine.add_device(Box::new(Slow));
+        let gain = engine.add_device(Box::new(Gain::new()));
+        let sink = engine.add_device(Box::new(Sink {
+            lens: Arc::new(Mutex::new(vec![])),
+        }));
+        engine.test_connect(&[slow, gain, sink]);
+
+        // Nothing is measured until profiling is enabled
+        engine.process(64);
+        assert_eq!(engine.device_load(slow), None);
+
+        engine.enable_profiling(true);
+        for _ in 0..4 {
+            engine.process(64);
+        }
+        let slow_load = engine.device_load(slow).unwrap();
+        assert!(slow_load >= Duration::from_millis(2));
+        assert!(slow_load > engine.device_load(gain).unwrap());
+
+        engine.enable_profiling(false);
+        assert_eq!(engine.device_load(slow), None);
+    }
+}
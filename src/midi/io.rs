@@ -0,0 +1,108 @@
+use super::MidiEvent;
+use thiserror::Error;
+
+/// An open connection to a MIDI device, which is closed by [`Disconnect::disconnect`].
+pub trait Disconnect: Send {
+    fn disconnect(self: Box<Self>);
+}
+
+impl<T: Send> Disconnect for midir::MidiInputConnection<T> {
+    fn disconnect(self: Box<Self>) {
+        self.close();
+    }
+}
+
+/// Owns a connection to a MIDI input port along with its callback, disconnecting when dropped.
+pub struct MidiConnection {
+    connection: Option<Box<dyn Disconnect>>,
+}
+
+#[derive(Error, Debug)]
+pub enum MidiConnectionError {
+    #[error("Failed to initialise MIDI input: {0}")]
+    InitError(#[from] midir::InitError),
+    #[error("Failed to connect to MIDI port: {0}")]
+    ConnectError(midir::ConnectErrorKind),
+}
+
+impl MidiConnection {
+    /// Connects to a MIDI input port, calling `callback` with each valid event that arrives, other than active
+    /// sensing. The callback is called on a thread owned by the MIDI backend.
+    pub fn connect(
+        port: &midir::MidiInputPort,
+        mut callback: impl FnMut(MidiEvent) + Send + 'static,
+    ) -> Result<Self, MidiConnectionError> {
+        let mut midi_in = midir::MidiInput::new("MIDI input")?;
+        midi_in.ignore(midir::Ignore::ActiveSense);
+        let connection = midi_in
+            .connect(
+                port,
+                "midi-read-connection",
+                move |_, message: &[u8], _: &mut ()| {
+                    let event = MidiEvent::from_raw(message);
+                    if !event.is_invalid() {
+                        callback(event);
+                    }
+                },
+                (),
+            )
+            .map_err(|err| MidiConnectionError::ConnectError(err.kind()))?;
+        Ok(Self::from_connection(connection))
+    }
+
+    /// Takes ownership of an open connection.
+    pub fn from_connection(connection: impl Disconnect + 'static) -> Self {
+        Self {
+            connection: Some(Box::new(connection)),
+        }
+    }
+
+    /// Disconnects straight away, which is otherwise done when the connection is dropped.
+    pub fn disconnect(mut self) {
+        self.close();
+    }
+
+    fn close(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            connection.disconnect();
+        }
+    }
+}
+
+impl Drop for MidiConnection {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// Counts the number of times it is disconnected.
+    struct MockConnection(Arc<AtomicUsize>);
+
+    impl Disconnect for MockConnection {
+        fn disconnect(self: Box<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_disconnect_on_drop() {
+        let disconnects = Arc::new(AtomicUsize::new(0));
+        let connection = MidiConnection::from_connection(MockConnection(disconnects.clone()));
+        assert_eq!(disconnects.load(Ordering::SeqCst), 0);
+        drop(connection);
+        assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+
+        // Disconnecting explicitly doesn't disconnect again when dropped
+        let connection = MidiConnection::from_connection(MockConnection(disconnects.clone()));
+        connection.disconnect();
+        assert_eq!(disconnects.load(Ordering::SeqCst), 2);
+    }
+}
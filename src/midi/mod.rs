@@ -1,5 +1,10 @@
 use crate::note::Note;
 
+pub use parser::MidiParser;
+
+pub mod io;
+mod parser;
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct TimedMidiEvent {
     /// Number of samples that have elapsed since the last event,
@@ -11,10 +16,34 @@ pub struct TimedMidiEvent {
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum MidiEvent {
-    NoteOn { channel: u8, note: Note, velocity: u8 },
-    NoteOff { channel: u8, note: Note, velocity: u8 },
-    ControlChange { channel: u8, control: u8, value: u8 },
-    PitchBend { channel: u8, value: u16 },
+    NoteOn {
+        channel: u8,
+        note: Note,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: Note,
+        velocity: u8,
+    },
+    ControlChange {
+        channel: u8,
+        control: u8,
+        value: u8,
+    },
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    /// A timing clock pulse, sent 24 times per quarter note.
+    Clock,
+    Start,
+    Continue,
+    Stop,
     Invalid,
 }
 
@@ -36,15 +65,100 @@ impl MidiEvent {
                 control,
                 value,
             },
+            [a @ 0xc0..=0xcf, program] => MidiEvent::ProgramChange {
+                channel: a & 0x0f,
+                program,
+            },
             [a @ 0xe0..=0xef, lsb, msb] => MidiEvent::PitchBend {
                 channel: a & 0x0f,
                 value: lsb as u16 | ((msb as u16) << 7),
             },
+            [0xf8] => MidiEvent::Clock,
+            [0xfa] => MidiEvent::Start,
+            [0xfb] => MidiEvent::Continue,
+            [0xfc] => MidiEvent::Stop,
             _ => MidiEvent::Invalid,
         }
     }
 
+    /// Encodes the event as a raw MIDI message into `buffer`, and returns the length of the message.
+    /// Invalid events have a length of zero.
+    pub fn to_raw(&self, buffer: &mut [u8; 3]) -> usize {
+        let message: &[u8] = match *self {
+            MidiEvent::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => &[0x80 | channel, note.0, velocity],
+            MidiEvent::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => &[0x90 | channel, note.0, velocity],
+            MidiEvent::ControlChange {
+                channel,
+                control,
+                value,
+            } => &[0xb0 | channel, control, value],
+            MidiEvent::PitchBend { channel, value } => &[0xe0 | channel, (value & 0x7f) as u8, (value >> 7) as u8],
+            MidiEvent::ProgramChange { channel, program } => &[0xc0 | channel, program],
+            MidiEvent::Clock => &[0xf8],
+            MidiEvent::Start => &[0xfa],
+            MidiEvent::Continue => &[0xfb],
+            MidiEvent::Stop => &[0xfc],
+            MidiEvent::Invalid => &[],
+        };
+        buffer[..message.len()].copy_from_slice(message);
+        message.len()
+    }
+
     pub fn is_invalid(&self) -> bool {
         matches!(self, MidiEvent::Invalid)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_raw_round_trip() {
+        let events = [
+            MidiEvent::NoteOn {
+                channel: 2,
+                note: Note::middle_c(),
+                velocity: 100,
+            },
+            MidiEvent::PitchBend {
+                channel: 15,
+                value: 12345,
+            },
+            MidiEvent::ProgramChange {
+                channel: 9,
+                program: 42,
+            },
+            MidiEvent::Clock,
+            MidiEvent::Start,
+            MidiEvent::Continue,
+            MidiEvent::Stop,
+        ];
+        for event in events {
+            let mut buffer = [0; 3];
+            let len = event.to_raw(&mut buffer);
+            assert_eq!(MidiEvent::from_raw(&buffer[..len]), event);
+        }
+    }
+
+    #[test]
+    fn test_from_raw() {
+        assert_eq!(
+            MidiEvent::from_raw(&[0xc3, 17]),
+            MidiEvent::ProgramChange {
+                channel: 3,
+                program: 17
+            }
+        );
+        assert_eq!(MidiEvent::from_raw(&[0xf8]), MidiEvent::Clock);
+        assert!(MidiEvent::from_raw(&[0xc3, 17, 0]).is_invalid());
+    }
+}
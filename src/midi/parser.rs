@@ -0,0 +1,118 @@
+use super::MidiEvent;
+
+/// Parses MIDI events from a stream of bytes, as received from a serial MIDI port.
+///
+/// Handles running status, where the status byte is omitted for consecutive messages of the same type,
+/// and real-time messages, which may be interleaved within other messages.
+#[derive(Clone, Default)]
+pub struct MidiParser {
+    /// The status byte of the current message, if any.
+    status: Option<u8>,
+    /// The data bytes of the current message received so far.
+    data: [u8; 2],
+    /// Number of data bytes received for the current message.
+    len: usize,
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Feeds a byte to the parser, returning an event if it completes a message.
+    /// Messages which aren't supported by [`MidiEvent`] are consumed and ignored.
+    pub fn push(&mut self, byte: u8) -> Option<MidiEvent> {
+        match byte {
+            // Real-time messages don't interrupt the current message
+            0xf8..=0xff => Some(MidiEvent::from_raw(&[byte])).filter(|e| !e.is_invalid()),
+            // System common messages cancel running status, and their data bytes are ignored
+            0xf0..=0xf7 => {
+                self.status = None;
+                self.len = 0;
+                None
+            }
+            0x80..=0xef => {
+                self.status = Some(byte);
+                self.len = 0;
+                None
+            }
+            _ => {
+                let status = self.status?;
+                self.data[self.len] = byte;
+                self.len += 1;
+                if self.len < data_len(status) {
+                    return None;
+                }
+
+                // Keep the status byte so that subsequent messages can use running status
+                self.len = 0;
+                let [a, b] = self.data;
+                let event = match data_len(status) {
+                    1 => MidiEvent::from_raw(&[status, a]),
+                    _ => MidiEvent::from_raw(&[status, a, b]),
+                };
+                Some(event).filter(|e| !e.is_invalid())
+            }
+        }
+    }
+}
+
+/// Gets the number of data bytes in a channel message with the given status byte.
+fn data_len(status: u8) -> usize {
+    match status & 0xf0 {
+        0xc0 | 0xd0 => 1,
+        _ => 2,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::note::Note;
+
+    #[test]
+    fn test_running_status() {
+        let bytes = [
+            0x90, 60, 100, 64, 100, 0xf8, 67, 100, // Three note ons, with a clock pulse in the middle
+            0x80, 60, 0, 64, 0, 67, 0, // Three note offs
+        ];
+        let mut parser = MidiParser::new();
+        let events: Vec<_> = bytes.into_iter().filter_map(|b| parser.push(b)).collect();
+
+        let on = |note| MidiEvent::NoteOn {
+            channel: 0,
+            note: Note(note),
+            velocity: 100,
+        };
+        let off = |note| MidiEvent::NoteOff {
+            channel: 0,
+            note: Note(note),
+            velocity: 0,
+        };
+        assert_eq!(
+            events,
+            vec![on(60), on(64), MidiEvent::Clock, on(67), off(60), off(64), off(67)]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_messages() {
+        // Two program changes using running status, and a sysex message which cancels running status
+        let bytes = [0xc0, 5, 6, 0xf0, 1, 2, 3, 0xf7, 60, 0xb1, 7, 127];
+        let mut parser = MidiParser::new();
+        let events: Vec<_> = bytes.into_iter().filter_map(|b| parser.push(b)).collect();
+
+        assert_eq!(
+            events,
+            vec![
+                MidiEvent::ProgramChange { channel: 0, program: 5 },
+                MidiEvent::ProgramChange { channel: 0, program: 6 },
+                MidiEvent::ControlChange {
+                    channel: 1,
+                    control: 7,
+                    value: 127
+                }
+            ]
+        );
+    }
+}
@@ -32,12 +32,157 @@ impl Note {
         note_name(self.0)
     }
 
+    /// Parses a note name such as "C4", "A#3" or "Gb5", where "C-1" is the lowest note.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let mut chars = name.chars();
+        let pitch_class: i32 = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let rest = chars.as_str();
+        let (accidental, octave) = if let Some(octave) = rest.strip_prefix('#') {
+            (1, octave)
+        } else if let Some(octave) = rest.strip_prefix('b') {
+            (-1, octave)
+        } else {
+            (0, rest)
+        };
+        let octave: i32 = octave.parse().ok()?;
+        let note = 12 * (octave + 1) + pitch_class + accidental;
+        u8::try_from(note).ok().filter(|&n| n < 128).map(Self)
+    }
+
     pub fn frequency(&self) -> f32 {
         hz_from_note(self.0)
     }
 
     pub fn transpose(&self, offset: i8) -> Self {
-        Self(self.0.saturating_add_signed(offset))
+        Self(self.0.saturating_add_signed(offset).min(127))
+    }
+
+    /// Gets the number of semitones from this note up to `other`, which is negative if `other` is lower.
+    pub fn interval_to(&self, other: Note) -> i8 {
+        (other.0 as i16 - self.0 as i16) as i8
+    }
+
+    /// Gets the octave number of the note, where middle C is in octave 4.
+    pub fn octave(&self) -> i8 {
+        (self.0 / 12) as i8 - 1
+    }
+
+    /// Gets the pitch class of the note between 0 and 11, where C is 0.
+    pub fn pitch_class(&self) -> u8 {
+        self.0 % 12
+    }
+
+    /// Returns `true` if the note belongs to the given scale starting on `root`, in any octave.
+    pub fn in_scale(&self, root: Note, scale: &Scale) -> bool {
+        let degree = (self.pitch_class() + 12 - root.pitch_class()) % 12;
+        scale.intervals().contains(&degree)
+    }
+}
+
+/// A musical scale.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Scale {
+    Major,
+    Minor,
+    HarmonicMinor,
+    MelodicMinor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+    Chromatic,
+}
+
+impl Scale {
+    /// Gets the semitone offset of each degree of the scale from the root, in ascending order.
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::MelodicMinor => &[0, 2, 3, 5, 7, 9, 11],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            Scale::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+        }
+    }
+}
+
+/// Maps notes to frequencies, as an offset in cents from 12-tone equal temperament for each pitch class.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tuning {
+    cents: [f32; 12],
+}
+
+impl Tuning {
+    /// Creates a tuning from the offset in cents of each pitch class, starting from C.
+    pub fn from_cents_table(cents: [f32; 12]) -> Self {
+        Self { cents }
+    }
+
+    /// Parses a tuning from the contents of a Scala (.scl) file, with C as the first degree of the scale.
+    /// Only 12-note scales which repeat at the octave are supported.
+    pub fn from_scala(text: &str) -> Option<Self> {
+        let mut lines = text.lines().map(str::trim).filter(|l| !l.starts_with('!'));
+        let _description = lines.next()?;
+        let count: usize = lines.next()?.parse().ok()?;
+        if count != 12 {
+            return None;
+        }
+
+        // The pitch of each degree after the first is given in cents or as a ratio, with the octave last
+        let mut pitches = lines.map(|line| {
+            let value = line.split_whitespace().next()?;
+            if value.contains('.') {
+                return value.parse::<f32>().ok();
+            }
+            let (num, den) = value.split_once('/').unwrap_or((value, "1"));
+            let ratio = num.parse::<f32>().ok()? / den.parse::<f32>().ok()?;
+            Some(1200.0 * ratio.log2())
+        });
+        let mut cents = [0.0; 12];
+        for (degree, offset) in cents.iter_mut().enumerate().skip(1) {
+            *offset = pitches.next()?? - 100.0 * degree as f32;
+        }
+        let octave = pitches.next()??;
+        if (octave - 1200.0).abs() > 0.01 {
+            return None;
+        }
+        Some(Self { cents })
+    }
+
+    /// Sets the offset in cents of each pitch class, starting from C.
+    pub fn set_cents_table(&mut self, cents: [f32; 12]) {
+        self.cents = cents;
+    }
+
+    /// Gets the offset in cents of each pitch class, starting from C.
+    pub fn cents_table(&self) -> [f32; 12] {
+        self.cents
+    }
+
+    /// Gets the frequency of a note in Hz.
+    pub fn frequency(&self, note: Note) -> f32 {
+        let cents = self.cents[note.pitch_class() as usize];
+        note.frequency() * 2f32.powf(cents / 1200.0)
     }
 }
 
@@ -66,3 +211,118 @@ fn note_name(note: u8) -> &'static str {
 
     names[note as usize]
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        for i in 0..128 {
+            let note = Note(i);
+            assert_eq!(Note::from_name(note.name()), Some(note));
+        }
+        assert_eq!(Note::from_name("C-1"), Some(Note(0)));
+        assert_eq!(Note::from_name("C4"), Some(Note::middle_c()));
+        assert_eq!(Note::from_name("Db4"), Some(Note(61)));
+        assert_eq!(Note::from_name("Gb5"), Some(Note(78)));
+        assert_eq!(Note::from_name("G9"), Some(Note(127)));
+    }
+
+    #[test]
+    fn test_intervals() {
+        let c4 = Note::middle_c();
+        assert_eq!(c4.interval_to(Note(67)), 7);
+        assert_eq!(Note(67).interval_to(c4), -7);
+        assert_eq!(c4.interval_to(Note(84)), 24);
+        assert_eq!(Note(0).interval_to(Note(127)), 127);
+        assert_eq!(Note(127).interval_to(Note(0)), -127);
+        assert_eq!(Note(127).transpose(12), Note(127));
+    }
+
+    #[test]
+    fn test_pitch_class_and_octave() {
+        assert_eq!(Note(0).octave(), -1);
+        assert_eq!(Note(0).pitch_class(), 0);
+        assert_eq!(Note(59).octave(), 3);
+        assert_eq!(Note(59).pitch_class(), 11);
+        assert_eq!(Note(60).octave(), 4);
+        assert_eq!(Note(60).pitch_class(), 0);
+        assert_eq!(Note(127).octave(), 9);
+        assert_eq!(Note(127).pitch_class(), 7);
+    }
+
+    #[test]
+    fn test_in_scale() {
+        let a = Note::from_name("A3").unwrap();
+        let in_a_minor: Vec<_> = (60..72).map(Note).filter(|n| n.in_scale(a, &Scale::Minor)).collect();
+        let c_major: Vec<_> = (60..72)
+            .map(Note)
+            .filter(|n| n.in_scale(Note(0), &Scale::Major))
+            .collect();
+        assert_eq!(in_a_minor, c_major);
+        assert!(Note::from_name("F#2")
+            .unwrap()
+            .in_scale(Note::from_name("G5").unwrap(), &Scale::Major));
+        assert!(!Note::from_name("F2")
+            .unwrap()
+            .in_scale(Note::from_name("G5").unwrap(), &Scale::Major));
+    }
+
+    #[test]
+    fn test_from_name_invalid() {
+        for name in [
+            "", "C", "H4", "#4", "C##4", "Cx4", "C4.5", "G#9", "Cb-1", "C10", "C-2", "C 4",
+        ] {
+            assert_eq!(Note::from_name(name), None, "{name:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn test_tuning() {
+        let a4 = Note(69);
+        let mut tuning = Tuning::default();
+        assert_eq!(tuning.frequency(a4), 440.0);
+
+        // Offsetting a pitch class only changes the frequency of notes with that pitch class
+        let mut cents = [0.0; 12];
+        cents[9] = 50.0;
+        tuning.set_cents_table(cents);
+        assert!((tuning.frequency(a4) - 440.0 * 2f32.powf(50.0 / 1200.0)).abs() < 1e-3);
+        assert!((tuning.frequency(Note(57)) - 220.0 * 2f32.powf(50.0 / 1200.0)).abs() < 1e-3);
+        assert_eq!(tuning.frequency(Note(70)), Note(70).frequency());
+    }
+
+    #[test]
+    fn test_scala_tuning() {
+        let scala = "! just.scl
+            !
+            5-limit just intonation
+             12
+            !
+            16/15
+            9/8
+            6/5
+            5/4
+            4/3
+            45/32
+            3/2
+            8/5
+            5/3
+            9/5
+            15/8
+            2/1
+        ";
+        let tuning = Tuning::from_scala(scala).unwrap();
+        let cents = tuning.cents_table();
+        assert_eq!(cents[0], 0.0);
+        assert!((cents[4] - -13.686).abs() < 1e-2);
+        assert!((cents[7] - 1.955).abs() < 1e-2);
+
+        // Scales without 12 notes aren't supported
+        assert_eq!(
+            Tuning::from_scala("Pentatonic\n5\n200.0\n400.0\n700.0\n900.0\n2/1"),
+            None
+        );
+    }
+}
@@ -1,25 +1,87 @@
 use crate::midi::TimedMidiEvent;
+use bumpalo::Bump;
+use std::ops::Range;
+
+pub use auto_duck::AutoDuck;
 pub use autopan::Autopan;
-pub use chord::Chord;
+pub use bass_mono::BassMono;
+pub use channel_strip::ChannelStrip;
+pub use chord::{Chord, ChordType};
+pub use clipper::Clipper;
+pub use compressor::Compressor;
+pub use convolution_reverb::ConvolutionReverb;
+pub use correlation_meter::{CorrelationHandle, CorrelationMeter};
+pub use crossfader::Crossfader;
+pub use crossfeed::Crossfeed;
+pub use crossover::Crossover;
 pub use delay::Delay;
-pub use filter::Filter;
+pub use filter::{Filter, IIRFilter, StereoMode};
+pub use fir_filter::{FirFilter, FirMode};
 pub use gain::Gain;
 pub use io::{AudioInput, AudioOutput, MidiInput};
+pub use master_utility::MasterUtility;
+pub use midi_clock::MidiClock;
+pub use mix::Mix;
 pub use mixer::Mixer;
+pub use oversampled::Oversampled;
 pub use pipeline::Pipeline;
-pub use sampler::Sampler;
+pub use pseudo_stereo::PseudoStereo;
+pub use recorder::Recorder;
+pub use reverb::Reverb;
+pub use safety_clamp::SafetyClamp;
+pub use sampler::{InterpolationQuality, Sampler};
 pub use saturator::Saturator;
+pub use scope::{Scope, ScopeHandle, ScopeTrigger};
+pub use step_sequencer::StepSequencer;
+#[cfg(test)]
+pub(crate) use test_util::{magnitude_at, ProcessorDataBuilder};
+pub use triggered_sampler::TriggeredSampler;
 
+mod auto_duck;
 mod autopan;
+mod bass_mono;
+mod channel_strip;
 mod chord;
+mod clipper;
+mod compressor;
+mod convolution_reverb;
+mod correlation_meter;
+mod crossfader;
+mod crossfeed;
+mod crossover;
 mod delay;
 mod filter;
+mod fir_filter;
 mod gain;
 mod io;
+mod master_utility;
+mod midi_clock;
+mod mix;
 mod mixer;
+mod oversampled;
 mod pipeline;
+mod pseudo_stereo;
+mod recorder;
+mod reverb;
+mod safety_clamp;
 mod sampler;
 mod saturator;
+mod scope;
+mod step_sequencer;
+#[cfg(test)]
+mod test_util;
+mod triggered_sampler;
+
+/// A change to the value of a parameter, timed to occur within a block of audio.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ParamEvent {
+    /// Offset of the event from the start of the block, in samples.
+    pub time: u32,
+    /// The parameter to change.
+    pub param_id: usize,
+    /// The new value of the parameter.
+    pub value: f32,
+}
 
 pub struct ProcessorData<'a> {
     /// List of input MIDI events
@@ -28,10 +90,99 @@ pub struct ProcessorData<'a> {
     pub midi_out: &'a mut Vec<TimedMidiEvent>,
     /// Number of samples in each audio block
     pub samples: usize,
-    /// List of input audio blocks
+    /// List of input audio blocks, where required inputs which aren't connected are silent
     pub audio_in: &'a [&'a [f32]],
     /// List of output audio blocks
     pub audio_out: &'a mut [&'a mut [f32]],
+    /// List of parameter changes within the block, in order of time.
+    /// Processors which don't support sample-accurate automation may ignore these.
+    pub param_events: &'a [ParamEvent],
+    /// Whether the processor should add its output to the existing contents of the output blocks,
+    /// rather than replacing them. This is only set for processors which support it.
+    pub accumulate: bool,
+}
+
+/// Reusable storage for the events and audio blocks of a sub-block, see [`ProcessorData::sub_block`].
+#[derive(Default)]
+pub struct SubBlockEvents {
+    midi_in: Vec<TimedMidiEvent>,
+    param_events: Vec<ParamEvent>,
+    /// Allocator for the lists of sliced audio blocks, which is reused across sub-blocks to avoid allocating.
+    bump: Bump,
+}
+
+impl ProcessorData<'_> {
+    /// Calls `f` with the part of the block within a range of samples, such as to apply an event at its sample offset.
+    ///
+    /// The audio blocks are sliced to the range, and the times of the input events are made relative to its start,
+    /// with events at or beyond the end of the block given to the last sub-block. MIDI events output by `f`
+    /// are shifted back to the time of the whole block, following any events already output.
+    pub fn sub_block<R>(
+        &mut self,
+        range: Range<usize>,
+        events: &mut SubBlockEvents,
+        f: impl FnOnce(ProcessorData) -> R,
+    ) -> R {
+        assert!(range.start <= range.end && range.end <= self.samples);
+
+        // Copy the events within the range, relative to its start, where the time of each MIDI event is relative
+        // to the previous event, and the time of each parameter change is relative to the start of the block
+        let samples = self.samples;
+        let in_range = |time: u32| {
+            let time = time as usize;
+            time >= range.start && (time < range.end || range.end == samples)
+        };
+        let offset = range.start as u32;
+        events.midi_in.clear();
+        let (mut time, mut prev_time) = (0, offset);
+        for event in self.midi_in {
+            time += event.time;
+            if in_range(time) {
+                events.midi_in.push(TimedMidiEvent {
+                    time: time - prev_time,
+                    event: event.event,
+                });
+                prev_time = time;
+            }
+        }
+        events.param_events.clear();
+        events.param_events.extend(
+            self.param_events
+                .iter()
+                .filter(|e| in_range(e.time))
+                .map(|e| ParamEvent {
+                    time: e.time - offset,
+                    ..*e
+                }),
+        );
+
+        // Slice the audio blocks
+        events.bump.reset();
+        let audio_in = events
+            .bump
+            .alloc_slice_fill_iter(self.audio_in.iter().map(|buffer| &buffer[range.clone()]));
+        let audio_out = events
+            .bump
+            .alloc_slice_fill_iter(self.audio_out.iter_mut().map(|buffer| &mut buffer[range.clone()]));
+
+        // Find the time of the last event already output, which the first event output by `f` follows
+        let midi_start = self.midi_out.len();
+        let out_time: u32 = self.midi_out.iter().map(|e| e.time).sum();
+        let result = f(ProcessorData {
+            midi_in: &events.midi_in,
+            midi_out: self.midi_out,
+            samples: range.len(),
+            audio_in,
+            audio_out,
+            param_events: &events.param_events,
+            accumulate: self.accumulate,
+        });
+        if let Some(event) = self.midi_out.get_mut(midi_start) {
+            // Events can't be output before those already output, which may run past the start of the range
+            event.time = (offset + event.time).saturating_sub(out_time);
+        }
+        result
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -47,10 +198,26 @@ pub trait Processor: std::any::Any {
 
     /// Provides the audio sample rate to the processor.
     /// This must be called before calling `process` or that method may panic.
-    fn set_sample_rate(&mut self, sample_rate: u32) {}
+    fn set_sample_rate(&mut self, _sample_rate: u32) {}
 
     /// Sets the value of an automatable parameter.
-    fn set_parameter(&mut self, param_id: usize, value: f32) {}
+    fn set_parameter(&mut self, _param_id: usize, _value: f32) {}
+
+    /// Gets the delay, in samples, that the processor introduces between its inputs and outputs.
+    fn latency_samples(&self) -> usize {
+        0
+    }
+
+    /// Gets the number of samples for which the processor keeps producing output after its inputs fall silent,
+    /// such as the tail of a reverb, or `usize::MAX` if it never falls silent.
+    fn tail_samples(&self) -> usize {
+        0
+    }
+
+    /// Whether the processor can add its output to the output blocks, see `ProcessorData::accumulate`.
+    fn supports_accumulate(&self) -> bool {
+        false
+    }
 
     /// Processes a batch of MIDI and audio data.
     fn process(&mut self, data: ProcessorData);
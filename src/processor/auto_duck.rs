@@ -0,0 +1,195 @@
+use super::Processor;
+use crate::{audio::envelope_follower::EnvelopeFollower, util::scale_from_gain};
+
+/// Attenuates a program signal while a trigger signal is active, such as to duck music under a voice.
+///
+/// The first two inputs are the program, which is output, and the last two are the trigger. Unlike a sidechain
+/// compressor the program is attenuated by a fixed amount whenever the trigger exceeds the threshold, which is
+/// held for a while after the trigger falls silent so that the program doesn't swell back up between words.
+pub struct AutoDuck {
+    /// The sample rate in `Hz`.
+    sample_rate: f32,
+    /// Level in dB above which the trigger is active.
+    threshold: f32,
+    /// Attenuation in dB of the program while ducked.
+    amount: f32,
+    /// Time in seconds for which the program stays ducked after the trigger falls below the threshold.
+    hold: f32,
+    /// Number of samples remaining until the hold ends.
+    hold_remaining: usize,
+    /// Follows the peak level of the trigger.
+    detector: EnvelopeFollower,
+    /// Follows how far the program is ducked, from `0.0` to `1.0`, over the attack and release times.
+    ducking: EnvelopeFollower,
+}
+
+impl Default for AutoDuck {
+    fn default() -> Self {
+        let mut detector = EnvelopeFollower::new();
+        detector.set_release(0.05);
+        let mut ducking = EnvelopeFollower::new();
+        ducking.set_attack(0.05);
+        ducking.set_release(0.5);
+        Self {
+            sample_rate: 0.0,
+            threshold: -30.0,
+            amount: 12.0,
+            hold: 0.3,
+            hold_remaining: 0,
+            detector,
+            ducking,
+        }
+    }
+}
+
+impl AutoDuck {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.detector.set_sample_rate(sample_rate);
+        self.ducking.set_sample_rate(sample_rate);
+    }
+
+    /// Sets the level in dB above which the trigger ducks the program.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold.min(0.0);
+    }
+
+    /// Sets the attenuation in dB of the program while ducked, where a positive amount turns it down.
+    pub fn set_amount(&mut self, amount: f32) {
+        self.amount = amount.max(0.0);
+    }
+
+    /// Sets the time in seconds taken to duck the program once the trigger is active.
+    pub fn set_attack(&mut self, attack: f32) {
+        self.ducking.set_attack(attack);
+    }
+
+    /// Sets the time in seconds for which the program stays ducked after the trigger falls below the threshold.
+    pub fn set_hold(&mut self, hold: f32) {
+        self.hold = hold.max(0.0);
+    }
+
+    /// Sets the time in seconds taken to restore the program after the hold ends.
+    pub fn set_release(&mut self, release: f32) {
+        self.ducking.set_release(release);
+    }
+
+    /// Gets the current attenuation of the program in dB.
+    pub fn gain_reduction(&self) -> f32 {
+        self.amount * self.ducking.level()
+    }
+
+    pub fn reset(&mut self) {
+        self.hold_remaining = 0;
+        self.detector.reset();
+        self.ducking.reset();
+    }
+
+    pub fn process(&mut self, program: [&[f32]; 2], trigger: [&[f32]; 2], audio_out: [&mut [f32]; 2]) {
+        let threshold = scale_from_gain(self.threshold);
+        let hold_samples = (self.hold * self.sample_rate) as usize;
+
+        let [program_left, program_right] = program;
+        let [trigger_left, trigger_right] = trigger;
+        let [out_left, out_right] = audio_out;
+        for i in 0..out_left.len() {
+            let level = self
+                .detector
+                .process_sample(trigger_left[i].abs().max(trigger_right[i].abs()));
+            if level > threshold {
+                self.hold_remaining = hold_samples;
+            } else {
+                self.hold_remaining = self.hold_remaining.saturating_sub(1);
+            }
+
+            let active = level > threshold || self.hold_remaining > 0;
+            let ducking = self.ducking.process_sample(if active { 1.0 } else { 0.0 });
+            let gain = scale_from_gain(-self.amount * ducking);
+            out_left[i] = gain * program_left[i];
+            out_right[i] = gain * program_right[i];
+        }
+    }
+}
+
+impl Processor for AutoDuck {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 4,
+            max_audio_ins: 4,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_threshold(value),
+            1 => self.set_amount(value),
+            2 => self.set_attack(value),
+            3 => self.set_hold(value),
+            4 => self.set_release(value),
+            _ => {}
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [program_left, program_right, trigger_left, trigger_right] = data.audio_in else {
+            panic!("Expected four input audio buffers");
+        };
+        let [out_left, out_right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        self.process(
+            [program_left, program_right],
+            [trigger_left, trigger_right],
+            [out_left, out_right],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Processes a constant program under a constant trigger for the given time, and returns the last output.
+    fn process_for(duck: &mut AutoDuck, trigger: f32, seconds: f32) -> f32 {
+        let len = (seconds * 48000.0) as usize;
+        let program = vec![0.5; len];
+        let trigger = vec![trigger; len];
+        let mut left = vec![0.0; len];
+        let mut right = vec![0.0; len];
+        duck.process([&program, &program], [&trigger, &trigger], [&mut left, &mut right]);
+        assert_eq!(left, right);
+        left[len - 1]
+    }
+
+    #[test]
+    fn test_ducking() {
+        let mut duck = AutoDuck::new();
+        duck.set_sample_rate(48000);
+        duck.set_threshold(-20.0);
+        duck.set_amount(12.0);
+        duck.set_attack(0.01);
+        duck.set_hold(0.2);
+        duck.set_release(0.05);
+
+        // A quiet trigger leaves the program untouched
+        assert_eq!(process_for(&mut duck, 0.01, 0.5), 0.5);
+
+        // A loud trigger attenuates the program by the configured amount
+        let ducked = process_for(&mut duck, 0.5, 0.5);
+        assert!((20.0 * (ducked / 0.5).log10() - -12.0).abs() < 1e-2);
+        assert!((duck.gain_reduction() - 12.0).abs() < 1e-2);
+
+        // The program stays ducked during the hold, then is restored
+        assert!((process_for(&mut duck, 0.0, 0.1) - ducked).abs() < 1e-6);
+        assert!((process_for(&mut duck, 0.0, 1.0) - 0.5).abs() < 1e-3);
+    }
+}
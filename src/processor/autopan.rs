@@ -1,52 +1,65 @@
 use super::Processor;
-use crate::audio::buffer::{AudioBufferMut, StereoBuffer, StereoBufferMut, StereoChannel};
-use std::f32::consts::PI;
+use crate::audio::{
+    buffer::{StereoBuffer, StereoBufferMut},
+    lfo::{Lfo, NoteDivision},
+};
+use std::f32::consts::{FRAC_PI_4, PI};
 
+/// Moves a stereo signal between the left and right speakers with an LFO, using a constant power pan law
+/// so that the overall level stays the same as it moves.
 pub struct Autopan {
-    inv_sample_rate: f32,
-    frequency: f32,
-    phase: f32,
+    lfo: Lfo,
+    /// How far the LFO moves the pan position, between 0 and 1.
     amount: f32,
+    /// The pan position the LFO moves around, from -1 for left to 1 for right.
+    center: f32,
 }
 
 impl Autopan {
     pub fn new() -> Self {
         Self {
-            inv_sample_rate: 0.0,
-            frequency: 0.0,
-            phase: 0.0,
+            lfo: Lfo::new(),
             amount: 1.0,
+            center: 0.0,
         }
     }
 
     pub fn set_frequency(&mut self, frequency: f32) {
-        self.frequency = frequency;
+        self.lfo.set_frequency(frequency);
     }
 
+    /// Syncs the rate to tempo, so that each cycle lasts for the given note division.
+    pub fn set_rate_sync(&mut self, division: NoteDivision, bpm: f32) {
+        self.lfo.set_rate_sync(division, bpm);
+    }
+
+    /// Toggles between the tempo synced rate and the free-running frequency.
+    pub fn set_sync(&mut self, sync: bool) {
+        self.lfo.set_sync(sync);
+    }
+
+    /// Sets how far the pan position moves between `0.0` and `1.0`, where `1.0` swings fully left and right.
     pub fn set_amount(&mut self, amount: f32) {
-        self.amount = amount;
+        self.amount = amount.clamp(0.0, 1.0);
     }
 
-    pub fn process(&mut self, audio_in: StereoBuffer, mut audio_out: StereoBufferMut) {
-        let phase = |n: usize| self.phase + self.frequency * self.inv_sample_rate * (n as f32);
-
-        for channel in StereoChannel::both() {
-            let buffer_in = audio_in.channel(channel);
-            let buffer_out = audio_out.channel_mut(channel);
-            let offset = match channel {
-                StereoChannel::Left => 0.0,
-                StereoChannel::Right => PI,
-            };
-            buffer_out.map(buffer_in, |i, sample| {
-                let sin = (2.0 * PI * phase(i) + offset).sin();
-                sample * (1.0 + self.amount * sin)
-            })
-        }
+    /// Sets the pan position the LFO moves around, from `-1.0` for left to `1.0` for right.
+    /// The pan position is limited to this range, so an off-center signal stays at the edge for longer.
+    pub fn set_center(&mut self, center: f32) {
+        self.center = center.clamp(-1.0, 1.0);
+    }
 
-        self.phase = phase(audio_in.len());
-        while self.phase > 1.0 {
-            self.phase -= 1.0;
+    pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
+        let samples_in = audio_in.left.iter().zip(audio_in.right.iter());
+        let samples_out = audio_out.left.iter_mut().zip(audio_out.right.iter_mut());
+        for (i, ((in_left, in_right), (out_left, out_right))) in samples_in.zip(samples_out).enumerate() {
+            let pan = self.center + self.amount * (2.0 * PI * self.lfo.phase_at(i)).sin();
+            let angle = FRAC_PI_4 * (pan.clamp(-1.0, 1.0) + 1.0);
+            *out_left = angle.cos() * in_left;
+            *out_right = angle.sin() * in_right;
         }
+
+        self.lfo.advance(audio_in.len());
     }
 }
 
@@ -60,7 +73,7 @@ impl Processor for Autopan {
     }
 
     fn set_sample_rate(&mut self, sample_rate: u32) {
-        self.inv_sample_rate = (sample_rate as f32).recip();
+        self.lfo.set_sample_rate(sample_rate);
     }
 
     fn process(&mut self, data: super::ProcessorData) {
@@ -77,3 +90,34 @@ impl Processor for Autopan {
         self.process(audio_in, audio_out)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_constant_power() {
+        let mut autopan = Autopan::new();
+        autopan.set_sample_rate(48000);
+        autopan.set_frequency(10.0);
+        autopan.set_amount(1.0);
+
+        let input = [1.0; 4800];
+        let mut left = [0.0; 4800];
+        let mut right = [0.0; 4800];
+        autopan.process(
+            StereoBuffer::new(&input, &input),
+            StereoBufferMut::new(&mut left, &mut right),
+        );
+
+        // The signal swings fully left and right, without the overall level changing
+        let max = |s: &[f32]| s.iter().fold(0.0f32, |max, s| s.max(max));
+        let min = |s: &[f32]| s.iter().fold(1.0f32, |min, s| s.min(min));
+        assert!(max(&left) > 0.999 && min(&left) < 1e-3);
+        assert!(max(&right) > 0.999 && min(&right) < 1e-3);
+        assert!(left
+            .iter()
+            .zip(right.iter())
+            .all(|(l, r)| (l * l + r * r - 1.0).abs() < 1e-4));
+    }
+}
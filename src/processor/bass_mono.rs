@@ -0,0 +1,114 @@
+use super::{MasterUtility, Processor};
+use crate::audio::buffer::{StereoBuffer, StereoBufferMut};
+
+/// Makes a stereo signal mono below a crossover frequency while leaving the higher frequencies in stereo,
+/// such as to avoid phase problems on vinyl and club systems. The low end of the side channel is removed,
+/// using the same filters as [`MasterUtility::set_mono_below`].
+pub struct BassMono {
+    utility: MasterUtility,
+}
+
+impl Default for BassMono {
+    fn default() -> Self {
+        let mut utility = MasterUtility::new();
+        utility.set_mono_below(Some(120.0));
+        Self { utility }
+    }
+}
+
+impl BassMono {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.utility.set_sample_rate(sample_rate);
+    }
+
+    /// Sets the frequency in Hz below which the signal is made mono, which defaults to 120 Hz.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.utility.set_mono_below(Some(frequency));
+    }
+
+    pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
+        self.utility.process(audio_in, audio_out);
+    }
+}
+
+impl Processor for BassMono {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        if param_id == 0 {
+            self.set_frequency(value)
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [left, right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let audio_in = StereoBuffer::new(left, right);
+
+        let [left, right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        let audio_out = StereoBufferMut::new(left, right);
+
+        self.process(audio_in, audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::PI;
+
+    /// Processes a sine wave in the left channel and its inverse in the right channel, and returns the RMS of the
+    /// output channels and of the difference between them, once the filters have settled.
+    fn process_sine(bass_mono: &mut BassMono, frequency: f32) -> (f32, f32) {
+        let left: Vec<_> = (0..48000)
+            .map(|i| (2.0 * PI * frequency * i as f32 / 48000.0).sin())
+            .collect();
+        let right: Vec<_> = left.iter().map(|s| -s).collect();
+        let mut out_left = vec![0.0; 48000];
+        let mut out_right = vec![0.0; 48000];
+        bass_mono.process(
+            StereoBuffer::new(&left, &right),
+            StereoBufferMut::new(&mut out_left, &mut out_right),
+        );
+
+        let rms = |s: &mut dyn Iterator<Item = f32>| (s.map(|s| s * s).sum::<f32>() / 24000.0).sqrt();
+        let outputs = out_left[24000..].iter().zip(out_right[24000..].iter());
+        let level = rms(&mut out_left[24000..].iter().copied());
+        let difference = rms(&mut outputs.map(|(l, r)| l - r));
+        (level, difference)
+    }
+
+    #[test]
+    fn test_crossover() {
+        let mut bass_mono = BassMono::new();
+        bass_mono.set_sample_rate(48000);
+        bass_mono.set_frequency(200.0);
+
+        // The out of phase bass is entirely in the side channel, so it is removed
+        let (level, difference) = process_sine(&mut bass_mono, 40.0);
+        assert!(level < 0.01 && difference < 0.01);
+
+        // The highs keep their difference
+        let (level, difference) = process_sine(&mut bass_mono, 5000.0);
+        let rms = 0.5f32.sqrt();
+        assert!((level - rms).abs() < 0.01);
+        assert!((difference - 2.0 * rms).abs() < 0.01);
+    }
+}
@@ -0,0 +1,186 @@
+use super::{Compressor, Filter, Gain, Processor};
+use crate::audio::buffer::{StereoBuffer, StereoBufferMut};
+
+/// Number of samples processed at a time, which the scratch buffers are sized for.
+const BLOCK_SIZE: usize = 256;
+
+/// A mixer channel strip, which passes a stereo signal through an input gain, a highpass filter,
+/// a compressor and an output gain, in that order.
+pub struct ChannelStrip {
+    input_gain: Gain,
+    hpf: Filter,
+    /// Whether the highpass filter is enabled.
+    hpf_enabled: bool,
+    compressor: Compressor,
+    output_gain: Gain,
+    /// Scratch buffers holding the signal between stages.
+    buffer: Vec<f32>,
+}
+
+impl Default for ChannelStrip {
+    fn default() -> Self {
+        Self {
+            input_gain: Gain::new(),
+            hpf: Filter::new(),
+            hpf_enabled: false,
+            compressor: Compressor::new(),
+            output_gain: Gain::new(),
+            buffer: vec![0.0; 4 * BLOCK_SIZE],
+        }
+    }
+}
+
+impl ChannelStrip {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.input_gain.set_sample_rate(sample_rate);
+        self.hpf.set_sample_rate(sample_rate);
+        self.compressor.set_sample_rate(sample_rate);
+        self.output_gain.set_sample_rate(sample_rate);
+    }
+
+    /// Sets the gain in dB applied before the other stages.
+    pub fn set_input_gain(&mut self, gain: f32) {
+        self.input_gain.set_gain(gain);
+    }
+
+    /// Sets the cutoff of the highpass filter in Hz, or `None` to bypass it.
+    pub fn set_hpf(&mut self, frequency: Option<f32>) {
+        self.hpf_enabled = frequency.is_some();
+        if let Some(frequency) = frequency {
+            self.hpf.set_cutoff(frequency);
+        }
+    }
+
+    /// Sets the level in dB above which the compressor reduces the gain.
+    pub fn set_comp_threshold(&mut self, threshold: f32) {
+        self.compressor.set_threshold(threshold);
+    }
+
+    /// Sets the gain in dB applied after the other stages.
+    pub fn set_output_gain(&mut self, gain: f32) {
+        self.output_gain.set_gain(gain);
+    }
+
+    /// Gets the compressor, such as to change its ratio or timing.
+    pub fn compressor_mut(&mut self) -> &mut Compressor {
+        &mut self.compressor
+    }
+
+    pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
+        assert!(audio_in.len() == audio_out.len());
+        let StereoBufferMut { left, right } = audio_out;
+        let parts = audio_in
+            .left
+            .chunks(BLOCK_SIZE)
+            .zip(audio_in.right.chunks(BLOCK_SIZE))
+            .zip(left.chunks_mut(BLOCK_SIZE).zip(right.chunks_mut(BLOCK_SIZE)));
+        for ((in_left, in_right), (out_left, out_right)) in parts {
+            self.process_part(
+                StereoBuffer::new(in_left, in_right),
+                StereoBufferMut::new(out_left, out_right),
+            );
+        }
+    }
+
+    /// Processes at most `BLOCK_SIZE` samples.
+    fn process_part(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
+        let len = audio_in.len();
+
+        // Alternate between two pairs of scratch buffers
+        let (a, b) = self.buffer[..(4 * len)].split_at_mut(2 * len);
+        let (a_left, a_right) = a.split_at_mut(len);
+        let (b_left, b_right) = b.split_at_mut(len);
+
+        self.input_gain
+            .process(&[audio_in.left, audio_in.right], &mut [&mut *a_left, &mut *a_right]);
+        if self.hpf_enabled {
+            self.hpf.process(
+                StereoBuffer::new(a_left, a_right),
+                StereoBufferMut::new(b_left, b_right),
+            );
+        } else {
+            b_left.copy_from_slice(a_left);
+            b_right.copy_from_slice(a_right);
+        }
+        self.compressor.process(
+            StereoBuffer::new(b_left, b_right),
+            StereoBufferMut::new(a_left, a_right),
+        );
+        self.output_gain
+            .process(&[a_left, a_right], &mut [audio_out.left, audio_out.right]);
+    }
+}
+
+impl Processor for ChannelStrip {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_input_gain(value),
+            1 => self.set_hpf((value > 0.0).then_some(value)),
+            2 => self.set_comp_threshold(value),
+            3 => self.set_output_gain(value),
+            _ => {}
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [left, right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let audio_in = StereoBuffer::new(left, right);
+
+        let [left, right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        let audio_out = StereoBufferMut::new(left, right);
+
+        self.process(audio_in, audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::PI;
+
+    /// Gets the level of a 50Hz sine at the output of the strip, once the filter has settled.
+    fn low_level(strip: &mut ChannelStrip) -> f32 {
+        strip.set_sample_rate(48000);
+        let input: Vec<_> = (0..9600)
+            .map(|i| 0.5 * (2.0 * PI * 50.0 * i as f32 / 48000.0).sin())
+            .collect();
+        let mut left = vec![0.0; 9600];
+        let mut right = vec![0.0; 9600];
+        strip.process(
+            StereoBuffer::new(&input, &input),
+            StereoBufferMut::new(&mut left, &mut right),
+        );
+        assert_eq!(left, right);
+        left[4800..].iter().fold(0.0, |max, s| s.abs().max(max))
+    }
+
+    #[test]
+    fn test_hpf() {
+        let mut strip = ChannelStrip::new();
+        assert!((low_level(&mut strip) - 0.5).abs() < 1e-3);
+
+        // The highpass filter attenuates the low frequency
+        strip.set_hpf(Some(400.0));
+        assert!(low_level(&mut strip) < 0.05);
+    }
+}
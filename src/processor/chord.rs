@@ -1,13 +1,70 @@
 use super::Processor;
 use crate::{
     midi::{MidiEvent, TimedMidiEvent},
-    note::Note,
+    note::{Note, Scale},
 };
 
 pub struct Chord {
+    /// The MIDI channel of the notes to harmonize.
     channel: u8,
+    /// The MIDI channel to output harmonized notes on, or `None` to use the input channel.
+    output_channel: Option<u8>,
+    /// Bitmask of the semitone offsets of the chord's notes.
     chord: u64,
+    /// Offset in semitones applied to every note of the chord.
+    offset: i8,
+    /// The root and scale used to harmonize diatonically, if any.
+    scale: Option<(Note, Scale)>,
     notes: Vec<GeneratedNote>,
+    /// The channel that the generated notes were output on, if it has since changed while notes were held,
+    /// so that they can be released on it.
+    flush_channel: Option<u8>,
+}
+
+/// A named chord, used to generate a chord bitmask.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ChordType {
+    Major,
+    Minor,
+    Dim,
+    Aug,
+    Sus2,
+    Sus4,
+    Power,
+    Dom7,
+    Maj7,
+    Min7,
+    MinMaj7,
+    Dim7,
+    HalfDim7,
+    Add9,
+}
+
+impl ChordType {
+    /// Gets the semitone offsets of each note of the chord from its root.
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            ChordType::Major => &[0, 4, 7],
+            ChordType::Minor => &[0, 3, 7],
+            ChordType::Dim => &[0, 3, 6],
+            ChordType::Aug => &[0, 4, 8],
+            ChordType::Sus2 => &[0, 2, 7],
+            ChordType::Sus4 => &[0, 5, 7],
+            ChordType::Power => &[0, 7, 12],
+            ChordType::Dom7 => &[0, 4, 7, 10],
+            ChordType::Maj7 => &[0, 4, 7, 11],
+            ChordType::Min7 => &[0, 3, 7, 10],
+            ChordType::MinMaj7 => &[0, 3, 7, 11],
+            ChordType::Dim7 => &[0, 3, 6, 9],
+            ChordType::HalfDim7 => &[0, 3, 6, 10],
+            ChordType::Add9 => &[0, 4, 7, 14],
+        }
+    }
+
+    /// Gets the bitmask of the chord, as accepted by [`Chord::set_chord`].
+    pub fn mask(&self) -> u64 {
+        self.intervals().iter().fold(0, |mask, i| mask | (1 << i))
+    }
 }
 
 struct GeneratedNote {
@@ -19,22 +76,104 @@ impl Chord {
     pub fn new() -> Self {
         Self {
             channel: 0,
+            output_channel: None,
             chord: 1,
+            offset: 0,
+            scale: None,
             notes: vec![],
+            flush_channel: None,
         }
     }
 
+    /// Sets the MIDI channel of the notes to harmonize. If this changes the channel, any held notes are released at
+    /// the start of the next block, as their note offs would no longer be received.
     pub fn set_channel(&mut self, channel: u8) {
+        let prev = self.out_channel();
+        if channel != self.channel {
+            self.release_held(prev);
+        }
         self.channel = channel;
     }
 
+    /// Sets the MIDI channel to output harmonized notes on, or `None` to use the input channel.
+    /// If this changes the output channel, any held notes are released on the previous channel at the start of the
+    /// next block, so that none are left stuck on.
+    pub fn set_output_channel(&mut self, channel: Option<u8>) {
+        let prev = self.out_channel();
+        self.output_channel = channel;
+        if self.out_channel() != prev {
+            self.release_held(prev);
+        }
+    }
+
+    fn out_channel(&self) -> u8 {
+        self.output_channel.unwrap_or(self.channel)
+    }
+
+    /// Schedules the held notes to be released on the channel they were output on.
+    fn release_held(&mut self, channel: u8) {
+        if !self.notes.is_empty() && self.flush_channel.is_none() {
+            self.flush_channel = Some(channel);
+        }
+    }
+
+    /// Sets the chord as a bitmask, where each set bit `i` generates a note `i` semitones above the input note.
     pub fn set_chord(&mut self, chord: u64) {
         self.chord = chord;
+        self.offset = 0;
+    }
+
+    /// Sets the chord from a named chord type, with its root `root_offset` semitones from the input note.
+    pub fn set_chord_type(&mut self, root_offset: i8, chord_type: ChordType) {
+        self.chord = chord_type.mask();
+        self.offset = root_offset;
+    }
+
+    /// Enables diatonic harmonization, where the generated notes stay within the given scale.
+    /// Each semitone offset of the chord is treated as an interval of the nearest number of scale degrees,
+    /// so that both a major and minor third become a diatonic third, and the input note is snapped to the scale.
+    pub fn set_scale(&mut self, root: Note, scale: Scale) {
+        self.scale = Some((root, scale));
+    }
+
+    /// Disables diatonic harmonization, so that chords are built from fixed semitone offsets.
+    pub fn clear_scale(&mut self) {
+        self.scale = None;
+    }
+
+    /// Gets the note generated from the source `note` with the given chord `offset`.
+    fn harmonize(&self, note: Note, offset: i8) -> Note {
+        let Some((root, scale)) = self.scale else {
+            return note.transpose(offset);
+        };
+        let intervals = scale.intervals();
+        let degrees = intervals.len() as i32;
+
+        // Find the nearest scale degree to the source note, preferring the lower degree when equidistant
+        let relative = note.0 as i32 - root.pitch_class() as i32;
+        let pitch_class = relative.rem_euclid(12);
+        let nearest = (0..=degrees)
+            .min_by_key(|&d| {
+                let interval = intervals.get(d as usize).map(|&i| i as i32).unwrap_or(12);
+                (interval - pitch_class).abs()
+            })
+            .unwrap();
+        let degree = degrees * relative.div_euclid(12) + nearest;
+
+        // Move by the number of scale degrees closest to the semitone offset
+        let steps = (offset as f32 * degrees as f32 / 12.0).round() as i32;
+        let degree = degree + steps;
+        let octave = degree.div_euclid(degrees);
+        let target = root.pitch_class() as i32 + 12 * octave + intervals[degree.rem_euclid(degrees) as usize] as i32;
+        Note(target.clamp(0, 127) as u8)
     }
 
     fn chord_notes(&self) -> impl Iterator<Item = i8> {
         let chord = self.chord;
-        (0..64).filter(move |i| (chord >> i) & 1 == 1)
+        let offset = self.offset;
+        (0..64i8)
+            .filter(move |i| (chord >> i) & 1 == 1)
+            .map(move |i| i.saturating_add(offset))
     }
 
     fn get_mask(&self) -> u128 {
@@ -42,6 +181,21 @@ impl Chord {
     }
 
     pub fn process(&mut self, midi_in: &[TimedMidiEvent], midi_out: &mut Vec<TimedMidiEvent>) {
+        if let Some(channel) = self.flush_channel.take() {
+            diff_masks(self.get_mask(), 0, |note, _| {
+                midi_out.push(TimedMidiEvent {
+                    time: 0,
+                    event: MidiEvent::NoteOff {
+                        channel,
+                        note,
+                        velocity: 0,
+                    },
+                });
+            });
+            self.notes.clear();
+        }
+
+        let out_channel = self.out_channel();
         for &TimedMidiEvent { time, event } in midi_in {
             match event {
                 MidiEvent::NoteOn {
@@ -54,7 +208,7 @@ impl Chord {
                     for i in self.chord_notes() {
                         self.notes.push(GeneratedNote {
                             src: note,
-                            dst: note.transpose(i),
+                            dst: self.harmonize(note, i),
                         });
                     }
                     let next = self.get_mask();
@@ -63,13 +217,13 @@ impl Chord {
                             time,
                             event: if on {
                                 MidiEvent::NoteOn {
-                                    channel,
+                                    channel: out_channel,
                                     note,
                                     velocity,
                                 }
                             } else {
                                 MidiEvent::NoteOff {
-                                    channel,
+                                    channel: out_channel,
                                     note,
                                     velocity: 0,
                                 }
@@ -78,6 +232,8 @@ impl Chord {
                     });
                 }
                 MidiEvent::NoteOff { channel, note, .. } if channel == self.channel => {
+                    // Destinations which are still generated by another held source note remain in the mask,
+                    // so they are only released once the last source using them is released
                     let prev = self.get_mask();
                     self.notes.retain(|n| n.src != note);
                     let next = self.get_mask();
@@ -88,7 +244,7 @@ impl Chord {
                                 unreachable!()
                             } else {
                                 MidiEvent::NoteOff {
-                                    channel,
+                                    channel: out_channel,
                                     note,
                                     velocity: 0,
                                 }
@@ -135,3 +291,173 @@ impl Processor for Chord {
         self.process(data.midi_in, data.midi_out)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn note_on(note: Note) -> TimedMidiEvent {
+        TimedMidiEvent {
+            time: 0,
+            event: MidiEvent::NoteOn {
+                channel: 0,
+                note,
+                velocity: 100,
+            },
+        }
+    }
+
+    fn notes_on(events: &[TimedMidiEvent]) -> Vec<Note> {
+        events
+            .iter()
+            .filter_map(|e| match e.event {
+                MidiEvent::NoteOn { note, .. } => Some(note),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chord_type() {
+        let c = Note::from_name("C4").unwrap();
+        let mut chord = Chord::new();
+        let mut midi_out = vec![];
+
+        chord.set_chord_type(0, ChordType::Major);
+        chord.process(&[note_on(c)], &mut midi_out);
+        let expected = ["C4", "E4", "G4"].map(|n| Note::from_name(n).unwrap());
+        assert_eq!(notes_on(&midi_out), expected);
+
+        let mut chord = Chord::new();
+        let mut midi_out = vec![];
+        chord.set_chord_type(-12, ChordType::Min7);
+        chord.process(&[note_on(Note::from_name("A4").unwrap())], &mut midi_out);
+        let expected = ["A3", "C4", "E4", "G4"].map(|n| Note::from_name(n).unwrap());
+        assert_eq!(notes_on(&midi_out), expected);
+    }
+
+    #[test]
+    fn test_shared_destination() {
+        let note_off = |note: Note| TimedMidiEvent {
+            time: 0,
+            event: MidiEvent::NoteOff {
+                channel: 0,
+                note,
+                velocity: 0,
+            },
+        };
+        let notes_off = |events: &[TimedMidiEvent]| -> Vec<Note> {
+            events
+                .iter()
+                .filter_map(|e| match e.event {
+                    MidiEvent::NoteOff { note, .. } => Some(note),
+                    _ => None,
+                })
+                .collect()
+        };
+        let [c4, e4, g4, b4] = ["C4", "E4", "G4", "B4"].map(|n| Note::from_name(n).unwrap());
+
+        // C4 and E4 both generate E4
+        let mut chord = Chord::new();
+        chord.set_chord_type(0, ChordType::Major);
+        let mut midi_out = vec![];
+        chord.process(&[note_on(c4), note_on(e4)], &mut midi_out);
+        assert_eq!(notes_on(&midi_out), [c4, e4, g4, g4.transpose(1), b4]);
+
+        // Releasing C4 keeps the shared E4 sounding
+        midi_out.clear();
+        chord.process(&[note_off(c4)], &mut midi_out);
+        assert_eq!(notes_off(&midi_out), [c4, g4]);
+
+        // Releasing E4 releases it along with the rest of its chord
+        midi_out.clear();
+        chord.process(&[note_off(e4)], &mut midi_out);
+        assert_eq!(notes_off(&midi_out), [e4, g4.transpose(1), b4]);
+    }
+
+    #[test]
+    fn test_output_channel() {
+        let mut chord = Chord::new();
+        chord.set_chord_type(0, ChordType::Major);
+        chord.set_output_channel(Some(3));
+
+        let cc = TimedMidiEvent {
+            time: 5,
+            event: MidiEvent::ControlChange {
+                channel: 0,
+                control: 1,
+                value: 64,
+            },
+        };
+        let mut midi_out = vec![];
+        chord.process(&[note_on(Note::middle_c()), cc], &mut midi_out);
+
+        assert_eq!(midi_out.len(), 4);
+        for event in &midi_out[..3] {
+            assert!(matches!(event.event, MidiEvent::NoteOn { channel: 3, .. }));
+        }
+        assert_eq!(midi_out[3], cc);
+    }
+
+    #[test]
+    fn test_change_output_channel_while_held() {
+        let mut chord = Chord::new();
+        chord.set_chord_type(0, ChordType::Major);
+        chord.set_output_channel(Some(3));
+        let mut midi_out = vec![];
+        chord.process(&[note_on(Note::middle_c())], &mut midi_out);
+
+        // The held notes are released on the channel they were played on
+        chord.set_output_channel(Some(5));
+        midi_out.clear();
+        chord.process(&[], &mut midi_out);
+        assert_eq!(midi_out.len(), 3);
+        for event in &midi_out {
+            assert!(matches!(event.event, MidiEvent::NoteOff { channel: 3, .. }));
+        }
+
+        // So releasing the source note has nothing left to release
+        midi_out.clear();
+        let note_off = TimedMidiEvent {
+            time: 0,
+            event: MidiEvent::NoteOff {
+                channel: 0,
+                note: Note::middle_c(),
+                velocity: 0,
+            },
+        };
+        chord.process(&[note_off], &mut midi_out);
+        assert!(midi_out.is_empty());
+    }
+
+    #[test]
+    fn test_diatonic_thirds() {
+        let c = Note::from_name("C4").unwrap();
+        let mut chord = Chord::new();
+        chord.set_chord(0b1_0001);
+        chord.set_scale(c, Scale::Major);
+
+        let melody = ["C4", "D4", "E4", "F4", "G4", "A4", "B4", "C5"].map(|n| Note::from_name(n).unwrap());
+        let thirds = ["E4", "F4", "G4", "A4", "B4", "C5", "D5", "E5"].map(|n| Note::from_name(n).unwrap());
+        for (&note, &third) in melody.iter().zip(thirds.iter()) {
+            let mut midi_out = vec![];
+            let note_off = TimedMidiEvent {
+                time: 0,
+                event: MidiEvent::NoteOff {
+                    channel: 0,
+                    note,
+                    velocity: 0,
+                },
+            };
+            chord.process(&[note_on(note), note_off], &mut midi_out);
+            assert_eq!(notes_on(&midi_out), [note, third]);
+            assert!(notes_on(&midi_out).iter().all(|n| n.in_scale(c, &Scale::Major)));
+        }
+
+        // Notes outside of the scale are snapped into it
+        let mut midi_out = vec![];
+        chord.process(&[note_on(Note::from_name("C#4").unwrap())], &mut midi_out);
+        let expected = ["C4", "E4"].map(|n| Note::from_name(n).unwrap());
+        assert_eq!(notes_on(&midi_out), expected);
+    }
+}
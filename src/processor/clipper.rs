@@ -0,0 +1,148 @@
+use super::Processor;
+use crate::util::scale_from_gain;
+
+/// Clips each sample to a ceiling, rounding over samples which approach the ceiling with a soft knee.
+/// Unlike a limiter there is no lookahead or release, so this shapes the tone of the signal rather than
+/// transparently controlling its level.
+pub struct Clipper {
+    /// The ceiling in dBFS.
+    ceiling: f32,
+    /// The width of the knee in dB.
+    knee: f32,
+    /// The ceiling, as a linear amplitude.
+    ceiling_scale: f32,
+    /// The amplitude above which samples are rounded over.
+    threshold: f32,
+}
+
+impl Default for Clipper {
+    fn default() -> Self {
+        let mut clipper = Self {
+            ceiling: 0.0,
+            knee: 6.0,
+            ceiling_scale: 1.0,
+            threshold: 1.0,
+        };
+        clipper.calc_thresholds();
+        clipper
+    }
+}
+
+impl Clipper {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the level in dBFS which the output never exceeds.
+    pub fn set_ceiling(&mut self, ceiling: f32) {
+        self.ceiling = ceiling;
+        self.calc_thresholds();
+    }
+
+    /// Sets the width of the knee in dB, where a width of zero clips hard. Samples are rounded over from this far
+    /// below the ceiling, and reach the ceiling once they exceed it by the same amplitude.
+    pub fn set_knee(&mut self, knee: f32) {
+        self.knee = knee.max(0.0);
+        self.calc_thresholds();
+    }
+
+    fn calc_thresholds(&mut self) {
+        self.ceiling_scale = scale_from_gain(self.ceiling);
+        self.threshold = scale_from_gain(self.ceiling - self.knee);
+    }
+
+    /// Clips a single sample.
+    #[inline]
+    pub fn clip(&self, sample: f32) -> f32 {
+        let magnitude = sample.abs();
+        if magnitude <= self.threshold {
+            return sample;
+        }
+
+        // Within the knee, the gradient falls linearly from one to zero across twice the width of the knee,
+        // so that the curve meets the ceiling smoothly
+        let width = self.ceiling_scale - self.threshold;
+        let over = magnitude - self.threshold;
+        let clipped = if over < 2.0 * width {
+            self.threshold + over - over * over / (4.0 * width)
+        } else {
+            self.ceiling_scale
+        };
+        clipped.copysign(sample)
+    }
+
+    pub fn process(&mut self, audio_in: &[&[f32]], audio_out: &mut [&mut [f32]]) {
+        for (buffer_in, buffer_out) in audio_in.iter().zip(audio_out.iter_mut()) {
+            for (&sample_in, sample_out) in buffer_in.iter().zip(buffer_out.iter_mut()) {
+                *sample_out = self.clip(sample_in);
+            }
+        }
+    }
+}
+
+impl Processor for Clipper {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_ceiling(value),
+            1 => self.set_knee(value),
+            _ => {}
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        self.process(data.audio_in, data.audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ceiling() {
+        let mut clipper = Clipper::new();
+        clipper.set_ceiling(-6.0);
+        clipper.set_knee(3.0);
+        let ceiling = scale_from_gain(-6.0);
+
+        // The knee starts at -9dB, and reaches the ceiling as far above it as it starts below it
+        let threshold = scale_from_gain(-9.0);
+        for sample in [2.0 * ceiling - threshold, 1.0, 4.0, 100.0] {
+            assert_eq!(clipper.clip(sample), ceiling);
+            assert_eq!(clipper.clip(-sample), -ceiling);
+        }
+
+        // Samples below the knee are untouched
+        assert_eq!(clipper.clip(0.9 * threshold), 0.9 * threshold);
+        assert!(clipper.clip(1.1 * threshold) < 1.1 * threshold);
+
+        // Without a knee, samples are clipped hard
+        clipper.set_knee(0.0);
+        assert_eq!(clipper.clip(0.99 * ceiling), 0.99 * ceiling);
+        assert_eq!(clipper.clip(1.01 * ceiling), ceiling);
+    }
+
+    #[test]
+    fn test_knee_continuous() {
+        let mut clipper = Clipper::new();
+        clipper.set_knee(12.0);
+
+        // The curve never jumps, never decreases and never steepens
+        let step = 1e-4;
+        let curve: Vec<_> = (0..20_000).map(|i| clipper.clip(i as f32 * step)).collect();
+        for w in curve.windows(3) {
+            let (d1, d2) = (w[1] - w[0], w[2] - w[1]);
+            assert!(d1 > -1e-6 && d1 < step * 1.01);
+            assert!(d2 <= d1 + 1e-6);
+        }
+        assert_eq!(curve[curve.len() - 1], 1.0);
+    }
+}
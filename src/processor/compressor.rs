@@ -0,0 +1,265 @@
+use super::Processor;
+use crate::{
+    audio::buffer::{StereoBuffer, StereoBufferMut},
+    util::scale_from_gain,
+};
+
+/// A feed-forward compressor with a stereo-linked peak detector.
+pub struct Compressor {
+    /// The sample rate in `Hz`.
+    sample_rate: f32,
+    /// Level in dB above which the signal is compressed.
+    threshold: f32,
+    /// Ratio of the input level to the output level above the threshold.
+    ratio: f32,
+    /// Attack time in seconds.
+    attack: f32,
+    /// Release time in seconds.
+    release: f32,
+    /// Gain in dB applied after compression.
+    makeup_gain: f32,
+    /// Whether the makeup gain is estimated from the threshold and ratio.
+    auto_makeup: bool,
+    /// Proportion of the compressed signal in the output, with the rest being the uncompressed input.
+    blend: f32,
+    /// The current gain reduction in dB.
+    reduction: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+}
+
+impl Default for Compressor {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.0,
+            threshold: 0.0,
+            ratio: 4.0,
+            attack: 0.01,
+            release: 0.1,
+            makeup_gain: 0.0,
+            auto_makeup: false,
+            blend: 1.0,
+            reduction: 0.0,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+        }
+    }
+}
+
+impl Compressor {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.calc_coefficients();
+    }
+
+    /// Sets the level in dB above which the signal is compressed.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold.min(0.0);
+    }
+
+    /// Sets the compression ratio, which is at least `1.0`.
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio.max(1.0);
+    }
+
+    /// Sets the attack time in seconds.
+    pub fn set_attack(&mut self, attack: f32) {
+        self.attack = attack.max(0.0);
+        self.calc_coefficients();
+    }
+
+    /// Sets the release time in seconds.
+    pub fn set_release(&mut self, release: f32) {
+        self.release = release.max(0.0);
+        self.calc_coefficients();
+    }
+
+    /// Sets the gain in dB applied after compression, which is ignored while auto makeup is enabled.
+    pub fn set_makeup_gain(&mut self, gain: f32) {
+        self.makeup_gain = gain;
+    }
+
+    /// Sets whether the makeup gain is estimated from the threshold and ratio,
+    /// so that the level roughly matches that of the uncompressed signal.
+    pub fn set_auto_makeup(&mut self, auto_makeup: bool) {
+        self.auto_makeup = auto_makeup;
+    }
+
+    /// Sets the proportion of the compressed signal in the output, from `0.0` (dry) to `1.0` (fully compressed),
+    /// for parallel compression.
+    pub fn set_blend(&mut self, blend: f32) {
+        self.blend = blend.clamp(0.0, 1.0);
+    }
+
+    /// Gets the gain in dB applied after compression.
+    pub fn makeup_gain(&self) -> f32 {
+        if self.auto_makeup {
+            (1.0 - self.ratio.recip()) * -self.threshold / 2.0
+        } else {
+            self.makeup_gain
+        }
+    }
+
+    /// Gets the current gain reduction in dB.
+    pub fn gain_reduction(&self) -> f32 {
+        self.reduction
+    }
+
+    fn calc_coefficients(&mut self) {
+        let coeff = |time: f32| {
+            if time > 0.0 && self.sample_rate > 0.0 {
+                (-1.0 / (time * self.sample_rate)).exp()
+            } else {
+                0.0
+            }
+        };
+        self.attack_coeff = coeff(self.attack);
+        self.release_coeff = coeff(self.release);
+    }
+
+    pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
+        assert!(audio_in.len() == audio_out.len());
+
+        let makeup_gain = self.makeup_gain();
+        let slope = 1.0 - self.ratio.recip();
+        let samples_in = audio_in.left.iter().zip(audio_in.right.iter());
+        let samples_out = audio_out.left.iter_mut().zip(audio_out.right.iter_mut());
+        for ((&in_left, &in_right), (out_left, out_right)) in samples_in.zip(samples_out) {
+            // Compute the gain reduction needed to bring the peak level down to the compression curve
+            let level = 20.0 * in_left.abs().max(in_right.abs()).max(1e-9).log10();
+            let target = slope * (level - self.threshold).max(0.0);
+
+            // Smooth the gain reduction, using the attack time when it is increasing
+            let coeff = if target > self.reduction {
+                self.attack_coeff
+            } else {
+                self.release_coeff
+            };
+            self.reduction = target + coeff * (self.reduction - target);
+
+            // The gain is applied to the compressed part of the blend only
+            let gain = scale_from_gain(makeup_gain - self.reduction);
+            let gain = (1.0 - self.blend) + self.blend * gain;
+            *out_left = gain * in_left;
+            *out_right = gain * in_right;
+        }
+    }
+}
+
+impl Processor for Compressor {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_threshold(value),
+            1 => self.set_ratio(value),
+            2 => self.set_attack(value),
+            3 => self.set_release(value),
+            4 => self.set_makeup_gain(value),
+            5 => self.set_blend(value),
+            _ => {}
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [left, right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let audio_in = StereoBuffer::new(left, right);
+
+        let [left, right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        let audio_out = StereoBufferMut::new(left, right);
+
+        self.process(audio_in, audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Compresses a constant signal at the given level in dB, and returns the gain in dB once it has settled.
+    fn settled_gain(compressor: &mut Compressor, level: f32) -> f32 {
+        let input = [scale_from_gain(level); 4800];
+        let mut left = [0.0; 4800];
+        let mut right = [0.0; 4800];
+        for _ in 0..10 {
+            compressor.process(
+                StereoBuffer::new(&input, &input),
+                StereoBufferMut::new(&mut left, &mut right),
+            );
+        }
+        20.0 * (left[4799] / input[4799]).log10()
+    }
+
+    #[test]
+    fn test_compression() {
+        let mut compressor = Compressor::new();
+        compressor.set_sample_rate(48000);
+        compressor.set_threshold(-20.0);
+        compressor.set_ratio(4.0);
+
+        assert!(settled_gain(&mut compressor, -30.0).abs() < 1e-3);
+        assert!((settled_gain(&mut compressor, 0.0) - -15.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_auto_makeup() {
+        let mut compressor = Compressor::new();
+        compressor.set_sample_rate(48000);
+        compressor.set_threshold(-20.0);
+        compressor.set_ratio(4.0);
+        compressor.set_auto_makeup(true);
+        assert!((compressor.makeup_gain() - 7.5).abs() < 1e-6);
+
+        // The makeup gain matches the gain reduction of a signal halfway between the threshold and full scale
+        assert!(settled_gain(&mut compressor, -10.0).abs() < 1e-2);
+
+        // The makeup gain follows changes to the threshold and ratio
+        compressor.set_threshold(-40.0);
+        compressor.set_ratio(2.0);
+        assert!((compressor.makeup_gain() - 10.0).abs() < 1e-6);
+        assert!(settled_gain(&mut compressor, -20.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_blend() {
+        let input: Vec<_> = (0..4800).map(|i| (i as f32 * 0.05).sin()).collect();
+        let render = |blend| {
+            let mut compressor = Compressor::new();
+            compressor.set_sample_rate(48000);
+            compressor.set_threshold(-20.0);
+            compressor.set_makeup_gain(6.0);
+            compressor.set_blend(blend);
+            let mut left = vec![0.0; 4800];
+            let mut right = vec![0.0; 4800];
+            compressor.process(
+                StereoBuffer::new(&input, &input),
+                StereoBufferMut::new(&mut left, &mut right),
+            );
+            left
+        };
+
+        assert_eq!(render(0.0), input);
+        let compressed = render(1.0);
+        let blended = render(0.5);
+        let mut samples = input.iter().zip(compressed.iter()).zip(blended.iter());
+        assert!(samples.all(|((dry, wet), s)| (0.5 * (dry + wet) - s).abs() < 1e-6));
+    }
+}
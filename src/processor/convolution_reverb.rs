@@ -0,0 +1,348 @@
+use super::Processor;
+use crate::audio::{
+    buffer::{StereoBuffer, StereoBufferMut, StereoChannel},
+    sample::AudioSample,
+};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::sync::Arc;
+
+/// Number of samples in each partition of the impulse response, which is also the latency of the reverb.
+const PARTITION_SIZE: usize = 256;
+/// Size of the FFTs used to perform the convolution.
+const FFT_SIZE: usize = 2 * PARTITION_SIZE;
+
+/// The spectra of each partition of an impulse response.
+type Partitions = Vec<Box<[Complex<f32>]>>;
+
+/// A reverb which convolves its input with an impulse response,
+/// using uniformly partitioned FFT convolution.
+///
+/// Each input channel can be convolved with a separate impulse response for each output channel, so that
+/// true-stereo impulse responses can feed the left input into the right output and vice versa. A plain stereo
+/// impulse response is applied independently to the left and right channels, and a mono one is applied to both.
+pub struct ConvolutionReverb {
+    /// The recent input of the left and right channels.
+    inputs: [InputFrames; 2],
+    /// The spectra of each partition of the impulse response from each input channel to each output channel,
+    /// indexed by input and then output.
+    paths: [[Partitions; 2]; 2],
+    /// Length of the longest impulse response in samples.
+    impulse_len: usize,
+    /// The wet output samples for the current partition of the left and right channels.
+    outputs: [Box<[f32]>; 2],
+    /// Buffer used to accumulate the output spectrum.
+    spectrum: Box<[Complex<f32>]>,
+    /// Position within the current partition of the next sample to be read or written.
+    idx: usize,
+    /// Wet/dry mix between `0.0` (fully dry) and `1.0` (fully wet).
+    mix: f32,
+    fft: Arc<dyn Fft<f32>>,
+    ifft: Arc<dyn Fft<f32>>,
+    /// Scratch space for the FFTs.
+    scratch: Vec<Complex<f32>>,
+}
+
+struct InputFrames {
+    /// The spectra of the most recent input frames, used as a ring buffer.
+    history: Vec<Box<[Complex<f32>]>>,
+    /// Index of the most recent frame in `history`.
+    history_idx: usize,
+    /// The previous and current partition of input samples.
+    input: Box<[f32]>,
+}
+
+impl Default for ConvolutionReverb {
+    fn default() -> Self {
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(FFT_SIZE);
+        let ifft = planner.plan_fft_inverse(FFT_SIZE);
+        let scratch_len = fft.get_inplace_scratch_len().max(ifft.get_inplace_scratch_len());
+        Self {
+            inputs: [InputFrames::new(), InputFrames::new()],
+            paths: Default::default(),
+            impulse_len: 0,
+            outputs: [(); 2].map(|_| vec![0.0; PARTITION_SIZE].into_boxed_slice()),
+            spectrum: vec![Complex::default(); FFT_SIZE].into_boxed_slice(),
+            idx: 0,
+            mix: 0.5,
+            fft,
+            ifft,
+            scratch: vec![Complex::default(); scratch_len],
+        }
+    }
+}
+
+impl ConvolutionReverb {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the impulse response to convolve the input with, where the left and right channels of the impulse
+    /// response are applied to the left and right channels of the input respectively.
+    /// The impulse response is used as-is, regardless of its sample rate.
+    pub fn set_impulse(&mut self, impulse: Arc<AudioSample>) {
+        let data = impulse.stereo_data();
+        let [left, right] = StereoChannel::both().map(|channel| self.partition(data.channel(channel)));
+        self.paths = [[left, vec![]], [vec![], right]];
+        self.impulse_len = impulse.length();
+        self.reset_history();
+    }
+
+    /// Sets a true-stereo impulse response, made up of the stereo responses to an impulse on the left input and
+    /// to an impulse on the right input, so that each input channel feeds both output channels.
+    /// The impulse responses are used as-is, regardless of their sample rate.
+    pub fn set_true_stereo_impulse(&mut self, left: Arc<AudioSample>, right: Arc<AudioSample>) {
+        self.paths = [&left, &right].map(|impulse| {
+            let data = impulse.stereo_data();
+            StereoChannel::both().map(|channel| self.partition(data.channel(channel)))
+        });
+        self.impulse_len = left.length().max(right.length());
+        self.reset_history();
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Gets the delay introduced by the reverb in samples.
+    pub fn latency_samples(&self) -> usize {
+        PARTITION_SIZE
+    }
+
+    /// Gets the number of samples for which the reverb keeps ringing after its input falls silent.
+    pub fn tail_samples(&self) -> usize {
+        self.impulse_len.saturating_sub(1)
+    }
+
+    pub fn process(&mut self, audio_in: StereoBuffer, mut audio_out: StereoBufferMut) {
+        assert!(audio_in.len() == audio_out.len());
+
+        let mut i = 0;
+        while i < audio_in.len() {
+            // Process up to the end of the current partition
+            let len = (PARTITION_SIZE - self.idx).min(audio_in.len() - i);
+            let range = self.idx..(self.idx + len);
+
+            for channel in StereoChannel::both() {
+                let frames = &mut self.inputs[channel as usize];
+                let s_in = &audio_in.channel(channel)[i..(i + len)];
+                let s_out = &mut audio_out.channel_mut(channel)[i..(i + len)];
+
+                // The dry signal is delayed by a partition to stay aligned with the wet signal
+                let dry = &frames.input[range.clone()];
+                let wet = &self.outputs[channel as usize][range.clone()];
+                for ((out, &dry), &wet) in s_out.iter_mut().zip(dry.iter()).zip(wet.iter()) {
+                    *out = (1.0 - self.mix) * dry + self.mix * wet;
+                }
+
+                // Write the input samples into the current partition
+                let offset = PARTITION_SIZE + self.idx;
+                frames.input[offset..(offset + len)].copy_from_slice(s_in);
+            }
+
+            // Compute the next partition of output once the current one is complete
+            self.idx += len;
+            if self.idx == PARTITION_SIZE {
+                self.process_partition();
+                self.idx = 0;
+            }
+
+            i += len;
+        }
+    }
+
+    /// Splits an impulse response into partitions, and transforms each into the frequency domain.
+    fn partition(&mut self, ir: &[f32]) -> Partitions {
+        ir.chunks(PARTITION_SIZE)
+            .map(|chunk| {
+                let mut spectrum = vec![Complex::default(); FFT_SIZE].into_boxed_slice();
+                for (dst, &src) in spectrum.iter_mut().zip(chunk.iter()) {
+                    dst.re = src;
+                }
+                self.fft.process_with_scratch(&mut spectrum, &mut self.scratch);
+                spectrum
+            })
+            .collect()
+    }
+
+    /// Clears the input history, resizing it to fit the longest impulse response from each input channel.
+    fn reset_history(&mut self) {
+        for (frames, paths) in self.inputs.iter_mut().zip(self.paths.iter()) {
+            let len = paths.iter().map(|p| p.len()).max().unwrap_or(0);
+            frames.history = vec![vec![Complex::default(); FFT_SIZE].into_boxed_slice(); len];
+            frames.history_idx = 0;
+        }
+    }
+
+    /// Convolves the most recent partition of input with the impulse responses,
+    /// and then shifts the input buffers to make room for the next partition.
+    fn process_partition(&mut self) {
+        // Transform the previous and current input partitions into the frequency domain
+        for frames in self.inputs.iter_mut() {
+            let num_frames = frames.history.len();
+            if num_frames > 0 {
+                frames.history_idx = (frames.history_idx + 1) % num_frames;
+                let frame = &mut frames.history[frames.history_idx];
+                for (dst, &src) in frame.iter_mut().zip(frames.input.iter()) {
+                    *dst = Complex::new(src, 0.0);
+                }
+                self.fft.process_with_scratch(frame, &mut self.scratch);
+            }
+        }
+
+        for (out_channel, output) in self.outputs.iter_mut().enumerate() {
+            // Multiply each past input frame with the corresponding partition of the impulse response,
+            // summing over the paths from both input channels
+            self.spectrum.fill(Complex::default());
+            for (frames, paths) in self.inputs.iter().zip(self.paths.iter()) {
+                let num_frames = frames.history.len();
+                for (p, partition) in paths[out_channel].iter().enumerate() {
+                    let frame = &frames.history[(frames.history_idx + num_frames - p) % num_frames];
+                    for ((acc, x), h) in self.spectrum.iter_mut().zip(frame.iter()).zip(partition.iter()) {
+                        *acc += x * h;
+                    }
+                }
+            }
+
+            // Transform back to the time domain, keeping only the second half (overlap-save)
+            self.ifft.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+            let scale = (FFT_SIZE as f32).recip();
+            for (dst, src) in output.iter_mut().zip(self.spectrum[PARTITION_SIZE..].iter()) {
+                *dst = scale * src.re;
+            }
+        }
+
+        for frames in self.inputs.iter_mut() {
+            frames.input.copy_within(PARTITION_SIZE.., 0);
+        }
+    }
+}
+
+impl InputFrames {
+    fn new() -> Self {
+        Self {
+            history: vec![],
+            history_idx: 0,
+            input: vec![0.0; FFT_SIZE].into_boxed_slice(),
+        }
+    }
+}
+
+impl Processor for ConvolutionReverb {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        if param_id == 0 {
+            self.set_mix(value);
+        }
+    }
+
+    fn latency_samples(&self) -> usize {
+        self.latency_samples()
+    }
+
+    fn tail_samples(&self) -> usize {
+        self.tail_samples()
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [left, right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let audio_in = StereoBuffer::new(left, right);
+
+        let [left, right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        let audio_out = StereoBufferMut::new(left, right);
+
+        self.process(audio_in, audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_impulse_reproduces_ir() {
+        // A stereo impulse response spanning several partitions
+        let len = 3 * PARTITION_SIZE + 17;
+        let left: Vec<f32> = (0..len)
+            .map(|i| (i as f32 * 0.1).sin() * 0.99f32.powi(i as i32))
+            .collect();
+        let right: Vec<f32> = (0..len)
+            .map(|i| (i as f32 * 0.3).cos() * 0.98f32.powi(i as i32))
+            .collect();
+        let ir = AudioSample::new_stereo(48000, StereoBuffer::new(&left, &right));
+
+        let mut reverb = ConvolutionReverb::new();
+        reverb.set_impulse(Arc::new(ir));
+        reverb.set_mix(1.0);
+
+        // Process an impulse in uneven block sizes
+        let total = len + reverb.latency_samples() + 100;
+        let mut input = vec![0.0; total];
+        input[0] = 1.0;
+        let mut out_left = vec![0.0; total];
+        let mut out_right = vec![0.0; total];
+        let mut i = 0;
+        for block in [100, 1, 300, 512].iter().cycle() {
+            let j = (i + block).min(total);
+            reverb.process(
+                StereoBuffer::new(&input[i..j], &input[i..j]),
+                StereoBufferMut::new(&mut out_left[i..j], &mut out_right[i..j]),
+            );
+            i = j;
+            if i == total {
+                break;
+            }
+        }
+
+        let latency = reverb.latency_samples();
+        assert_eq!(reverb.tail_samples(), len - 1);
+        assert!(out_left[..latency].iter().all(|s| s.abs() < 1e-6));
+        for (n, (&l, &r)) in left.iter().zip(right.iter()).enumerate() {
+            assert!((out_left[latency + n] - l).abs() < 1e-4);
+            assert!((out_right[latency + n] - r).abs() < 1e-4);
+        }
+        assert!(out_left[(latency + len)..].iter().all(|s| s.abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_true_stereo() {
+        // The left input feeds both outputs
+        let ir = |left: &[f32], right: &[f32]| Arc::new(AudioSample::new_stereo(48000, StereoBuffer::new(left, right)));
+        let mut reverb = ConvolutionReverb::new();
+        reverb.set_true_stereo_impulse(ir(&[1.0, 0.5, 0.0], &[0.0, 0.0, 0.25]), ir(&[0.0], &[0.75]));
+        reverb.set_mix(1.0);
+
+        // Process an impulse on the left input alone
+        let total = 2 * PARTITION_SIZE;
+        let mut in_left = vec![0.0; total];
+        in_left[0] = 1.0;
+        let in_right = vec![0.0; total];
+        let mut out_left = vec![0.0; total];
+        let mut out_right = vec![0.0; total];
+        reverb.process(
+            StereoBuffer::new(&in_left, &in_right),
+            StereoBufferMut::new(&mut out_left, &mut out_right),
+        );
+
+        let latency = reverb.latency_samples();
+        let expect = |out: &[f32], expected: &[f32]| {
+            assert!(out[..latency].iter().all(|s| s.abs() < 1e-6));
+            let mut padded = expected.to_vec();
+            padded.resize(total - latency, 0.0);
+            assert!(out[latency..].iter().zip(padded).all(|(a, b)| (a - b).abs() < 1e-5));
+        };
+        expect(&out_left, &[1.0, 0.5]);
+        expect(&out_right, &[0.0, 0.0, 0.25]);
+    }
+}
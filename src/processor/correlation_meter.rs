@@ -0,0 +1,169 @@
+use super::Processor;
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+/// Measures the correlation between the left and right channels of a stereo signal, which it passes through
+/// unchanged. The correlation ranges from `1.0` when the channels are identical, to `-1.0` when one is the
+/// inverse of the other, which would cancel out when the signal is folded down to mono.
+pub struct CorrelationMeter {
+    sample_rate: f32,
+    /// The length in seconds of the window over which the correlation is measured.
+    window: f32,
+    /// The coefficient of the exponential moving averages.
+    coeff: f32,
+    /// The moving averages of the products `L * R`, `L * L` and `R * R`.
+    averages: [f32; 3],
+    correlation: CorrelationHandle,
+}
+
+/// A handle to the correlation measured by a [`CorrelationMeter`], which can be read from another thread.
+#[derive(Clone, Default)]
+pub struct CorrelationHandle(Arc<AtomicU32>);
+
+impl Default for CorrelationMeter {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.0,
+            window: 0.3,
+            coeff: 0.0,
+            averages: [0.0; 3],
+            correlation: CorrelationHandle::default(),
+        }
+    }
+}
+
+impl CorrelationMeter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.calc_coefficient();
+    }
+
+    /// Sets the length in seconds of the window over which the correlation is measured.
+    pub fn set_window(&mut self, window: f32) {
+        self.window = window.max(0.0);
+        self.calc_coefficient();
+    }
+
+    /// Gets the correlation between `-1.0` and `1.0`, which is zero while the signal is silent.
+    pub fn correlation(&self) -> f32 {
+        self.correlation.correlation()
+    }
+
+    /// Gets a handle for reading the correlation from another thread, such as the UI thread.
+    pub fn handle(&self) -> CorrelationHandle {
+        self.correlation.clone()
+    }
+
+    pub fn reset(&mut self) {
+        self.averages = [0.0; 3];
+        self.correlation.0.store(0f32.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn process(&mut self, audio_in: [&[f32]; 2], audio_out: [&mut [f32]; 2]) {
+        let [in_left, in_right] = audio_in;
+        let [out_left, out_right] = audio_out;
+        out_left.copy_from_slice(in_left);
+        out_right.copy_from_slice(in_right);
+
+        let [mut lr, mut ll, mut rr] = self.averages;
+        for (&left, &right) in in_left.iter().zip(in_right.iter()) {
+            lr = left * right + self.coeff * (lr - left * right);
+            ll = left * left + self.coeff * (ll - left * left);
+            rr = right * right + self.coeff * (rr - right * right);
+        }
+        self.averages = [lr, ll, rr];
+
+        let power = (ll * rr).sqrt();
+        let correlation = if power > 1e-12 {
+            (lr / power).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        self.correlation.0.store(correlation.to_bits(), Ordering::Relaxed);
+    }
+
+    fn calc_coefficient(&mut self) {
+        self.coeff = if self.window > 0.0 && self.sample_rate > 0.0 {
+            (-1.0 / (self.window * self.sample_rate)).exp()
+        } else {
+            0.0
+        };
+    }
+}
+
+impl CorrelationHandle {
+    /// Gets the correlation between `-1.0` and `1.0`, as of the last block processed.
+    pub fn correlation(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+impl Processor for CorrelationMeter {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        if param_id == 0 {
+            self.set_window(value)
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [in_left, in_right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let [out_left, out_right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        self.process([in_left, in_right], [out_left, out_right]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// Measures the correlation of one second of a stereo signal.
+    fn measure(left: &[f32], right: &[f32]) -> f32 {
+        let mut meter = CorrelationMeter::new();
+        meter.set_sample_rate(48000);
+        let handle = meter.handle();
+        let mut out_left = [0.0; 480];
+        let mut out_right = [0.0; 480];
+        for (left, right) in left.chunks(480).zip(right.chunks(480)) {
+            meter.process([left, right], [&mut out_left, &mut out_right]);
+        }
+        assert_eq!(handle.correlation(), meter.correlation());
+        handle.correlation()
+    }
+
+    #[test]
+    fn test_correlation() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut noise = || (0..48000).map(|_| rng.gen_range(-1.0..1.0)).collect::<Vec<f32>>();
+        let left = noise();
+        let inverted: Vec<_> = left.iter().map(|s| -s).collect();
+        let uncorrelated = noise();
+
+        assert!((measure(&left, &left) - 1.0).abs() < 1e-4);
+        assert!((measure(&left, &inverted) + 1.0).abs() < 1e-4);
+        assert!(measure(&left, &uncorrelated).abs() < 0.05);
+        assert_eq!(measure(&[0.0; 48000], &[0.0; 48000]), 0.0);
+    }
+}
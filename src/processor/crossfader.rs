@@ -0,0 +1,155 @@
+use super::{Processor, SubBlockEvents};
+use crate::util::LinearRamp;
+use std::f32::consts::FRAC_PI_2;
+
+/// Time in seconds over which changes in position are smoothed.
+const RAMP_TIME: f32 = 0.005;
+
+/// Crossfades between two stereo input pairs using an equal-power curve.
+///
+/// The first two inputs are pair A, and the last two are pair B.
+pub struct Crossfader {
+    /// The position of the crossfade, from `0.0` (pair A) to `1.0` (pair B).
+    position: LinearRamp,
+    /// Number of samples over which changes in position are smoothed.
+    ramp_samples: usize,
+    /// Storage for the events of each part of the block between changes in position.
+    events: SubBlockEvents,
+}
+
+impl Default for Crossfader {
+    fn default() -> Self {
+        Self {
+            position: LinearRamp::new(0.0),
+            ramp_samples: 0,
+            events: SubBlockEvents::default(),
+        }
+    }
+}
+
+impl Crossfader {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Smoothly moves the crossfade to a position from `0.0` (pair A) to `1.0` (pair B).
+    pub fn set_position(&mut self, position: f32) {
+        self.position.ramp_to(position.clamp(0.0, 1.0), self.ramp_samples);
+    }
+
+    /// Processes a block of samples, smoothing any change in position that is in progress.
+    pub fn process(&mut self, audio_in: &[&[f32]], audio_out: &mut [&mut [f32]]) {
+        let [in_a_left, in_a_right, in_b_left, in_b_right] = audio_in else {
+            panic!("Expected four input audio buffers");
+        };
+        let [out_left, out_right, ..] = audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+
+        for i in 0..out_left.len() {
+            let (gain_a, gain_b) = equal_power(self.position.advance());
+            out_left[i] = gain_a * in_a_left[i] + gain_b * in_b_left[i];
+            out_right[i] = gain_a * in_a_right[i] + gain_b * in_b_right[i];
+        }
+    }
+}
+
+/// Gets the gains of each input at a crossfade position, which have a constant combined power.
+fn equal_power(position: f32) -> (f32, f32) {
+    let angle = FRAC_PI_2 * position;
+    (angle.cos(), angle.sin())
+}
+
+impl Processor for Crossfader {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 4,
+            max_audio_ins: 4,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.ramp_samples = (RAMP_TIME * sample_rate as f32).round() as usize;
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        if param_id == 0 {
+            self.set_position(value)
+        }
+    }
+
+    fn process(&mut self, mut data: super::ProcessorData) {
+        let mut events = std::mem::take(&mut self.events);
+
+        // Apply each change in position at its sample offset
+        let mut start = 0;
+        let param_events = data.param_events;
+        for event in param_events.iter().filter(|e| e.param_id == 0) {
+            let time = (event.time as usize).clamp(start, data.samples);
+            data.sub_block(start..time, &mut events, |sub| {
+                self.process(sub.audio_in, sub.audio_out)
+            });
+            self.set_position(event.value);
+            start = time;
+        }
+        data.sub_block(start..data.samples, &mut events, |sub| {
+            self.process(sub.audio_in, sub.audio_out)
+        });
+
+        self.events = events;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn process(crossfader: &mut Crossfader, position: f32) -> [f32; 1024] {
+        let input_a = [0.5; 1024];
+        let input_b = [-0.25; 1024];
+        let mut left = [0.0; 1024];
+        let mut right = [0.0; 1024];
+
+        // Allow the change in position to settle before rendering the output
+        crossfader.set_position(position);
+        for _ in 0..2 {
+            crossfader.process(&[&input_a, &input_a, &input_b, &input_b], &mut [&mut left, &mut right]);
+        }
+        assert_eq!(left, right);
+        left
+    }
+
+    #[test]
+    fn test_crossfade() {
+        let mut crossfader = Crossfader::new();
+        crossfader.set_sample_rate(48000);
+
+        assert!(process(&mut crossfader, 0.0).iter().all(|&s| s == 0.5));
+        assert!(process(&mut crossfader, 1.0).iter().all(|&s| (s - -0.25).abs() < 1e-6));
+
+        // Halfway through, each pair is attenuated by 3dB
+        let gain = 0.5f32.sqrt();
+        let expected = gain * 0.5 + gain * -0.25;
+        assert!(process(&mut crossfader, 0.5)
+            .iter()
+            .all(|&s| (s - expected).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_smoothing() {
+        let mut crossfader = Crossfader::new();
+        crossfader.set_sample_rate(48000);
+        crossfader.set_position(1.0);
+
+        let input_a = [1.0; 64];
+        let input_b = [0.0; 64];
+        let mut left = [0.0; 64];
+        let mut right = [0.0; 64];
+        crossfader.process(&[&input_a, &input_a, &input_b, &input_b], &mut [&mut left, &mut right]);
+
+        // The gain of pair A falls gradually rather than jumping to zero
+        assert!(left.windows(2).all(|w| w[1] < w[0]));
+        assert!(left[63] > 0.5);
+    }
+}
@@ -0,0 +1,191 @@
+use super::{IIRFilter, Processor};
+use crate::audio::{
+    buffer::{StereoBuffer, StereoBufferMut},
+    ring::RingBuffer,
+};
+
+const BATCH_SIZE: usize = 32;
+const MAX_DELAY: f32 = 0.002;
+/// Cutoff of the lowpass filter applied to the crossfed signal, approximating head shadowing.
+const CUTOFF: f32 = 700.0;
+
+/// Mixes a delayed and lowpassed portion of each channel into the other,
+/// to make hard-panned stereo material sound more natural on headphones.
+pub struct Crossfeed {
+    /// Holds the recent input of the left and right channels.
+    rings: [RingBuffer; 2],
+    /// Lowpass filters for the signal crossfed from the left and right channels.
+    filters: [IIRFilter; 2],
+    /// The sample rate in `Hz`.
+    sample_rate: f32,
+    /// Level of the crossfed signal between `0.0` and `1.0`.
+    amount: f32,
+    /// Delay of the crossfed signal in seconds.
+    delay: f32,
+}
+
+impl Default for Crossfeed {
+    fn default() -> Self {
+        Self {
+            rings: [RingBuffer::new(BATCH_SIZE), RingBuffer::new(BATCH_SIZE)],
+            filters: [IIRFilter::new(); 2],
+            sample_rate: 0.0,
+            amount: 0.3,
+            delay: 0.0003,
+        }
+    }
+}
+
+impl Crossfeed {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        let size = (MAX_DELAY * self.sample_rate) as usize + BATCH_SIZE;
+        self.rings = [RingBuffer::new(size), RingBuffer::new(size)];
+        for filter in self.filters.iter_mut() {
+            *filter = IIRFilter::new();
+            filter.set_lowpass(CUTOFF, self.sample_rate);
+        }
+    }
+
+    pub fn set_amount(&mut self, amount: f32) {
+        self.amount = amount.clamp(0.0, 1.0);
+    }
+
+    /// Sets the delay of the crossfed signal in seconds, up to 2ms.
+    pub fn set_delay(&mut self, delay: f32) {
+        self.delay = delay.clamp(0.0, MAX_DELAY);
+    }
+
+    pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
+        let len = audio_in.len();
+        assert!(audio_in.len() == audio_out.len());
+
+        let delay = (self.delay * self.sample_rate) as usize;
+        let dry = (1.0 + self.amount).recip();
+        let wet = self.amount * dry;
+
+        let mut i = 0;
+        let mut left = [0.0f32; BATCH_SIZE];
+        let mut right = [0.0f32; BATCH_SIZE];
+
+        while i < len {
+            let j = (i + BATCH_SIZE).min(len);
+            let (left, right) = (&mut left[..(j - i)], &mut right[..(j - i)]);
+
+            // Read the delayed input of each channel
+            let [ring_left, ring_right] = &mut self.rings;
+            ring_left.write(&audio_in.left[i..j]);
+            ring_left.seek(delay + (j - i));
+            ring_left.read(left, true);
+            ring_right.write(&audio_in.right[i..j]);
+            ring_right.seek(delay + (j - i));
+            ring_right.read(right, true);
+
+            // Mix the filtered signal from each channel into the other
+            let [filter_left, filter_right] = &mut self.filters;
+            let samples = audio_out.left[i..j]
+                .iter_mut()
+                .zip(audio_in.left[i..j].iter())
+                .zip(right.iter());
+            for ((out, &s), &cross) in samples {
+                *out = dry * s + wet * filter_right.process_sample(cross);
+            }
+            let samples = audio_out.right[i..j]
+                .iter_mut()
+                .zip(audio_in.right[i..j].iter())
+                .zip(left.iter());
+            for ((out, &s), &cross) in samples {
+                *out = dry * s + wet * filter_left.process_sample(cross);
+            }
+
+            i = j;
+        }
+    }
+}
+
+impl Processor for Crossfeed {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_amount(value),
+            1 => self.set_delay(value),
+            _ => {}
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [left, right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let audio_in = StereoBuffer::new(left, right);
+
+        let [left, right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        let audio_out = StereoBufferMut::new(left, right);
+
+        self.process(audio_in, audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn process_hard_left(crossfeed: &mut Crossfeed) -> (Vec<f32>, Vec<f32>) {
+        let input: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.05).sin()).collect();
+        let silence = vec![0.0; input.len()];
+        let mut left = vec![0.0; input.len()];
+        let mut right = vec![0.0; input.len()];
+        for ((l_in, r_in), (l_out, r_out)) in input
+            .chunks(100)
+            .zip(silence.chunks(100))
+            .zip(left.chunks_mut(100).zip(right.chunks_mut(100)))
+        {
+            crossfeed.process(StereoBuffer::new(l_in, r_in), StereoBufferMut::new(l_out, r_out));
+        }
+        (left, right)
+    }
+
+    #[test]
+    fn test_hard_left() {
+        let mut crossfeed = Crossfeed::new();
+        crossfeed.set_sample_rate(48000);
+        crossfeed.set_amount(0.5);
+        crossfeed.set_delay(0.0005);
+
+        let (left, right) = process_hard_left(&mut crossfeed);
+        let energy = |s: &[f32]| s.iter().map(|s| s * s).sum::<f32>();
+
+        // The crossfed signal is delayed, then present but quieter than the direct signal
+        assert!(right[..24].iter().all(|&s| s == 0.0));
+        assert!(right[24..].iter().any(|&s| s.abs() > 0.01));
+        assert!(energy(&right) < 0.5 * energy(&left));
+    }
+
+    #[test]
+    fn test_transparent_at_zero() {
+        let mut crossfeed = Crossfeed::new();
+        crossfeed.set_sample_rate(48000);
+        crossfeed.set_amount(0.0);
+
+        let (left, right) = process_hard_left(&mut crossfeed);
+        assert!(left.iter().enumerate().all(|(i, &s)| s == (i as f32 * 0.05).sin()));
+        assert!(right.iter().all(|&s| s == 0.0));
+    }
+}
@@ -0,0 +1,266 @@
+use super::{IIRFilter, Processor};
+
+/// The default crossover frequencies for each number of bands, in Hz.
+const DEFAULT_FREQS: [&[f32]; 3] = [&[500.0], &[200.0, 2000.0], &[120.0, 800.0, 5000.0]];
+/// The range of the crossover frequencies, in Hz.
+const MIN_FREQ: f32 = 10.0;
+const MAX_FREQ: f32 = 22_000.0;
+
+/// Splits a stereo signal into two to four frequency bands, which are output on consecutive pairs of channels
+/// from the lowest band to the highest, such as for multiband compression.
+///
+/// The bands are split with 4th-order Linkwitz-Riley filters, and the lower bands are passed through allpass
+/// filters matching each higher split, so that summing the bands reconstructs the input with a flat magnitude.
+pub struct Crossover {
+    sample_rate: f32,
+    /// The crossover frequencies in Hz, in ascending order.
+    freqs: Vec<f32>,
+    channels: [CrossoverChannel; 2],
+}
+
+#[derive(Clone)]
+struct CrossoverChannel {
+    /// The filters splitting off each band from the higher bands.
+    splits: Vec<LinkwitzRiley>,
+    /// For each band below the highest, the allpass filters matching the phase of each higher split.
+    allpasses: Vec<Vec<LinkwitzRiley>>,
+}
+
+/// A 4th-order Linkwitz-Riley lowpass and highpass pair, made from two cascaded Butterworth filters each.
+#[derive(Clone, Copy, Default)]
+struct LinkwitzRiley {
+    lowpass: [IIRFilter; 2],
+    highpass: [IIRFilter; 2],
+}
+
+impl Crossover {
+    /// Creates a crossover with between two and four bands.
+    pub fn new(num_bands: usize) -> Self {
+        assert!(
+            (2..=4).contains(&num_bands),
+            "A crossover must have between 2 and 4 bands"
+        );
+        let num_splits = num_bands - 1;
+        let channel = CrossoverChannel {
+            splits: vec![LinkwitzRiley::default(); num_splits],
+            allpasses: (0..num_splits)
+                .map(|band| vec![LinkwitzRiley::default(); num_splits - band - 1])
+                .collect(),
+        };
+        Self {
+            sample_rate: 0.0,
+            freqs: DEFAULT_FREQS[num_bands - 2].to_vec(),
+            channels: [channel.clone(), channel],
+        }
+    }
+
+    pub fn num_bands(&self) -> usize {
+        self.freqs.len() + 1
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.calc_filters();
+    }
+
+    /// Sets the frequencies in Hz at which the bands are split, of which there must be one fewer than the bands.
+    pub fn set_crossover_freqs(&mut self, freqs: &[f32]) {
+        assert!(
+            freqs.len() == self.freqs.len(),
+            "Expected one fewer frequency than bands"
+        );
+        for (freq, &value) in self.freqs.iter_mut().zip(freqs) {
+            *freq = value.clamp(MIN_FREQ, MAX_FREQ);
+        }
+        self.freqs.sort_by(f32::total_cmp);
+        self.calc_filters();
+    }
+
+    /// Processes a stereo signal, where `audio_out` has a pair of buffers for each band.
+    pub fn process(&mut self, audio_in: [&[f32]; 2], audio_out: &mut [&mut [f32]]) {
+        assert!(audio_out.len() == 2 * self.num_bands());
+        for (c, (channel, audio_in)) in self.channels.iter_mut().zip(audio_in).enumerate() {
+            for (i, &sample) in audio_in.iter().enumerate() {
+                // Split off each band in turn, keeping the remaining higher frequencies
+                let mut rest = sample;
+                for (band, (split, allpasses)) in channel.splits.iter_mut().zip(&mut channel.allpasses).enumerate() {
+                    let (low, high) = split.process_sample(rest);
+                    audio_out[2 * band + c][i] = allpasses.iter_mut().fold(low, |s, ap| ap.allpass(s));
+                    rest = high;
+                }
+                audio_out[2 * channel.splits.len() + c][i] = rest;
+            }
+        }
+    }
+
+    /// Sets the crossover frequency of the split at `index` in Hz, which is kept between the neighbouring
+    /// frequencies so that each index continues to refer to the same split.
+    pub fn set_crossover_freq(&mut self, index: usize, freq: f32) {
+        let low = index.checked_sub(1).map_or(MIN_FREQ, |i| self.freqs[i]);
+        let high = self.freqs.get(index + 1).copied().unwrap_or(MAX_FREQ);
+        self.freqs[index] = freq.clamp(low, high);
+        self.calc_filters();
+    }
+
+    /// Updates the coefficients of the filters in place, keeping their state so that changing the frequencies
+    /// doesn't click. The frequencies are kept below the Nyquist frequency, so that the filters stay stable.
+    fn calc_filters(&mut self) {
+        let max_freq = 0.49 * self.sample_rate;
+        for channel in self.channels.iter_mut() {
+            for (band, (split, allpasses)) in channel.splits.iter_mut().zip(&mut channel.allpasses).enumerate() {
+                split.set_freq(self.freqs[band].min(max_freq), self.sample_rate);
+                for (allpass, &freq) in allpasses.iter_mut().zip(&self.freqs[(band + 1)..]) {
+                    allpass.set_freq(freq.min(max_freq), self.sample_rate);
+                }
+            }
+        }
+    }
+}
+
+impl LinkwitzRiley {
+    fn set_freq(&mut self, freq: f32, sample_rate: f32) {
+        for lowpass in self.lowpass.iter_mut() {
+            lowpass.set_lowpass(freq, sample_rate);
+        }
+        for highpass in self.highpass.iter_mut() {
+            highpass.set_highpass(freq, sample_rate);
+        }
+    }
+
+    /// Splits a sample into its low and high frequency parts.
+    #[inline]
+    fn process_sample(&mut self, sample: f32) -> (f32, f32) {
+        let [lp1, lp2] = &mut self.lowpass;
+        let [hp1, hp2] = &mut self.highpass;
+        let low = lp2.process_sample(lp1.process_sample(sample));
+        let high = hp2.process_sample(hp1.process_sample(sample));
+        (low, high)
+    }
+
+    /// Passes a sample through the allpass response of the summed low and high parts.
+    #[inline]
+    fn allpass(&mut self, sample: f32) -> f32 {
+        let (low, high) = self.process_sample(sample);
+        low + high
+    }
+}
+
+impl Processor for Crossover {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2 * self.num_bands(),
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        if param_id < self.freqs.len() {
+            self.set_crossover_freq(param_id, value);
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [left, right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        self.process([left, right], data.audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processor::magnitude_at;
+
+    /// Feeds an impulse through the crossover, and returns the impulse response of each band.
+    fn impulse_responses(crossover: &mut Crossover) -> Vec<Vec<f32>> {
+        let mut impulse = vec![0.0; 4800];
+        impulse[0] = 1.0;
+        let mut bands = vec![vec![0.0; impulse.len()]; 2 * crossover.num_bands()];
+        let mut audio_out: Vec<_> = bands.iter_mut().map(|b| b.as_mut_slice()).collect();
+        crossover.process([&impulse, &impulse], &mut audio_out);
+        bands
+    }
+
+    /// Gets the magnitude of a frequency in an impulse response sampled at 48kHz.
+    fn magnitude(response: &[f32], freq: f32) -> f32 {
+        magnitude_at(response, freq, 48000.0)
+    }
+
+    #[test]
+    fn test_reconstruction() {
+        for freqs in [&[1000.0][..], &[200.0, 4000.0], &[100.0, 1000.0, 8000.0]] {
+            let mut crossover = Crossover::new(freqs.len() + 1);
+            crossover.set_sample_rate(48000);
+            crossover.set_crossover_freqs(freqs);
+            let bands = impulse_responses(&mut crossover);
+            assert_eq!(bands[0], bands[1]);
+
+            // The sum of the bands has a flat magnitude response
+            let sum: Vec<f32> = (0..bands[0].len())
+                .map(|i| bands.iter().step_by(2).map(|b| b[i]).sum())
+                .collect();
+            for freq in [30.0, 100.0, 500.0, 1000.0, 3000.0, 10_000.0, 18_000.0] {
+                assert!((magnitude(&sum, freq) - 1.0).abs() < 1e-2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_band_split() {
+        let mut crossover = Crossover::new(2);
+        crossover.set_sample_rate(48000);
+        crossover.set_crossover_freqs(&[1000.0]);
+        let bands = impulse_responses(&mut crossover);
+
+        // Each Linkwitz-Riley filter is 6dB down at the crossover frequency, and falls at 24dB per octave
+        assert!((magnitude(&bands[0], 1000.0) - 0.5).abs() < 1e-2);
+        assert!((magnitude(&bands[2], 1000.0) - 0.5).abs() < 1e-2);
+        assert!(magnitude(&bands[0], 4000.0) < 0.01);
+        assert!(magnitude(&bands[2], 250.0) < 0.01);
+    }
+
+    #[test]
+    fn test_parameter_keeps_state() {
+        // Setting a frequency to its current value mid-stream doesn't change the output
+        let input: Vec<f32> = (0..1024).map(|i| (i as f32 * 0.05).sin()).collect();
+        let render = |split: bool| {
+            let mut crossover = Crossover::new(3);
+            crossover.set_sample_rate(48000);
+            let mut bands = vec![vec![0.0; input.len()]; 6];
+            for range in [0..512, 512..1024] {
+                if split && range.start > 0 {
+                    crossover.set_parameter(0, 200.0);
+                }
+                let mut audio_out: Vec<_> = bands.iter_mut().map(|b| &mut b[range.clone()]).collect();
+                crossover.process([&input[range.clone()], &input[range.clone()]], &mut audio_out);
+            }
+            bands
+        };
+        assert_eq!(render(false), render(true));
+    }
+
+    #[test]
+    fn test_parameter_bounds() {
+        let mut crossover = Crossover::new(4);
+        crossover.set_sample_rate(48000);
+
+        // Each parameter keeps referring to the same split, which is kept between its neighbours
+        crossover.set_parameter(0, 1000.0);
+        assert_eq!(crossover.freqs, [800.0, 800.0, 5000.0]);
+        crossover.set_parameter(2, 100.0);
+        assert_eq!(crossover.freqs, [800.0, 800.0, 800.0]);
+
+        // A frequency above the Nyquist frequency leaves the filters stable
+        crossover.set_parameter(2, 30_000.0);
+        assert_eq!(crossover.freqs[2], 22_000.0);
+        crossover.set_sample_rate(22050);
+        let bands = impulse_responses(&mut crossover);
+        assert!(bands.iter().flatten().all(|s| s.is_finite() && s.abs() < 2.0));
+    }
+}
@@ -13,12 +13,16 @@ pub struct Delay {
     delay_lines: [DelayLine; 2],
     /// The sample rate in `Hz`.
     sample_rate: f32,
-    /// The target delay value in seconds.
-    delay: f32,
+    /// The target delay values of the left and right channels in seconds.
+    delay: [f32; 2],
     /// Feedback between `0.0` and `1.0`.
     feedback: f32,
     /// Whether "ping pong" delay is enabled.
     ping_pong: bool,
+    /// Approximate time in seconds taken to glide to a new delay value.
+    glide_time: f32,
+    /// Whether the delay is frozen, looping its current contents indefinitely.
+    freeze: bool,
 }
 
 impl Delay {
@@ -26,39 +30,97 @@ impl Delay {
         Self {
             delay_lines: [DelayLine::new(MAX_DELAY), DelayLine::new(MAX_DELAY)],
             sample_rate: 0.0,
-            delay: 0.001,
+            delay: [0.001; 2],
             feedback: 0.5,
             ping_pong: false,
+            glide_time: 1.0 / 6.0,
+            freeze: false,
         }
     }
 
     pub fn set_sample_rate(&mut self, sample_rate: u32) {
         self.sample_rate = sample_rate as f32;
-        for line in self.delay_lines.iter_mut() {
+        for (line, &delay) in self.delay_lines.iter_mut().zip(self.delay.iter()) {
             line.set_sample_rate(sample_rate);
-            line.seek_seconds(self.delay);
+            line.seek_seconds(delay);
         }
     }
 
     pub fn set_delay(&mut self, delay: f32) {
-        self.delay = delay.clamp(MIN_DELAY, MAX_DELAY);
+        self.set_delay_lr(delay, delay);
+    }
+
+    /// Sets the delays of the left and right channels in seconds independently.
+    pub fn set_delay_lr(&mut self, left: f32, right: f32) {
+        self.delay = [left, right].map(|delay| delay.clamp(MIN_DELAY, MAX_DELAY));
     }
 
     pub fn set_feedback(&mut self, feedback: f32) {
         self.feedback = feedback.clamp(0.0, 2.0);
     }
 
+    /// Sets the approximate time in seconds taken to glide to a new delay value, which repitches the delayed signal.
+    /// Longer times give slower and more dramatic pitch slides, and a time of zero retargets immediately.
+    pub fn set_glide_time(&mut self, glide_time: f32) {
+        self.glide_time = glide_time.max(0.0);
+        for line in self.delay_lines.iter_mut() {
+            line.set_warp_stiffness(self.glide_time.max(0.0001).recip());
+        }
+    }
+
     pub fn set_ping_pong(&mut self, ping_pong: bool) {
         self.ping_pong = ping_pong;
     }
 
+    /// Gets the actual delay of the left channel in seconds,
+    /// which moves smoothly towards the target delay after it is changed.
+    pub fn current_delay(&self) -> f32 {
+        if self.sample_rate > 0.0 {
+            self.delay_lines[0].delay_seconds()
+        } else {
+            self.delay[0]
+        }
+    }
+
+    /// Gets the number of samples taken for the echoes to decay by 60dB,
+    /// or `usize::MAX` if they never decay because the delay is frozen or the feedback is at least `1.0`.
+    pub fn tail_samples(&self) -> usize {
+        if self.freeze || self.feedback >= 1.0 {
+            return usize::MAX;
+        }
+        let echoes = if self.feedback > 0.0 {
+            (1e-3f32.ln() / self.feedback.ln()).ceil()
+        } else {
+            0.0
+        };
+        // In ping pong mode, the feedback is only applied once the echo has passed through both channels
+        let echoes = if self.ping_pong { 2.0 * echoes } else { echoes };
+        let delay = self.delay[0].max(self.delay[1]);
+        ((echoes + 1.0) * delay * self.sample_rate).ceil() as usize
+    }
+
+    /// Freezes the delay, which stops new input from being written into the delay lines
+    /// and loops their current contents indefinitely without decaying.
+    pub fn set_freeze(&mut self, freeze: bool) {
+        self.freeze = freeze;
+    }
+
+    /// Silences both delay lines, such as when the transport jumps to a new position,
+    /// and jumps to the configured delay if it was gliding towards it.
+    pub fn clear(&mut self) {
+        for (line, &delay) in self.delay_lines.iter_mut().zip(self.delay.iter()) {
+            line.clear();
+            line.seek_seconds(delay);
+        }
+    }
+
     pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
         let len = audio_in.len();
         assert!(audio_in.len() == audio_out.len());
 
         let lines = &mut self.delay_lines;
-        lines[0].set_target_delay(self.delay);
-        lines[1].set_target_delay(self.delay);
+        lines[0].set_target_delay(self.delay[0]);
+        lines[1].set_target_delay(self.delay[1]);
 
         let mut i = 0;
         let mut buffer1 = [0.0f32; BATCH_SIZE];
@@ -78,20 +140,26 @@ impl Delay {
             audio_out.right[i..j].copy(&*buffers[1]);
 
             // Combine input and feedback signals, and write to ring buffers
+            // When frozen, the feedback is exactly unity and no input is written
+            let feedback = if self.freeze { 1.0 } else { self.feedback };
             if self.ping_pong {
                 // Write input only to right channel and swap feedback lines
                 // Don't scale the feedback from the left to right channel
-                buffers[1].scale(self.feedback);
-                buffers[1].add_scaled(&audio_in.left[i..j], 0.5);
-                buffers[1].add_scaled(&audio_in.right[i..j], 0.5);
+                buffers[1].scale(feedback);
+                if !self.freeze {
+                    buffers[1].add_scaled(&audio_in.left[i..j], 0.5);
+                    buffers[1].add_scaled(&audio_in.right[i..j], 0.5);
+                }
                 lines[0].write(buffers[1]);
                 lines[1].write(buffers[0]);
             } else {
                 // Write input to respective channels and don't swap feedback lines
-                buffers[0].scale(self.feedback);
-                buffers[1].scale(self.feedback);
-                buffers[0].add(&audio_in.left[i..j]);
-                buffers[1].add(&audio_in.right[i..j]);
+                buffers[0].scale(feedback);
+                buffers[1].scale(feedback);
+                if !self.freeze {
+                    buffers[0].add(&audio_in.left[i..j]);
+                    buffers[1].add(&audio_in.right[i..j]);
+                }
                 lines[0].write(buffers[0]);
                 lines[1].write(buffers[1]);
             }
@@ -119,10 +187,15 @@ impl Processor for Delay {
         match param_id {
             0 => self.set_delay(value),
             1 => self.set_feedback(value),
+            2 => self.set_glide_time(value),
             _ => {}
         }
     }
 
+    fn tail_samples(&self) -> usize {
+        self.tail_samples()
+    }
+
     fn process(&mut self, data: super::ProcessorData) {
         let [left, right, ..] = data.audio_in else {
             panic!("Expected at least two input audio buffers");
@@ -137,3 +210,110 @@ impl Processor for Delay {
         self.process(audio_in, audio_out);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn process(delay: &mut Delay, input: &[f32]) -> Vec<f32> {
+        let mut left = vec![0.0; input.len()];
+        let mut right = vec![0.0; input.len()];
+        for ((input, left), right) in input.chunks(100).zip(left.chunks_mut(100)).zip(right.chunks_mut(100)) {
+            delay.process(StereoBuffer::new(input, input), StereoBufferMut::new(left, right));
+        }
+        left
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut delay = Delay::new();
+        delay.set_delay(0.01);
+        delay.set_feedback(0.0);
+        delay.set_sample_rate(48000);
+
+        let input: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.05).sin()).collect();
+        process(&mut delay, &input);
+
+        // Once frozen, the output loops the captured buffer and new input doesn't appear
+        delay.set_freeze(true);
+        let output = process(&mut delay, &[0.5; 9600]);
+        assert!(output.iter().any(|s| s.abs() > 0.5));
+        for (a, b) in output.iter().zip(output[480..].iter()) {
+            assert!((a - b).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut delay = Delay::new();
+        delay.set_delay(0.01);
+        delay.set_feedback(0.9);
+        delay.set_sample_rate(48000);
+        process(&mut delay, &[0.5; 4800]);
+
+        // Neither the delayed input nor its feedback is heard after clearing
+        delay.clear();
+        let output = process(&mut delay, &[0.0; 4800]);
+        assert!(output.iter().all(|&s| s == 0.0));
+
+        // Clearing jumps to a delay set since the last block, rather than the delay of that block
+        delay.set_delay(0.02);
+        delay.clear();
+        assert_eq!(delay.current_delay(), 0.02);
+    }
+
+    #[test]
+    fn test_delay_lr() {
+        let mut delay = Delay::new();
+        delay.set_delay_lr(0.01, 0.015);
+        delay.set_feedback(0.5);
+        delay.set_sample_rate(48000);
+
+        let mut input = vec![0.0; 2400];
+        input[0] = 1.0;
+        let mut left = vec![0.0; 2400];
+        let mut right = vec![0.0; 2400];
+        delay.process(
+            StereoBuffer::new(&input, &input),
+            StereoBufferMut::new(&mut left, &mut right),
+        );
+
+        // Each channel echoes at multiples of its own delay time
+        let echoes = |output: &[f32]| {
+            let peaks = output
+                .windows(3)
+                .enumerate()
+                .filter(|(_, w)| w[1] > 0.1 && w[1] >= w[0] && w[1] > w[2]);
+            peaks.map(|(i, _)| i + 1).collect::<Vec<_>>()
+        };
+        let (left, right) = (echoes(&left), echoes(&right));
+        assert_eq!(left.len(), 4);
+        assert_eq!(right.len(), 3);
+        assert!(left.windows(2).all(|w| w[1] - w[0] == 480));
+        assert!(right.windows(2).all(|w| w[1] - w[0] == 720));
+    }
+
+    #[test]
+    fn test_current_delay() {
+        let mut delay = Delay::new();
+        delay.set_delay(0.1);
+        delay.set_sample_rate(48000);
+        assert!((delay.current_delay() - 0.1).abs() < 1e-4);
+
+        // The reported delay moves steadily from the old to the new delay
+        delay.set_delay(0.2);
+        let mut previous = delay.current_delay();
+        let mut moved = false;
+        for _ in 0..100 {
+            process(&mut delay, &[0.0; 4800]);
+            let current = delay.current_delay();
+            assert!(current >= previous - 1e-4 && current - previous < 0.05);
+            moved |= current > 0.1 + 1e-3 && current < 0.2 - 1e-3;
+            previous = current;
+        }
+        assert!(moved);
+
+        // The reported delay includes the few samples buffered by the resampler
+        assert!((delay.current_delay() - 0.2).abs() < 1e-3);
+    }
+}
@@ -1,84 +1,127 @@
 use super::Processor;
-use crate::audio::buffer::{AudioBuffer, AudioBufferMut, StereoBuffer, StereoBufferMut};
-use std::{char::MAX, f32::consts::PI};
+use crate::{
+    audio::buffer::{StereoBuffer, StereoBufferMut},
+    convert::{leftright_to_midside, midside_to_leftright},
+    util::LinearRamp,
+};
+use std::{
+    f32::consts::{FRAC_1_SQRT_2, PI},
+    ops::Range,
+};
 
-const MAX_COEFFS: usize = 8;
+/// The maximum order of the filter.
+const ORDER: usize = 3;
 
-/// An infinite impulse response filter.
+/// An infinite impulse response filter, implemented in direct form II transposed.
 #[derive(Copy, Clone)]
 pub struct IIRFilter {
-    /// The coefficients, interlaced as [a0, b0, a1, b1, ...],
-    /// and with the `a` coefficients inverted.
-    coeffs: [f32; MAX_COEFFS],
-    /// A buffer containing historical input and output samples from the filter,
-    /// interlaced in the same manner as the filter coefficients, with outputs preceeding inputs.
-    /// The most recent samples are at the start of the buffer.
-    buffer: [f32; MAX_COEFFS],
+    /// The feedforward coefficients, `b0` to `b3`.
+    b: [f32; ORDER + 1],
+    /// The feedback coefficients, `a1` to `a3`, inverted.
+    a: [f32; ORDER],
+    /// The state variables, which hold the partially computed future output samples.
+    state: [f32; ORDER],
+}
+
+impl Default for IIRFilter {
+    fn default() -> Self {
+        Self {
+            b: [1.0, 0.0, 0.0, 0.0],
+            a: [0.0; ORDER],
+            state: [0.0; ORDER],
+        }
+    }
 }
 
 impl IIRFilter {
     pub fn new() -> Self {
-        let mut coeffs = [0.0; MAX_COEFFS];
-        coeffs[1] = 1.0;
+        Default::default()
+    }
+
+    /// Creates a second-order section from its coefficients `b0` to `b2`, and `a1` and `a2`,
+    /// normalized so that `a0` is one.
+    pub fn from_biquad(b: [f32; 3], a: [f32; 2]) -> Self {
         Self {
-            coeffs,
-            buffer: [0.0; MAX_COEFFS],
+            b: [b[0], b[1], b[2], 0.0],
+            a: [-a[0], -a[1], 0.0],
+            state: [0.0; ORDER],
         }
     }
 
+    /// Clears the state of the filter, keeping its coefficients.
+    pub fn reset(&mut self) {
+        self.state = [0.0; ORDER];
+    }
+
     pub fn set_lowpass(&mut self, cutoff_hz: f32, sample_rate: f32) {
+        self.set_resonant_lowpass(cutoff_hz, FRAC_1_SQRT_2, sample_rate);
+    }
+
+    /// Sets the filter to a second-order lowpass with the given quality factor,
+    /// where a `q` of `1 / sqrt(2)` gives a Butterworth response and higher values give a resonant peak.
+    pub fn set_resonant_lowpass(&mut self, cutoff_hz: f32, q: f32, sample_rate: f32) {
         let a = (PI * cutoff_hz / sample_rate).tan().recip();
-        let a0 = 1.0 + 2f32.sqrt() * a + a.powi(2);
-        self.coeffs = [
-            0.0,
-            1.0 / a0,
-            (2.0 * a.powi(2) - 2.0) / a0,
-            2.0 / a0,
-            (-1.0 + 2f32.sqrt() * a - a.powi(2)) / a0,
-            1.0 / a0,
-            0.0,
-            0.0,
-        ];
+        let a0 = 1.0 + a / q + a.powi(2);
+        self.b = [1.0 / a0, 2.0 / a0, 1.0 / a0, 0.0];
+        self.a = [(2.0 * a.powi(2) - 2.0) / a0, (-1.0 + a / q - a.powi(2)) / a0, 0.0];
     }
 
     pub fn set_highpass(&mut self, cutoff_hz: f32, sample_rate: f32) {
         let a = (PI * cutoff_hz / sample_rate).tan().recip();
         let a0 = 1.0 + 2f32.sqrt() * a + a.powi(2);
-        self.coeffs = [
-            0.0,
-            a.powi(2) / a0,
+        self.b = [a.powi(2) / a0, -2.0 * a.powi(2) / a0, a.powi(2) / a0, 0.0];
+        self.a = [
             (2.0 * a.powi(2) - 2.0) / a0,
-            -2.0 * a.powi(2) / a0,
             (-1.0 + 2f32.sqrt() * a - a.powi(2)) / a0,
-            a.powi(2) / a0,
-            0.0,
             0.0,
         ];
     }
 
     pub fn process(&mut self, audio_in: &[f32], audio_out: &mut [f32]) {
         assert!(audio_in.len() == audio_out.len());
-        audio_out.map(audio_in, |_, s| self.process_sample(s));
+        for (s_out, &s_in) in audio_out.iter_mut().zip(audio_in.iter()) {
+            *s_out = self.process_sample(s_in);
+        }
     }
 
+    #[inline]
     pub fn process_sample(&mut self, s_in: f32) -> f32 {
-        // Shift the buffer and write the input sample.
-        self.buffer.copy_within(..(MAX_COEFFS - 2), 2);
-        self.buffer[1] = s_in;
+        let [b0, b1, b2, b3] = self.b;
+        let [a1, a2, a3] = self.a;
+        let [s1, s2, s3] = self.state;
 
-        // Perform the convolution
-        let s_out = self.coeffs.iter().zip(self.buffer.iter()).map(|(c, s)| c * s).sum();
-
-        // Write the output sample and return it
-        self.buffer[0] = s_out;
+        let s_out = b0 * s_in + s1;
+        self.state = [
+            b1 * s_in + a1 * s_out + s2,
+            b2 * s_in + a2 * s_out + s3,
+            b3 * s_in + a3 * s_out,
+        ];
         s_out
     }
 }
 
+/// Number of samples over which timed cutoff changes are smoothed.
+const RAMP_SAMPLES: usize = 32;
+/// Number of samples converted to mid/side at a time.
+const BLOCK_SIZE: usize = 64;
+
+/// How a [`Filter`] treats the two channels of a stereo signal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StereoMode {
+    /// The left and right channels are filtered independently.
+    LeftRight,
+    /// The signal is converted to mid and side channels, which are each filtered and then converted back.
+    MidSide,
+    /// Only the side channel is filtered, leaving the mid channel untouched.
+    /// This can be used to make the low end of a stereo signal mono.
+    SideOnly,
+}
+
 pub struct Filter {
     filters: [IIRFilter; 2],
     sample_rate: f32,
-    cutoff: f32,
+    cutoff: LinearRamp,
+    stereo_mode: StereoMode,
 }
 
 impl Filter {
@@ -86,7 +129,8 @@ impl Filter {
         Self {
             filters: [IIRFilter::new(); 2],
             sample_rate: 0.0,
-            cutoff: 0.0,
+            cutoff: LinearRamp::new(0.0),
+            stereo_mode: StereoMode::LeftRight,
         }
     }
 
@@ -96,19 +140,74 @@ impl Filter {
     }
 
     pub fn set_cutoff(&mut self, frequency: f32) {
-        self.cutoff = frequency.clamp(10.0, 22_000.0);
+        self.cutoff.set(frequency.clamp(10.0, 22_000.0));
         self.calc_coefficients();
     }
 
+    pub fn set_stereo_mode(&mut self, stereo_mode: StereoMode) {
+        self.stereo_mode = stereo_mode;
+    }
+
     pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
-        self.filters[0].process(audio_in.left, audio_out.left);
-        self.filters[1].process(audio_in.right, audio_out.right);
+        let len = audio_in.len();
+        self.process_range(audio_in, audio_out, 0..len);
+    }
+
+    /// Processes a range of samples, smoothing any cutoff change that is in progress.
+    fn process_range(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut, range: Range<usize>) {
+        let (in_left, in_right) = (&audio_in.left[range.clone()], &audio_in.right[range.clone()]);
+        let (out_left, out_right) = (&mut audio_out.left[range.clone()], &mut audio_out.right[range]);
+
+        match self.stereo_mode {
+            StereoMode::LeftRight => {
+                out_left.copy_from_slice(in_left);
+                out_right.copy_from_slice(in_right);
+                self.filter_in_place(&mut [out_left, out_right]);
+            }
+            StereoMode::MidSide | StereoMode::SideOnly => {
+                let side_only = self.stereo_mode == StereoMode::SideOnly;
+                let mut mid = [0.0; BLOCK_SIZE];
+                let mut side = [0.0; BLOCK_SIZE];
+                let chunks_in = in_left.chunks(BLOCK_SIZE).zip(in_right.chunks(BLOCK_SIZE));
+                let chunks_out = out_left.chunks_mut(BLOCK_SIZE).zip(out_right.chunks_mut(BLOCK_SIZE));
+                for ((in_left, in_right), (out_left, out_right)) in chunks_in.zip(chunks_out) {
+                    let (mid, side) = (&mut mid[..in_left.len()], &mut side[..in_left.len()]);
+                    leftright_to_midside(in_left, in_right, mid, side);
+                    if side_only {
+                        self.filter_in_place(&mut [side]);
+                    } else {
+                        self.filter_in_place(&mut [mid, side]);
+                    }
+                    midside_to_leftright(mid, side, out_left, out_right);
+                }
+            }
+        }
+    }
+
+    /// Filters each of the buffers in place, updating the coefficients on every sample while the cutoff is changing.
+    fn filter_in_place(&mut self, buffers: &mut [&mut [f32]]) {
+        if !self.cutoff.is_ramping() {
+            for (filter, buffer) in self.filters.iter_mut().zip(buffers.iter_mut()) {
+                for sample in buffer.iter_mut() {
+                    *sample = filter.process_sample(*sample);
+                }
+            }
+            return;
+        }
+
+        for i in 0..buffers[0].len() {
+            self.cutoff.advance();
+            self.calc_coefficients();
+            for (filter, buffer) in self.filters.iter_mut().zip(buffers.iter_mut()) {
+                buffer[i] = filter.process_sample(buffer[i]);
+            }
+        }
     }
 
     fn calc_coefficients(&mut self) {
         if self.sample_rate > 0.0 {
-            self.filters[0].set_highpass(self.cutoff, self.sample_rate);
-            self.filters[1].set_highpass(self.cutoff, self.sample_rate);
+            self.filters[0].set_highpass(self.cutoff.value(), self.sample_rate);
+            self.filters[1].set_highpass(self.cutoff.value(), self.sample_rate);
         }
     }
 }
@@ -142,8 +241,145 @@ impl Processor for Filter {
         let [left, right, ..] = data.audio_out else {
             panic!("Expected at least two output audio buffers");
         };
-        let audio_out = StereoBufferMut::new(left, right);
+        let mut audio_out = StereoBufferMut::new(left, right);
+
+        // Apply each cutoff change at its sample offset
+        let mut start = 0;
+        for event in data.param_events.iter().filter(|e| e.param_id == 0) {
+            let time = (event.time as usize).clamp(start, data.samples);
+            self.process_range(audio_in, audio_out.as_mut(), start..time);
+            self.cutoff.ramp_to(event.value.clamp(10.0, 22_000.0), RAMP_SAMPLES);
+            start = time;
+        }
+        self.process_range(audio_in, audio_out, start..data.samples);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The previous direct form I implementation, which shifts a buffer of past samples on each sample.
+    fn process_reference(filter: &IIRFilter, audio_in: &[f32]) -> Vec<f32> {
+        let mut inputs = [0.0; ORDER + 1];
+        let mut outputs = [0.0; ORDER];
+        audio_in
+            .iter()
+            .map(|&s_in| {
+                inputs.copy_within(..ORDER, 1);
+                inputs[0] = s_in;
+                let ff: f32 = filter.b.iter().zip(inputs.iter()).map(|(c, s)| c * s).sum();
+                let fb: f32 = filter.a.iter().zip(outputs.iter()).map(|(c, s)| c * s).sum();
+                let s_out = ff + fb;
+                outputs.copy_within(..(ORDER - 1), 1);
+                outputs[0] = s_out;
+                s_out
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        // A sine sweep from 20 Hz to 20 kHz
+        let sample_rate = 48000.0;
+        let mut phase = 0.0f32;
+        let sweep: Vec<f32> = (0..48000)
+            .map(|i| {
+                phase += 20.0 * 1000f32.powf(i as f32 / 48000.0) / sample_rate;
+                (2.0 * PI * phase).sin()
+            })
+            .collect();
+
+        for cutoff in [100.0, 1000.0, 10_000.0] {
+            let mut lowpass = IIRFilter::new();
+            lowpass.set_resonant_lowpass(cutoff, 4.0, sample_rate);
+            let mut highpass = IIRFilter::new();
+            highpass.set_highpass(cutoff, sample_rate);
+
+            for mut filter in [lowpass, highpass] {
+                let expected = process_reference(&filter, &sweep);
+                let mut output = vec![0.0; sweep.len()];
+                filter.process(&sweep, &mut output);
+                for (a, b) in output.iter().zip(expected.iter()) {
+                    assert!((a - b).abs() < 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_side_only() {
+        // A low frequency signal panned hard left
+        let left: Vec<f32> = (0..48000)
+            .map(|i| (2.0 * PI * 50.0 * i as f32 / 48000.0).sin())
+            .collect();
+        let right = vec![0.0; left.len()];
+
+        let mut filter = Filter::new();
+        filter.set_sample_rate(48000);
+        filter.set_cutoff(500.0);
+        filter.set_stereo_mode(StereoMode::SideOnly);
+
+        let mut out_left = vec![0.0; left.len()];
+        let mut out_right = vec![0.0; left.len()];
+        filter.process(
+            StereoBuffer::new(&left, &right),
+            StereoBufferMut::new(&mut out_left, &mut out_right),
+        );
+
+        // The side channel is removed, leaving the mid channel in both output channels
+        let energy = |s: &mut dyn Iterator<Item = f32>| s.map(|s| s * s).sum::<f32>();
+        let side_in = energy(&mut left[24000..].iter().zip(right[24000..].iter()).map(|(l, r)| l - r));
+        let side_out = energy(
+            &mut out_left[24000..]
+                .iter()
+                .zip(out_right[24000..].iter())
+                .map(|(l, r)| l - r),
+        );
+        let mid_in = energy(&mut left[24000..].iter().zip(right[24000..].iter()).map(|(l, r)| l + r));
+        let mid_out = energy(
+            &mut out_left[24000..]
+                .iter()
+                .zip(out_right[24000..].iter())
+                .map(|(l, r)| l + r),
+        );
+        assert!(side_out < 0.05 * side_in);
+        assert!((mid_out / mid_in - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_mid_side() {
+        // A signal with different content in each channel
+        let left: Vec<f32> = (0..4800)
+            .map(|i| (2.0 * PI * 50.0 * i as f32 / 48000.0).sin())
+            .collect();
+        let right: Vec<f32> = (0..4800)
+            .map(|i| (2.0 * PI * 3000.0 * i as f32 / 48000.0).sin())
+            .collect();
+        let render = |stereo_mode: StereoMode| {
+            let mut filter = Filter::new();
+            filter.set_sample_rate(48000);
+            filter.set_cutoff(500.0);
+            filter.set_stereo_mode(stereo_mode);
+            let mut out_left = vec![0.0; left.len()];
+            let mut out_right = vec![0.0; left.len()];
+            filter.process(
+                StereoBuffer::new(&left, &right),
+                StereoBufferMut::new(&mut out_left, &mut out_right),
+            );
+            (out_left, out_right)
+        };
 
-        self.process(audio_in, audio_out);
+        // Filtering both the mid and side channels is the same as filtering the left and right channels
+        let (mid_side_left, mid_side_right) = render(StereoMode::MidSide);
+        let (left_right_left, left_right_right) = render(StereoMode::LeftRight);
+        assert!(mid_side_left
+            .iter()
+            .zip(left_right_left.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-4));
+        assert!(mid_side_right
+            .iter()
+            .zip(left_right_right.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-4));
     }
 }
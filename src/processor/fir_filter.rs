@@ -0,0 +1,268 @@
+use super::Processor;
+use crate::audio::{
+    buffer::{StereoBuffer, StereoBufferMut},
+    fir::FirKernel,
+};
+
+const MIN_TAPS: usize = 3;
+const MAX_TAPS: usize = 4095;
+/// Number of samples convolved at a time, which the scratch buffer is sized for.
+const BLOCK_SIZE: usize = 256;
+
+/// The response of a [`FirFilter`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FirMode {
+    Lowpass,
+    Highpass,
+}
+
+/// A linear-phase finite impulse response filter, designed using the windowed-sinc method,
+/// which convolves its input with the kernel using overlap-add.
+///
+/// This is a more expensive alternative to [`super::Filter`] which doesn't introduce any phase distortion,
+/// at the cost of delaying the signal by half the length of the kernel.
+pub struct FirFilter {
+    /// The filter kernel, which is always symmetric and of odd length.
+    kernel: FirKernel,
+    /// The tail of the convolution of previous blocks, which overlaps the next `taps - 1` output samples,
+    /// for the left and right channels.
+    overlap: [Vec<f32>; 2],
+    /// Scratch buffer holding the convolution of the current part of the block, including its tail.
+    scratch: Vec<f32>,
+    mode: FirMode,
+    sample_rate: f32,
+    cutoff: f32,
+    taps: usize,
+}
+
+impl Default for FirFilter {
+    fn default() -> Self {
+        let mut filter = Self {
+            kernel: FirKernel::new(vec![0.0]),
+            overlap: [vec![], vec![]],
+            scratch: vec![],
+            mode: FirMode::Lowpass,
+            sample_rate: 0.0,
+            cutoff: 1000.0,
+            taps: 255,
+        };
+        filter.calc_kernel();
+        filter
+    }
+}
+
+impl FirFilter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.calc_kernel();
+    }
+
+    pub fn set_cutoff(&mut self, frequency: f32) {
+        self.cutoff = frequency.clamp(10.0, 22_000.0);
+        self.calc_kernel();
+    }
+
+    pub fn set_mode(&mut self, mode: FirMode) {
+        self.mode = mode;
+        self.calc_kernel();
+    }
+
+    /// Sets the length of the filter kernel, which is rounded up to the nearest odd number.
+    /// Longer kernels give a steeper roll-off, but have more latency and use more CPU.
+    pub fn set_taps(&mut self, taps: usize) {
+        self.taps = taps.clamp(MIN_TAPS, MAX_TAPS) | 1;
+        self.calc_kernel();
+    }
+
+    /// Gets the delay introduced by the filter in samples.
+    pub fn latency_samples(&self) -> usize {
+        self.kernel.latency_samples()
+    }
+
+    pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
+        assert!(audio_in.len() == audio_out.len());
+
+        let kernel = self.kernel.coefficients();
+        let channels = [(audio_in.left, audio_out.left), (audio_in.right, audio_out.right)];
+        for (overlap, (audio_in, audio_out)) in self.overlap.iter_mut().zip(channels) {
+            for (audio_in, audio_out) in audio_in.chunks(BLOCK_SIZE).zip(audio_out.chunks_mut(BLOCK_SIZE)) {
+                convolve(kernel, overlap, &mut self.scratch, audio_in, audio_out);
+            }
+        }
+    }
+
+    fn calc_kernel(&mut self) {
+        let taps = self.taps;
+
+        // Use a normalized cutoff of a quarter of the sample rate until the sample rate is known
+        let fc = if self.sample_rate > 0.0 {
+            (self.cutoff / self.sample_rate).min(0.5)
+        } else {
+            0.25
+        };
+
+        // Only reallocate the kernel if the number of taps has changed
+        if self.kernel.taps() != taps {
+            self.kernel = FirKernel::new(vec![0.0; taps]);
+        }
+        match self.mode {
+            FirMode::Lowpass => self.kernel.set_lowpass(fc),
+            FirMode::Highpass => self.kernel.set_highpass(fc),
+        }
+
+        // Keep the tail of the previous kernel so that changing the cutoff doesn't click,
+        // resizing it only if the number of taps has changed
+        for overlap in self.overlap.iter_mut() {
+            overlap.resize(taps - 1, 0.0);
+        }
+        self.scratch.resize(taps - 1 + BLOCK_SIZE, 0.0);
+    }
+}
+
+/// Convolves `audio_in` with `kernel` using overlap-add, adding the `overlap` left by previous blocks
+/// and then replacing it with the tail of this block. `scratch` must fit the block and its tail.
+fn convolve(kernel: &[f32], overlap: &mut [f32], scratch: &mut [f32], audio_in: &[f32], audio_out: &mut [f32]) {
+    // Start from the tail of the previous blocks
+    let scratch = &mut scratch[..(audio_in.len() + overlap.len())];
+    let (tail, rest) = scratch.split_at_mut(overlap.len());
+    tail.copy_from_slice(overlap);
+    rest.fill(0.0);
+
+    // Add the response to each input sample, which spans the length of the kernel
+    for (n, &s_in) in audio_in.iter().enumerate() {
+        for (acc, &coeff) in scratch[n..(n + kernel.len())].iter_mut().zip(kernel.iter()) {
+            *acc += coeff * s_in;
+        }
+    }
+
+    // The start of the result is complete, and the rest overlaps the next block
+    audio_out.copy_from_slice(&scratch[..audio_in.len()]);
+    overlap.copy_from_slice(&scratch[audio_in.len()..]);
+}
+
+impl Processor for FirFilter {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_cutoff(value),
+            1 => self.set_taps(value as usize),
+            _ => {}
+        }
+    }
+
+    fn latency_samples(&self) -> usize {
+        self.latency_samples()
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [left, right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let audio_in = StereoBuffer::new(left, right);
+
+        let [left, right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        let audio_out = StereoBufferMut::new(left, right);
+
+        self.process(audio_in, audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processor::magnitude_at;
+
+    fn impulse_response(filter: &mut FirFilter) -> Vec<f32> {
+        let len = 2 * filter.latency_samples() + 1;
+        let mut input = vec![0.0; len];
+        input[0] = 1.0;
+        let mut left = vec![0.0; len];
+        let mut right = vec![0.0; len];
+        filter.process(
+            StereoBuffer::new(&input, &input),
+            StereoBufferMut::new(&mut left, &mut right),
+        );
+        left
+    }
+
+    #[test]
+    fn test_impulse_response_is_symmetric() {
+        let mut filter = FirFilter::new();
+        filter.set_sample_rate(48000);
+        filter.set_cutoff(2000.0);
+
+        // The longer kernel is convolved with the impulse over several parts of the block
+        for taps in [101, 1001] {
+            filter.set_taps(taps);
+            let response = impulse_response(&mut filter);
+            assert_eq!(response.len(), taps);
+            for (a, b) in response.iter().zip(response.iter().rev()) {
+                assert!((a - b).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cutoff() {
+        let mut filter = FirFilter::new();
+        filter.set_sample_rate(48000);
+        filter.set_cutoff(4000.0);
+        filter.set_taps(255);
+
+        // A windowed-sinc filter is at -6 dB at the cutoff frequency
+        let response = impulse_response(&mut filter);
+        assert!((magnitude_at(&response, 100.0, 48000.0) - 1.0).abs() < 0.01);
+        assert!((magnitude_at(&response, 4000.0, 48000.0) - 0.5).abs() < 0.02);
+        assert!(magnitude_at(&response, 8000.0, 48000.0) < 0.001);
+
+        filter.set_mode(FirMode::Highpass);
+        let response = impulse_response(&mut filter);
+        assert!(magnitude_at(&response, 100.0, 48000.0) < 0.001);
+        assert!((magnitude_at(&response, 4000.0, 48000.0) - 0.5).abs() < 0.02);
+        assert!((magnitude_at(&response, 8000.0, 48000.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_cutoff_change_keeps_state() {
+        let mut filter = FirFilter::new();
+        filter.set_sample_rate(48000);
+        filter.set_taps(101);
+
+        // Feed a constant signal through in uneven blocks, changing the cutoff partway through
+        let input = vec![1.0; 400];
+        let mut left = vec![0.0; 400];
+        let mut right = vec![0.0; 400];
+        for (i, range) in [0..150, 150..151, 151..400].into_iter().enumerate() {
+            if i == 2 {
+                filter.set_cutoff(3000.0);
+            }
+            filter.process(
+                StereoBuffer::new(&input[range.clone()], &input[range.clone()]),
+                StereoBufferMut::new(&mut left[range.clone()], &mut right[range]),
+            );
+        }
+
+        // DC passes at unity gain once the filter has filled, and the tail of the previous kernel carries the
+        // signal through the change rather than it dropping out, until the new kernel has filled
+        assert!(left[100..151].iter().all(|s| (s - 1.0).abs() < 1e-4));
+        assert!(left[151..251].iter().all(|s| (s - 1.0).abs() < 0.5));
+        assert!(left[251..].iter().all(|s| (s - 1.0).abs() < 1e-4));
+    }
+}
@@ -1,13 +1,26 @@
 use super::Processor;
-use crate::{audio::buffer::AudioBufferMut, util::scale_from_gain};
+use crate::{
+    audio::buffer::AudioBufferMut,
+    util::{scale_from_gain, LinearRamp},
+};
+use std::ops::Range;
+
+/// Time in seconds over which timed gain changes are smoothed.
+const RAMP_TIME: f32 = 0.001;
+
+/// Number of channels the gain is applied to by default.
+const DEFAULT_CHANNELS: usize = 2;
 
 pub struct Gain {
-    scale: f32,
+    /// The scaling factor of each channel.
+    scales: Vec<LinearRamp>,
+    /// Number of samples over which timed gain changes are smoothed.
+    ramp_samples: usize,
 }
 
 impl Default for Gain {
     fn default() -> Self {
-        Self { scale: 1.0 }
+        Self::with_channels(DEFAULT_CHANNELS)
     }
 }
 
@@ -16,13 +29,72 @@ impl Gain {
         Default::default()
     }
 
+    /// Creates a gain which applies to up to `num_channels` channels, rather than just a stereo pair.
+    pub fn with_channels(num_channels: usize) -> Self {
+        Self {
+            scales: vec![LinearRamp::new(1.0); num_channels],
+            ramp_samples: 0,
+        }
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.scales.len()
+    }
+
+    /// Sets the gain of all channels in dB.
     pub fn set_gain(&mut self, gain: f32) {
-        self.scale = scale_from_gain(gain);
+        for scale in self.scales.iter_mut() {
+            scale.set(scale_from_gain(gain));
+        }
+    }
+
+    /// Sets the gain of a single channel in dB. Channels beyond the number of channels are ignored.
+    pub fn set_channel_gain(&mut self, channel: usize, gain: f32) {
+        if let Some(scale) = self.scales.get_mut(channel) {
+            scale.set(scale_from_gain(gain));
+        }
     }
 
     pub fn process(&mut self, audio_in: &[&[f32]], audio_out: &mut [&mut [f32]]) {
-        for (buf_in, buf_out) in audio_in.iter().zip(audio_out.iter_mut()) {
-            buf_out.copy_scaled(*buf_in, self.scale);
+        let len = audio_out.first().map(|b| b.len()).unwrap_or(0);
+        self.process_range(audio_in, audio_out, 0..len, false);
+    }
+
+    /// Like `process`, but adds to the existing contents of the output buffers.
+    pub fn process_adding(&mut self, audio_in: &[&[f32]], audio_out: &mut [&mut [f32]]) {
+        let len = audio_out.first().map(|b| b.len()).unwrap_or(0);
+        self.process_range(audio_in, audio_out, 0..len, true);
+    }
+
+    /// Processes a range of samples, smoothing any gain change that is in progress.
+    fn process_range(
+        &mut self,
+        audio_in: &[&[f32]],
+        audio_out: &mut [&mut [f32]],
+        range: Range<usize>,
+        accumulate: bool,
+    ) {
+        let buffers = audio_in.iter().zip(audio_out.iter_mut()).zip(self.scales.iter_mut());
+        for ((buf_in, buf_out), scale) in buffers {
+            let (buf_in, buf_out) = (&buf_in[range.clone()], &mut buf_out[range.clone()]);
+            match (scale.is_ramping(), accumulate) {
+                (true, false) => buf_out.map(buf_in, |_, s| scale.advance() * s),
+                (true, true) => buf_out.combine(buf_in, |_, out, s| out + scale.advance() * s),
+                (false, false) => buf_out.copy_scaled(buf_in, scale.value()),
+                (false, true) => buf_out.add_scaled(buf_in, scale.value()),
+            }
+        }
+    }
+
+    /// Smoothly changes the gain of the channels controlled by a parameter.
+    fn ramp_parameter(&mut self, param_id: usize, gain: f32) {
+        let channels = match param_id {
+            0 => 0..self.num_channels(),
+            n if n <= self.num_channels() => (n - 1)..n,
+            _ => return,
+        };
+        for scale in &mut self.scales[channels] {
+            scale.ramp_to(scale_from_gain(gain), self.ramp_samples);
         }
     }
 }
@@ -31,23 +103,230 @@ impl Processor for Gain {
     fn description(&self) -> super::ProcessorDescription {
         super::ProcessorDescription {
             min_audio_ins: 0,
-            max_audio_ins: 2,
-            num_audio_outs: 2,
+            max_audio_ins: self.num_channels(),
+            num_audio_outs: self.num_channels(),
         }
     }
 
     fn set_sample_rate(&mut self, sample_rate: u32) {
-        // Nothing to do
+        self.ramp_samples = (RAMP_TIME * sample_rate as f32).round() as usize;
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_gain(value),
+            n => self.set_channel_gain(n - 1, value),
+        }
+    }
+
+    fn supports_accumulate(&self) -> bool {
+        true
     }
 
     fn process(&mut self, data: super::ProcessorData) {
-        let mul = self.scale;
-        let buffers = data.audio_in.iter().zip(data.audio_out.iter_mut());
-        for (buffer_in, buffer_out) in buffers {
-            let samples = buffer_in.iter().zip(buffer_out.iter_mut());
-            for (sample_in, sample_out) in samples {
-                *sample_out = mul * *sample_in;
+        // Apply each gain change at its sample offset
+        let mut start = 0;
+        let num_channels = self.num_channels();
+        for event in data.param_events.iter().filter(|e| e.param_id <= num_channels) {
+            let time = (event.time as usize).clamp(start, data.samples);
+            self.process_range(data.audio_in, data.audio_out, start..time, data.accumulate);
+            self.ramp_parameter(event.param_id, event.value);
+            start = time;
+        }
+        self.process_range(data.audio_in, data.audio_out, start..data.samples, data.accumulate);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        midi::{MidiEvent, TimedMidiEvent},
+        processor::{ParamEvent, ProcessorDataBuilder, SubBlockEvents},
+    };
+
+    #[test]
+    fn test_timed_gain_change() {
+        let mut gain = Gain::new();
+        gain.set_sample_rate(32000);
+        let input = [1.0; 128];
+        let events = [ParamEvent {
+            time: 40,
+            param_id: 0,
+            value: -20.0,
+        }];
+        let mut data = ProcessorDataBuilder::new(128)
+            .with_input(&input)
+            .with_input(&input)
+            .with_outputs(2, 0.0)
+            .with_param_events(&events);
+        data.process(&mut gain);
+
+        // The gain is unchanged until the event, then ramps to its new value
+        let left = data.output(0);
+        assert!(left[..40].iter().all(|&s| s == 1.0));
+        assert!(left[40] < 1.0 && left[40] > 0.1);
+        assert!(left[(40 + gain.ramp_samples - 1)..]
+            .iter()
+            .all(|&s| (s - 0.1).abs() < 1e-6));
+        assert_eq!(left, data.output(1));
+    }
+
+    #[test]
+    fn test_channel_gain() {
+        let mut gain = Gain::new();
+        gain.set_channel_gain(1, -20.0);
+
+        let input = [0.5; 16];
+        let mut data = ProcessorDataBuilder::new(16)
+            .with_input(&input)
+            .with_input(&input)
+            .with_outputs(2, 0.0);
+        data.process(&mut gain);
+
+        let output = data.interleaved_output();
+        assert!(output
+            .chunks_exact(2)
+            .all(|s| s[0] == 0.5 && (s[1] - 0.05).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_multichannel() {
+        let mut gain = Gain::with_channels(4);
+        assert_eq!(gain.description().max_audio_ins, 4);
+        assert_eq!(gain.description().num_audio_outs, 4);
+        gain.set_gain(-20.0);
+        gain.set_parameter(4, 0.0);
+
+        let input = [1.0; 16];
+        let data = (0..4).fold(ProcessorDataBuilder::new(16), |data, _| data.with_input(&input));
+        let mut data = data.with_outputs(4, 0.0);
+        data.process(&mut gain);
+
+        // The gain applies to every channel, and the last can be set on its own
+        for output in &data.outputs()[..3] {
+            assert!(output.iter().all(|&s| (s - 0.1).abs() < 1e-6));
+        }
+        assert!(data.output(3).iter().all(|&s| s == 1.0));
+    }
+
+    #[test]
+    fn test_accumulate() {
+        let render = |accumulate| {
+            let mut gain = Gain::new();
+            gain.set_gain(-20.0);
+            let input = [1.0; 32];
+            let mut data = ProcessorDataBuilder::new(32)
+                .with_input(&input)
+                .with_input(&input)
+                .with_outputs(2, 0.5)
+                .with_accumulate(accumulate);
+            data.process(&mut gain);
+            assert_eq!(data.output(0), data.output(1));
+            data.output(0).to_vec()
+        };
+
+        // The pre-filled output is either replaced or added to
+        assert!(render(false).iter().all(|&s| (s - 0.1).abs() < 1e-6));
+        assert!(render(true).iter().all(|&s| (s - 0.6).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_sub_blocks() {
+        let input = [1.0; 128];
+        // MIDI events at 30, 50, 80 and 120 samples, which are spread across both sub-blocks
+        let midi_in = [
+            (30, MidiEvent::Start),
+            (20, MidiEvent::Stop),
+            (30, MidiEvent::Continue),
+            (40, MidiEvent::Stop),
+        ]
+        .map(|(time, event)| TimedMidiEvent { time, event });
+        let param_events = [ParamEvent {
+            time: 40,
+            param_id: 0,
+            value: -20.0,
+        }];
+
+        // Process the whole block, and the same block split into two sub-blocks
+        let mut outputs = vec![];
+        for split in [false, true] {
+            let mut gain = Gain::new();
+            gain.set_sample_rate(32000);
+            let mut data = ProcessorDataBuilder::new(128)
+                .with_input(&input)
+                .with_outputs(1, 0.0)
+                .with_midi_in(&midi_in)
+                .with_param_events(&param_events);
+            let ranges = if split { [0..64, 64..128] } else { [0..128, 128..128] };
+            let mut events = SubBlockEvents::default();
+            let mut times = vec![];
+            data.borrow(|mut data| {
+                for range in ranges {
+                    data.sub_block(range, &mut events, |sub| {
+                        times.push(sub.midi_in.iter().map(|e| e.time).collect::<Vec<_>>());
+                        sub.midi_out.extend_from_slice(sub.midi_in);
+                        Processor::process(&mut gain, sub);
+                    });
+                }
+            });
+            if split {
+                assert_eq!(times, [vec![30, 20], vec![16, 40]]);
             }
+            assert_eq!(data.midi_out(), midi_in);
+            outputs.push(data.output(0).to_vec());
         }
+
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    #[test]
+    fn test_sub_block_output() {
+        let input = [1.0; 128];
+        let mut gain = Gain::with_channels(16);
+        gain.set_sample_rate(48000);
+        let mut data = (0..16)
+            .fold(ProcessorDataBuilder::new(128), |data, _| data.with_input(&input))
+            .with_outputs(16, 0.0);
+        let mut events = SubBlockEvents::default();
+        data.borrow(|mut data| {
+            // The first sub-block outputs an event beyond its end, which the event of the second follows
+            for (range, time) in [(0..64, 70), (64..128, 10)] {
+                data.sub_block(range, &mut events, |sub| {
+                    assert_eq!((sub.audio_in.len(), sub.audio_out.len()), (16, 16));
+                    sub.midi_out.push(TimedMidiEvent {
+                        time,
+                        event: MidiEvent::Clock,
+                    });
+                    Processor::process(&mut gain, sub);
+                });
+            }
+        });
+        assert_eq!(data.midi_out().iter().map(|e| e.time).collect::<Vec<_>>(), [70, 4]);
+        assert!((0..16).all(|ch| data.output(ch) == input));
+    }
+
+    #[test]
+    fn test_ramp_time() {
+        // Count the samples taken to reach the new gain after a change at the start of the block
+        let ramp_length = |sample_rate| {
+            let mut gain = Gain::new();
+            gain.set_sample_rate(sample_rate);
+            let input = [1.0; 512];
+            let events = [ParamEvent {
+                time: 0,
+                param_id: 0,
+                value: -20.0,
+            }];
+            let mut data = ProcessorDataBuilder::new(512)
+                .with_input(&input)
+                .with_outputs(1, 0.0)
+                .with_param_events(&events);
+            data.process(&mut gain);
+            data.output(0).iter().position(|&s| (s - 0.1).abs() < 1e-6).unwrap() + 1
+        };
+
+        assert_eq!(ramp_length(48000), 48);
+        assert_eq!(ramp_length(96000), 96);
     }
 }
@@ -1,6 +1,6 @@
 use super::Processor;
 use crate::{
-    convert::{interleave_stereo, uninterleave_stereo},
+    convert::{interleave, uninterleave},
     midi::{MidiEvent, TimedMidiEvent},
 };
 use basedrop::Handle;
@@ -44,13 +44,16 @@ impl Processor for MidiInput {
     }
 }
 
+/// Plays its inputs on an output device, with one input for each channel of the device.
 pub struct AudioOutput {
+    channels: usize,
     channel: ringbuf::Producer<f32>,
     buffer: Vec<f32>,
     notify: mpsc::Receiver<()>,
 }
 
 impl AudioOutput {
+    /// Creates an output which plays through `device`, with as many channels as `config`.
     pub fn from_cpal(
         device: Device,
         config: &StreamConfig,
@@ -74,22 +77,24 @@ impl AudioOutput {
             )
             .unwrap();
 
-        (
-            Self {
-                channel: tx,
-                buffer: vec![],
-                notify: rx2,
-            },
-            stream,
-        )
+        (Self::new(config.channels as usize, tx, rx2), stream)
+    }
+
+    fn new(channels: usize, channel: ringbuf::Producer<f32>, notify: mpsc::Receiver<()>) -> Self {
+        Self {
+            channels,
+            channel,
+            buffer: vec![],
+            notify,
+        }
     }
 }
 
 impl Processor for AudioOutput {
     fn description(&self) -> super::ProcessorDescription {
         super::ProcessorDescription {
-            min_audio_ins: 2,
-            max_audio_ins: 2,
+            min_audio_ins: self.channels,
+            max_audio_ins: self.channels,
             num_audio_outs: 0,
         }
     }
@@ -99,13 +104,11 @@ impl Processor for AudioOutput {
     }
 
     fn process(&mut self, data: super::ProcessorData) {
-        let [left, right, ..] = data.audio_in else {
-            panic!("Expected at least two input audio buffers");
-        };
+        let audio_in = &data.audio_in[..self.channels];
 
-        self.buffer.resize(left.len() + right.len(), 0.0);
+        self.buffer.resize(self.channels * data.samples, 0.0);
 
-        interleave_stereo(left, right, &mut self.buffer[..]);
+        interleave(audio_in, &mut self.buffer[..]);
 
         while self.channel.remaining() < self.buffer.len() {
             self.notify.recv().unwrap();
@@ -114,12 +117,15 @@ impl Processor for AudioOutput {
     }
 }
 
+/// Records from an input device, with one output for each channel of the device.
 pub struct AudioInput {
+    channels: usize,
     channel: ringbuf::Consumer<f32>,
     buffer: Vec<f32>,
 }
 
 impl AudioInput {
+    /// Creates an input which records from `device`, with as many channels as `config`.
     pub fn from_cpal(
         device: Device,
         config: &StreamConfig,
@@ -143,6 +149,7 @@ impl AudioInput {
 
         (
             Self {
+                channels: config.channels as usize,
                 channel: rx,
                 buffer: vec![],
             },
@@ -156,7 +163,7 @@ impl Processor for AudioInput {
         super::ProcessorDescription {
             min_audio_ins: 0,
             max_audio_ins: 0,
-            num_audio_outs: 2,
+            num_audio_outs: self.channels,
         }
     }
 
@@ -165,11 +172,9 @@ impl Processor for AudioInput {
     }
 
     fn process(&mut self, data: super::ProcessorData) {
-        let [left, right, ..] = data.audio_out else {
-            panic!("Expected at least two output audio buffers");
-        };
+        let audio_out = &mut data.audio_out[..self.channels];
 
-        self.buffer.resize(left.len() + right.len(), 0.0);
+        self.buffer.resize(self.channels * data.samples, 0.0);
 
         let read = self.channel.pop_slice(&mut self.buffer);
         if read < self.buffer.len() {
@@ -178,6 +183,42 @@ impl Processor for AudioInput {
             self.buffer[read..].fill(0.0);
         }
 
-        uninterleave_stereo(&self.buffer, left, right);
+        uninterleave(&self.buffer, audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processor::ProcessorData;
+    use basedrop::Collector;
+
+    #[test]
+    fn test_mono_output() {
+        let collector = Collector::new();
+        let (tx, mut rx) = ringbuf::RingBuffer::new(1024).split(&collector.handle());
+        let (_notify_tx, notify_rx) = mpsc::sync_channel(0);
+        let mut output = AudioOutput::new(1, tx, notify_rx);
+        assert_eq!(output.description().min_audio_ins, 1);
+        assert_eq!(output.description().max_audio_ins, 1);
+
+        let mono: Vec<_> = (0..64).map(|i| i as f32 / 64.0).collect();
+        Processor::process(
+            &mut output,
+            ProcessorData {
+                samples: 64,
+                audio_in: &[&mono],
+                audio_out: &mut [],
+                midi_in: &[],
+                midi_out: &mut vec![],
+                param_events: &[],
+                accumulate: false,
+            },
+        );
+
+        // The samples are written to the ring one per frame, rather than interleaved with a second channel
+        let mut played = [0.0; 128];
+        assert_eq!(rx.pop_slice(&mut played), 64);
+        assert_eq!(played[..64], mono[..]);
     }
 }
@@ -0,0 +1,203 @@
+use super::{IIRFilter, Processor};
+use crate::{
+    audio::buffer::{StereoBuffer, StereoBufferMut},
+    convert::{leftright_to_midside, midside_to_leftright},
+};
+
+/// Number of samples converted to mid/side at a time.
+const BLOCK_SIZE: usize = 64;
+
+/// A final stereo trim for the master output, combining balance, stereo width and bass mono.
+pub struct MasterUtility {
+    sample_rate: f32,
+    /// Balance from `-1.0` (left) to `1.0` (right).
+    balance: f32,
+    /// Scaling factor of the side channel.
+    width: f32,
+    /// The frequency below which the signal is made mono, if any.
+    mono_below: Option<f32>,
+    /// Cascaded highpass filters which remove the low end of the side channel.
+    side_filters: [IIRFilter; 2],
+}
+
+impl Default for MasterUtility {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.0,
+            balance: 0.0,
+            width: 1.0,
+            mono_below: None,
+            side_filters: [IIRFilter::new(); 2],
+        }
+    }
+}
+
+impl MasterUtility {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.calc_coefficients();
+    }
+
+    /// Sets the balance from `-1.0` (left only) to `1.0` (right only), attenuating the opposite channel.
+    pub fn set_balance(&mut self, balance: f32) {
+        self.balance = balance.clamp(-1.0, 1.0);
+    }
+
+    /// Sets the stereo width, where `0.0` is mono, `1.0` is unchanged and larger values widen the signal.
+    pub fn set_width(&mut self, width: f32) {
+        self.width = width.max(0.0);
+    }
+
+    /// Makes the signal mono below the given frequency in Hz, or `None` to keep the full stereo image.
+    pub fn set_mono_below(&mut self, frequency: Option<f32>) {
+        self.mono_below = frequency.map(|f| f.clamp(10.0, 1000.0));
+        self.calc_coefficients();
+    }
+
+    fn calc_coefficients(&mut self) {
+        if let (Some(frequency), true) = (self.mono_below, self.sample_rate > 0.0) {
+            for filter in self.side_filters.iter_mut() {
+                filter.set_highpass(frequency, self.sample_rate);
+            }
+        }
+    }
+
+    pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
+        assert!(audio_in.len() == audio_out.len());
+
+        let gain_left = (1.0 - self.balance).min(1.0);
+        let gain_right = (1.0 + self.balance).min(1.0);
+
+        let mut mid = [0.0; BLOCK_SIZE];
+        let mut side = [0.0; BLOCK_SIZE];
+        let chunks_in = audio_in.left.chunks(BLOCK_SIZE).zip(audio_in.right.chunks(BLOCK_SIZE));
+        let chunks_out = audio_out
+            .left
+            .chunks_mut(BLOCK_SIZE)
+            .zip(audio_out.right.chunks_mut(BLOCK_SIZE));
+        for ((in_left, in_right), (out_left, out_right)) in chunks_in.zip(chunks_out) {
+            let (mid, side) = (&mut mid[..in_left.len()], &mut side[..in_left.len()]);
+            leftright_to_midside(in_left, in_right, mid, side);
+
+            // Remove the low end of the side channel, then scale it to adjust the width
+            for sample in side.iter_mut() {
+                if self.mono_below.is_some() {
+                    *sample = self
+                        .side_filters
+                        .iter_mut()
+                        .fold(*sample, |s, filter| filter.process_sample(s));
+                }
+                *sample *= self.width;
+            }
+
+            midside_to_leftright(mid, side, out_left, out_right);
+            out_left.iter_mut().for_each(|s| *s *= gain_left);
+            out_right.iter_mut().for_each(|s| *s *= gain_right);
+        }
+    }
+}
+
+impl Processor for MasterUtility {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_balance(value),
+            1 => self.set_width(value),
+            2 => self.set_mono_below(Some(value).filter(|&f| f > 0.0)),
+            _ => {}
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [left, right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let audio_in = StereoBuffer::new(left, right);
+
+        let [left, right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        let audio_out = StereoBufferMut::new(left, right);
+
+        self.process(audio_in, audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::consts::PI;
+
+    /// Processes a sine wave which is only present in the left channel,
+    /// and returns the RMS of the difference between the output channels once the filters have settled.
+    fn channel_difference(utility: &mut MasterUtility, frequency: f32) -> f32 {
+        let left: Vec<_> = (0..48000)
+            .map(|i| (2.0 * PI * frequency * i as f32 / 48000.0).sin())
+            .collect();
+        let right = vec![0.0; 48000];
+        let mut out_left = vec![0.0; 48000];
+        let mut out_right = vec![0.0; 48000];
+        utility.process(
+            StereoBuffer::new(&left, &right),
+            StereoBufferMut::new(&mut out_left, &mut out_right),
+        );
+
+        let diff = out_left[24000..].iter().zip(out_right[24000..].iter());
+        (diff.map(|(l, r)| (l - r).powi(2)).sum::<f32>() / 24000.0).sqrt()
+    }
+
+    #[test]
+    fn test_bass_mono() {
+        let mut utility = MasterUtility::new();
+        utility.set_sample_rate(48000);
+        utility.set_mono_below(Some(120.0));
+
+        // Low frequencies are the same in both channels
+        assert!(channel_difference(&mut utility, 30.0) < 0.01);
+
+        // High frequencies keep their stereo image, where the difference has the RMS of the input sine
+        let rms = 0.5f32.sqrt();
+        assert!((channel_difference(&mut utility, 5000.0) - rms).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_balance_and_width() {
+        let mut utility = MasterUtility::new();
+        utility.set_sample_rate(48000);
+        let left = [1.0; 16];
+        let right = [0.5; 16];
+        let mut out_left = [0.0; 16];
+        let mut out_right = [0.0; 16];
+
+        utility.set_balance(0.5);
+        utility.process(
+            StereoBuffer::new(&left, &right),
+            StereoBufferMut::new(&mut out_left, &mut out_right),
+        );
+        assert!(out_left.iter().all(|&s| s == 0.5));
+        assert!(out_right.iter().all(|&s| s == 0.5));
+
+        utility.set_balance(0.0);
+        utility.set_width(0.0);
+        utility.process(
+            StereoBuffer::new(&left, &right),
+            StereoBufferMut::new(&mut out_left, &mut out_right),
+        );
+        assert!(out_left.iter().chain(out_right.iter()).all(|&s| s == 0.75));
+    }
+}
@@ -0,0 +1,192 @@
+use super::Processor;
+use crate::midi::{MidiEvent, TimedMidiEvent};
+
+/// Number of MIDI clock pulses per quarter note.
+const PULSES_PER_BEAT: f64 = 24.0;
+
+/// A transport source which emits MIDI clock pulses at a given tempo, along with start, stop and continue messages.
+pub struct MidiClock {
+    sample_rate: f64,
+    /// Tempo in beats per minute.
+    bpm: f64,
+    running: bool,
+    /// Number of samples from the start of the next block until the next clock pulse.
+    next_pulse: f64,
+    /// Transport message to send at the start of the next block.
+    pending: Option<MidiEvent>,
+}
+
+impl Default for MidiClock {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.0,
+            bpm: 120.0,
+            running: false,
+            next_pulse: 0.0,
+            pending: None,
+        }
+    }
+}
+
+impl MidiClock {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the tempo in beats per minute.
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.bpm = bpm.max(1.0) as f64;
+    }
+
+    /// Starts the transport from the beginning, sending a start message followed by a clock pulse.
+    pub fn start(&mut self) {
+        self.running = true;
+        self.next_pulse = 0.0;
+        self.pending = Some(MidiEvent::Start);
+    }
+
+    /// Stops the transport, sending a stop message.
+    pub fn stop(&mut self) {
+        if self.running {
+            self.running = false;
+            self.pending = Some(MidiEvent::Stop);
+        }
+    }
+
+    /// Resumes the transport from where it was stopped, sending a continue message.
+    pub fn resume(&mut self) {
+        if !self.running {
+            self.running = true;
+            self.next_pulse = 0.0;
+            self.pending = Some(MidiEvent::Continue);
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    fn samples_per_pulse(&self) -> f64 {
+        self.sample_rate * 60.0 / (self.bpm * PULSES_PER_BEAT)
+    }
+
+    pub fn process(&mut self, samples: usize, midi_out: &mut Vec<TimedMidiEvent>) {
+        let mut last_time = 0;
+        if let Some(event) = self.pending.take() {
+            midi_out.push(TimedMidiEvent { time: 0, event });
+        }
+
+        if !self.running {
+            return;
+        }
+
+        // No pulses can be sent until the sample rate is known
+        let samples_per_pulse = self.samples_per_pulse();
+        if samples_per_pulse <= 0.0 {
+            return;
+        }
+        while self.next_pulse < samples as f64 {
+            let time = self.next_pulse as u32;
+            midi_out.push(TimedMidiEvent {
+                time: time - last_time,
+                event: MidiEvent::Clock,
+            });
+            last_time = time;
+            self.next_pulse += samples_per_pulse;
+        }
+        self.next_pulse -= samples as f64;
+    }
+}
+
+impl Processor for MidiClock {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 0,
+            max_audio_ins: 0,
+            num_audio_outs: 0,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f64;
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        if param_id == 0 {
+            self.set_tempo(value)
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        self.process(data.samples, data.midi_out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clock_pulses() {
+        let mut clock = MidiClock::new();
+        clock.set_sample_rate(48000);
+        clock.set_tempo(120.0);
+        clock.start();
+
+        // Collect the absolute times of each event over two beats
+        let mut events = vec![];
+        let mut block_start = 0;
+        while block_start < 48000 {
+            let mut midi_out = vec![];
+            clock.process(512, &mut midi_out);
+            let mut time = block_start;
+            for event in midi_out {
+                time += event.time;
+                events.push((time, event.event));
+            }
+            block_start += 512;
+        }
+
+        assert_eq!(events[0], (0, MidiEvent::Start));
+        assert!(events[1..].iter().all(|&(_, event)| event == MidiEvent::Clock));
+
+        // At 120 BPM a beat lasts 24000 samples, so pulses are 1000 samples apart
+        let times: Vec<_> = events[1..]
+            .iter()
+            .map(|&(time, _)| time)
+            .filter(|&t| t < 48000)
+            .collect();
+        assert_eq!(times, (0..48).map(|k| k * 1000).collect::<Vec<_>>());
+
+        // Stopping sends a stop message and no more pulses
+        clock.stop();
+        let mut midi_out = vec![];
+        clock.process(4096, &mut midi_out);
+        assert_eq!(
+            midi_out,
+            vec![TimedMidiEvent {
+                time: 0,
+                event: MidiEvent::Stop
+            }]
+        );
+    }
+
+    #[test]
+    fn test_no_sample_rate() {
+        let mut clock = MidiClock::new();
+        clock.start();
+
+        // Only the start message is sent before the sample rate is set
+        let mut midi_out = vec![];
+        clock.process(512, &mut midi_out);
+        assert_eq!(midi_out.len(), 1);
+        assert_eq!(midi_out[0].event, MidiEvent::Start);
+
+        // Pulses follow once it is set
+        midi_out.clear();
+        clock.set_sample_rate(48000);
+        clock.process(512, &mut midi_out);
+        assert_eq!(midi_out.len(), 1);
+        assert_eq!(midi_out[0].event, MidiEvent::Clock);
+    }
+}
@@ -0,0 +1,287 @@
+use super::{Processor, ProcessorData};
+use crate::{
+    audio::ring::RingBuffer,
+    util::{scale_from_gain, LinearRamp},
+};
+
+/// Maximum number of channels the mix can be applied to.
+const MAX_CHANNELS: usize = 2;
+
+/// Length in seconds of the window over which the loudness of the dry and wet signals is measured.
+const LOUDNESS_WINDOW: f32 = 0.4;
+
+/// The largest change in dB the loudness matching applies to the wet signal.
+const MAX_MATCH_GAIN: f32 = 24.0;
+
+/// Number of samples per block that the buffers are sized for when the sample rate is set.
+const RESERVED_BLOCK_SIZE: usize = 4096;
+
+/// Wraps a processor to blend its output with its input, for effects that don't provide their own dry/wet control.
+///
+/// The input is delayed by the inner processor's latency so that the dry and wet signals stay aligned.
+/// The wet output is rendered into a scratch buffer which, like the dry delay lines, is sized for blocks of up to
+/// 4096 samples when the sample rate is set, and only grown if a larger block is processed.
+/// Growing the delay lines keeps their contents, so that the dry signal continues without a glitch.
+pub struct Mix<P: Processor> {
+    inner: P,
+    /// Proportion of the wet signal in the output, between `0.0` and `1.0`.
+    mix: f32,
+    /// Scratch buffer holding the output of the inner processor for each channel.
+    buffer: Vec<f32>,
+    /// Delay lines which align the dry signal of each channel with the wet signal.
+    delays: [RingBuffer; MAX_CHANNELS],
+    sample_rate: f32,
+    /// Whether the wet signal is scaled to match the loudness of the dry signal.
+    loudness_match: bool,
+    /// The moving averages of the squared dry and wet samples.
+    mean_squares: [f32; 2],
+    /// The gain applied to the wet signal to match its loudness to the dry signal.
+    match_gain: LinearRamp,
+}
+
+impl<P: Processor> Mix<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            mix: 1.0,
+            buffer: vec![],
+            delays: [RingBuffer::new(1), RingBuffer::new(1)],
+            sample_rate: 0.0,
+            loudness_match: false,
+            mean_squares: [0.0; 2],
+            match_gain: LinearRamp::new(1.0),
+        }
+    }
+
+    /// Sets the proportion of the wet signal in the output, from `0.0` (dry) to `1.0` (wet).
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Scales the wet signal so that its loudness matches the dry signal, measured by their RMS levels.
+    /// This allows the effect to be compared with bypassing it, without the louder one sounding better.
+    pub fn set_loudness_match(&mut self, loudness_match: bool) {
+        self.loudness_match = loudness_match;
+        self.mean_squares = [0.0; 2];
+        self.match_gain.set(1.0);
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.inner
+    }
+
+    /// Measures the loudness of the dry and wet signals, and ramps the gain of the wet signal over the block
+    /// towards the gain which matches their loudness.
+    fn update_match_gain(&mut self, audio_dry: &[&mut [f32]], len: usize) {
+        let coeff = (-1.0 / (LOUDNESS_WINDOW * self.sample_rate)).exp();
+        let scale = (audio_dry.len() as f32).recip();
+        let [mut dry, mut wet] = self.mean_squares;
+        for i in 0..len {
+            let dry_power = scale * audio_dry.iter().map(|b| b[i] * b[i]).sum::<f32>();
+            let wet_power = scale
+                * (0..audio_dry.len())
+                    .map(|c| self.buffer[c * len + i].powi(2))
+                    .sum::<f32>();
+            dry = dry_power + coeff * (dry - dry_power);
+            wet = wet_power + coeff * (wet - wet_power);
+        }
+        self.mean_squares = [dry, wet];
+
+        let max_gain = scale_from_gain(MAX_MATCH_GAIN);
+        let target = if wet > 1e-12 {
+            (dry / wet).sqrt().clamp(max_gain.recip(), max_gain)
+        } else {
+            1.0
+        };
+        self.match_gain.ramp_to(target, len);
+    }
+}
+
+impl<P: Processor> Processor for Mix<P> {
+    fn description(&self) -> super::ProcessorDescription {
+        self.inner.description()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.inner.set_sample_rate(sample_rate);
+        self.buffer.resize(MAX_CHANNELS * RESERVED_BLOCK_SIZE, 0.0);
+        let latency = self.inner.latency_samples();
+        for delay in self.delays.iter_mut() {
+            delay.grow(latency + RESERVED_BLOCK_SIZE + 1);
+        }
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        self.inner.set_parameter(param_id, value);
+    }
+
+    fn latency_samples(&self) -> usize {
+        self.inner.latency_samples()
+    }
+
+    fn tail_samples(&self) -> usize {
+        self.inner.tail_samples()
+    }
+
+    fn process(&mut self, data: ProcessorData) {
+        let len = data.samples;
+        if len == 0 {
+            return;
+        }
+        let num_outputs = data.audio_out.len().min(MAX_CHANNELS);
+
+        // Render the wet signal into the scratch buffer
+        if self.buffer.len() < MAX_CHANNELS * len {
+            self.buffer.resize(MAX_CHANNELS * len, 0.0);
+        }
+        let (wet_left, wet_right) = self.buffer[..(MAX_CHANNELS * len)].split_at_mut(len);
+        self.inner.process(ProcessorData {
+            midi_in: data.midi_in,
+            midi_out: data.midi_out,
+            samples: len,
+            audio_in: data.audio_in,
+            audio_out: &mut [wet_left, wet_right][..num_outputs],
+            param_events: data.param_events,
+            accumulate: false,
+        });
+
+        let latency = self.inner.latency_samples();
+        let outputs = data.audio_out.iter_mut().zip(self.delays.iter_mut()).enumerate();
+        for (channel, (buf_out, delay)) in outputs {
+            // Mono inputs are mixed into both channels
+            let buf_out = &mut buf_out[..len];
+            match data.audio_in.get(channel).or(data.audio_in.first()) {
+                Some(buf_in) => buf_out.copy_from_slice(&buf_in[..len]),
+                None => buf_out.fill(0.0),
+            }
+
+            // Delay the dry signal by the latency of the inner processor
+            delay.grow(latency + len + 1);
+            delay.write(buf_out);
+            delay.seek(latency + len);
+            delay.read(buf_out, true);
+        }
+
+        if self.loudness_match {
+            self.update_match_gain(&data.audio_out[..num_outputs], len);
+        }
+
+        // Each channel applies the same gain ramp, which is then advanced past the block
+        let (dry, wet) = (1.0 - self.mix, self.mix);
+        let mut match_gain = self.match_gain;
+        for (channel, buf_out) in data.audio_out.iter_mut().enumerate().take(num_outputs) {
+            let buf_wet = &self.buffer[(channel * len)..((channel + 1) * len)];
+            match_gain = self.match_gain;
+            for (out, &s) in buf_out[..len].iter_mut().zip(buf_wet.iter()) {
+                *out = dry * *out + wet * match_gain.advance() * s;
+            }
+        }
+        self.match_gain = match_gain;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processor::ProcessorDescription;
+
+    /// A processor which doubles its input and delays it by a fixed number of samples.
+    struct TestEffect {
+        history: Vec<f32>,
+    }
+
+    const LATENCY: usize = 5;
+
+    impl Processor for TestEffect {
+        fn description(&self) -> ProcessorDescription {
+            ProcessorDescription {
+                min_audio_ins: 1,
+                max_audio_ins: 1,
+                num_audio_outs: 1,
+            }
+        }
+
+        fn latency_samples(&self) -> usize {
+            LATENCY
+        }
+
+        fn process(&mut self, data: ProcessorData) {
+            self.history.extend(data.audio_in[0].iter().map(|s| 2.0 * s));
+            let start = self.history.len() - data.samples - LATENCY;
+            data.audio_out[0].copy_from_slice(&self.history[start..(start + data.samples)]);
+        }
+    }
+
+    fn process(mix: &mut Mix<TestEffect>, input: &[f32]) -> Vec<f32> {
+        let mut output = vec![0.0; input.len()];
+        mix.process(ProcessorData {
+            midi_in: &[],
+            midi_out: &mut vec![],
+            samples: input.len(),
+            audio_in: &[input],
+            audio_out: &mut [&mut output],
+            param_events: &[],
+            accumulate: false,
+        });
+        output
+    }
+
+    #[test]
+    fn test_mix() {
+        let input: Vec<f32> = (0..64).map(|i| i as f32).collect();
+        let delayed = |i: usize| i.checked_sub(LATENCY).map(|i| i as f32).unwrap_or(0.0);
+        let effect = || TestEffect {
+            history: vec![0.0; LATENCY],
+        };
+
+        // A fully dry mix gives the input, delayed to match the latency of the effect
+        let mut mix = Mix::new(effect());
+        mix.set_mix(0.0);
+        let output: Vec<_> = input.chunks(16).flat_map(|chunk| process(&mut mix, chunk)).collect();
+        assert!(output.iter().enumerate().all(|(i, &s)| s == delayed(i)));
+
+        // A fully wet mix gives the output of the effect
+        let mut mix = Mix::new(effect());
+        mix.set_mix(1.0);
+        let output: Vec<_> = input.chunks(16).flat_map(|chunk| process(&mut mix, chunk)).collect();
+        assert!(output.iter().enumerate().all(|(i, &s)| s == 2.0 * delayed(i)));
+    }
+
+    #[test]
+    fn test_loudness_match() {
+        let input: Vec<f32> = (0..48000).map(|i| (i as f32 * 0.05).sin()).collect();
+        let mut mix = Mix::new(TestEffect {
+            history: vec![0.0; LATENCY],
+        });
+        mix.set_sample_rate(48000);
+        mix.set_mix(1.0);
+        mix.set_loudness_match(true);
+        let output: Vec<_> = input.chunks(480).flat_map(|chunk| process(&mut mix, chunk)).collect();
+
+        // The effect is 6dB louder than the input, but once the levels have been measured it is matched to the input
+        let peak = |s: &[f32]| s.iter().fold(0.0f32, |max, s| s.abs().max(max));
+        assert!((peak(&output[24000..]) / peak(&input[24000..]) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_growing_block() {
+        let input: Vec<f32> = (0..6000).map(|i| i as f32).collect();
+        let mut mix = Mix::new(TestEffect {
+            history: vec![0.0; LATENCY],
+        });
+        mix.set_sample_rate(48000);
+        mix.set_mix(0.0);
+
+        // A block larger than the buffers were sized for continues the dry signal from the previous blocks
+        let mut output = process(&mut mix, &input[..16]);
+        output.extend(process(&mut mix, &input[16..5016]));
+        output.extend(process(&mut mix, &input[5016..]));
+        let delayed = |i: usize| i.checked_sub(LATENCY).map(|i| i as f32).unwrap_or(0.0);
+        assert!(output.iter().enumerate().all(|(i, &s)| s == delayed(i)));
+    }
+}
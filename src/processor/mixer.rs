@@ -1,7 +1,7 @@
 use super::Processor;
 use crate::{
     audio::buffer::{AudioBufferMut, StereoBufferMut},
-    util::scale_from_gain,
+    util::{scale_from_gain, LinearRamp},
 };
 
 const MAX_INPUTS: usize = 128;
@@ -11,6 +11,15 @@ pub struct Mixer {
     gains: [f32; MAX_INPUTS],
     /// The pan for each input channel, from -1.0 for left and 1.0 for right
     pans: [f32; MAX_INPUTS],
+    /// The scaling factors of the left and right channels of each input, which move smoothly towards
+    /// those given by the gain and pan
+    scales: [[LinearRamp; 2]; MAX_INPUTS],
+    /// Whether the sum is divided by the number of inputs
+    auto_gain: bool,
+    /// The sample rate in `Hz`
+    sample_rate: f32,
+    /// Time in milliseconds over which gain and pan changes are smoothed
+    smoothing_ms: f32,
 }
 
 impl Default for Mixer {
@@ -18,6 +27,10 @@ impl Default for Mixer {
         Self {
             gains: [1.0; MAX_INPUTS],
             pans: [0.0; MAX_INPUTS],
+            scales: [[LinearRamp::new(1.0); 2]; MAX_INPUTS],
+            auto_gain: false,
+            sample_rate: 0.0,
+            smoothing_ms: 0.0,
         }
     }
 }
@@ -27,21 +40,58 @@ impl Mixer {
         Default::default()
     }
 
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+    }
+
     pub fn set_gain(&mut self, input_idx: usize, gain: f32) {
         self.gains[input_idx] = scale_from_gain(gain);
+        self.update_scales(input_idx);
     }
 
     pub fn set_pan(&mut self, input_idx: usize, pan: f32) {
         self.pans[input_idx] = pan.clamp(-1.0, 1.0);
+        self.update_scales(input_idx);
+    }
+
+    /// Sets the time in milliseconds over which gain and pan changes are smoothed, such as to avoid zipper noise
+    /// while a fader moves. Defaults to zero, which applies changes immediately.
+    pub fn set_smoothing_ms(&mut self, smoothing_ms: f32) {
+        self.smoothing_ms = smoothing_ms.max(0.0);
+    }
+
+    /// Starts moving the scaling factors of an input towards those of its gain and pan.
+    fn update_scales(&mut self, input_idx: usize) {
+        let samples = (0.001 * self.smoothing_ms * self.sample_rate).round() as usize;
+        let (gain, pan) = (self.gains[input_idx], self.pans[input_idx]);
+        let [left, right] = &mut self.scales[input_idx];
+        left.ramp_to(gain * (1.0 - pan), samples);
+        right.ramp_to(gain * (1.0 + pan), samples);
+    }
+
+    /// Sets whether the sum is divided by the number of inputs, so that mixing many inputs doesn't clip.
+    /// Inputs at unity gain and centre pan then stay within the range of the loudest of them.
+    pub fn set_auto_gain(&mut self, auto_gain: bool) {
+        self.auto_gain = auto_gain;
     }
 
     pub fn process(&mut self, audio_in: &[&[f32]], mut audio_out: StereoBufferMut) {
         audio_out.clear();
-        for (idx, buffers) in audio_in.chunks_exact(2).enumerate() {
-            let gain = self.gains[idx];
-            let pan = self.pans[idx];
-            audio_out.left.add_scaled(buffers[0], gain * (1.0 - pan));
-            audio_out.right.add_scaled(buffers[1], gain * (1.0 + pan));
+        let num_inputs = audio_in.len() / 2;
+        let auto_gain = if self.auto_gain && num_inputs > 0 {
+            (num_inputs as f32).recip()
+        } else {
+            1.0
+        };
+        let mut outputs = [&mut *audio_out.left, &mut *audio_out.right];
+        for (buffers, scales) in audio_in.chunks_exact(2).zip(self.scales.iter_mut()) {
+            for ((buf_out, &buf_in), scale) in outputs.iter_mut().zip(buffers).zip(scales.iter_mut()) {
+                if scale.is_ramping() {
+                    buf_out.combine(buf_in, |_, out, s| out + auto_gain * scale.advance() * s);
+                } else {
+                    buf_out.add_scaled(buf_in, auto_gain * scale.value());
+                }
+            }
         }
     }
 }
@@ -55,11 +105,15 @@ impl Processor for Mixer {
         }
     }
 
-    fn set_sample_rate(&mut self, _sample_rate: u32) {
-        // Nothing to do
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
     }
 
     fn set_parameter(&mut self, param_id: usize, value: f32) {
+        if param_id == 2 * MAX_INPUTS {
+            self.set_auto_gain(value >= 0.5);
+            return;
+        }
         let channel = param_id / 2;
         if channel < MAX_INPUTS {
             match param_id % 2 {
@@ -83,3 +137,52 @@ impl Processor for Mixer {
         self.process(data.audio_in, audio_out);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_auto_gain() {
+        let input = [1.0; 64];
+        let audio_in = [&input[..]; 8];
+        let mut left = [0.0; 64];
+        let mut right = [0.0; 64];
+        let mut mixer = Mixer::new();
+
+        // The sum of four unity inputs clips
+        mixer.process(&audio_in, StereoBufferMut::new(&mut left, &mut right));
+        assert!(left.iter().chain(right.iter()).all(|&s| s == 4.0));
+
+        // With auto gain, it stays within the range of the inputs
+        mixer.set_auto_gain(true);
+        mixer.process(&audio_in, StereoBufferMut::new(&mut left, &mut right));
+        assert!(left.iter().chain(right.iter()).all(|&s| (s - 1.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_smoothing() {
+        let input = [1.0; 64];
+        let mut left = [0.0; 64];
+        let mut right = [0.0; 64];
+        let mut mixer = Mixer::new();
+        mixer.set_sample_rate(32000);
+        mixer.set_smoothing_ms(1.0);
+
+        // The gain moves gradually over 32 samples rather than stepping
+        mixer.set_gain(0, -20.0);
+        mixer.process(&[&input, &input], StereoBufferMut::new(&mut left, &mut right));
+        assert!(left[..32].windows(2).all(|w| w[1] < w[0]));
+        assert!(left[0] > 0.9 && left[0] < 1.0);
+        assert!(left[31..].iter().all(|&s| (s - 0.1).abs() < 1e-6));
+        assert_eq!(left, right);
+
+        // So does the pan
+        mixer.set_pan(0, 1.0);
+        mixer.process(&[&input, &input], StereoBufferMut::new(&mut left, &mut right));
+        assert!(left[..32].windows(2).all(|w| w[1] < w[0]));
+        assert!(right[..32].windows(2).all(|w| w[1] > w[0]));
+        assert!(left[31..].iter().all(|&s| s.abs() < 1e-6));
+        assert!(right[31..].iter().all(|&s| (s - 0.2).abs() < 1e-6));
+    }
+}
@@ -0,0 +1,378 @@
+use super::{ParamEvent, Processor, ProcessorData, SubBlockEvents};
+use crate::{audio::fir::FirKernel, midi::TimedMidiEvent};
+
+/// Maximum number of channels which are oversampled.
+const MAX_CHANNELS: usize = 2;
+/// Number of taps of the resampling filters for each sample at the original rate.
+const TAPS_PER_SAMPLE: usize = 32;
+/// Cutoff of the resampling filters, as a proportion of the original sample rate.
+const CUTOFF: f32 = 0.45;
+/// Number of samples at the original rate processed at a time, which the scratch buffers are sized for.
+const BLOCK_SIZE: usize = 256;
+
+/// Wraps a processor to run it at a multiple of the sample rate, reducing the aliasing of nonlinear effects.
+///
+/// The input is upsampled and the output downsampled using polyphase windowed-sinc filters,
+/// which delay the signal by `TAPS_PER_SAMPLE` samples at the original rate.
+pub struct Oversampled<P: Processor> {
+    inner: P,
+    /// Ratio of the inner sample rate to the outer sample rate.
+    factor: usize,
+    /// The lowpass kernel shared by the upsampler and downsampler, padded to a multiple of `factor`.
+    kernel: Vec<f32>,
+    /// The most recent input samples of each channel, needed by the upsampler.
+    up_history: [Vec<f32>; MAX_CHANNELS],
+    /// The most recent output samples of the inner processor for each channel, needed by the downsampler.
+    down_history: [Vec<f32>; MAX_CHANNELS],
+    /// Scratch buffer holding the upsampled inputs followed by the outputs of the inner processor.
+    buffer: Vec<f32>,
+    /// Scratch buffer holding the history followed by a block of samples.
+    scratch: Vec<f32>,
+    /// The MIDI and parameter events with their times scaled to the inner sample rate.
+    midi_in: Vec<TimedMidiEvent>,
+    param_events: Vec<ParamEvent>,
+    /// Storage for the events of each part of the block processed at a time.
+    events: SubBlockEvents,
+}
+
+impl<P: Processor> Oversampled<P> {
+    /// Wraps a processor to run at `factor` times the sample rate, typically 2 or 4.
+    pub fn new(inner: P, factor: usize) -> Self {
+        let factor = factor.clamp(1, 8);
+        let taps = TAPS_PER_SAMPLE * factor + 1;
+        let mut kernel = FirKernel::lowpass(taps, CUTOFF / factor as f32).coefficients().to_vec();
+        kernel.resize(taps.div_ceil(factor) * factor, 0.0);
+
+        let up_history = vec![0.0; kernel.len() / factor - 1];
+        let down_history = vec![0.0; kernel.len() - 1];
+        Self {
+            inner,
+            factor,
+            up_history: [up_history.clone(), up_history],
+            down_history: [down_history.clone(), down_history],
+            buffer: vec![0.0; 2 * MAX_CHANNELS * factor * BLOCK_SIZE],
+            scratch: Vec::with_capacity(kernel.len() - 1 + factor * BLOCK_SIZE),
+            kernel,
+            midi_in: vec![],
+            param_events: vec![],
+            events: SubBlockEvents::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.inner
+    }
+}
+
+impl<P: Processor> Processor for Oversampled<P> {
+    fn description(&self) -> super::ProcessorDescription {
+        self.inner.description()
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.inner.set_sample_rate(sample_rate * self.factor as u32);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        self.inner.set_parameter(param_id, value);
+    }
+
+    fn latency_samples(&self) -> usize {
+        let inner = (self.inner.latency_samples() + self.factor / 2) / self.factor;
+        TAPS_PER_SAMPLE + inner
+    }
+
+    fn tail_samples(&self) -> usize {
+        // The resampling filters keep ringing for their combined length after the inner processor falls silent
+        match self.inner.tail_samples() {
+            usize::MAX => usize::MAX,
+            inner => 2 * TAPS_PER_SAMPLE + inner.div_ceil(self.factor),
+        }
+    }
+
+    fn process(&mut self, mut data: ProcessorData) {
+        let mut events = std::mem::take(&mut self.events);
+        let mut start = 0;
+        while start < data.samples {
+            let end = (start + BLOCK_SIZE).min(data.samples);
+            data.sub_block(start..end, &mut events, |sub| self.process_block(sub));
+            start = end;
+        }
+        self.events = events;
+    }
+}
+
+impl<P: Processor> Oversampled<P> {
+    /// Processes a block of at most `BLOCK_SIZE` samples.
+    fn process_block(&mut self, data: ProcessorData) {
+        let len = data.samples;
+        let factor = self.factor;
+        let num_inputs = data.audio_in.len().min(MAX_CHANNELS);
+        let num_outputs = data.audio_out.len().min(MAX_CHANNELS);
+
+        // Upsample each of the inputs
+        let buffer = &mut self.buffer[..(2 * MAX_CHANNELS * factor * len)];
+        let (buf_in, buf_out) = buffer.split_at_mut(MAX_CHANNELS * factor * len);
+        let mut inputs = buf_in.chunks_exact_mut(factor * len);
+        for ((input, history), upsampled) in data.audio_in.iter().zip(&mut self.up_history).zip(&mut inputs) {
+            upsample(
+                &self.kernel,
+                factor,
+                history,
+                &mut self.scratch,
+                &input[..len],
+                upsampled,
+            );
+        }
+
+        // Scale the times of events to the inner sample rate
+        self.midi_in.clear();
+        self.midi_in.extend(data.midi_in.iter().map(|e| TimedMidiEvent {
+            time: e.time * factor as u32,
+            event: e.event,
+        }));
+        self.param_events.clear();
+        self.param_events.extend(data.param_events.iter().map(|e| ParamEvent {
+            time: e.time * factor as u32,
+            ..*e
+        }));
+        let midi_start = data.midi_out.len();
+
+        // Run the inner processor at the higher sample rate
+        let (in_left, in_right) = buf_in.split_at(factor * len);
+        let (out_left, out_right) = buf_out.split_at_mut(factor * len);
+        self.inner.process(ProcessorData {
+            midi_in: &self.midi_in,
+            midi_out: data.midi_out,
+            samples: factor * len,
+            audio_in: &[in_left, in_right][..num_inputs],
+            audio_out: &mut [out_left, out_right][..num_outputs],
+            param_events: &self.param_events,
+            accumulate: false,
+        });
+        // Scale the absolute times of the output events, as scaling each delta would accumulate rounding errors
+        let (mut inner_time, mut outer_time) = (0, 0);
+        for event in &mut data.midi_out[midi_start..] {
+            inner_time += event.time;
+            let time = inner_time / factor as u32;
+            event.time = time - outer_time;
+            outer_time = time;
+        }
+
+        // Downsample each of the outputs
+        let outputs = buf_out.chunks_exact(factor * len);
+        for ((output, history), upsampled) in data.audio_out.iter_mut().zip(&mut self.down_history).zip(outputs) {
+            downsample(
+                &self.kernel,
+                factor,
+                history,
+                &mut self.scratch,
+                upsampled,
+                &mut output[..len],
+            );
+        }
+    }
+}
+
+/// Upsamples `input` by inserting zeros between samples and lowpass filtering the result,
+/// where only the non-zero input samples are multiplied with each phase of the kernel.
+fn upsample(
+    kernel: &[f32],
+    factor: usize,
+    history: &mut Vec<f32>,
+    scratch: &mut Vec<f32>,
+    input: &[f32],
+    output: &mut [f32],
+) {
+    let taps = kernel.len() / factor;
+    scratch.clear();
+    scratch.extend_from_slice(history);
+    scratch.extend_from_slice(input);
+
+    for (n, out) in output.chunks_exact_mut(factor).enumerate() {
+        let window = &scratch[n..(n + taps)];
+        for (phase, out) in out.iter_mut().enumerate() {
+            let coeffs = kernel[phase..].iter().step_by(factor);
+            // Compensate for the energy lost to the inserted zeros
+            *out = factor as f32 * coeffs.zip(window.iter().rev()).map(|(c, s)| c * s).sum::<f32>();
+        }
+    }
+
+    history.clear();
+    history.extend_from_slice(&scratch[(scratch.len() - (taps - 1))..]);
+}
+
+/// Lowpass filters `input` and keeps every `factor`th sample.
+fn downsample(
+    kernel: &[f32],
+    factor: usize,
+    history: &mut Vec<f32>,
+    scratch: &mut Vec<f32>,
+    input: &[f32],
+    output: &mut [f32],
+) {
+    let taps = kernel.len();
+    scratch.clear();
+    scratch.extend_from_slice(history);
+    scratch.extend_from_slice(input);
+
+    for (n, out) in output.iter_mut().enumerate() {
+        let window = &scratch[(n * factor)..(n * factor + taps)];
+        *out = kernel.iter().zip(window.iter().rev()).map(|(c, s)| c * s).sum();
+    }
+
+    history.clear();
+    history.extend_from_slice(&scratch[(scratch.len() - (taps - 1))..]);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        midi::MidiEvent,
+        processor::{magnitude_at, ProcessorDescription},
+    };
+    use std::f32::consts::PI;
+
+    /// A processor which hard clips its input.
+    struct HardClip;
+
+    impl Processor for HardClip {
+        fn description(&self) -> ProcessorDescription {
+            ProcessorDescription {
+                min_audio_ins: 1,
+                max_audio_ins: 1,
+                num_audio_outs: 1,
+            }
+        }
+
+        fn process(&mut self, data: ProcessorData) {
+            for (out, &s) in data.audio_out[0].iter_mut().zip(data.audio_in[0].iter()) {
+                *out = s.clamp(-0.5, 0.5);
+            }
+        }
+    }
+
+    /// Renders a 9kHz sine through the processor at 48kHz.
+    fn render(processor: &mut impl Processor) -> Vec<f32> {
+        processor.set_sample_rate(48000);
+        let input: Vec<_> = (0..9600)
+            .map(|i| (2.0 * PI * 9000.0 * i as f32 / 48000.0).sin())
+            .collect();
+        let mut output = vec![0.0; 9600];
+        for (input, output) in input.chunks(512).zip(output.chunks_mut(512)) {
+            processor.process(ProcessorData {
+                midi_in: &[],
+                midi_out: &mut vec![],
+                samples: input.len(),
+                audio_in: &[input],
+                audio_out: &mut [output],
+                param_events: &[],
+                accumulate: false,
+            });
+        }
+        output
+    }
+
+    /// Gets the magnitude of a frequency in the last 4800 samples of a signal at 48kHz.
+    fn magnitude(signal: &[f32], frequency: f32) -> f32 {
+        magnitude_at(&signal[(signal.len() - 4800)..], frequency, 48000.0) / 2400.0
+    }
+
+    #[test]
+    fn test_aliasing() {
+        let bare = render(&mut HardClip);
+        let oversampled = render(&mut Oversampled::new(HardClip, 4));
+
+        // The fundamental is preserved
+        let fundamental = magnitude(&bare, 9000.0);
+        assert!((magnitude(&oversampled, 9000.0) - fundamental).abs() < 0.05 * fundamental);
+
+        // The fifth harmonic at 45kHz aliases to 3kHz, unless it is filtered out at the higher sample rate
+        let alias = magnitude(&bare, 3000.0);
+        assert!(alias > 0.01);
+        assert!(magnitude(&oversampled, 3000.0) < 0.1 * alias);
+    }
+
+    #[test]
+    fn test_latency() {
+        struct Identity;
+
+        impl Processor for Identity {
+            fn description(&self) -> ProcessorDescription {
+                HardClip.description()
+            }
+
+            fn process(&mut self, data: ProcessorData) {
+                data.audio_out[0].copy_from_slice(data.audio_in[0]);
+            }
+        }
+
+        let mut oversampled = Oversampled::new(Identity, 2);
+        let mut input = [0.0; 128];
+        input[10] = 1.0;
+        let mut output = [0.0; 128];
+        oversampled.process(ProcessorData {
+            midi_in: &[],
+            midi_out: &mut vec![],
+            samples: 128,
+            audio_in: &[&input],
+            audio_out: &mut [&mut output],
+            param_events: &[],
+            accumulate: false,
+        });
+
+        // The impulse is delayed by the reported latency
+        let peak = (0..128).max_by(|&a, &b| output[a].total_cmp(&output[b])).unwrap();
+        assert_eq!(peak, 10 + oversampled.latency_samples());
+        // The resampling filters ring for their combined length after the input falls silent
+        assert!(output[(peak + oversampled.tail_samples())..]
+            .iter()
+            .all(|s| s.abs() < 1e-6));
+        // The resampling filters band limit the impulse, which lowers its peak
+        assert!((output[peak] - 2.0 * CUTOFF).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_midi_out_times() {
+        /// A processor which emits a clock pulse at each of the given deltas.
+        struct Emitter(Vec<u32>);
+
+        impl Processor for Emitter {
+            fn description(&self) -> ProcessorDescription {
+                HardClip.description()
+            }
+
+            fn process(&mut self, data: ProcessorData) {
+                for &time in &self.0 {
+                    data.midi_out.push(TimedMidiEvent {
+                        time,
+                        event: MidiEvent::Clock,
+                    });
+                }
+            }
+        }
+
+        let mut oversampled = Oversampled::new(Emitter(vec![3, 3, 5, 1, 1, 7]), 2);
+        let input = [0.0; 32];
+        let mut output = [0.0; 32];
+        let mut midi_out = vec![];
+        oversampled.process(ProcessorData {
+            midi_in: &[],
+            midi_out: &mut midi_out,
+            samples: 32,
+            audio_in: &[&input],
+            audio_out: &mut [&mut output],
+            param_events: &[],
+            accumulate: false,
+        });
+
+        // The absolute inner times 3, 6, 11, 12, 13 and 20 are halved and rounded down
+        let times: Vec<_> = midi_out.iter().map(|e| e.time).collect();
+        assert_eq!(times, [1, 2, 2, 1, 0, 4]);
+    }
+}
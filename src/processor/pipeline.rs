@@ -30,9 +30,19 @@ impl Processor for Pipeline {
         }
     }
 
+    fn tail_samples(&self) -> usize {
+        // Each component extends the tail of the components before it
+        self.components
+            .iter()
+            .fold(0, |tail, component| tail.saturating_add(component.tail_samples()))
+    }
+
     fn process(&mut self, data: ProcessorData) {
         // Ensure buffer is large enough
         let len = data.samples;
+        if len == 0 {
+            return;
+        }
         self.buffer.resize(4 * len, 0.0);
 
         // Split buffer into two stereo pairs for double buffering
@@ -48,26 +58,48 @@ impl Processor for Pipeline {
 
         // Process each component in the pipeline
         for component in &mut self.components {
-            let (current_left, current_right) = buffer_a.split_at_mut(len);
+            // Components which need more inputs than the pipeline provides are bypassed
+            let descr = component.description();
+            if descr.min_audio_ins > 2 {
+                continue;
+            }
+            let num_inputs = descr.max_audio_ins.min(2);
+            let num_outputs = descr.num_audio_outs.min(2);
+
+            let (current_left, current_right) = buffer_a.split_at(len);
             let (next_left, next_right) = buffer_b.split_at_mut(len);
 
             component.process(ProcessorData {
                 midi_in: &midi_current,
                 midi_out: &mut midi_next,
                 samples: len,
-                audio_in: &[current_left, current_right],
-                audio_out: &mut [next_left, next_right],
+                audio_in: &[current_left, current_right][..num_inputs],
+                audio_out: &mut [next_left, next_right][..num_outputs],
+                param_events: &[],
+                accumulate: false,
             });
 
+            // Fill in any channels the component didn't output
+            let (next_left, next_right) = buffer_b.split_at_mut(len);
+            match num_outputs {
+                0 => {
+                    next_left.fill(0.0);
+                    next_right.fill(0.0);
+                }
+                1 => next_right.copy_from_slice(next_left),
+                _ => {}
+            }
+
             // Swap buffers and MIDI vectors
             std::mem::swap(&mut buffer_a, &mut buffer_b);
             std::mem::swap(&mut midi_current, &mut midi_next);
             midi_next.clear();
         }
 
-        // Copy results from buffer_a to output
-        data.audio_out[0].copy_from_slice(&buffer_a[0..len]);
-        data.audio_out[1].copy_from_slice(&buffer_a[len..2 * len]);
+        // Copy results from buffer_a to as many outputs as are provided
+        for (buffer_out, buffer) in data.audio_out.iter_mut().zip(buffer_a.chunks(len)) {
+            buffer_out.copy_from_slice(buffer);
+        }
         data.midi_out.extend(midi_current.iter().cloned());
     }
 }
@@ -79,3 +111,55 @@ fn copy_or_clear(src: Option<&[f32]>, dst: &mut [f32]) {
         dst.fill(0.0);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processor::{Gain, ProcessorDescription};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// A processor with no outputs, which counts the samples it receives.
+    struct Sink(Arc<AtomicUsize>);
+
+    impl Processor for Sink {
+        fn description(&self) -> ProcessorDescription {
+            ProcessorDescription {
+                min_audio_ins: 2,
+                max_audio_ins: 2,
+                num_audio_outs: 0,
+            }
+        }
+
+        fn process(&mut self, data: ProcessorData) {
+            assert!(data.audio_out.is_empty());
+            self.0.fetch_add(data.audio_in[0].len(), Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_sink_as_last_stage() {
+        let received = Arc::new(AtomicUsize::new(0));
+        let mut pipeline = Pipeline::new([
+            Box::new(Gain::new()) as Box<dyn Processor + Send>,
+            Box::new(Sink(received.clone())),
+        ]);
+
+        let input = [1.0; 64];
+        let mut left = [1.0; 64];
+        pipeline.process(ProcessorData {
+            midi_in: &[],
+            midi_out: &mut vec![],
+            samples: 64,
+            audio_in: &[&input, &input],
+            audio_out: &mut [&mut left],
+            param_events: &[],
+            accumulate: false,
+        });
+
+        assert_eq!(received.load(Ordering::Relaxed), 64);
+        assert!(left.iter().all(|&s| s == 0.0));
+    }
+}
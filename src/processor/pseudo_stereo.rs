@@ -0,0 +1,175 @@
+use super::Processor;
+use crate::audio::{
+    buffer::{StereoBuffer, StereoBufferMut},
+    ring::RingBuffer,
+};
+
+const BATCH_SIZE: usize = 32;
+const MAX_DELAY: f32 = 0.03;
+
+/// Widens a mono signal with complementary comb filters, adding a delayed copy of the signal to the left channel
+/// and subtracting it from the right. The filters notch out opposite frequencies in each channel, which
+/// decorrelates them, but cancel out when the channels are summed, so the signal still folds down to mono.
+pub struct PseudoStereo {
+    /// Holds the recent input of the mid channel.
+    ring: RingBuffer,
+    /// The sample rate in `Hz`.
+    sample_rate: f32,
+    /// Level of the delayed signal between `0.0` and `1.0`.
+    amount: f32,
+    /// Delay of the delayed signal in seconds.
+    delay: f32,
+}
+
+impl Default for PseudoStereo {
+    fn default() -> Self {
+        Self {
+            ring: RingBuffer::new(BATCH_SIZE),
+            sample_rate: 0.0,
+            amount: 0.5,
+            delay: 0.015,
+        }
+    }
+}
+
+impl PseudoStereo {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        let size = (MAX_DELAY * self.sample_rate) as usize + BATCH_SIZE;
+        self.ring = RingBuffer::new(size);
+    }
+
+    /// Sets the width between `0.0`, which passes the input through unchanged, and `1.0`.
+    pub fn set_amount(&mut self, amount: f32) {
+        self.amount = amount.clamp(0.0, 1.0);
+    }
+
+    /// Sets the delay of the comb filters in seconds, up to 30ms. Shorter delays space the notches further apart.
+    pub fn set_delay(&mut self, delay: f32) {
+        self.delay = delay.clamp(0.0, MAX_DELAY);
+    }
+
+    pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
+        let len = audio_in.len();
+        assert!(audio_in.len() == audio_out.len());
+
+        let delay = (self.delay * self.sample_rate) as usize;
+
+        let mut i = 0;
+        let mut mid = [0.0f32; BATCH_SIZE];
+
+        while i < len {
+            let j = (i + BATCH_SIZE).min(len);
+            let mid = &mut mid[..(j - i)];
+
+            // Read the delayed mid signal
+            let samples_in = audio_in.left[i..j].iter().zip(audio_in.right[i..j].iter());
+            for (m, (&l, &r)) in mid.iter_mut().zip(samples_in) {
+                *m = 0.5 * (l + r);
+            }
+            self.ring.write(mid);
+            self.ring.seek(delay + (j - i));
+            self.ring.read(mid, true);
+
+            // Add the delayed signal to one channel and subtract it from the other
+            let samples_in = audio_in.left[i..j].iter().zip(audio_in.right[i..j].iter());
+            let samples_out = audio_out.left[i..j].iter_mut().zip(audio_out.right[i..j].iter_mut());
+            for (((&l, &r), (out_l, out_r)), &m) in samples_in.zip(samples_out).zip(mid.iter()) {
+                *out_l = l + self.amount * m;
+                *out_r = r - self.amount * m;
+            }
+
+            i = j;
+        }
+    }
+}
+
+impl Processor for PseudoStereo {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_amount(value),
+            1 => self.set_delay(value),
+            _ => {}
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [left, right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let audio_in = StereoBuffer::new(left, right);
+
+        let [left, right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        let audio_out = StereoBufferMut::new(left, right);
+
+        self.process(audio_in, audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// Processes a second of mono noise, and returns the input and the output.
+    fn process_noise(pseudo: &mut PseudoStereo) -> (Vec<f32>, [Vec<f32>; 2]) {
+        let mut rng = StdRng::seed_from_u64(1);
+        let input: Vec<f32> = (0..48000).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let mut left = vec![0.0; 48000];
+        let mut right = vec![0.0; 48000];
+        for (k, chunk) in input.chunks(500).enumerate() {
+            let range = (500 * k)..(500 * k + chunk.len());
+            pseudo.process(
+                StereoBuffer::new(chunk, chunk),
+                StereoBufferMut::new(&mut left[range.clone()], &mut right[range]),
+            );
+        }
+        (input, [left, right])
+    }
+
+    #[test]
+    fn test_pass_through() {
+        let mut pseudo = PseudoStereo::new();
+        pseudo.set_sample_rate(48000);
+        pseudo.set_amount(0.0);
+        let (input, [left, right]) = process_noise(&mut pseudo);
+        assert_eq!(left, input);
+        assert_eq!(right, input);
+    }
+
+    #[test]
+    fn test_decorrelation() {
+        let mut pseudo = PseudoStereo::new();
+        pseudo.set_sample_rate(48000);
+        pseudo.set_amount(0.8);
+        let (input, [left, right]) = process_noise(&mut pseudo);
+
+        // The channels are decorrelated
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        let correlation = dot(&left, &right) / (dot(&left, &left) * dot(&right, &right)).sqrt();
+        assert!(correlation < 0.5);
+
+        // Folding down to mono recovers the input
+        for ((&l, &r), &s) in left.iter().zip(right.iter()).zip(input.iter()) {
+            assert!((0.5 * (l + r) - s).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use super::Processor;
+use crate::convert::{float_to_int, interleave_stereo};
+use basedrop::Handle;
+use ringbuf_basedrop as ringbuf;
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Length in seconds of the audio which can be buffered before it's written to disk.
+const BUFFER_SECONDS: usize = 2;
+
+/// How long the writer thread sleeps when there are no samples to write.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Passes a stereo signal through unchanged, and while armed records it to a WAV file.
+///
+/// The samples are handed to a background thread which writes the file, so that disk I/O never blocks the
+/// audio thread. If the writer can't keep up and the buffer between them fills, samples are dropped.
+pub struct Recorder {
+    handle: Handle,
+    sample_rate: u32,
+    /// Number of bits per sample written, where 32 bits are written as floats and fewer as integers.
+    bits_per_sample: u16,
+    /// The recording in progress, if any.
+    recording: Option<Recording>,
+    /// Scratch buffer holding the interleaved samples of each block.
+    buffer: Vec<f32>,
+    /// Number of samples dropped because the buffer was full.
+    dropped: usize,
+}
+
+struct Recording {
+    channel: ringbuf::Producer<f32>,
+    /// Set to tell the writer thread to write the remaining samples and finalize the file.
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Result<(), hound::Error>>,
+}
+
+impl Recorder {
+    pub fn new(handle: &Handle) -> Self {
+        Self {
+            handle: handle.clone(),
+            sample_rate: 44100,
+            bits_per_sample: 32,
+            recording: None,
+            buffer: vec![],
+            dropped: 0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Sets the number of bits per sample of the next recording, which is either 16 or 24 bits for an integer
+    /// WAV file, or 32 bits for a float WAV file. Other values are ignored.
+    pub fn set_bits_per_sample(&mut self, bits_per_sample: u16) {
+        if matches!(bits_per_sample, 16 | 24 | 32) {
+            self.bits_per_sample = bits_per_sample;
+        }
+    }
+
+    /// Starts recording to a WAV file at `path`, stopping any recording already in progress.
+    pub fn arm(&mut self, path: impl AsRef<Path>) -> Result<(), hound::Error> {
+        self.stop()?;
+
+        let bits_per_sample = self.bits_per_sample;
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: self.sample_rate,
+            bits_per_sample,
+            sample_format: match bits_per_sample {
+                32 => hound::SampleFormat::Float,
+                _ => hound::SampleFormat::Int,
+            },
+        };
+        let mut writer = hound::WavWriter::create(path, spec)?;
+
+        let capacity = 2 * BUFFER_SECONDS * self.sample_rate as usize;
+        let (tx, mut rx) = ringbuf::RingBuffer::new(capacity).split(&self.handle);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let mut buffer = vec![0.0; capacity];
+                loop {
+                    // Check the flag before reading, so that no samples pushed before stopping are missed
+                    let stopping = stop.load(Ordering::Acquire);
+                    let read = rx.pop_slice(&mut buffer);
+                    for &sample in &buffer[..read] {
+                        match spec.sample_format {
+                            hound::SampleFormat::Float => writer.write_sample(sample)?,
+                            hound::SampleFormat::Int => writer.write_sample(float_to_int(sample, bits_per_sample))?,
+                        }
+                    }
+                    if read == 0 {
+                        if stopping {
+                            break;
+                        }
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                }
+                writer.finalize()
+            }
+        });
+
+        self.recording = Some(Recording {
+            channel: tx,
+            stop,
+            thread,
+        });
+        self.dropped = 0;
+        Ok(())
+    }
+
+    /// Stops recording, waiting for the remaining samples to be written and the file to be finalized.
+    /// Does nothing if not recording.
+    pub fn stop(&mut self) -> Result<(), hound::Error> {
+        let Some(recording) = self.recording.take() else {
+            return Ok(());
+        };
+        recording.stop.store(true, Ordering::Release);
+        recording.thread.join().expect("WAV writer thread panicked")
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Gets the number of samples dropped from the current recording because the writer couldn't keep up.
+    pub fn dropped_samples(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn process(&mut self, audio_in: [&[f32]; 2], audio_out: [&mut [f32]; 2]) {
+        let [in_left, in_right] = audio_in;
+        let [out_left, out_right] = audio_out;
+        out_left.copy_from_slice(in_left);
+        out_right.copy_from_slice(in_right);
+
+        if let Some(recording) = self.recording.as_mut() {
+            self.buffer.resize(in_left.len() + in_right.len(), 0.0);
+            interleave_stereo(in_left, in_right, &mut self.buffer[..]);
+            let written = recording.channel.push_slice(&self.buffer);
+            self.dropped += self.buffer.len() - written;
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Let the writer thread finish the file by itself, rather than blocking here
+        if let Some(recording) = self.recording.take() {
+            recording.stop.store(true, Ordering::Release);
+        }
+    }
+}
+
+impl Processor for Recorder {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [in_left, in_right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let [out_left, out_right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        self.process([in_left, in_right], [out_left, out_right]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use basedrop::Collector;
+
+    #[test]
+    fn test_record() {
+        let collector = Collector::new();
+        let path = std::env::temp_dir().join(format!("daw-engine-recorder-{}.wav", std::process::id()));
+
+        let mut recorder = Recorder::new(&collector.handle());
+        recorder.set_sample_rate(48000);
+        recorder.arm(&path).unwrap();
+        assert!(recorder.is_armed());
+
+        let left: Vec<_> = (0..1024).map(|i| (i as f32 / 1024.0) - 0.5).collect();
+        let right: Vec<_> = left.iter().map(|s| -s).collect();
+        let mut out_left = [0.0; 256];
+        let mut out_right = [0.0; 256];
+        for (left, right) in left.chunks(256).zip(right.chunks(256)) {
+            recorder.process([left, right], [&mut out_left, &mut out_right]);
+            assert_eq!(out_left, left);
+            assert_eq!(out_right, right);
+        }
+        recorder.stop().unwrap();
+        assert!(!recorder.is_armed());
+        assert_eq!(recorder.dropped_samples(), 0);
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 48000);
+        let samples: Vec<f32> = reader.samples().map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut expected = vec![0.0; 2048];
+        interleave_stereo(&left, &right, &mut expected);
+        assert_eq!(samples, expected);
+    }
+
+    #[test]
+    fn test_record_int() {
+        let collector = Collector::new();
+        let path = std::env::temp_dir().join(format!("daw-engine-recorder-int-{}.wav", std::process::id()));
+
+        let mut recorder = Recorder::new(&collector.handle());
+        recorder.set_sample_rate(48000);
+        recorder.set_bits_per_sample(16);
+        // Unsupported bit depths are ignored
+        recorder.set_bits_per_sample(12);
+        recorder.arm(&path).unwrap();
+
+        // Samples round to the nearest integer, and those beyond full scale are clamped rather than wrapping
+        let left = [1.0, 1.001, 1.5, 0.6 / 32767.0, -0.6 / 32767.0];
+        let right = [-1.0, -1.001, -1.5, 0.4 / 32767.0, -0.4 / 32767.0];
+        let mut out_left = [0.0; 5];
+        let mut out_right = [0.0; 5];
+        recorder.process([&left, &right], [&mut out_left, &mut out_right]);
+        recorder.stop().unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().bits_per_sample, 16);
+        assert_eq!(reader.spec().sample_format, hound::SampleFormat::Int);
+        let samples: Vec<i32> = reader.samples().map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        let (max, min) = (i16::MAX as i32, -(i16::MAX as i32));
+        assert_eq!(samples, [max, min, max, min, max, min, 1, 0, -1, 0]);
+    }
+}
@@ -0,0 +1,342 @@
+use super::Processor;
+use crate::audio::buffer::{StereoBuffer, StereoBufferMut};
+
+/// Delays of the parallel comb filters in seconds.
+const COMB_DELAYS: [f32; 4] = [0.0253, 0.0269, 0.0290, 0.0307];
+/// Delays of the series allpass filters which diffuse the output of the combs in seconds.
+const ALLPASS_DELAYS: [f32; 2] = [0.0126, 0.0100];
+/// Delays of the allpass filters which decorrelate the left and right inputs when the stereo spread is enabled.
+const DECORRELATION_DELAYS: [f32; 2] = [0.0031, 0.0077];
+/// Time in seconds by which the delays of the right channel are lengthened when the stereo spread is enabled.
+const SPREAD_DELAY: f32 = 0.0005;
+/// Gain applied to the input of the combs, which keeps the wet signal at a similar level to the input.
+const INPUT_GAIN: f32 = 0.1;
+/// Feedback of the allpass filters.
+const ALLPASS_GAIN: f32 = 0.5;
+
+/// An algorithmic reverb, made of parallel feedback comb filters followed by series allpass filters.
+pub struct Reverb {
+    /// The sample rate in `Hz`.
+    sample_rate: f32,
+    /// Size of the room between `0.0` and `1.0`, which controls the length of the tail.
+    room_size: f32,
+    /// Damping of high frequencies in the tail between `0.0` and `1.0`.
+    damping: f32,
+    /// Wet/dry mix between `0.0` (fully dry) and `1.0` (fully wet).
+    mix: f32,
+    /// Whether the left and right channels are decorrelated to widen the tail.
+    spread: bool,
+    /// The filters of the left and right channels.
+    channels: [ReverbChannel; 2],
+}
+
+struct ReverbChannel {
+    decorrelator: Allpass,
+    combs: [Comb; 4],
+    allpasses: [Allpass; 2],
+}
+
+impl Default for Reverb {
+    fn default() -> Self {
+        let mut reverb = Self {
+            sample_rate: 0.0,
+            room_size: 0.5,
+            damping: 0.5,
+            mix: 0.3,
+            spread: false,
+            channels: [ReverbChannel::new(), ReverbChannel::new()],
+        };
+        reverb.resize();
+        reverb
+    }
+}
+
+impl Reverb {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.resize();
+    }
+
+    /// Sets the size of the room between `0.0` and `1.0`, where larger rooms have longer tails.
+    pub fn set_room_size(&mut self, room_size: f32) {
+        self.room_size = room_size.clamp(0.0, 1.0);
+    }
+
+    /// Sets how quickly high frequencies decay relative to low frequencies, between `0.0` and `1.0`.
+    pub fn set_damping(&mut self, damping: f32) {
+        self.damping = damping.clamp(0.0, 1.0);
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Sets whether the left and right channels are decorrelated, which widens the tail of mono sources.
+    /// The input of each channel is passed through a different allpass filter,
+    /// and the delays of the right channel are lengthened slightly.
+    pub fn set_stereo_spread(&mut self, spread: bool) {
+        if self.spread != spread {
+            self.spread = spread;
+            self.update_spread();
+        }
+    }
+
+    /// Gets the time in seconds taken for the tail to decay by 60dB, known as the RT60.
+    /// This is the decay of the low frequencies, as the damping shortens the decay of the high frequencies.
+    pub fn rt60(&self) -> f32 {
+        let spread = if self.spread { SPREAD_DELAY } else { 0.0 };
+        let delay = COMB_DELAYS.iter().fold(0.0f32, |max, &d| max.max(d)) + spread;
+        delay * 1e-3f32.ln() / self.feedback().ln()
+    }
+
+    /// Gets the feedback of the comb filters, which sets how quickly the tail decays.
+    fn feedback(&self) -> f32 {
+        0.7 + 0.28 * self.room_size
+    }
+
+    /// Gets a function which converts a delay in seconds to samples.
+    fn delay_samples(&self) -> impl Fn(f32) -> usize {
+        // Use a sample rate of 48kHz until the sample rate is known
+        let sample_rate = if self.sample_rate > 0.0 {
+            self.sample_rate
+        } else {
+            48000.0
+        };
+        move |delay| ((delay * sample_rate) as usize).max(1)
+    }
+
+    /// Reallocates the delay lines of each filter, clearing their contents.
+    /// The delay lines of the right channel are long enough for the stereo spread,
+    /// so that it can be toggled without reallocating.
+    fn resize(&mut self) {
+        let samples = self.delay_samples();
+        let spreads = [0.0, SPREAD_DELAY];
+        for ((channel, decorrelation), spread) in self.channels.iter_mut().zip(DECORRELATION_DELAYS).zip(spreads) {
+            channel.decorrelator = Allpass::new(samples(decorrelation));
+            channel.combs = COMB_DELAYS.map(|delay| Comb::new(samples(delay + spread)));
+            channel.allpasses = ALLPASS_DELAYS.map(|delay| Allpass::new(samples(delay + spread)));
+        }
+        self.update_spread();
+    }
+
+    /// Sets the lengths of the delays of the right channel, depending on whether the stereo spread is enabled.
+    fn update_spread(&mut self) {
+        let samples = self.delay_samples();
+        let spread = if self.spread { SPREAD_DELAY } else { 0.0 };
+        let channel = &mut self.channels[1];
+        for (comb, delay) in channel.combs.iter_mut().zip(COMB_DELAYS) {
+            comb.set_len(samples(delay + spread));
+        }
+        for (allpass, delay) in channel.allpasses.iter_mut().zip(ALLPASS_DELAYS) {
+            allpass.set_len(samples(delay + spread));
+        }
+    }
+
+    pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
+        assert!(audio_in.len() == audio_out.len());
+
+        let feedback = self.feedback();
+        let damping = 0.4 * self.damping;
+        let ins = [audio_in.left, audio_in.right];
+        let outs = [audio_out.left, audio_out.right];
+        for ((channel, s_in), s_out) in self.channels.iter_mut().zip(ins).zip(outs) {
+            for (out, &dry) in s_out.iter_mut().zip(s_in.iter()) {
+                let input = if self.spread {
+                    channel.decorrelator.process(dry)
+                } else {
+                    dry
+                };
+                let input = INPUT_GAIN * input;
+                let wet = channel
+                    .combs
+                    .iter_mut()
+                    .map(|c| c.process(input, feedback, damping))
+                    .sum();
+                let wet = channel.allpasses.iter_mut().fold(wet, |s, a| a.process(s));
+                *out = (1.0 - self.mix) * dry + self.mix * wet;
+            }
+        }
+    }
+}
+
+impl ReverbChannel {
+    fn new() -> Self {
+        Self {
+            decorrelator: Allpass::new(1),
+            combs: [(); 4].map(|_| Comb::new(1)),
+            allpasses: [(); 2].map(|_| Allpass::new(1)),
+        }
+    }
+}
+
+/// A feedback comb filter with a lowpass filter in the feedback path.
+struct Comb {
+    buffer: Box<[f32]>,
+    /// Length of the delay, which may be shorter than the buffer.
+    len: usize,
+    idx: usize,
+    /// State of the lowpass filter.
+    filtered: f32,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len].into_boxed_slice(),
+            len,
+            idx: 0,
+            filtered: 0.0,
+        }
+    }
+
+    /// Sets the length of the delay, up to the length of the buffer, keeping its contents.
+    fn set_len(&mut self, len: usize) {
+        self.len = len.clamp(1, self.buffer.len());
+        self.idx %= self.len;
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.idx];
+        self.filtered = output * (1.0 - damping) + self.filtered * damping;
+        self.buffer[self.idx] = input + self.filtered * feedback;
+        self.idx = (self.idx + 1) % self.len;
+        output
+    }
+}
+
+/// An allpass filter, which smears the phase of the signal without changing its frequency response.
+struct Allpass {
+    buffer: Box<[f32]>,
+    /// Length of the delay, which may be shorter than the buffer.
+    len: usize,
+    idx: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len].into_boxed_slice(),
+            len,
+            idx: 0,
+        }
+    }
+
+    /// Sets the length of the delay, up to the length of the buffer, keeping its contents.
+    fn set_len(&mut self, len: usize) {
+        self.len = len.clamp(1, self.buffer.len());
+        self.idx %= self.len;
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.idx];
+        let output = delayed - ALLPASS_GAIN * input;
+        self.buffer[self.idx] = input + ALLPASS_GAIN * output;
+        self.idx = (self.idx + 1) % self.len;
+        output
+    }
+}
+
+impl Processor for Reverb {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_room_size(value),
+            1 => self.set_damping(value),
+            2 => self.set_mix(value),
+            3 => self.set_stereo_spread(value >= 0.5),
+            _ => {}
+        }
+    }
+
+    fn tail_samples(&self) -> usize {
+        (self.rt60() * self.sample_rate).ceil() as usize
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [left, right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let audio_in = StereoBuffer::new(left, right);
+
+        let [left, right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        let audio_out = StereoBufferMut::new(left, right);
+
+        self.process(audio_in, audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Feeds a mono impulse into the reverb, and returns the correlation between the channels of the tail.
+    fn tail_correlation(spread: bool) -> f32 {
+        let mut reverb = Reverb::new();
+        reverb.set_sample_rate(48000);
+        reverb.set_mix(1.0);
+        reverb.set_stereo_spread(spread);
+
+        let mut input = vec![0.0; 48000];
+        input[0] = 1.0;
+        let mut left = vec![0.0; 48000];
+        let mut right = vec![0.0; 48000];
+        reverb.process(
+            StereoBuffer::new(&input, &input),
+            StereoBufferMut::new(&mut left, &mut right),
+        );
+
+        let (left, right) = (&left[4800..], &right[4800..]);
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b.iter()).map(|(a, b)| a * b).sum::<f32>();
+        assert!(dot(left, left) > 1e-6);
+        dot(left, right) / (dot(left, left) * dot(right, right)).sqrt()
+    }
+
+    #[test]
+    fn test_stereo_spread() {
+        assert!(tail_correlation(false) > 0.999);
+        assert!(tail_correlation(true).abs() < 0.3);
+    }
+
+    #[test]
+    fn test_toggle_spread_keeps_tail() {
+        let mut reverb = Reverb::new();
+        reverb.set_sample_rate(48000);
+        reverb.set_mix(1.0);
+
+        let mut input = vec![0.0; 4800];
+        input[0] = 1.0;
+        let mut left = vec![0.0; 4800];
+        let mut right = vec![0.0; 4800];
+        reverb.process(
+            StereoBuffer::new(&input, &input),
+            StereoBufferMut::new(&mut left, &mut right),
+        );
+
+        // Enabling the spread shouldn't clear the tail of the right channel
+        reverb.set_stereo_spread(true);
+        input[0] = 0.0;
+        reverb.process(
+            StereoBuffer::new(&input, &input),
+            StereoBufferMut::new(&mut left, &mut right),
+        );
+        let energy = |s: &[f32]| s.iter().map(|x| x * x).sum::<f32>();
+        assert!(energy(&right) > 0.5 * energy(&left));
+    }
+}
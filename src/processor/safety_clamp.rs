@@ -0,0 +1,106 @@
+use super::Processor;
+
+/// Protects the rest of the graph from a misbehaving processor, by replacing NaN and infinite samples with silence
+/// and clamping the remaining samples to a hard limit.
+pub struct SafetyClamp {
+    /// The largest absolute value of an output sample.
+    limit: f32,
+    /// Number of NaN or infinite samples replaced since the counts were reset.
+    non_finite: usize,
+    /// Number of samples clamped to the limit since the counts were reset.
+    clamped: usize,
+}
+
+impl Default for SafetyClamp {
+    fn default() -> Self {
+        Self {
+            limit: 1.0,
+            non_finite: 0,
+            clamped: 0,
+        }
+    }
+}
+
+impl SafetyClamp {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the largest absolute value of an output sample, which defaults to `1.0`.
+    pub fn set_limit(&mut self, limit: f32) {
+        self.limit = limit.abs();
+    }
+
+    /// Gets the number of NaN or infinite samples which have been replaced with silence.
+    pub fn non_finite_count(&self) -> usize {
+        self.non_finite
+    }
+
+    /// Gets the number of samples which have been clamped to the limit.
+    pub fn clamped_count(&self) -> usize {
+        self.clamped
+    }
+
+    pub fn reset_counts(&mut self) {
+        self.non_finite = 0;
+        self.clamped = 0;
+    }
+
+    pub fn process(&mut self, audio_in: &[&[f32]], audio_out: &mut [&mut [f32]]) {
+        for (buffer_in, buffer_out) in audio_in.iter().zip(audio_out.iter_mut()) {
+            for (&sample_in, sample_out) in buffer_in.iter().zip(buffer_out.iter_mut()) {
+                *sample_out = if !sample_in.is_finite() {
+                    self.non_finite += 1;
+                    0.0
+                } else if sample_in.abs() > self.limit {
+                    self.clamped += 1;
+                    sample_in.clamp(-self.limit, self.limit)
+                } else {
+                    sample_in
+                };
+            }
+        }
+    }
+}
+
+impl Processor for SafetyClamp {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        if param_id == 0 {
+            self.set_limit(value)
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        self.process(data.audio_in, data.audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clamp() {
+        let mut clamp = SafetyClamp::new();
+        clamp.set_limit(0.5);
+
+        let left = [0.25, f32::NAN, -2.0, f32::INFINITY];
+        let right = [f32::NEG_INFINITY, 0.75, -0.5, 0.0];
+        let mut out_left = [0.0; 4];
+        let mut out_right = [0.0; 4];
+        clamp.process(&[&left, &right], &mut [&mut out_left, &mut out_right]);
+
+        assert_eq!(out_left, [0.25, 0.0, -0.5, 0.0]);
+        assert_eq!(out_right, [0.0, 0.5, -0.5, 0.0]);
+        assert_eq!(clamp.non_finite_count(), 3);
+        assert_eq!(clamp.clamped_count(), 2);
+    }
+}
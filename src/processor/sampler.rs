@@ -1,13 +1,42 @@
 use super::Processor;
-use crate::audio::{
-    buffer::{MonoBuffer, StereoBuffer, StereoBufferMut},
-    resample::{CubicInterpolator, Resampler},
-    sample::AudioSample,
+use crate::{
+    audio::{
+        buffer::{MonoBuffer, StereoBuffer, StereoBufferMut},
+        resample::{CubicInterpolator, Interpolator, LagrangeInterpolator, LinearInterpolator, Resampler},
+        sample::AudioSample,
+    },
+    midi::{MidiEvent, TimedMidiEvent},
+    note::Note,
+};
+use std::{
+    f32::consts::{FRAC_PI_4, SQRT_2},
+    sync::{Arc, OnceLock},
 };
-use std::sync::{Arc, OnceLock};
 
 static EMPTY_SAMPLE: OnceLock<Arc<AudioSample>> = OnceLock::new();
 
+/// The interpolation used when a sample is played at a different speed or sample rate.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InterpolationQuality {
+    /// Linear interpolation, which is the cheapest but dulls high frequencies.
+    Linear,
+    /// Cubic interpolation.
+    #[default]
+    Cubic,
+    /// 6-point Lagrange interpolation, which is the most accurate but the most expensive.
+    Lagrange,
+}
+
+/// The resamplers of the left and right channels, using the interpolator for the chosen quality.
+#[derive(Clone)]
+enum Resamplers {
+    Linear([Resampler<LinearInterpolator>; 2]),
+    Cubic([Resampler<CubicInterpolator>; 2]),
+    Lagrange([Resampler<LagrangeInterpolator>; 2]),
+}
+
+#[derive(Clone)]
 pub struct Sampler {
     /// The audio sample.
     sample: Arc<AudioSample>,
@@ -18,9 +47,19 @@ pub struct Sampler {
     /// The sample rate of the audio output.
     sample_rate_out: f32,
     /// The samplers used to resample the left and right channels.
-    samplers: [Resampler<CubicInterpolator>; 2],
+    samplers: Resamplers,
+    /// The buffers which hold the raw audio of the left and right channels to be resampled.
+    buffers: [Vec<f32>; 2],
     /// If `true`, the sampler does not repeat.
     one_hit: bool,
+    /// The note which plays the sample at its original pitch, or `None` to ignore the pitch of notes.
+    root_note: Option<Note>,
+    /// The note currently being played.
+    note: Option<Note>,
+    /// The ratio of the playback speed to the original speed of the sample.
+    speed: f32,
+    /// The pan position, from `-1.0` for left to `1.0` for right.
+    pan: f32,
 }
 
 impl Sampler {
@@ -31,8 +70,13 @@ impl Sampler {
             read_idx: 0,
             sample_rate_in,
             sample_rate_out: 0.0,
-            samplers: [Resampler::new(), Resampler::new()],
-            one_hit: false, // FIXME
+            samplers: Resamplers::new(InterpolationQuality::default()),
+            buffers: [vec![], vec![]],
+            one_hit: false,
+            root_note: None,
+            note: None,
+            speed: 1.0,
+            pan: 0.0,
         }
     }
 
@@ -41,6 +85,7 @@ impl Sampler {
     }
 
     pub fn set_sample(&mut self, sample: Arc<AudioSample>) {
+        self.sample_rate_in = sample.sample_rate() as f32;
         self.sample = sample;
         self.read_idx = 0;
     }
@@ -49,31 +94,127 @@ impl Sampler {
         self.sample_rate_out = sample_rate as f32;
     }
 
+    /// Sets whether the sample plays once when triggered, rather than looping.
+    pub fn set_one_hit(&mut self, one_hit: bool) {
+        self.one_hit = one_hit;
+    }
+
+    /// Sets the note which plays the sample at its original pitch, so that other notes are pitched relative to it,
+    /// or `None` to play every note at the original pitch.
+    pub fn set_root_note(&mut self, root_note: Option<Note>) {
+        self.root_note = root_note;
+    }
+
+    /// Sets the pan position of the output, from `-1.0` for left to `1.0` for right, with an equal-power
+    /// pan law. The centre leaves the sample unchanged, while each edge boosts one channel by 3dB and silences
+    /// the other, so that a mono sample keeps the same power wherever it is placed.
+    pub fn set_pan(&mut self, pan: f32) {
+        self.pan = pan.clamp(-1.0, 1.0);
+    }
+
+    /// Gets the interpolation used when the sample is played at a different speed or sample rate.
+    pub fn quality(&self) -> InterpolationQuality {
+        match self.samplers {
+            Resamplers::Linear(_) => InterpolationQuality::Linear,
+            Resamplers::Cubic(_) => InterpolationQuality::Cubic,
+            Resamplers::Lagrange(_) => InterpolationQuality::Lagrange,
+        }
+    }
+
+    /// Sets the interpolation used when the sample is played at a different speed or sample rate.
+    pub fn set_quality(&mut self, quality: InterpolationQuality) {
+        if quality != self.quality() {
+            self.samplers = Resamplers::new(quality);
+        }
+    }
+
+    /// Plays the sample from the start, pitched according to the note.
+    pub fn trigger(&mut self, note: Note) {
+        self.note = Some(note);
+        self.read_idx = 0;
+        self.samplers.reset();
+        self.speed = match self.root_note {
+            Some(root) => 2f32.powf(root.interval_to(note) as f32 / 12.0),
+            None => 1.0,
+        };
+    }
+
+    /// Stops a one-hit sample if it was triggered by the given note.
+    pub fn release(&mut self, note: Note) {
+        if self.one_hit && self.note == Some(note) {
+            self.note = None;
+            self.read_idx = self.length();
+        }
+    }
+
+    /// Returns `false` once a one-hit sample has finished playing.
+    pub fn is_playing(&self) -> bool {
+        !self.one_hit || self.read_idx < self.length()
+    }
+
     /// Returns the length of the internal sample in samples.
     fn length(&self) -> usize {
         self.sample.length()
     }
 
-    pub fn process(&mut self, audio_out: StereoBufferMut) {
-        let vout = audio_out;
+    pub fn process_midi(&mut self, midi_in: &[TimedMidiEvent], audio_out: StereoBufferMut) {
+        let mut vout = audio_out;
+
+        for &TimedMidiEvent { time, event } in midi_in {
+            // Process audio up to this event, and update the output buffer
+            let time = (time as usize).min(vout.len());
+            self.process(vout.slice_mut(..time));
+            vout = vout.into_slice_mut(time..);
+
+            // Process the MIDI event
+            match event {
+                MidiEvent::NoteOn { note, .. } => self.trigger(note),
+                MidiEvent::NoteOff { note, .. } => self.release(note),
+                _ => {}
+            }
+        }
+
+        self.process(vout);
+    }
+
+    pub fn process(&mut self, mut audio_out: StereoBufferMut) {
+        // An empty sample plays silence
+        if self.length() == 0 {
+            audio_out.clear();
+            return;
+        }
 
         // Compute the resampling ratio
-        let ratio = if self.sample_rate_in > 0.0 && self.sample_rate_out > 0.0 {
-            self.sample_rate_in / self.sample_rate_out
-        } else {
-            1.0
-        };
+        let mut ratio = self.speed;
+        if self.sample_rate_in > 0.0 && self.sample_rate_out > 0.0 {
+            ratio *= self.sample_rate_in / self.sample_rate_out;
+        }
+
+        let StereoBufferMut {
+            left: out_left,
+            right: out_right,
+        } = audio_out;
+        let vout = StereoBufferMut::new(&mut *out_left, &mut *out_right);
 
-        // Fill the input buffers
-        let input_size = self.samplers[0].next_input_size(vout.len(), ratio);
-        let left = &mut [0.0; 4096][..input_size];
-        let right = &mut [0.0; 4096][..input_size];
-        self.fill_buffers(StereoBufferMut::new(left, right));
+        // Fill the input buffers, growing them if needed
+        let input_size = self.samplers.next_input_size(vout.len(), ratio);
+        let [mut left, mut right] = std::mem::take(&mut self.buffers);
+        left.resize(input_size, 0.0);
+        right.resize(input_size, 0.0);
+        self.fill_buffers(StereoBufferMut::new(&mut left, &mut right));
 
         // Perform the resampling directly into the output buffers
-        let o1 = self.samplers[0].resample(left, vout.left, ratio);
-        let o2 = self.samplers[1].resample(right, vout.right, ratio);
-        debug_assert!(o1 == o2);
+        let o1 = self.samplers.resample(StereoBuffer::new(&left, &right), vout, ratio);
+        self.buffers = [left, right];
+
+        // Apply the pan
+        if self.pan != 0.0 {
+            let angle = FRAC_PI_4 * (self.pan + 1.0);
+            let (gain_left, gain_right) = (SQRT_2 * angle.cos(), SQRT_2 * angle.sin());
+            out_left.iter_mut().for_each(|s| *s *= gain_left);
+            out_right.iter_mut().for_each(|s| *s *= gain_right);
+        }
+
         if self.one_hit {
             self.read_idx = (self.read_idx + o1).min(self.length());
         } else {
@@ -83,6 +224,7 @@ impl Sampler {
 
     /// Fills the provided buffer with raw audio from the internal sample,
     /// without advancing the read position into the sample.
+    /// The sample is repeated as many times as needed, so it may be shorter than the buffer.
     fn fill_buffers(&mut self, audio_out: StereoBufferMut) {
         let vin = self.sample.stereo_data();
         let mut vout = audio_out;
@@ -109,6 +251,53 @@ impl Sampler {
     }
 }
 
+impl Resamplers {
+    fn new(quality: InterpolationQuality) -> Self {
+        match quality {
+            InterpolationQuality::Linear => Self::Linear([Resampler::new(), Resampler::new()]),
+            InterpolationQuality::Cubic => Self::Cubic([Resampler::new(), Resampler::new()]),
+            InterpolationQuality::Lagrange => Self::Lagrange([Resampler::new(), Resampler::new()]),
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Self::Linear(samplers) => samplers.iter_mut().for_each(|s| _ = s.reset()),
+            Self::Cubic(samplers) => samplers.iter_mut().for_each(|s| _ = s.reset()),
+            Self::Lagrange(samplers) => samplers.iter_mut().for_each(|s| _ = s.reset()),
+        }
+    }
+
+    fn next_input_size(&self, output_samples: usize, ratio: f32) -> usize {
+        match self {
+            Self::Linear(samplers) => samplers[0].next_input_size(output_samples, ratio),
+            Self::Cubic(samplers) => samplers[0].next_input_size(output_samples, ratio),
+            Self::Lagrange(samplers) => samplers[0].next_input_size(output_samples, ratio),
+        }
+    }
+
+    /// Resamples both channels, returning the number of samples by which to advance the read position.
+    fn resample(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut, ratio: f32) -> usize {
+        fn resample_stereo<I: Interpolator>(
+            samplers: &mut [Resampler<I>; 2],
+            audio_in: StereoBuffer,
+            audio_out: StereoBufferMut,
+            ratio: f32,
+        ) -> usize {
+            let o1 = samplers[0].resample(audio_in.left, audio_out.left, ratio);
+            let o2 = samplers[1].resample(audio_in.right, audio_out.right, ratio);
+            debug_assert!(o1 == o2);
+            o1
+        }
+
+        match self {
+            Self::Linear(samplers) => resample_stereo(samplers, audio_in, audio_out, ratio),
+            Self::Cubic(samplers) => resample_stereo(samplers, audio_in, audio_out, ratio),
+            Self::Lagrange(samplers) => resample_stereo(samplers, audio_in, audio_out, ratio),
+        }
+    }
+}
+
 impl Processor for Sampler {
     fn description(&self) -> super::ProcessorDescription {
         super::ProcessorDescription {
@@ -122,13 +311,19 @@ impl Processor for Sampler {
         self.set_sample_rate(sample_rate);
     }
 
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        if param_id == 0 {
+            self.set_pan(value)
+        }
+    }
+
     fn process(&mut self, data: super::ProcessorData) {
         let [left, right] = data.audio_out else {
             panic!("Expected at least two output audio buffers");
         };
         let audio_out = StereoBufferMut::new(left, right);
 
-        self.process(audio_out);
+        self.process_midi(data.midi_in, audio_out);
     }
 }
 
@@ -141,3 +336,158 @@ fn empty_sample() -> Arc<AudioSample> {
         })
         .clone()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processor::magnitude_at;
+    use std::f32::consts::PI;
+
+    fn note_on(time: u32, note: Note) -> TimedMidiEvent {
+        TimedMidiEvent {
+            time,
+            event: MidiEvent::NoteOn {
+                channel: 0,
+                note,
+                velocity: 100,
+            },
+        }
+    }
+
+    #[test]
+    fn test_note_pitch() {
+        // A sample which rises by one on each sample
+        let data: Vec<_> = (0..4800).map(|i| i as f32).collect();
+        let sample = Arc::new(AudioSample::new_mono(48000, MonoBuffer::new(&data)));
+        let mut sampler = Sampler::new(sample);
+        sampler.set_sample_rate(48000);
+        sampler.set_root_note(Some(Note::middle_c()));
+
+        let mut left = [0.0; 256];
+        let mut right = [0.0; 256];
+        let midi_in = [
+            note_on(0, Note::middle_c()),
+            note_on(128, Note::middle_c().transpose(12)),
+        ];
+        sampler.process_midi(&midi_in, StereoBufferMut::new(&mut left, &mut right));
+
+        // The root note plays at the original speed, and the note an octave above plays at double speed
+        assert!(left[1..128].windows(2).all(|w| (w[1] - w[0] - 1.0).abs() < 1e-3));
+        assert!(left[130..].windows(2).all(|w| (w[1] - w[0] - 2.0).abs() < 1e-3));
+        assert!(left[128] < 4.0);
+    }
+
+    #[test]
+    fn test_large_block() {
+        let data: Vec<_> = (0..48000).map(|i| i as f32).collect();
+        let sample = Arc::new(AudioSample::new_mono(48000, MonoBuffer::new(&data)));
+        let mut sampler = Sampler::new(sample);
+        sampler.set_sample_rate(48000);
+        sampler.set_root_note(Some(Note::middle_c()));
+        sampler.trigger(Note::middle_c().transpose(12));
+
+        // The whole block is rendered at once, at double speed
+        let mut left = vec![0.0; 5000];
+        let mut right = vec![0.0; 5000];
+        sampler.process(StereoBufferMut::new(&mut left, &mut right));
+        assert!(left[1..].windows(2).all(|w| (w[1] - w[0] - 2.0).abs() < 1e-3));
+        assert!((left[4999] - 9999.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_empty_sample() {
+        let sample = Arc::new(AudioSample::new_mono(48000, MonoBuffer::new(&[])));
+        for one_hit in [false, true] {
+            let mut sampler = Sampler::new(sample.clone());
+            sampler.set_sample_rate(44100);
+            sampler.set_one_hit(one_hit);
+            sampler.trigger(Note::middle_c());
+
+            let mut left = [1.0; 256];
+            let mut right = [1.0; 256];
+            sampler.process(StereoBufferMut::new(&mut left, &mut right));
+            assert!(left.iter().chain(right.iter()).all(|&s| s == 0.0));
+        }
+    }
+
+    #[test]
+    fn test_tiny_sample() {
+        let sample = Arc::new(AudioSample::new_mono(48000, MonoBuffer::new(&[0.25, 0.75])));
+        let mut left = [0.0; 256];
+        let mut right = [0.0; 256];
+
+        // A looping sample which is shorter than the interpolation window repeats
+        let mut sampler = Sampler::new(sample.clone());
+        sampler.set_sample_rate(48000);
+        sampler.process(StereoBufferMut::new(&mut left, &mut right));
+        assert!(left.windows(2).all(|w| w[0] + w[1] == 1.0 && w[0] != w[1]));
+
+        // A one-hit sample plays once, even when resampled
+        let mut sampler = Sampler::new(sample);
+        sampler.set_sample_rate(44100);
+        sampler.set_one_hit(true);
+        sampler.trigger(Note::middle_c());
+        sampler.process(StereoBufferMut::new(&mut left, &mut right));
+        assert!(!sampler.is_playing());
+        assert!(left[8..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_quality() {
+        // Play a 9kHz sine recorded at 44.1kHz at 48kHz, and measure the amplitude of the 9kHz component
+        let amplitude = |quality| {
+            let data: Vec<_> = (0..44100)
+                .map(|i| (2.0 * PI * 9000.0 * i as f32 / 44100.0).sin())
+                .collect();
+            let sample = Arc::new(AudioSample::new_mono(44100, MonoBuffer::new(&data)));
+            let mut sampler = Sampler::new(sample);
+            sampler.set_sample_rate(48000);
+            sampler.set_quality(quality);
+            assert_eq!(sampler.quality(), quality);
+
+            // At 48kHz, 9kHz has exactly 3 cycles every 16 samples
+            let mut left = vec![0.0; 4800];
+            let mut right = vec![0.0; 4800];
+            sampler.process(StereoBufferMut::new(&mut left, &mut right));
+            assert!(left.iter().all(|s| s.is_finite() && s.abs() < 1.1));
+            let output = &left[160..];
+            2.0 * magnitude_at(output, 3.0, 16.0) / output.len() as f32
+        };
+
+        // Linear interpolation noticeably dulls high frequencies, whereas the others are much closer
+        let linear = amplitude(InterpolationQuality::Linear);
+        let cubic = amplitude(InterpolationQuality::Cubic);
+        let lagrange = amplitude(InterpolationQuality::Lagrange);
+        assert!(linear > 0.5 && linear < 0.95);
+        assert!((1.0 - cubic).abs() < 1.0 - linear);
+        assert!((1.0 - lagrange).abs() < 1.0 - linear);
+    }
+
+    #[test]
+    fn test_pan() {
+        let data: Vec<_> = (0..480).map(|i| (2.0 * PI * i as f32 / 48.0).sin()).collect();
+        let sample = Arc::new(AudioSample::new_mono(48000, MonoBuffer::new(&data)));
+        let mut sampler = Sampler::new(sample);
+        sampler.set_sample_rate(48000);
+        let mut power = |pan| {
+            Processor::set_parameter(&mut sampler, 0, pan);
+            sampler.trigger(Note::middle_c());
+            let mut left = [0.0; 480];
+            let mut right = [0.0; 480];
+            sampler.process(StereoBufferMut::new(&mut left, &mut right));
+            let power = |buffer: &[f32]| buffer.iter().map(|s| s * s).sum::<f32>();
+            (power(&left), power(&right))
+        };
+
+        // Panning hard left sends all of the energy to the left channel, keeping the total power
+        let (left, right) = power(0.0);
+        let (panned_left, panned_right) = power(-1.0);
+        assert_eq!(panned_right, 0.0);
+        assert!((panned_left - (left + right)).abs() < 1e-3);
+
+        // Panning halfway keeps the total power too
+        let (half_left, half_right) = power(0.5);
+        assert!(half_right > half_left);
+        assert!((half_left + half_right - (left + right)).abs() < 1e-3);
+    }
+}
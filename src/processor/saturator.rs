@@ -20,14 +20,22 @@ impl Processor for Saturator {
         }
     }
 
-    fn set_sample_rate(&mut self, sample_rate: u32) {}
+    fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+    fn supports_accumulate(&self) -> bool {
+        true
+    }
 
     fn process(&mut self, data: super::ProcessorData) {
         let buffers = data.audio_in.iter().zip(data.audio_out.iter_mut());
         for (buffer_in, buffer_out) in buffers {
             let samples = buffer_in.iter().zip(buffer_out.iter_mut());
             for (sample_in, sample_out) in samples {
-                *sample_out = (self.curve)(*sample_in);
+                if data.accumulate {
+                    *sample_out += (self.curve)(*sample_in);
+                } else {
+                    *sample_out = (self.curve)(*sample_in);
+                }
             }
         }
     }
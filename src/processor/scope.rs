@@ -0,0 +1,201 @@
+use super::Processor;
+use basedrop::{Handle, Shared, SharedCell};
+
+/// How a [`Scope`] chooses where each capture starts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScopeTrigger {
+    /// Each capture starts straight after the last, so a periodic signal drifts across the display.
+    Free,
+    /// Each capture starts at a rising zero crossing, so a periodic signal stays still on the display.
+    /// If no crossing arrives within the length of a capture, such as while the signal is silent, the capture
+    /// starts anyway so that the display keeps updating.
+    #[default]
+    RisingEdge,
+}
+
+/// Captures the most recent stretch of a stereo signal for display on an oscilloscope, which it passes through
+/// unchanged. The mid channel is captured, and the latest capture can be read from another thread with a
+/// [`ScopeHandle`] without blocking the audio thread.
+pub struct Scope {
+    trigger: ScopeTrigger,
+    /// The capture being filled.
+    capture: Vec<f32>,
+    /// Number of samples in the capture being filled, which is zero while waiting for the trigger.
+    filled: usize,
+    /// Number of samples spent waiting for the trigger.
+    waiting: usize,
+    /// The previous sample, used to detect a rising zero crossing.
+    prev: f32,
+    /// The latest complete capture.
+    latest: Vec<f32>,
+    /// The buffer that the next capture is published in, which is swapped with the published one.
+    spare: Shared<Vec<f32>>,
+    handle: ScopeHandle,
+    collector: Handle,
+}
+
+/// A handle to the captures of a [`Scope`], which can be read from another thread.
+#[derive(Clone)]
+pub struct ScopeHandle(Shared<SharedCell<Vec<f32>>>);
+
+impl Scope {
+    pub fn new(handle: &Handle) -> Self {
+        let mut scope = Self {
+            trigger: ScopeTrigger::default(),
+            capture: vec![],
+            filled: 0,
+            waiting: 0,
+            prev: 0.0,
+            latest: vec![],
+            spare: Shared::new(handle, vec![]),
+            handle: ScopeHandle(Shared::new(handle, SharedCell::new(Shared::new(handle, vec![])))),
+            collector: handle.clone(),
+        };
+        scope.set_length(2048);
+        scope
+    }
+
+    /// Sets the number of samples in each capture, which defaults to 2048, discarding the latest capture.
+    pub fn set_length(&mut self, length: usize) {
+        let length = length.max(1);
+        self.capture = vec![0.0; length];
+        self.latest = Vec::with_capacity(length);
+        self.filled = 0;
+        self.waiting = 0;
+        self.spare = Shared::new(&self.collector, Vec::with_capacity(length));
+        let published = Shared::new(&self.collector, Vec::with_capacity(length));
+        self.handle.0.set(published);
+    }
+
+    pub fn set_trigger(&mut self, trigger: ScopeTrigger) {
+        self.trigger = trigger;
+    }
+
+    /// Gets the latest complete capture, which is empty until the first capture is complete.
+    pub fn latest(&self) -> &[f32] {
+        &self.latest
+    }
+
+    /// Gets a handle for reading the captures from another thread, such as the UI thread.
+    pub fn handle(&self) -> ScopeHandle {
+        self.handle.clone()
+    }
+
+    pub fn process(&mut self, audio_in: [&[f32]; 2], audio_out: [&mut [f32]; 2]) {
+        let [in_left, in_right] = audio_in;
+        let [out_left, out_right] = audio_out;
+        out_left.copy_from_slice(in_left);
+        out_right.copy_from_slice(in_right);
+
+        let length = self.capture.len();
+        for (&left, &right) in in_left.iter().zip(in_right.iter()) {
+            let sample = 0.5 * (left + right);
+            let rising = self.prev < 0.0 && sample >= 0.0;
+            self.prev = sample;
+
+            // Wait for the trigger before starting a capture
+            if self.filled == 0 {
+                let triggered = match self.trigger {
+                    ScopeTrigger::Free => true,
+                    ScopeTrigger::RisingEdge => rising || self.waiting >= length,
+                };
+                if !triggered {
+                    self.waiting += 1;
+                    continue;
+                }
+                self.waiting = 0;
+            }
+
+            self.capture[self.filled] = sample;
+            self.filled += 1;
+            if self.filled == length {
+                self.latest.clear();
+                self.latest.extend_from_slice(&self.capture);
+                self.filled = 0;
+
+                // Skip publishing if a reader still holds the spare buffer, rather than waiting for it
+                if let Some(spare) = Shared::get_mut(&mut self.spare) {
+                    spare.clear();
+                    spare.extend_from_slice(&self.capture);
+                    self.spare = self.handle.0.replace(Shared::clone(&self.spare));
+                }
+            }
+        }
+    }
+}
+
+impl ScopeHandle {
+    /// Copies the latest complete capture into `out`, which is left empty until the first capture is complete.
+    pub fn read(&self, out: &mut Vec<f32>) {
+        let latest = self.0.get();
+        out.clear();
+        out.extend_from_slice(&latest);
+    }
+}
+
+impl Processor for Scope {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [in_left, in_right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let [out_left, out_right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        self.process([in_left, in_right], [out_left, out_right]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use basedrop::Collector;
+    use std::f32::consts::PI;
+
+    /// Captures a 100Hz sine wave, starting partway through a cycle.
+    fn capture(scope: &mut Scope) {
+        let sine: Vec<_> = (0..4800)
+            .map(|i| (2.0 * PI * 100.0 * (i as f32 + 100.0) / 48000.0).sin())
+            .collect();
+        let mut left = [0.0; 256];
+        let mut right = [0.0; 256];
+        for block in sine.chunks(256) {
+            let len = block.len();
+            scope.process([block, block], [&mut left[..len], &mut right[..len]]);
+        }
+    }
+
+    #[test]
+    fn test_rising_edge() {
+        let collector = Collector::new();
+        let mut scope = Scope::new(&collector.handle());
+        scope.set_length(1000);
+        let handle = scope.handle();
+        let mut shared = vec![];
+        handle.read(&mut shared);
+        assert!(shared.is_empty() && scope.latest().is_empty());
+
+        // The capture starts at a rising zero crossing
+        capture(&mut scope);
+        let latest = scope.latest();
+        assert_eq!(latest.len(), 1000);
+        assert!(latest[0].abs() < 0.02);
+        assert!(latest[1] > latest[0]);
+        handle.read(&mut shared);
+        assert_eq!(shared, latest);
+
+        // Free captures start wherever the last one ended
+        let mut scope = Scope::new(&collector.handle());
+        scope.set_length(1000);
+        scope.set_trigger(ScopeTrigger::Free);
+        capture(&mut scope);
+        assert!(scope.latest()[0] > 0.1);
+    }
+}
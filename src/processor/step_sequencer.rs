@@ -0,0 +1,292 @@
+use super::Processor;
+use crate::{
+    midi::{MidiEvent, TimedMidiEvent},
+    note::Note,
+};
+
+/// Maximum number of steps in a pattern.
+const MAX_STEPS: usize = 64;
+
+/// A MIDI source which plays a looping pattern of steps at a given tempo, such as to drive a synth.
+pub struct StepSequencer {
+    sample_rate: f64,
+    /// Tempo in beats per minute.
+    bpm: f64,
+    /// Number of steps per beat.
+    rate: f64,
+    /// The MIDI channel of the output notes.
+    channel: u8,
+    steps: [Step; MAX_STEPS],
+    /// Number of steps in the pattern.
+    length: usize,
+    /// Index of the next step to play.
+    position: usize,
+    /// Number of samples from the start of the next block until the next step.
+    next_step: f64,
+    /// Number of samples from the start of the next block until the sounding note is released, if it isn't tied.
+    next_off: Option<f64>,
+    /// The note which is sounding, if any.
+    sounding: Option<Note>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Step {
+    /// The note to play, or `None` for a rest.
+    note: Option<Note>,
+    velocity: u8,
+    /// Length of the note as a fraction of the step.
+    gate: f32,
+    /// Whether the note is held into the next step, which continues it rather than retriggering if it plays the
+    /// same note.
+    tie: bool,
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Self {
+            note: None,
+            velocity: 100,
+            gate: 0.5,
+            tie: false,
+        }
+    }
+}
+
+impl Default for StepSequencer {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.0,
+            bpm: 120.0,
+            rate: 4.0,
+            channel: 0,
+            steps: [Step::default(); MAX_STEPS],
+            length: 16,
+            position: 0,
+            next_step: 0.0,
+            next_off: None,
+            sounding: None,
+        }
+    }
+}
+
+impl StepSequencer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the tempo in beats per minute.
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.bpm = bpm.max(1.0) as f64;
+    }
+
+    /// Sets the number of steps per beat, which defaults to four so that each step is a sixteenth note.
+    pub fn set_rate(&mut self, steps_per_beat: f32) {
+        self.rate = steps_per_beat.max(0.01) as f64;
+    }
+
+    /// Sets the number of steps in the pattern, up to 64, after which it loops.
+    pub fn set_length(&mut self, length: usize) {
+        self.length = length.clamp(1, MAX_STEPS);
+        self.position %= self.length;
+    }
+
+    pub fn set_channel(&mut self, channel: u8) {
+        self.channel = channel;
+    }
+
+    /// Sets the note of a step, or `None` for a rest, along with its velocity and its length as a fraction of the
+    /// step. Steps beyond the maximum length are ignored.
+    pub fn set_step(&mut self, idx: usize, note: Option<Note>, velocity: u8, gate: f32) {
+        if let Some(step) = self.steps.get_mut(idx) {
+            step.note = note;
+            step.velocity = velocity.min(127);
+            step.gate = gate.clamp(0.0, 1.0);
+        }
+    }
+
+    /// Sets whether the note of a step is held into the next step, ignoring its gate.
+    /// If the next step plays the same note, the note continues rather than being retriggered.
+    pub fn set_tie(&mut self, idx: usize, tie: bool) {
+        if let Some(step) = self.steps.get_mut(idx) {
+            step.tie = tie;
+        }
+    }
+
+    /// Restarts the pattern from the first step at the start of the next block, releasing the sounding note.
+    pub fn reset(&mut self) {
+        self.position = 0;
+        self.next_step = 0.0;
+        if self.sounding.is_some() {
+            self.next_off = Some(0.0);
+        }
+    }
+
+    fn samples_per_step(&self) -> f64 {
+        self.sample_rate * 60.0 / (self.bpm * self.rate)
+    }
+
+    pub fn process(&mut self, samples: usize, midi_out: &mut Vec<TimedMidiEvent>) {
+        // Nothing can be played until the sample rate is known
+        let samples_per_step = self.samples_per_step();
+        if samples_per_step <= 0.0 {
+            return;
+        }
+        let mut last_time = 0;
+        let mut push = |time: f64, event| {
+            let time = time as u32;
+            midi_out.push(TimedMidiEvent {
+                time: time - last_time,
+                event,
+            });
+            last_time = time;
+        };
+
+        loop {
+            // Release the sounding note before starting a step at the same time
+            match self.next_off {
+                Some(off) if off <= self.next_step && off < samples as f64 => {
+                    if let Some(note) = self.sounding.take() {
+                        push(off, self.note_off(note));
+                    }
+                    self.next_off = None;
+                    continue;
+                }
+                _ => {}
+            }
+            if self.next_step >= samples as f64 {
+                break;
+            }
+
+            // Start the next step, continuing a tied note of the same pitch
+            let time = self.next_step;
+            let step = self.steps[self.position];
+            self.position = (self.position + 1) % self.length;
+            self.next_step += samples_per_step;
+            if self.sounding.is_none() || self.sounding != step.note {
+                if let Some(note) = self.sounding.take() {
+                    push(time, self.note_off(note));
+                }
+                if let Some(note) = step.note {
+                    push(
+                        time,
+                        MidiEvent::NoteOn {
+                            channel: self.channel,
+                            note,
+                            velocity: step.velocity,
+                        },
+                    );
+                    self.sounding = Some(note);
+                }
+            }
+            self.next_off = match (self.sounding, step.tie) {
+                (Some(_), false) => Some(time + step.gate as f64 * samples_per_step),
+                _ => None,
+            };
+        }
+
+        self.next_step -= samples as f64;
+        if let Some(off) = self.next_off.as_mut() {
+            *off -= samples as f64;
+        }
+    }
+
+    fn note_off(&self, note: Note) -> MidiEvent {
+        MidiEvent::NoteOff {
+            channel: self.channel,
+            note,
+            velocity: 0,
+        }
+    }
+}
+
+impl Processor for StepSequencer {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 0,
+            max_audio_ins: 0,
+            num_audio_outs: 0,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f64;
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_tempo(value),
+            1 => self.set_rate(value),
+            _ => {}
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        self.process(data.samples, data.midi_out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_no_sample_rate() {
+        let mut sequencer = StepSequencer::new();
+        sequencer.set_step(0, Some(Note::middle_c()), 100, 0.5);
+        let mut midi_out = vec![];
+        sequencer.process(512, &mut midi_out);
+        assert!(midi_out.is_empty());
+    }
+
+    #[test]
+    fn test_pattern() {
+        let [c4, e4] = ["C4", "E4"].map(|n| Note::from_name(n).unwrap());
+        let mut sequencer = StepSequencer::new();
+        sequencer.set_sample_rate(48000);
+        sequencer.set_tempo(120.0);
+        sequencer.set_rate(4.0);
+        sequencer.set_length(4);
+        sequencer.set_step(0, Some(c4), 100, 0.5);
+        sequencer.set_step(1, None, 100, 0.5);
+        sequencer.set_step(2, Some(e4), 80, 0.5);
+        sequencer.set_tie(2, true);
+        sequencer.set_step(3, Some(e4), 80, 0.25);
+
+        // Collect the absolute times of each event over a bar and a bit
+        let mut events = vec![];
+        let mut block_start = 0;
+        while block_start < 30000 {
+            let mut midi_out = vec![];
+            sequencer.process(512, &mut midi_out);
+            let mut time = block_start;
+            for event in midi_out {
+                time += event.time;
+                events.push((time, event.event));
+            }
+            block_start += 512;
+        }
+
+        // At 120 BPM each sixteenth note step lasts 6000 samples, and the tied note continues into the last step
+        let note_on = |note, velocity| MidiEvent::NoteOn {
+            channel: 0,
+            note,
+            velocity,
+        };
+        let note_off = |note| MidiEvent::NoteOff {
+            channel: 0,
+            note,
+            velocity: 0,
+        };
+        assert_eq!(
+            events,
+            [
+                (0, note_on(c4, 100)),
+                (3000, note_off(c4)),
+                (12000, note_on(e4, 80)),
+                (19500, note_off(e4)),
+                (24000, note_on(c4, 100)),
+                (27000, note_off(c4)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use super::{ParamEvent, Processor, ProcessorData};
+use crate::{convert::interleave, midi::TimedMidiEvent};
+use std::f32::consts::PI;
+
+/// Gets the magnitude of a frequency in a signal, from a single bin of its discrete Fourier transform.
+/// This is the gain at that frequency for an impulse response, or half the length of the signal times the
+/// amplitude for a sinusoid.
+pub fn magnitude_at(signal: &[f32], frequency: f32, sample_rate: f32) -> f32 {
+    let omega = 2.0 * PI * frequency / sample_rate;
+    let (re, im) = signal.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, &s)| {
+        (re + s * (omega * n as f32).cos(), im - s * (omega * n as f32).sin())
+    });
+    (re * re + im * im).sqrt()
+}
+
+/// Owns the buffers and events of a block, and lends them to a processor as [`ProcessorData`], so that tests
+/// don't have to build the nested slices by hand.
+#[derive(Clone, Default)]
+pub struct ProcessorDataBuilder {
+    samples: usize,
+    audio_in: Vec<Vec<f32>>,
+    audio_out: Vec<Vec<f32>>,
+    midi_in: Vec<TimedMidiEvent>,
+    midi_out: Vec<TimedMidiEvent>,
+    param_events: Vec<ParamEvent>,
+    accumulate: bool,
+}
+
+impl ProcessorDataBuilder {
+    /// Creates a block of `samples` samples, with no inputs or outputs.
+    pub fn new(samples: usize) -> Self {
+        Self {
+            samples,
+            ..Default::default()
+        }
+    }
+
+    /// Adds an input channel, which must have the length of the block.
+    pub fn with_input(mut self, samples: &[f32]) -> Self {
+        assert!(
+            samples.len() == self.samples,
+            "Expected an input of {} samples",
+            self.samples
+        );
+        self.audio_in.push(samples.to_vec());
+        self
+    }
+
+    /// Adds `channels` output channels, which are filled with `value` before the block is processed.
+    pub fn with_outputs(mut self, channels: usize, value: f32) -> Self {
+        self.audio_out.extend((0..channels).map(|_| vec![value; self.samples]));
+        self
+    }
+
+    pub fn with_midi_in(mut self, events: &[TimedMidiEvent]) -> Self {
+        self.midi_in = events.to_vec();
+        self
+    }
+
+    pub fn with_param_events(mut self, events: &[ParamEvent]) -> Self {
+        self.param_events = events.to_vec();
+        self
+    }
+
+    pub fn with_accumulate(mut self, accumulate: bool) -> Self {
+        self.accumulate = accumulate;
+        self
+    }
+
+    /// Processes the block with a processor, appending its MIDI output to that of any previous blocks.
+    pub fn process(&mut self, processor: &mut dyn Processor) {
+        self.borrow(|data| processor.process(data));
+    }
+
+    /// Calls `f` with the block as [`ProcessorData`].
+    pub fn borrow<R>(&mut self, f: impl FnOnce(ProcessorData) -> R) -> R {
+        let audio_in: Vec<&[f32]> = self.audio_in.iter().map(|b| &b[..]).collect();
+        let mut audio_out: Vec<&mut [f32]> = self.audio_out.iter_mut().map(|b| &mut b[..]).collect();
+        f(ProcessorData {
+            midi_in: &self.midi_in,
+            midi_out: &mut self.midi_out,
+            samples: self.samples,
+            audio_in: &audio_in,
+            audio_out: &mut audio_out,
+            param_events: &self.param_events,
+            accumulate: self.accumulate,
+        })
+    }
+
+    /// Gets an output channel.
+    pub fn output(&self, channel: usize) -> &[f32] {
+        &self.audio_out[channel]
+    }
+
+    pub fn outputs(&self) -> &[Vec<f32>] {
+        &self.audio_out
+    }
+
+    /// Gets the output channels interleaved into a single buffer.
+    pub fn interleaved_output(&self) -> Vec<f32> {
+        let outputs: Vec<&[f32]> = self.audio_out.iter().map(|b| &b[..]).collect();
+        let mut interleaved = vec![0.0; outputs.len() * self.samples];
+        interleave(&outputs, &mut interleaved);
+        interleaved
+    }
+
+    pub fn midi_out(&self) -> &[TimedMidiEvent] {
+        &self.midi_out
+    }
+}
@@ -0,0 +1,201 @@
+use super::{Processor, Sampler};
+use crate::{
+    audio::{
+        buffer::{StereoBuffer, StereoBufferMut},
+        envelope_follower::EnvelopeFollower,
+        sample::AudioSample,
+    },
+    note::Note,
+    util::scale_from_gain,
+};
+use std::sync::Arc;
+
+/// Plays a sample once whenever the level of the input rises above a threshold, such as to replace or
+/// reinforce drum hits. After each trigger, further triggers are ignored until the lockout time has passed.
+pub struct TriggeredSampler {
+    sampler: Sampler,
+    follower: EnvelopeFollower,
+    sample_rate: f32,
+    /// Level in dB above which the sample is triggered.
+    threshold: f32,
+    /// Minimum time in seconds between triggers.
+    lockout: f32,
+    /// Proportion of the sample in the output, with the rest being the input.
+    mix: f32,
+    /// Whether the input level was above the threshold at the previous sample.
+    above: bool,
+    /// Number of samples since the last trigger, or `None` if the sample hasn't been triggered.
+    since_trigger: Option<usize>,
+    /// Scratch buffers for the output of the sampler.
+    buffers: [Vec<f32>; 2],
+}
+
+impl TriggeredSampler {
+    pub fn new(sample: Arc<AudioSample>) -> Self {
+        let mut sampler = Sampler::new(sample);
+        sampler.set_one_hit(true);
+        Self {
+            sampler,
+            follower: EnvelopeFollower::new(),
+            sample_rate: 0.0,
+            threshold: -20.0,
+            lockout: 0.05,
+            mix: 1.0,
+            above: false,
+            since_trigger: None,
+            buffers: [vec![], vec![]],
+        }
+    }
+
+    pub fn set_sample(&mut self, sample: Arc<AudioSample>) {
+        self.sampler.set_sample(sample);
+        self.since_trigger = None;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.sampler.set_sample_rate(sample_rate);
+        self.follower.set_sample_rate(sample_rate);
+    }
+
+    /// Sets the level in dB above which the sample is triggered.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Sets the minimum time in seconds between triggers.
+    pub fn set_lockout(&mut self, lockout: f32) {
+        self.lockout = lockout.max(0.0);
+    }
+
+    /// Sets the proportion of the sample in the output between `0.0` and `1.0`, where `1.0` replaces the input.
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Gets the envelope follower used to detect transients, such as to change its release time.
+    pub fn follower_mut(&mut self) -> &mut EnvelopeFollower {
+        &mut self.follower
+    }
+
+    pub fn process(&mut self, audio_in: StereoBuffer, audio_out: StereoBufferMut) {
+        assert!(audio_in.len() == audio_out.len());
+        let len = audio_in.len();
+
+        let [mut left, mut right] = std::mem::take(&mut self.buffers);
+        left.resize(len, 0.0);
+        right.resize(len, 0.0);
+        left.fill(0.0);
+        right.fill(0.0);
+
+        // Play the sample from each sample where the input rises above the threshold
+        let threshold = scale_from_gain(self.threshold);
+        let lockout = (self.lockout * self.sample_rate) as usize;
+        let mut start = 0;
+        for (i, (&in_left, &in_right)) in audio_in.left.iter().zip(audio_in.right.iter()).enumerate() {
+            let above = self.follower.process_sample(in_left.abs().max(in_right.abs())) > threshold;
+            let locked = self.since_trigger.is_some_and(|since| since + (i - start) < lockout);
+            if above && !self.above && !locked {
+                if self.since_trigger.is_some() {
+                    self.sampler
+                        .process(StereoBufferMut::new(&mut left[start..i], &mut right[start..i]));
+                }
+                self.sampler.trigger(Note::middle_c());
+                self.since_trigger = Some(0);
+                start = i;
+            }
+            self.above = above;
+        }
+        if let Some(since) = self.since_trigger.as_mut() {
+            self.sampler
+                .process(StereoBufferMut::new(&mut left[start..], &mut right[start..]));
+            *since += len - start;
+        }
+
+        let (dry, wet) = (1.0 - self.mix, self.mix);
+        let samples_in = audio_in.left.iter().zip(audio_in.right.iter());
+        let samples_out = audio_out.left.iter_mut().zip(audio_out.right.iter_mut());
+        let samples_wet = left.iter().zip(right.iter());
+        for (((in_left, in_right), (out_left, out_right)), (wet_left, wet_right)) in
+            samples_in.zip(samples_out).zip(samples_wet)
+        {
+            *out_left = dry * in_left + wet * wet_left;
+            *out_right = dry * in_right + wet * wet_right;
+        }
+        self.buffers = [left, right];
+    }
+}
+
+impl Processor for TriggeredSampler {
+    fn description(&self) -> super::ProcessorDescription {
+        super::ProcessorDescription {
+            min_audio_ins: 2,
+            max_audio_ins: 2,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.set_sample_rate(sample_rate);
+    }
+
+    fn set_parameter(&mut self, param_id: usize, value: f32) {
+        match param_id {
+            0 => self.set_threshold(value),
+            1 => self.set_lockout(value),
+            2 => self.set_mix(value),
+            _ => {}
+        }
+    }
+
+    fn process(&mut self, data: super::ProcessorData) {
+        let [left, right, ..] = data.audio_in else {
+            panic!("Expected at least two input audio buffers");
+        };
+        let audio_in = StereoBuffer::new(left, right);
+
+        let [left, right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        let audio_out = StereoBufferMut::new(left, right);
+
+        self.process(audio_in, audio_out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::buffer::MonoBuffer;
+
+    /// Feeds clicks at the given times into the processor, and returns the number of times the sample plays.
+    fn count_triggers(clicks: &[usize]) -> usize {
+        let sample = AudioSample::new_mono(48000, MonoBuffer::new(&[0.5; 100]));
+        let mut triggered = TriggeredSampler::new(Arc::new(sample));
+        triggered.set_sample_rate(48000);
+        triggered.set_lockout(0.05);
+
+        let mut input = vec![0.0; 12000];
+        for &click in clicks {
+            input[click] = 1.0;
+        }
+        let mut left = vec![0.0; 12000];
+        let mut right = vec![0.0; 12000];
+        for ((input, left), right) in input.chunks(512).zip(left.chunks_mut(512)).zip(right.chunks_mut(512)) {
+            triggered.process(StereoBuffer::new(input, input), StereoBufferMut::new(left, right));
+        }
+
+        assert_eq!(left, right);
+        left.windows(2).filter(|w| w[0] < 0.25 && w[1] >= 0.25).count()
+    }
+
+    #[test]
+    fn test_lockout() {
+        assert_eq!(count_triggers(&[]), 0);
+        assert_eq!(count_triggers(&[1000]), 1);
+        // The second click is within the lockout time
+        assert_eq!(count_triggers(&[1000, 2000]), 1);
+        // The second click is beyond the lockout time
+        assert_eq!(count_triggers(&[1000, 6000]), 2);
+    }
+}
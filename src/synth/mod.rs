@@ -1,28 +1,141 @@
 use self::voice::VoiceManager;
 use crate::{
-    audio::buffer::StereoBufferMut,
     midi::{MidiEvent, TimedMidiEvent},
+    note::Tuning,
     processor::{Processor, ProcessorData, ProcessorDescription},
-    voice::oscillator::SimpleOscillator,
+    voice::{
+        filtered::Filtered,
+        legato::Legato,
+        oscillator::{SimpleOscillator, VoiceOpts},
+    },
 };
+pub use patch::SynthPatch;
+pub use sample_instrument::{SampleInstrument, SampleZone};
+pub use voice::StealPolicy;
 
+mod patch;
+mod sample_instrument;
 mod voice;
 
+/// A voice of a [`SimpleSynth`], which glides between overlapping notes and is filtered as set by its patch.
+/// Without a glide or a filter cutoff, the `Legato` and `Filtered` wrappers are bypassed.
+type SynthVoice = Filtered<Legato<SimpleOscillator>>;
+
 pub struct SimpleSynth {
-    voices: VoiceManager<SimpleOscillator>,
+    voices: VoiceManager<SynthVoice>,
+    /// The settings applied to each voice.
+    patch: SynthPatch,
+    /// The tuning applied to each voice.
+    tuning: Tuning,
 }
 
 impl SimpleSynth {
     pub fn new() -> Self {
+        let patch = SynthPatch::default();
+        let tuning = Tuning::default();
         Self {
-            voices: VoiceManager::new(32, SimpleOscillator::new()),
+            voices: VoiceManager::new(32, Self::voice(&patch, tuning)),
+            patch,
+            tuning,
         }
     }
+
+    /// Sets the settings of the oscillator of each voice. Sounding voices keep their settings until they fall idle.
+    pub fn set_voice_opts(&mut self, opts: VoiceOpts) {
+        self.patch.voice = opts;
+        self.update_template();
+    }
+
+    /// Sets the time constant of the glide between overlapping notes played by the same voice in seconds, or `0.0`
+    /// for no glide, in which case each note retriggers its voice. Sounding voices keep their settings until they
+    /// fall idle.
+    pub fn set_glide(&mut self, glide: f32) {
+        self.patch.glide = glide;
+        self.update_template();
+    }
+
+    /// Sets the cutoff in Hz and the quality factor of the lowpass filter of each voice, where a cutoff of `None`
+    /// leaves the filter open. Sounding voices keep their settings until they fall idle.
+    pub fn set_filter(&mut self, cutoff: Option<f32>, resonance: f32) {
+        self.patch.filter_cutoff = cutoff;
+        self.patch.filter_resonance = resonance;
+        self.update_template();
+    }
+
+    /// Sets the tuning of each voice. Sounding voices keep their tuning until they fall idle.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+        self.update_template();
+    }
+
+    /// Gets the strategy used to choose which voice to steal when every voice is in use.
+    pub fn steal_policy(&self) -> StealPolicy {
+        self.voices.steal_policy()
+    }
+
+    /// Sets the strategy used to choose which voice to steal when every voice is in use.
+    pub fn set_steal_policy(&mut self, policy: StealPolicy) {
+        self.voices.set_steal_policy(policy);
+    }
+
+    /// Gets the current level of each voice, such as to display voice activity.
+    pub fn voice_levels(&self, out: &mut Vec<f32>) {
+        self.voices.voice_levels(out);
+    }
+
+    /// Sets the range of pitch bend messages in semitones either side of the note, which defaults to one semitone.
+    pub fn set_pitch_bend_range(&mut self, semitones: f32) {
+        self.voices.set_pitch_bend_range(semitones);
+    }
+
+    /// Sets whether the summed output of the voices is soft clipped, so that dense chords saturate gracefully.
+    pub fn set_soft_clip(&mut self, soft_clip: bool) {
+        self.voices.set_soft_clip(soft_clip);
+    }
+
+    /// Sets the number of stereo output buses which voices are routed to, such as for per-voice effects.
+    /// Each voice is routed to its own bus while there is one free, and the remaining voices share the last bus.
+    /// This should be set before the synth is added to an engine, as it changes the number of outputs.
+    pub fn set_num_buses(&mut self, num_buses: usize) {
+        self.voices.set_num_buses(num_buses);
+    }
+
+    /// Builds a voice with the settings of a patch.
+    fn voice(patch: &SynthPatch, tuning: Tuning) -> SynthVoice {
+        let mut osc = SimpleOscillator::new();
+        osc.set_opts(patch.voice);
+        osc.set_tuning(tuning);
+        let mut legato = Legato::new(osc);
+        legato.set_enabled(patch.glide > 0.0);
+        legato.set_glide_time(patch.glide);
+        let mut voice = Filtered::new(legato);
+        voice.set_cutoff(patch.filter_cutoff);
+        voice.set_resonance(patch.filter_resonance);
+        voice
+    }
+
+    fn update_template(&mut self) {
+        self.voices.set_template(Self::voice(&self.patch, self.tuning));
+    }
+
+    /// Captures the current settings of the synth as a patch.
+    pub fn save_patch(&self) -> SynthPatch {
+        self.patch
+    }
+
+    /// Restores the settings of the synth from a patch.
+    pub fn load_patch(&mut self, patch: &SynthPatch) {
+        self.patch = *patch;
+        self.update_template();
+    }
 }
 
 impl SimpleSynth {
-    fn process(&mut self, midi_in: &[TimedMidiEvent], audio_out: StereoBufferMut) {
-        self.voices.process_midi(midi_in, audio_out)
+    fn process(&mut self, midi_in: &[TimedMidiEvent], audio_out: &mut [&mut [f32]], accumulate: bool) {
+        if !accumulate {
+            audio_out.iter_mut().for_each(|buffer| buffer.fill(0.0));
+        }
+        self.voices.process_midi_adding(midi_in, audio_out)
     }
 }
 
@@ -31,7 +144,7 @@ impl Processor for SimpleSynth {
         ProcessorDescription {
             min_audio_ins: 0,
             max_audio_ins: 0,
-            num_audio_outs: 2,
+            num_audio_outs: 2 * self.voices.num_buses(),
         }
     }
 
@@ -39,13 +152,12 @@ impl Processor for SimpleSynth {
         self.voices.set_sample_rate(sample_rate)
     }
 
-    fn process(&mut self, data: ProcessorData) {
-        let [left, right] = data.audio_out else {
-            panic!("Expected at least two output audio buffers");
-        };
-        let audio_out = StereoBufferMut::new(left, right);
+    fn supports_accumulate(&self) -> bool {
+        true
+    }
 
-        self.process(data.midi_in, audio_out);
+    fn process(&mut self, data: ProcessorData) {
+        self.process(data.midi_in, data.audio_out, data.accumulate);
     }
 }
 
@@ -0,0 +1,145 @@
+use crate::voice::oscillator::VoiceOpts;
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// A recallable set of settings for a [`super::SimpleSynth`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SynthPatch {
+    /// The settings of the oscillator of each voice.
+    pub voice: VoiceOpts,
+    /// Time constant in seconds of the glide between overlapping notes played by the same voice, or zero for no
+    /// glide. See [`crate::voice::legato::Legato`].
+    pub glide: f32,
+    /// Cutoff of the lowpass filter of each voice in Hz, or `None` to leave the filter open.
+    /// See [`crate::voice::filtered::Filtered`].
+    pub filter_cutoff: Option<f32>,
+    /// Quality factor of the lowpass filter, where `1 / sqrt(2)` gives no resonant peak.
+    pub filter_resonance: f32,
+}
+
+impl Default for SynthPatch {
+    fn default() -> Self {
+        Self {
+            voice: VoiceOpts::default(),
+            glide: 0.0,
+            filter_cutoff: None,
+            filter_resonance: FRAC_1_SQRT_2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        audio::buffer::StereoBufferMut,
+        midi::{MidiEvent, TimedMidiEvent},
+        note::{Note, Tuning},
+        processor::Processor,
+        synth::SimpleSynth,
+        voice::{
+            oscillator::{SimpleOscillator, Waveform},
+            VelocityCurve, Voice,
+        },
+    };
+
+    #[test]
+    fn test_save_and_load() {
+        let mut synth = SimpleSynth::new();
+        synth.set_voice_opts(VoiceOpts {
+            wave: Waveform::Sawtooth,
+            attack: 0.05,
+            decay: 0.3,
+            sustain: 0.6,
+            release: 0.8,
+            unison: 3,
+            unison_detune: 15.0,
+            vibrato_rate: 6.0,
+            vibrato_depth: 20.0,
+            velocity_curve: VelocityCurve::Exponential,
+            retrigger_phase: Some(0.25),
+            sync_ratio: Some(1.5),
+        });
+        synth.set_glide(0.1);
+        synth.set_filter(Some(2000.0), 2.0);
+        let patch = synth.save_patch();
+
+        synth.load_patch(&SynthPatch::default());
+        assert_ne!(synth.save_patch(), patch);
+
+        synth.load_patch(&patch);
+        assert_eq!(synth.save_patch(), patch);
+        assert!(synth.voices.voices().all(|v| v.inner().inner().opts() == patch.voice));
+    }
+
+    #[test]
+    fn test_filter() {
+        // Gets the energy of the first difference of a sawtooth note, which is dominated by its harmonics
+        let high_frequency_energy = |filter_cutoff| {
+            let mut synth = SimpleSynth::new();
+            synth.set_sample_rate(48000);
+            synth.load_patch(&SynthPatch {
+                voice: VoiceOpts {
+                    wave: Waveform::Sawtooth,
+                    ..Default::default()
+                },
+                filter_cutoff,
+                ..Default::default()
+            });
+            let note_on = TimedMidiEvent {
+                time: 0,
+                event: MidiEvent::NoteOn {
+                    channel: 0,
+                    note: Note::from_name("A2").unwrap(),
+                    velocity: 127,
+                },
+            };
+            let mut left = vec![0.0; 4800];
+            let mut right = vec![0.0; 4800];
+            synth.process(&[note_on], &mut [&mut left, &mut right], false);
+            left[2400..].windows(2).map(|w| (w[1] - w[0]).powi(2)).sum::<f32>()
+        };
+
+        // The filter of each voice attenuates the harmonics
+        assert!(high_frequency_energy(Some(500.0)) < 0.1 * high_frequency_energy(None));
+    }
+
+    #[test]
+    fn test_retrigger_without_glide() {
+        // Triggers two overlapping notes, then releases the second one
+        let render = |voice: &mut dyn Voice| {
+            let [c4, e4] = ["C4", "E4"].map(|n| Note::from_name(n).unwrap());
+            let mut left = vec![0.0; 1440];
+            let mut right = vec![0.0; 1440];
+            voice.set_sample_rate(48000);
+            voice.trigger(c4, 100);
+            voice.process(StereoBufferMut::new(&mut left[..480], &mut right[..480]));
+            voice.trigger(e4, 100);
+            voice.process(StereoBufferMut::new(&mut left[480..960], &mut right[480..960]));
+            voice.release();
+            voice.process(StereoBufferMut::new(&mut left[960..], &mut right[960..]));
+            left
+        };
+        let patch = SynthPatch {
+            voice: VoiceOpts {
+                wave: Waveform::Sawtooth,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut osc = SimpleOscillator::new();
+        osc.set_opts(patch.voice);
+        let expected = render(&mut osc);
+
+        // Without glide, each note retriggers the voice at its exact pitch, as with a bare oscillator
+        let mut voice = SimpleSynth::voice(&patch, Tuning::default());
+        assert_eq!(render(&mut voice), expected);
+        assert_eq!(voice.held_note(), None);
+
+        // With glide, the second note bends the first, and releasing it returns to the first
+        let patch = SynthPatch { glide: 0.05, ..patch };
+        let mut voice = SimpleSynth::voice(&patch, Tuning::default());
+        assert_ne!(render(&mut voice), expected);
+        assert_eq!(voice.held_note(), Note::from_name("C4"));
+    }
+}
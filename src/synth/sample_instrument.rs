@@ -0,0 +1,340 @@
+use super::voice::{StealPolicy, VoiceManager};
+use crate::{
+    audio::{buffer::StereoBufferMut, sample::AudioSample},
+    note::Note,
+    processor::{Processor, ProcessorData, ProcessorDescription, Sampler},
+    util::LinearRamp,
+    voice::Voice,
+};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Number of samples rendered by a voice at a time.
+const BLOCK_SIZE: usize = 64;
+
+/// Time in seconds over which a released voice fades out, so that it doesn't click.
+const RELEASE_TIME: f32 = 0.01;
+
+/// A sample mapped to a range of notes and velocities.
+#[derive(Clone)]
+pub struct SampleZone {
+    /// The lowest note of the zone.
+    pub low: Note,
+    /// The highest note of the zone.
+    pub high: Note,
+    /// The lowest velocity of the zone.
+    pub vel_low: u8,
+    /// The highest velocity of the zone.
+    pub vel_high: u8,
+    /// The note which plays the sample at its original pitch.
+    pub root: Note,
+    pub sample: Arc<AudioSample>,
+}
+
+impl SampleZone {
+    fn contains(&self, note: Note, velocity: u8) -> bool {
+        (self.low.0..=self.high.0).contains(&note.0) && (self.vel_low..=self.vel_high).contains(&velocity)
+    }
+}
+
+/// A polyphonic sampler instrument, which plays a different sample depending on the note and velocity.
+///
+/// When several zones cover the same note and velocity, successive notes cycle through them in turn.
+pub struct SampleInstrument {
+    voices: VoiceManager<SamplerVoice>,
+    zones: Vec<SampleZone>,
+    /// Counter shared by the voices to cycle through overlapping zones.
+    round_robin: Arc<AtomicUsize>,
+}
+
+impl Default for SampleInstrument {
+    fn default() -> Self {
+        let round_robin = Arc::new(AtomicUsize::new(0));
+        Self {
+            voices: VoiceManager::new(32, SamplerVoice::new(Arc::new([]), round_robin.clone())),
+            zones: vec![],
+            round_robin,
+        }
+    }
+}
+
+impl SampleInstrument {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Maps a sample to the given range of notes and velocities, both inclusive.
+    /// The sample plays at its original pitch for the `root` note.
+    pub fn add_zone(&mut self, low: Note, high: Note, vel_low: u8, vel_high: u8, root: Note, sample: Arc<AudioSample>) {
+        self.zones.push(SampleZone {
+            low,
+            high,
+            vel_low,
+            vel_high,
+            root,
+            sample,
+        });
+        let voice = SamplerVoice::new(self.zones.clone().into(), self.round_robin.clone());
+        self.voices.set_template(voice);
+    }
+
+    /// Gets the zones of the instrument.
+    pub fn zones(&self) -> &[SampleZone] {
+        &self.zones
+    }
+
+    /// Gets the strategy used to choose which voice to steal when every voice is in use.
+    pub fn steal_policy(&self) -> StealPolicy {
+        self.voices.steal_policy()
+    }
+
+    /// Sets the strategy used to choose which voice to steal when every voice is in use.
+    pub fn set_steal_policy(&mut self, policy: StealPolicy) {
+        self.voices.set_steal_policy(policy);
+    }
+
+    /// Gets the current level of each voice, such as to display voice activity.
+    pub fn voice_levels(&self, out: &mut Vec<f32>) {
+        self.voices.voice_levels(out);
+    }
+}
+
+impl Processor for SampleInstrument {
+    fn description(&self) -> ProcessorDescription {
+        ProcessorDescription {
+            min_audio_ins: 0,
+            max_audio_ins: 0,
+            num_audio_outs: 2,
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.voices.set_sample_rate(sample_rate)
+    }
+
+    fn process(&mut self, data: ProcessorData) {
+        let [left, right, ..] = data.audio_out else {
+            panic!("Expected at least two output audio buffers");
+        };
+        let audio_out = StereoBufferMut::new(left, right);
+
+        self.voices.process_midi(data.midi_in, audio_out);
+    }
+}
+
+/// A voice which plays the sample of the zone matching each note.
+#[derive(Clone)]
+struct SamplerVoice {
+    sampler: Sampler,
+    zones: Arc<[SampleZone]>,
+    round_robin: Arc<AtomicUsize>,
+    /// Whether a zone matched the most recently triggered note, and it hasn't finished fading out.
+    playing: bool,
+    gain: f32,
+    /// The level of the voice, which fades out once it is released.
+    envelope: LinearRamp,
+    sample_rate: f32,
+    /// Scratch buffers for the output of the sampler.
+    scratch: [[f32; BLOCK_SIZE]; 2],
+}
+
+impl SamplerVoice {
+    fn new(zones: Arc<[SampleZone]>, round_robin: Arc<AtomicUsize>) -> Self {
+        let mut sampler = Sampler::new_empty();
+        sampler.set_one_hit(true);
+        Self {
+            sampler,
+            zones,
+            round_robin,
+            playing: false,
+            gain: 0.0,
+            envelope: LinearRamp::new(0.0),
+            sample_rate: 0.0,
+            scratch: [[0.0; BLOCK_SIZE]; 2],
+        }
+    }
+}
+
+impl Voice for SamplerVoice {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.sampler.set_sample_rate(sample_rate);
+    }
+
+    fn trigger(&mut self, note: Note, velocity: u8) {
+        let mut matching = self.zones.iter().filter(|z| z.contains(note, velocity));
+        let count = matching.clone().count();
+        self.playing = count > 0;
+        if !self.playing {
+            return;
+        }
+
+        let idx = self.round_robin.fetch_add(1, Ordering::Relaxed) % count;
+        let zone = matching.nth(idx).unwrap();
+        self.sampler.set_sample(zone.sample.clone());
+        self.sampler.set_root_note(Some(zone.root));
+        self.sampler.trigger(note);
+        self.gain = velocity as f32 / 127.0;
+        self.envelope.set(1.0);
+    }
+
+    fn release(&mut self) {
+        let samples = (RELEASE_TIME * self.sample_rate) as usize;
+        self.envelope.ramp_to(0.0, samples);
+        self.playing = self.playing && self.envelope.value() > 0.0;
+    }
+
+    fn set_pitch_bend(&mut self, _bend: f32) {}
+
+    fn amplitude(&self) -> f32 {
+        if self.playing {
+            self.gain * self.envelope.value()
+        } else {
+            0.0
+        }
+    }
+
+    fn tracks_amplitude(&self) -> bool {
+        true
+    }
+
+    fn process(&mut self, mut audio_out: StereoBufferMut) -> bool {
+        if !self.playing {
+            return false;
+        }
+
+        let mut i = 0;
+        while i < audio_out.len() {
+            let j = (i + BLOCK_SIZE).min(audio_out.len());
+            let [left, right] = &mut self.scratch;
+            let (left, right) = (&mut left[..(j - i)], &mut right[..(j - i)]);
+            self.sampler.process(StereoBufferMut::new(left, right));
+
+            let StereoBufferMut {
+                left: out_left,
+                right: out_right,
+            } = audio_out.slice_mut(i..j);
+            let out = out_left.iter_mut().zip(out_right.iter_mut());
+            for ((out_left, out_right), (&l, &r)) in out.zip(left.iter().zip(right.iter())) {
+                let gain = self.gain * self.envelope.advance();
+                *out_left += gain * l;
+                *out_right += gain * r;
+            }
+            i = j;
+        }
+
+        self.playing = self.sampler.is_playing() && self.envelope.value() > 0.0;
+        self.playing
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        audio::buffer::MonoBuffer,
+        midi::{MidiEvent, TimedMidiEvent},
+    };
+
+    fn constant_sample(value: f32) -> Arc<AudioSample> {
+        Arc::new(AudioSample::new_mono(48000, MonoBuffer::new(&[value; 4800])))
+    }
+
+    /// Plays a single note, returning the level of the first output sample divided by the velocity gain.
+    fn play(instrument: &mut SampleInstrument, note: Note, velocity: u8) -> f32 {
+        let midi_in = [TimedMidiEvent {
+            time: 0,
+            event: MidiEvent::NoteOn {
+                channel: 0,
+                note,
+                velocity,
+            },
+        }];
+        let off = [TimedMidiEvent {
+            time: 0,
+            event: MidiEvent::NoteOff {
+                channel: 0,
+                note,
+                velocity: 0,
+            },
+        }];
+        let mut left = [0.0; 64];
+        let mut right = [0.0; 64];
+        instrument
+            .voices
+            .process_midi(&midi_in, StereoBufferMut::new(&mut left, &mut right));
+        instrument
+            .voices
+            .process_midi(&off, StereoBufferMut::new(&mut [0.0; 1024], &mut [0.0; 1024]));
+        left[0] * 127.0 / velocity as f32
+    }
+
+    #[test]
+    fn test_zones() {
+        let mut instrument = SampleInstrument::new();
+        instrument.set_sample_rate(48000);
+        let [c0, b3, c4, c8] = ["C0", "B3", "C4", "C8"].map(|n| Note::from_name(n).unwrap());
+        instrument.add_zone(c0, b3, 0, 127, c0, constant_sample(0.25));
+        instrument.add_zone(c4, c8, 0, 63, c4, constant_sample(0.5));
+        instrument.add_zone(c4, c8, 64, 127, c4, constant_sample(0.75));
+
+        assert!((play(&mut instrument, Note(40), 100) - 0.25).abs() < 1e-4);
+        assert!((play(&mut instrument, Note(70), 30) - 0.5).abs() < 1e-4);
+        assert!((play(&mut instrument, Note(70), 100) - 0.75).abs() < 1e-4);
+
+        // Notes outside of every zone are silent
+        assert_eq!(play(&mut instrument, Note(127), 100), 0.0);
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut instrument = SampleInstrument::new();
+        instrument.set_sample_rate(48000);
+        let (low, high) = (Note(0), Note(127));
+        instrument.add_zone(low, high, 0, 127, low, constant_sample(0.25));
+        instrument.add_zone(low, high, 0, 127, low, constant_sample(0.5));
+
+        let levels: Vec<_> = (0..4).map(|_| play(&mut instrument, Note(60), 127)).collect();
+        assert!(levels[0] != levels[1]);
+        assert_eq!(levels[0], levels[2]);
+        assert_eq!(levels[1], levels[3]);
+    }
+
+    #[test]
+    fn test_release_fades_out() {
+        let mut instrument = SampleInstrument::new();
+        instrument.set_sample_rate(48000);
+        instrument.add_zone(Note(0), Note(127), 0, 127, Note(60), constant_sample(0.5));
+        let event = |time, event| TimedMidiEvent { time, event };
+        let midi_in = [
+            event(
+                0,
+                MidiEvent::NoteOn {
+                    channel: 0,
+                    note: Note(60),
+                    velocity: 127,
+                },
+            ),
+            event(
+                100,
+                MidiEvent::NoteOff {
+                    channel: 0,
+                    note: Note(60),
+                    velocity: 0,
+                },
+            ),
+        ];
+
+        // The note fades out over the release time, rather than stopping straight away
+        let mut left = [0.0; 1024];
+        let mut right = [0.0; 1024];
+        let audio_out = StereoBufferMut::new(&mut left, &mut right);
+        instrument.voices.process_midi(&midi_in, audio_out);
+        assert!(left[..100].iter().all(|&s| s == 0.5));
+        assert!(left[100..].windows(2).all(|w| w[1] <= w[0]));
+        assert!(left[300] > 0.1 && left[300] < 0.4);
+        assert_eq!(left[700], 0.0);
+    }
+}
@@ -4,77 +4,289 @@ use crate::{
     note::Note,
     voice::Voice,
 };
+use std::{cmp::Ordering, ops::Range};
+
+/// Number of MIDI channels, each of which has its own pitch bend range.
+const NUM_CHANNELS: usize = 16;
+/// The registered parameter number of the pitch bend range.
+const RPN_PITCH_BEND_RANGE: u16 = 0;
+/// Amplitude below which a released voice is inaudible, around -80dB, so it is freed before its release ends.
+const SILENCE_THRESHOLD: f32 = 1e-4;
+/// Number of samples of each output buffer which are soft clipped at a time.
+const CLIP_CHUNK: usize = 64;
+
+/// The strategy used to choose which sounding voice to steal when every voice is in use.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StealPolicy {
+    /// Steal the voice which was triggered or released the longest time ago, preferring released voices.
+    #[default]
+    Oldest,
+    /// Steal the voice with the lowest amplitude, whether or not it has been released.
+    Quietest,
+    /// Steal the voice playing the lowest note, preferring released voices.
+    LowestNote,
+    /// Steal the voice playing the highest note, preferring released voices.
+    HighestNote,
+}
 
 pub struct VoiceManager<V: Voice + Clone> {
-    /// The maximum amount of pitch bend in cents
-    max_pitch_bend: usize,
+    /// The maximum amount of pitch bend in cents on each MIDI channel
+    max_pitch_bend: [usize; NUM_CHANNELS],
+    /// The registered parameter selected for data entry on each MIDI channel, if any
+    rpn: [Option<u16>; NUM_CHANNELS],
     /// The voices
     voices: Vec<VoiceHandle<V>>,
+    /// The voice which idle voices are reset to after the template changes
+    template: V,
+    /// The sample rate, which is also applied to the template
+    sample_rate: u32,
+    /// Whether the sustain pedal is held, deferring note releases until it is lifted
+    sustain: bool,
     /// Monotonic counter used to determine the least recently used voices
     counter: usize,
+    /// The strategy used to choose which voice to steal
+    steal_policy: StealPolicy,
+    /// The number of stereo output buses which voices are routed to
+    num_buses: usize,
+    /// Whether the summed output of the voices is soft clipped
+    soft_clip: bool,
+    /// Holds the existing contents of a chunk of each output buffer while the voices are soft clipped
+    clip_buffer: Vec<f32>,
 }
 
 impl<V: Voice + Clone> VoiceManager<V> {
     pub fn new(num_voices: usize, voice: V) -> Self {
-        let handle = VoiceHandle::new(voice);
+        let handle = VoiceHandle::new(voice.clone());
         Self {
-            max_pitch_bend: 100,
+            max_pitch_bend: [100; NUM_CHANNELS],
+            rpn: [None; NUM_CHANNELS],
             voices: std::iter::repeat(handle).take(num_voices).collect(),
+            template: voice,
+            sample_rate: 0,
+            sustain: false,
             counter: 0,
+            steal_policy: StealPolicy::default(),
+            num_buses: 1,
+            soft_clip: false,
+            clip_buffer: vec![0.0; 2 * CLIP_CHUNK],
         }
     }
 
     pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.template.set_sample_rate(sample_rate);
         for voice in &mut self.voices {
             voice.set_sample_rate(sample_rate);
         }
     }
 
+    /// Gets the voices.
+    #[cfg(test)]
+    pub fn voices(&self) -> impl Iterator<Item = &V> {
+        self.voices.iter().map(|v| &v.voice)
+    }
+
+    /// Gets the current level of each voice, where idle voices are silent.
+    pub fn voice_levels(&self, out: &mut Vec<f32>) {
+        out.clear();
+        out.extend(
+            self.voices
+                .iter()
+                .map(|v| if v.active() { v.voice.amplitude() } else { 0.0 }),
+        );
+    }
+
+    /// Replaces the voice that all voices are copied from.
+    /// Idle voices are updated immediately, and sounding voices are updated once they fall idle.
+    pub fn set_template(&mut self, mut voice: V) {
+        if self.sample_rate > 0 {
+            voice.set_sample_rate(self.sample_rate);
+        }
+        self.template = voice;
+        for voice in &mut self.voices {
+            voice.stale = true;
+            voice.refresh(&self.template);
+        }
+    }
+
+    /// Gets the strategy used to choose which voice to steal when every voice is in use.
+    pub fn steal_policy(&self) -> StealPolicy {
+        self.steal_policy
+    }
+
+    /// Sets the strategy used to choose which voice to steal when every voice is in use.
+    pub fn set_steal_policy(&mut self, policy: StealPolicy) {
+        self.steal_policy = policy;
+    }
+
+    /// Gets the number of stereo output buses which voices are routed to.
+    pub fn num_buses(&self) -> usize {
+        self.num_buses
+    }
+
+    /// Sets the number of stereo output buses which voices are routed to, such as for per-voice effects.
+    /// Each triggered voice is routed to its own bus while there is one free, after which the remaining voices
+    /// are summed into the last bus. With a single bus, which is the default, all the voices are summed.
+    /// Voices which are already sounding stay on their bus until they are retriggered.
+    pub fn set_num_buses(&mut self, num_buses: usize) {
+        self.num_buses = num_buses.max(1);
+        self.clip_buffer.resize(2 * self.num_buses * CLIP_CHUNK, 0.0);
+    }
+
+    /// Sets whether the summed output of the voices is soft clipped with `tanh`, so that dense chords saturate
+    /// gracefully rather than clipping hard. This is off by default.
+    pub fn set_soft_clip(&mut self, soft_clip: bool) {
+        self.soft_clip = soft_clip;
+    }
+
     pub fn trigger(&mut self, note: Note, velocity: u8) {
-        let voice = self.voices.iter_mut().min_by_key(|v| v.priority(note)).unwrap();
-        voice.trigger(note, velocity, self.counter);
+        let policy = self.steal_policy;
+        let index = (0..self.voices.len())
+            .min_by(|&a, &b| self.voices[a].compare(&self.voices[b], note, policy))
+            .unwrap();
+
+        // Route the voice to the first bus not used by another sounding voice
+        let is_used = |bus| {
+            let mut others = self.voices.iter().enumerate().filter(|&(i, _)| i != index);
+            others.any(|(_, v)| v.active() && v.bus == bus)
+        };
+        let bus = (0..self.num_buses)
+            .find(|&bus| !is_used(bus))
+            .unwrap_or(self.num_buses - 1);
+
+        self.voices[index].trigger(note, velocity, self.counter, bus);
         self.counter += 1;
     }
 
     pub fn release(&mut self, note: Note) {
         if let Some(voice) = self.voices.iter_mut().find(|v| v.on_note() == Some(note)) {
-            voice.release(self.counter);
+            if self.sustain {
+                voice.sustained = true;
+            } else {
+                voice.release(self.counter);
+                self.counter += 1;
+            }
+        } else {
+            // The note may still be held by a voice which has since moved on to a newer note
+            for voice in self.voices.iter_mut().filter(|v| v.active()) {
+                voice.release_held(note);
+            }
+        }
+    }
+
+    /// Sets whether the sustain pedal is held.
+    /// Lifting the pedal releases all the notes that were released while it was held.
+    pub fn set_sustain(&mut self, sustain: bool) {
+        self.sustain = sustain;
+        if !sustain {
+            for voice in self.voices.iter_mut().filter(|v| v.sustained) {
+                voice.release(self.counter);
+            }
             self.counter += 1;
         }
     }
 
+    /// Sets the range of pitch bend messages on every MIDI channel, in semitones either side of the note,
+    /// which defaults to one semitone. The range can also be set for each channel by registered parameter 0.
+    pub fn set_pitch_bend_range(&mut self, semitones: f32) {
+        self.max_pitch_bend = [(100.0 * semitones.max(0.0)).round() as usize; NUM_CHANNELS];
+    }
+
+    /// Tracks the registered parameter messages which set the pitch bend range of a channel, which select the
+    /// parameter with controllers 101 and 100, then set its semitones and cents with controllers 6 and 38.
+    fn control_change(&mut self, channel: u8, control: u8, value: u8) {
+        let channel = channel as usize % NUM_CHANNELS;
+        let rpn = &mut self.rpn[channel];
+        let range = &mut self.max_pitch_bend[channel];
+        match control {
+            101 => *rpn = Some(((value as u16) << 7) | (rpn.unwrap_or(0) & 0x7f)),
+            100 => *rpn = Some((rpn.unwrap_or(0) & !0x7f) | value as u16),
+            // Selecting a non-registered parameter deselects the registered parameter
+            98 | 99 => *rpn = None,
+            6 if *rpn == Some(RPN_PITCH_BEND_RANGE) => *range = 100 * value as usize + *range % 100,
+            38 if *rpn == Some(RPN_PITCH_BEND_RANGE) => *range = 100 * (*range / 100) + (value as usize).min(99),
+            _ => {}
+        }
+    }
+
     pub fn set_pitch_bend(&mut self, bend: f32) {
         for voice in &mut self.voices {
             voice.set_pitch_bend(bend);
         }
     }
 
-    pub fn process(&mut self, mut audio_out: StereoBufferMut) {
+    /// Adds the output of the active voices to a range of samples in the buffers of their buses.
+    /// Voices on buses beyond the provided buffers are added to the last bus.
+    fn process_range(&mut self, audio_out: &mut [&mut [f32]], range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let last_bus = (audio_out.len() / 2)
+            .checked_sub(1)
+            .expect("Expected at least two output audio buffers");
+        if last_bus == 0 {
+            let [left, right, ..] = audio_out else { unreachable!() };
+            return self.process_adding(StereoBufferMut::new(&mut left[range.clone()], &mut right[range]));
+        }
+        for voice in self.voices.iter_mut().filter(|v| v.active()) {
+            let bus = voice.bus.min(last_bus);
+            let [left, right] = &mut audio_out[(2 * bus)..(2 * bus + 2)] else {
+                unreachable!()
+            };
+            let audio_out = StereoBufferMut::new(&mut left[range.clone()], &mut right[range.clone()]);
+            if !voice.process(audio_out) {
+                voice.refresh(&self.template);
+            }
+        }
+    }
+
+    /// Adds the output of the active voices to the existing contents of the output buffer,
+    /// whichever bus they are routed to.
+    pub fn process_adding(&mut self, mut audio_out: StereoBufferMut) {
         if audio_out.len() == 0 {
             return;
         }
 
-        audio_out.clear();
         for voice in self.voices.iter_mut().filter(|v| v.active()) {
-            voice.process(audio_out.as_mut());
+            if !voice.process(audio_out.as_mut()) {
+                voice.refresh(&self.template);
+            }
         }
     }
 
-    pub fn process_midi(&mut self, midi_in: &[TimedMidiEvent], audio_out: StereoBufferMut) {
-        let mut vout = audio_out;
+    pub fn process_midi(&mut self, midi_in: &[TimedMidiEvent], mut audio_out: StereoBufferMut) {
+        audio_out.clear();
+        let StereoBufferMut { left, right } = audio_out;
+        self.process_midi_adding(midi_in, &mut [left, right]);
+    }
+
+    /// Like `process_midi`, but adds to the existing contents of the output buffers,
+    /// where `audio_out` has a left and right buffer for each bus. See `set_num_buses`.
+    /// When soft clipping is enabled, it is applied to the output of the voices before it is added.
+    pub fn process_midi_adding(&mut self, midi_in: &[TimedMidiEvent], audio_out: &mut [&mut [f32]]) {
+        let len = audio_out.first().map_or(0, |buffer| buffer.len());
+        let mut start = 0;
 
         for &TimedMidiEvent { time, event } in midi_in {
-            // Process audio up to this event, and update the output buffer
-            let time = time as usize;
-            self.process(vout.slice_mut(..time));
-            vout = vout.into_slice_mut(time..);
+            // Process audio up to this event
+            let end = start + time as usize;
+            self.process_clipped(audio_out, start..end);
+            start = end;
 
             // Process the MIDI event
             match event {
                 MidiEvent::NoteOn { note, velocity, .. } => self.trigger(note, velocity),
                 MidiEvent::NoteOff { note, .. } => self.release(note),
-                MidiEvent::PitchBend { value, .. } => {
-                    let bend = calc_pitch_bend(value, self.max_pitch_bend);
+                MidiEvent::ControlChange { control: 64, value, .. } => self.set_sustain(value >= 64),
+                MidiEvent::ControlChange {
+                    channel,
+                    control,
+                    value,
+                } => self.control_change(channel, control, value),
+                MidiEvent::PitchBend { channel, value } => {
+                    let bend = calc_pitch_bend(value, self.max_pitch_bend[channel as usize % NUM_CHANNELS]);
                     self.set_pitch_bend(bend);
                 }
                 _ => {}
@@ -82,7 +294,32 @@ impl<V: Voice + Clone> VoiceManager<V> {
         }
 
         // Process the remainder of the output buffer
-        self.process(vout);
+        self.process_clipped(audio_out, start..len);
+    }
+
+    /// Like `process_range`, but soft clips the output of the voices when enabled, leaving the existing contents
+    /// of the buffers as they are.
+    fn process_clipped(&mut self, audio_out: &mut [&mut [f32]], range: Range<usize>) {
+        if !self.soft_clip {
+            return self.process_range(audio_out, range);
+        }
+
+        // Set aside the existing contents of each chunk while the voices are rendered on their own
+        let mut clip_buffer = std::mem::take(&mut self.clip_buffer);
+        for start in range.clone().step_by(CLIP_CHUNK) {
+            let chunk = start..(start + CLIP_CHUNK).min(range.end);
+            for (buffer, saved) in audio_out.iter_mut().zip(clip_buffer.chunks_mut(CLIP_CHUNK)) {
+                saved[..chunk.len()].copy_from_slice(&buffer[chunk.clone()]);
+                buffer[chunk.clone()].fill(0.0);
+            }
+            self.process_range(audio_out, chunk.clone());
+            for (buffer, saved) in audio_out.iter_mut().zip(clip_buffer.chunks(CLIP_CHUNK)) {
+                for (sample, saved) in buffer[chunk.clone()].iter_mut().zip(saved) {
+                    *sample = sample.tanh() + saved;
+                }
+            }
+        }
+        self.clip_buffer = clip_buffer;
     }
 }
 
@@ -102,6 +339,12 @@ pub struct VoiceHandle<V: Voice> {
     voice: V,
     phase: VoicePhase,
     counter: usize,
+    /// Whether the voice should be reset to the template once it is idle.
+    stale: bool,
+    /// Whether the note has been released while the sustain pedal is held.
+    sustained: bool,
+    /// The output bus the voice is routed to.
+    bus: usize,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -117,6 +360,20 @@ impl<V: Voice> VoiceHandle<V> {
             voice,
             phase: VoicePhase::Off,
             counter: 0,
+            stale: false,
+            sustained: false,
+            bus: 0,
+        }
+    }
+
+    /// Resets the voice to the template if it is stale and idle.
+    fn refresh(&mut self, template: &V)
+    where
+        V: Clone,
+    {
+        if self.stale && self.phase == VoicePhase::Off {
+            self.voice.clone_from(template);
+            self.stale = false;
         }
     }
 
@@ -139,7 +396,8 @@ impl<V: Voice> VoiceHandle<V> {
     }
 
     /// Gets the priority used for voice allocation, with the lowest priority being preferred.
-    pub fn priority(&self, note: Note) -> usize {
+    /// Voices with the same priority are ordered by the steal policy.
+    fn priority(&self, note: Note, policy: StealPolicy) -> usize {
         match self.phase {
             // Note has been retriggered
             VoicePhase::On(n) if n == note => 0,
@@ -147,17 +405,42 @@ impl<V: Voice> VoiceHandle<V> {
             VoicePhase::Off => 1,
             // Released voice for the same note
             VoicePhase::Released(n) if n == note => 2,
-            // Oldest released note
-            VoicePhase::Released(_) => 3 + self.counter,
-            // Oldest triggered note
-            VoicePhase::On(_) => usize::MAX / 2 + self.counter,
+            // Released note
+            VoicePhase::Released(_) => 3,
+            // Triggered note, which is only as likely to be stolen as a released note if it is quieter
+            VoicePhase::On(_) if policy == StealPolicy::Quietest => 3,
+            VoicePhase::On(_) => 4,
+        }
+    }
+
+    /// Compares two voices for the allocation of a note, where the lesser voice is preferred.
+    pub fn compare(&self, other: &Self, note: Note, policy: StealPolicy) -> Ordering {
+        let pitch = |v: &Self| match v.phase {
+            VoicePhase::On(n) | VoicePhase::Released(n) => n.0,
+            VoicePhase::Off => 0,
+        };
+        let priority = self.priority(note, policy);
+        let order = priority.cmp(&other.priority(note, policy));
+        if order != Ordering::Equal || priority < 3 {
+            return order;
         }
+
+        // Choose which voice to steal, falling back to the oldest
+        let order = match policy {
+            StealPolicy::Oldest => Ordering::Equal,
+            StealPolicy::Quietest => self.voice.amplitude().total_cmp(&other.voice.amplitude()),
+            StealPolicy::LowestNote => pitch(self).cmp(&pitch(other)),
+            StealPolicy::HighestNote => pitch(other).cmp(&pitch(self)),
+        };
+        order.then(self.counter.cmp(&other.counter))
     }
 
-    pub fn trigger(&mut self, note: Note, velocity: u8, counter: usize) {
+    pub fn trigger(&mut self, note: Note, velocity: u8, counter: usize, bus: usize) {
         self.voice.trigger(note, velocity);
         self.phase = VoicePhase::On(note);
+        self.sustained = false;
         self.counter = counter;
+        self.bus = bus;
     }
 
     pub fn release(&mut self, counter: usize) {
@@ -168,10 +451,18 @@ impl<V: Voice> VoiceHandle<V> {
         };
 
         self.voice.release();
-        self.phase = VoicePhase::Released(note);
+        self.sustained = false;
+        self.phase = match self.voice.held_note() {
+            Some(held) => VoicePhase::On(held),
+            None => VoicePhase::Released(note),
+        };
         self.counter = counter;
     }
 
+    pub fn release_held(&mut self, note: Note) {
+        self.voice.release_held(note);
+    }
+
     pub fn set_pitch_bend(&mut self, bend: f32) {
         self.voice.set_pitch_bend(bend);
     }
@@ -179,16 +470,347 @@ impl<V: Voice> VoiceHandle<V> {
     /// Synthesises audio into the provided stereo buffer.
     /// A return value of `false` indicates that the voice is off and
     /// will not produce any more sound until it is re-triggered.
+    /// Released voices which track their amplitude are freed early once they stay below the silence threshold
+    /// for the whole block.
     pub fn process(&mut self, audio_out: StereoBufferMut) -> bool {
         if self.phase == VoicePhase::Off {
             return false;
         }
 
-        let active = self.voice.process(audio_out);
+        let is_silent = |voice: &V| voice.tracks_amplitude() && voice.amplitude() < SILENCE_THRESHOLD;
+        let was_silent = is_silent(&self.voice);
+        let mut active = self.voice.process(audio_out);
 
+        // The amplitude only falls during the release, so it was below the threshold throughout the block
+        if matches!(self.phase, VoicePhase::Released(_)) && was_silent && is_silent(&self.voice) {
+            active = false;
+        }
         if !active {
             self.phase = VoicePhase::Off;
         }
         active
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::voice::oscillator::{SimpleOscillator, VoiceOpts, Waveform};
+
+    #[test]
+    fn test_voice_levels() {
+        let mut template = SimpleOscillator::new();
+        template.set_opts(VoiceOpts {
+            attack: 0.01,
+            release: 0.01,
+            ..Default::default()
+        });
+        let mut manager = VoiceManager::new(2, template);
+        manager.set_sample_rate(48000);
+
+        // Record the level of the first voice after each of a number of short blocks
+        let render = |manager: &mut VoiceManager<SimpleOscillator>, blocks: usize| {
+            let mut levels = vec![];
+            (0..blocks)
+                .map(|_| {
+                    manager.process_adding(StereoBufferMut::new(&mut [0.0; 48], &mut [0.0; 48]));
+                    manager.voice_levels(&mut levels);
+                    levels[0]
+                })
+                .collect::<Vec<_>>()
+        };
+
+        manager.trigger(Note::middle_c(), 127);
+        let attack = render(&mut manager, 10);
+        assert!(attack[0] > 0.0);
+        assert!(attack.windows(2).all(|w| w[1] > w[0]));
+
+        manager.release(Note::middle_c());
+        let release = render(&mut manager, 10);
+        assert!(release.windows(2).all(|w| w[1] < w[0] || w[1] == 0.0));
+        assert_eq!(release[9], 0.0);
+    }
+
+    #[test]
+    fn test_set_template() {
+        let mut manager = VoiceManager::new(2, SimpleOscillator::new());
+        manager.set_sample_rate(48000);
+        manager.trigger(Note::middle_c(), 100);
+
+        let mut template = SimpleOscillator::new();
+        template.set_waveform(Waveform::Square);
+        manager.set_template(template);
+
+        // Only the idle voice is updated while the other is sounding
+        let waves = |m: &VoiceManager<SimpleOscillator>| m.voices().map(|v| v.opts().wave).collect::<Vec<_>>();
+        assert_eq!(waves(&manager), [Waveform::Sine, Waveform::Square]);
+
+        // The sounding voice is updated once it has been released and falls idle
+        manager.release(Note::middle_c());
+        let mut left = [0.0; 480];
+        let mut right = [0.0; 480];
+        manager.process_adding(StereoBufferMut::new(&mut left, &mut right));
+        assert_eq!(waves(&manager), [Waveform::Square, Waveform::Square]);
+    }
+
+    #[test]
+    fn test_sustain_pedal() {
+        let note = Note::middle_c();
+        let mut manager = VoiceManager::new(2, SimpleOscillator::new());
+        manager.set_sample_rate(48000);
+        let mut left = [0.0; 64];
+        let mut right = [0.0; 64];
+        let mut process = |manager: &mut VoiceManager<SimpleOscillator>, events: &[MidiEvent]| {
+            let events: Vec<_> = events.iter().map(|&event| TimedMidiEvent { time: 0, event }).collect();
+            manager.process_midi(&events, StereoBufferMut::new(&mut left, &mut right));
+        };
+        let pedal = |value| MidiEvent::ControlChange {
+            channel: 0,
+            control: 64,
+            value,
+        };
+        let note_on = MidiEvent::NoteOn {
+            channel: 0,
+            note,
+            velocity: 100,
+        };
+        let note_off = MidiEvent::NoteOff {
+            channel: 0,
+            note,
+            velocity: 0,
+        };
+
+        // Releasing a note while the pedal is held keeps it on
+        process(&mut manager, &[pedal(127), note_on, note_off]);
+        assert_eq!(manager.voices[0].on_note(), Some(note));
+
+        // Lifting the pedal releases it
+        process(&mut manager, &[pedal(0)]);
+        assert_eq!(manager.voices[0].on_note(), None);
+
+        // Retriggering a sustained note cancels its pending release
+        process(&mut manager, &[pedal(127), note_on, note_off, note_on, pedal(0)]);
+        assert_eq!(manager.voices[0].on_note(), Some(note));
+    }
+
+    #[test]
+    fn test_pitch_bend_range() {
+        // At this sample rate, each cycle of A4 is exactly 64 samples
+        let period = |manager: &mut VoiceManager<SimpleOscillator>, events: &[MidiEvent]| {
+            let events: Vec<_> = events.iter().map(|&event| TimedMidiEvent { time: 0, event }).collect();
+            let mut left = [0.0; 4096];
+            let mut right = [0.0; 4096];
+            manager.process_midi(&events, StereoBufferMut::new(&mut left, &mut right));
+
+            // Measure the mean period between the first and last upward zero crossings
+            let crossings: Vec<_> = (1..left.len())
+                .filter(|&i| left[i - 1] < 0.0 && left[i] >= 0.0)
+                .collect();
+            (crossings[crossings.len() - 1] - crossings[0]) as f32 / (crossings.len() - 1) as f32
+        };
+        let note_on = MidiEvent::NoteOn {
+            channel: 0,
+            note: Note(69),
+            velocity: 100,
+        };
+        let full_bend = |channel| MidiEvent::PitchBend { channel, value: 16383 };
+        let control = |channel, control, value| MidiEvent::ControlChange {
+            channel,
+            control,
+            value,
+        };
+        let new_manager = || {
+            let mut manager = VoiceManager::new(1, SimpleOscillator::new());
+            manager.set_sample_rate(28160);
+            manager
+        };
+        let semitone = 64.0 / 2f32.powf(1.0 / 12.0);
+        let whole_tone = 64.0 / 2f32.powf(2.0 / 12.0);
+
+        // With a range of two semitones, full deflection bends the note up a whole tone
+        let mut manager = new_manager();
+        manager.set_pitch_bend_range(2.0);
+        assert!((period(&mut manager, &[note_on, full_bend(0)]) - whole_tone).abs() < 0.1);
+
+        // The range can be set for a channel by registered parameter 0
+        let mut manager = new_manager();
+        let rpn = |channel| {
+            [
+                control(channel, 101, 0),
+                control(channel, 100, 0),
+                control(channel, 6, 2),
+            ]
+        };
+        assert!((period(&mut manager, &[note_on]) - 64.0).abs() < 0.1);
+        assert!((period(&mut manager, &[&rpn(0)[..], &[full_bend(0)]].concat()) - whole_tone).abs() < 0.1);
+        assert!((period(&mut manager, &[&rpn(1)[..], &[full_bend(2)]].concat()) - semitone).abs() < 0.1);
+
+        // Data entry is ignored once the parameter is deselected
+        let mut manager = new_manager();
+        let events = [
+            note_on,
+            control(0, 101, 0),
+            control(0, 100, 0),
+            control(0, 99, 1),
+            control(0, 6, 2),
+        ];
+        assert!((period(&mut manager, &[&events[..], &[full_bend(0)]].concat()) - semitone).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_silent_release() {
+        let mut template = SimpleOscillator::new();
+        template.set_opts(VoiceOpts {
+            attack: 0.001,
+            decay: 0.001,
+            sustain: 0.00005,
+            release: 10.0,
+            ..Default::default()
+        });
+        let mut manager = VoiceManager::new(1, template);
+        manager.set_sample_rate(48000);
+        let process = |manager: &mut VoiceManager<SimpleOscillator>| {
+            manager.process_adding(StereoBufferMut::new(&mut [0.0; 480], &mut [0.0; 480]));
+        };
+
+        // A held note is never freed, however quiet
+        manager.trigger(Note::middle_c(), 127);
+        (0..10).for_each(|_| process(&mut manager));
+        assert!(manager.voices[0].active());
+
+        // Once released, it is freed long before its release time
+        manager.release(Note::middle_c());
+        process(&mut manager);
+        assert!(!manager.voices[0].active());
+    }
+
+    #[test]
+    fn test_untracked_release() {
+        /// A voice which doesn't track its amplitude, and plays for a fixed number of samples once released.
+        #[derive(Clone)]
+        struct Countdown(Option<usize>);
+
+        impl Voice for Countdown {
+            fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+            fn trigger(&mut self, _note: Note, _velocity: u8) {
+                self.0 = None;
+            }
+
+            fn release(&mut self) {
+                self.0 = Some(960);
+            }
+
+            fn set_pitch_bend(&mut self, _bend: f32) {}
+
+            fn process(&mut self, audio_out: StereoBufferMut) -> bool {
+                match &mut self.0 {
+                    Some(remaining) => {
+                        *remaining = remaining.saturating_sub(audio_out.len());
+                        *remaining > 0
+                    }
+                    None => true,
+                }
+            }
+        }
+
+        let mut manager = VoiceManager::new(1, Countdown(None));
+        manager.set_sample_rate(48000);
+        let process = |manager: &mut VoiceManager<Countdown>| {
+            manager.process_adding(StereoBufferMut::new(&mut [0.0; 480], &mut [0.0; 480]));
+        };
+
+        // Reporting silence doesn't free the voice before it finishes its release
+        manager.trigger(Note::middle_c(), 127);
+        manager.release(Note::middle_c());
+        process(&mut manager);
+        assert!(manager.voices[0].active());
+        process(&mut manager);
+        assert!(!manager.voices[0].active());
+    }
+
+    #[test]
+    fn test_steal_policy() {
+        let [c4, d4, e4] = ["C4", "D4", "E4"].map(|n| Note::from_name(n).unwrap());
+        let play = |policy| {
+            let mut manager = VoiceManager::new(2, SimpleOscillator::new());
+            manager.set_sample_rate(48000);
+            manager.set_steal_policy(policy);
+            let mut left = [0.0; 480];
+            let mut right = [0.0; 480];
+
+            // Trigger a loud note followed by a quiet one, then a third note once both are sounding
+            manager.trigger(c4, 127);
+            manager.trigger(d4, 5);
+            manager.process_adding(StereoBufferMut::new(&mut left, &mut right));
+            manager.trigger(e4, 100);
+            manager
+                .voices
+                .iter()
+                .filter_map(|v| v.on_note())
+                .map(|n| n.0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(play(StealPolicy::Oldest), [e4.0, d4.0]);
+        assert_eq!(play(StealPolicy::Quietest), [c4.0, e4.0]);
+        assert_eq!(play(StealPolicy::LowestNote), [e4.0, d4.0]);
+        assert_eq!(play(StealPolicy::HighestNote), [c4.0, e4.0]);
+    }
+
+    #[test]
+    fn test_voice_buses() {
+        let [c4, e4, g4] = ["C4", "E4", "G4"].map(|n| Note::from_name(n).unwrap());
+        let render = |num_buses: usize, notes: &[Note]| {
+            let mut manager = VoiceManager::new(4, SimpleOscillator::new());
+            manager.set_sample_rate(48000);
+            manager.set_num_buses(num_buses);
+            for &note in notes {
+                manager.trigger(note, 100);
+            }
+            let mut buffers = vec![vec![0.0; 480]; 2 * num_buses];
+            let mut audio_out: Vec<_> = buffers.iter_mut().map(|b| b.as_mut_slice()).collect();
+            manager.process_midi_adding(&[], &mut audio_out);
+            buffers
+        };
+
+        // Each note is routed to its own bus
+        let buses = render(2, &[c4, e4]);
+        assert_eq!(buses[0], render(1, &[c4])[0]);
+        assert_eq!(buses[2], render(1, &[e4])[0]);
+
+        // Once the buses run out, the remaining notes are summed into the last bus
+        let buses = render(2, &[c4, e4, g4]);
+        assert_eq!(buses[0], render(1, &[c4])[0]);
+        assert_eq!(buses[2], render(1, &[e4, g4])[0]);
+    }
+
+    #[test]
+    fn test_soft_clip() {
+        let render = |soft_clip: bool, existing: f32| {
+            let mut template = SimpleOscillator::new();
+            template.set_waveform(Waveform::Square);
+            let mut manager = VoiceManager::new(8, template);
+            manager.set_sample_rate(48000);
+            manager.set_soft_clip(soft_clip);
+            for note in ["C4", "E4", "G4", "C5", "E5", "G5"] {
+                manager.trigger(Note::from_name(note).unwrap(), 127);
+            }
+
+            let mut left = vec![existing; 4800];
+            let mut right = vec![existing; 4800];
+            manager.process_midi_adding(&[], &mut [&mut left, &mut right]);
+            [left, right].concat()
+        };
+        let peak = |soft_clip: bool| render(soft_clip, 0.0).iter().fold(0.0f32, |max, s| s.abs().max(max));
+
+        // The overdriven chord clips without soft clipping, but stays within range with it
+        assert!(peak(false) > 1.0);
+        assert!(peak(true) <= 1.0);
+
+        // When adding to existing audio, only the output of the voices is soft clipped
+        let clipped = render(true, 0.0);
+        let added = render(true, 1.0);
+        assert!(clipped.iter().zip(&added).all(|(a, b)| (b - a - 1.0).abs() < 1e-6));
+    }
+}
@@ -1,9 +1,14 @@
-use crate::audio::{buffer::StereoBufferMut, sample::AudioSample};
+use crate::audio::{
+    buffer::{StereoBufferMut, StereoChannel},
+    resample::{Interpolator, LinearInterpolator},
+    sample::AudioSample,
+};
 use std::sync::Arc;
 
 use super::timeline::Timeline;
 
 /// An audio track which contains audio clips.
+#[derive(Default)]
 pub struct AudioTrack {
     /// The clips on the track, in chronological order.
     clips: Vec<AudioClip>,
@@ -25,6 +30,16 @@ pub struct AudioClip {
 }
 
 impl AudioTrack {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a clip to the track, where overlapping clips are mixed together.
+    pub fn add_clip(&mut self, clip: AudioClip) {
+        let idx = self.clips.partition_point(|c| c.start <= clip.start);
+        self.clips.insert(idx, clip);
+    }
+
     pub fn set_sample_rate(&mut self, sample_rate: u32) {
         self.sample_rate = sample_rate as f32;
     }
@@ -38,34 +53,42 @@ impl AudioTrack {
         //   - If warped:
         //     - Use beat and tempo to compute sample range to resample and emit
 
-        let mut sample = timeline.curr_sample();
-        for clips in self.clips.windows(2) {
-            // Determine the number of samples to write from this clip
-            let [clip, next_clip] = clips else {
-                unreachable!("windows did not return 2 clips");
-            };
-            let next_trigger = timeline.time_to_sample(next_clip.start);
-            if next_trigger <= sample {
+        audio_out.clear();
+        let block_start = timeline.curr_sample();
+        let block_end = block_start + audio_out.len();
+        for clip in &self.clips {
+            // Determine the part of the block which this clip plays in
+            let start = timeline.time_to_sample(clip.start()).max(block_start);
+            let end = timeline.time_to_sample(clip.end()).min(block_end);
+            if start >= end {
                 continue;
             }
-            let len = (next_trigger - sample).min(audio_out.len());
 
             // Process the clip
-            clip.process(timeline, sample, self.sample_rate, audio_out.slice_mut(..len));
-
-            // Advance the playhead and audio buffer
-            sample += len;
-            audio_out = audio_out.into_slice_mut(len..);
-
-            // Terminate when the buffer is full
-            if audio_out.len() == 0 {
-                break;
-            }
+            let range = (start - block_start)..(end - block_start);
+            clip.process(timeline, start, self.sample_rate, audio_out.slice_mut(range));
         }
     }
 }
 
 impl AudioClip {
+    /// Creates a clip which plays `sample` from its start, for `duration` beats from `start` on the timeline.
+    pub fn new(sample: Arc<AudioSample>, start: f64, duration: f64) -> Self {
+        Self {
+            start,
+            duration,
+            sample_rate: sample.sample_rate() as f32,
+            sample,
+            sample_offset: 0,
+        }
+    }
+
+    /// Sets the offset into the audio sample to begin playback from, so that the clip can start partway into
+    /// its sample without trimming it.
+    pub fn set_sample_offset(&mut self, sample_offset: usize) {
+        self.sample_offset = sample_offset;
+    }
+
     fn start(&self) -> f64 {
         self.start
     }
@@ -74,20 +97,111 @@ impl AudioClip {
         self.start + self.duration
     }
 
-    // fn sample_idx_at(&self, pos: TrackPosition) -> usize {
-    //     let rel_pos = pos - self.start;
-    //     let rel_sample = self.sample_rate *
-    //     self.sample_offset + rel_sample
-    // }
-
-    fn process(&self, timeline: &Timeline, sample: usize, sample_rate: f32, mut audio_out: StereoBufferMut) {
+    fn process(&self, timeline: &Timeline, sample: usize, sample_rate: f32, audio_out: StereoBufferMut) {
         let start_sample = timeline.time_to_sample(self.start);
-        let start_offset = sample - start_sample;
-        let end_offset = start_offset + audio_out.len();
-        let ratio = sample_rate / self.sample.sample_rate() as f32;
-        let start = ratio * start_offset as f32;
-        let end = ratio * end_offset as f32;
+        self.render(sample - start_sample, sample_rate, audio_out);
+    }
 
-        // FIXME: Write samples start..end to audio_out
+    /// Adds the clip to `audio_out`, starting `start_offset` samples after the start of the clip.
+    fn render(&self, start_offset: usize, sample_rate: f32, mut audio_out: StereoBufferMut) {
+        if sample_rate <= 0.0 {
+            return;
+        }
+
+        // Position in the audio sample of each output sample, interpolating between the samples either side
+        let ratio = self.sample_rate as f64 / sample_rate as f64;
+        let data = self.sample.stereo_data();
+        for channel in StereoChannel::both() {
+            let input = data.channel(channel);
+            let at = |idx: usize| input.get(idx).copied().unwrap_or(0.0);
+            for (i, out) in audio_out.channel_mut(channel).iter_mut().enumerate() {
+                let pos = self.sample_offset as f64 + (start_offset + i) as f64 * ratio;
+                let idx = pos as usize;
+                let t = (pos - idx as f64) as f32;
+                *out += LinearInterpolator::interpolate(t, &[at(idx), at(idx + 1)]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::audio::buffer::MonoBuffer;
+
+    #[test]
+    fn test_sample_offset() {
+        let data: Vec<f32> = (0..64).map(|i| i as f32).collect();
+        let sample = Arc::new(AudioSample::new_mono(48000, MonoBuffer::new(&data)));
+        let mut clip = AudioClip::new(sample, 0.0, 1.0);
+        clip.set_sample_offset(10);
+
+        let render = |start_offset: usize, sample_rate: f32| {
+            let mut left = [0.0; 8];
+            let mut right = [0.0; 8];
+            clip.render(start_offset, sample_rate, StereoBufferMut::new(&mut left, &mut right));
+            assert_eq!(right, left);
+            left
+        };
+
+        // The clip starts from the offset, and continues from it in later blocks
+        assert_eq!(render(0, 48000.0), [10.0, 11.0, 12.0, 13.0, 14.0, 15.0, 16.0, 17.0]);
+        assert_eq!(render(8, 48000.0)[0], 18.0);
+
+        // The offset is in samples of the audio sample, regardless of the output sample rate
+        assert_eq!(render(0, 96000.0)[..4], [10.0, 10.5, 11.0, 11.5]);
+
+        // Silence follows the end of the sample
+        assert_eq!(render(50, 48000.0), [60.0, 61.0, 62.0, 63.0, 0.0, 0.0, 0.0, 0.0]);
+
+        // Nothing is rendered until the sample rate is set
+        assert_eq!(render(0, 0.0), [0.0; 8]);
+    }
+
+    /// Creates a clip of a constant sample, lasting for a beat.
+    fn constant_clip(value: f32, start: f64) -> AudioClip {
+        let data = vec![value; 48000];
+        let sample = Arc::new(AudioSample::new_mono(48000, MonoBuffer::new(&data)));
+        AudioClip::new(sample, start, 1.0)
+    }
+
+    #[test]
+    fn test_track() {
+        // Clips of constant samples, where the second starts as the first ends
+        let mut track = AudioTrack::new();
+        track.set_sample_rate(48000);
+        track.add_clip(constant_clip(2.0, 1.0));
+        track.add_clip(constant_clip(1.0, 0.0));
+
+        // At 120 BPM, a beat is 24000 samples
+        let mut timeline = Timeline::new(48000, 120.0);
+        let mut left = [0.0; 8];
+        let mut right = [0.0; 8];
+        for (time, expected) in [(23996, [1.0, 2.0]), (47996, [2.0, 0.0])] {
+            timeline.advance(time - timeline.curr_sample());
+            track.process(&timeline, StereoBufferMut::new(&mut left, &mut right));
+            assert_eq!(left[..4], [expected[0]; 4]);
+            assert_eq!(left[4..], [expected[1]; 4]);
+        }
+    }
+
+    #[test]
+    fn test_overlapping_clips() {
+        // The second clip starts half a beat in, and is mixed with the first where they overlap
+        let mut track = AudioTrack::new();
+        track.set_sample_rate(48000);
+        track.add_clip(constant_clip(2.0, 0.5));
+        track.add_clip(constant_clip(1.0, 0.0));
+
+        // At 120 BPM, half a beat is 12000 samples, and the second clip ends 36000 samples in
+        let mut timeline = Timeline::new(48000, 120.0);
+        let mut left = [0.0; 8];
+        let mut right = [0.0; 8];
+        for (time, expected) in [(11996, [1.0, 3.0]), (23996, [3.0, 2.0]), (35996, [2.0, 0.0])] {
+            timeline.advance(time - timeline.curr_sample());
+            track.process(&timeline, StereoBufferMut::new(&mut left, &mut right));
+            assert_eq!(left[..4], [expected[0]; 4]);
+            assert_eq!(left[4..], [expected[1]; 4]);
+        }
     }
 }
@@ -1,2 +1,5 @@
+pub use audio::{AudioClip, AudioTrack};
+pub use timeline::Timeline;
+
 mod audio;
 mod timeline;
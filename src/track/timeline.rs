@@ -12,6 +12,21 @@ struct TempoChange {
 }
 
 impl Timeline {
+    /// Creates a timeline which starts at a tempo of `bpm` beats per minute.
+    pub fn new(sample_rate: u32, bpm: f64) -> Self {
+        let sample_rate = sample_rate as f64;
+        Self {
+            sample_rate,
+            tempo_map: vec![TempoChange {
+                time: 0.0,
+                sample: 0,
+                samples_per_beat: sample_rate * 60.0 / bpm,
+            }],
+            curr_sample: 0,
+            curr_time: 0.0,
+        }
+    }
+
     pub fn advance(&mut self, offset: usize) {
         self.curr_sample += offset;
         self.curr_time = self.sample_to_time(self.curr_sample);
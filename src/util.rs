@@ -8,6 +8,65 @@ pub fn hz_from_note(note: u8) -> f32 {
     440.0 * 2.0f32.powf((note as f32 - 69.0) / 12.0)
 }
 
+/// A value which moves linearly towards a target over a number of samples, used to smooth parameter changes.
+#[derive(Clone, Copy, Debug)]
+pub struct LinearRamp {
+    value: f32,
+    target: f32,
+    step: f32,
+    remaining: usize,
+}
+
+impl LinearRamp {
+    pub fn new(value: f32) -> Self {
+        Self {
+            value,
+            target: value,
+            step: 0.0,
+            remaining: 0,
+        }
+    }
+
+    /// Sets the value immediately, cancelling any ramp in progress.
+    pub fn set(&mut self, value: f32) {
+        *self = Self::new(value);
+    }
+
+    /// Starts moving the value towards `target`, reaching it after `samples` calls to `advance`.
+    pub fn ramp_to(&mut self, target: f32, samples: usize) {
+        if samples == 0 {
+            self.set(target);
+        } else {
+            self.target = target;
+            self.step = (target - self.value) / samples as f32;
+            self.remaining = samples;
+        }
+    }
+
+    /// Returns `true` if the value is still moving towards its target.
+    pub fn is_ramping(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Gets the current value.
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Advances the ramp by a sample, and returns the new value.
+    pub fn advance(&mut self) -> f32 {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.value = if self.remaining == 0 {
+                self.target
+            } else {
+                self.value + self.step
+            };
+        }
+        self.value
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -28,4 +87,13 @@ mod test {
         assert_eq!(hz_from_note(69 + 12), 880.0);
         assert_eq!(hz_from_note(69 - 12), 220.0);
     }
+
+    #[test]
+    fn test_linear_ramp() {
+        let mut ramp = LinearRamp::new(1.0);
+        ramp.ramp_to(0.0, 4);
+        let values: Vec<f32> = (0..6).map(|_| ramp.advance()).collect();
+        assert_eq!(values, [0.75, 0.5, 0.25, 0.0, 0.0, 0.0]);
+        assert!(!ramp.is_ramping());
+    }
 }
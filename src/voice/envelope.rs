@@ -59,6 +59,15 @@ impl AdsrEnvelope {
         self.inv_sample_rate = (sample_rate as f32).recip();
     }
 
+    /// Sets the attack, decay and release times in seconds, and the sustain level between 0 and 1.
+    /// Each phase lasts for at least one sample, however short its time, so that the amplitude never jumps.
+    pub fn set_adsr(&mut self, attack: f32, decay: f32, sustain: f32, release: f32) {
+        self.inv_attack = attack.max(0.0).recip();
+        self.inv_decay = decay.max(0.0).recip();
+        self.sustain = sustain.clamp(0.0, 1.0);
+        self.inv_release = release.max(0.0).recip();
+    }
+
     pub fn trigger(&mut self) {
         self.state = AdsrState::Attack {
             start: self.amp,
@@ -73,16 +82,31 @@ impl AdsrEnvelope {
         };
     }
 
+    /// Gets the most recently computed amplitude.
+    pub fn amplitude(&self) -> f32 {
+        self.amp
+    }
+
     pub fn active(&self) -> bool {
         !matches!(self.state, AdsrState::Inactive)
     }
 
+    /// Gets the progress through a phase with the given rate in each sample, which is at most 1 so that the phase
+    /// lasts for at least one sample, including when its time is zero.
+    fn increment(&self, inv_time: f32) -> f32 {
+        if inv_time.is_finite() {
+            (inv_time * self.inv_sample_rate).min(1.0)
+        } else {
+            1.0
+        }
+    }
+
     pub fn process(&mut self) -> f32 {
         use AdsrState::*;
         match self.state {
             Attack { start, mut t } => {
                 self.amp = start + (1.0 - start) * t;
-                t += self.inv_attack * self.inv_sample_rate;
+                t += self.increment(self.inv_attack);
                 if t < 1.0 {
                     self.state = Attack { start, t };
                 } else {
@@ -91,7 +115,7 @@ impl AdsrEnvelope {
             }
             Decay { mut t } => {
                 self.amp = 1.0 - t * (1.0 - self.sustain);
-                t += self.inv_decay * self.inv_sample_rate;
+                t += self.increment(self.inv_decay);
                 if t < 1.0 {
                     self.state = Decay { t };
                 } else {
@@ -101,7 +125,7 @@ impl AdsrEnvelope {
             Sustain => self.amp = self.sustain,
             Release { start, mut t } => {
                 self.amp = start * (1.0 - t);
-                t += self.inv_release * self.inv_sample_rate;
+                t += self.increment(self.inv_release);
                 if t < 1.0 {
                     self.state = Release { start, t };
                 } else {
@@ -114,3 +138,49 @@ impl AdsrEnvelope {
         self.amp
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_zero_times() {
+        let mut env = AdsrEnvelope::new();
+        env.set_sample_rate(48000);
+        env.set_adsr(0.0, 0.0, 0.5, 0.0);
+
+        // Each phase takes one sample, rather than jumping straight to the next level
+        env.trigger();
+        let attack: Vec<_> = (0..4).map(|_| env.process()).collect();
+        assert_eq!(attack, [0.0, 1.0, 0.5, 0.5]);
+        env.release();
+        let release: Vec<_> = (0..3).map(|_| env.process()).collect();
+        assert_eq!(release, [0.5, 0.0, 0.0]);
+        assert!(!env.active());
+
+        // Retriggering during the release starts the attack from the current amplitude
+        env.trigger();
+        env.process();
+        env.process();
+        env.release();
+        env.trigger();
+        assert_eq!(env.process(), 1.0);
+    }
+
+    #[test]
+    fn test_short_times() {
+        // Times shorter than a sample behave as if they were zero
+        let mut env = AdsrEnvelope::new();
+        env.set_sample_rate(48000);
+        env.set_adsr(1e-6, 1e-6, 0.5, 1e-6);
+        env.trigger();
+        let samples: Vec<_> = (0..4).map(|_| env.process()).collect();
+        assert_eq!(samples, [0.0, 1.0, 0.5, 0.5]);
+
+        // Before the sample rate is known, the envelope doesn't produce NaN
+        let mut env = AdsrEnvelope::new();
+        env.set_adsr(0.0, 0.0, 0.5, 0.0);
+        env.trigger();
+        assert!((0..4).map(|_| env.process()).all(|amp| amp.is_finite()));
+    }
+}
@@ -0,0 +1,242 @@
+use super::{envelope::AdsrEnvelope, Voice};
+use crate::{audio::buffer::StereoBufferMut, note::Note, processor::IIRFilter};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// Number of samples processed between updates of the filter cutoff.
+const BLOCK_SIZE: usize = 32;
+
+/// Wraps a voice to pass its output through a resonant lowpass filter, with its own envelope.
+pub struct Filtered<V: Voice> {
+    /// The inner voice.
+    voice: V,
+    /// The filters for the left and right channels.
+    filters: [IIRFilter; 2],
+    /// Whether the filter is enabled, or the output of the inner voice is passed through.
+    enabled: bool,
+    /// The envelope used to modulate the cutoff.
+    envelope: AdsrEnvelope,
+    sample_rate: f32,
+    /// The cutoff frequency when the envelope is at zero.
+    cutoff: f32,
+    /// The quality factor of the filter.
+    resonance: f32,
+    /// The number of octaves the cutoff is raised by when the envelope is at its peak.
+    env_amount: f32,
+    /// Scratch buffers for the output of the inner voice.
+    scratch: [[f32; BLOCK_SIZE]; 2],
+}
+
+impl<V: Voice + Clone> Clone for Filtered<V> {
+    fn clone(&self) -> Self {
+        Self {
+            voice: self.voice.clone(),
+            ..*self
+        }
+    }
+
+    /// Delegates to the inner voice, so that it can reuse its allocations.
+    fn clone_from(&mut self, source: &Self) {
+        self.voice.clone_from(&source.voice);
+        self.filters = source.filters;
+        self.enabled = source.enabled;
+        self.envelope = source.envelope;
+        self.sample_rate = source.sample_rate;
+        self.cutoff = source.cutoff;
+        self.resonance = source.resonance;
+        self.env_amount = source.env_amount;
+        self.scratch = source.scratch;
+    }
+}
+
+impl<V: Voice> Filtered<V> {
+    pub fn new(voice: V) -> Self {
+        Self {
+            voice,
+            filters: [IIRFilter::new(); 2],
+            enabled: true,
+            envelope: AdsrEnvelope::new(),
+            sample_rate: 0.0,
+            cutoff: 22_000.0,
+            resonance: FRAC_1_SQRT_2,
+            env_amount: 0.0,
+            scratch: [[0.0; BLOCK_SIZE]; 2],
+        }
+    }
+
+    /// Sets the cutoff frequency in Hz, or `None` to bypass the filter.
+    pub fn set_cutoff(&mut self, frequency: Option<f32>) {
+        self.enabled = frequency.is_some();
+        if let Some(frequency) = frequency {
+            self.cutoff = frequency.clamp(10.0, 22_000.0);
+        }
+    }
+
+    /// Sets the quality factor of the filter, where `1 / sqrt(2)` gives no resonant peak.
+    pub fn set_resonance(&mut self, q: f32) {
+        self.resonance = q.clamp(0.5, 20.0);
+    }
+
+    /// Sets the filter envelope, where `amount` is the number of octaves it raises the cutoff by at its peak.
+    /// The times are in seconds, and the sustain level is between 0 and 1.
+    pub fn set_filter_env(&mut self, amount: f32, attack: f32, decay: f32, sustain: f32, release: f32) {
+        self.env_amount = amount;
+        self.envelope.set_adsr(attack, decay, sustain, release);
+    }
+
+    pub fn inner(&self) -> &V {
+        &self.voice
+    }
+
+    pub fn inner_mut(&mut self) -> &mut V {
+        &mut self.voice
+    }
+
+    /// Advances the envelope by `num_samples`, and updates the filter coefficients.
+    fn update_filters(&mut self, num_samples: usize) {
+        let mut env = 0.0;
+        for _ in 0..num_samples {
+            env = self.envelope.process();
+        }
+
+        if self.sample_rate > 0.0 {
+            let cutoff = (self.cutoff * 2f32.powf(self.env_amount * env)).clamp(10.0, 0.49 * self.sample_rate);
+            for filter in self.filters.iter_mut() {
+                filter.set_resonant_lowpass(cutoff, self.resonance, self.sample_rate);
+            }
+        }
+    }
+}
+
+impl<V: Voice> Voice for Filtered<V> {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate as f32;
+        self.envelope.set_sample_rate(sample_rate);
+        self.voice.set_sample_rate(sample_rate);
+    }
+
+    fn trigger(&mut self, note: Note, velocity: u8) {
+        self.envelope.trigger();
+        self.voice.trigger(note, velocity);
+    }
+
+    fn release(&mut self) {
+        // Only close the filter once the inner voice has released its last note, rather than when it returns
+        // to a note which is still held
+        self.voice.release();
+        if self.voice.held_note().is_none() {
+            self.envelope.release();
+        }
+    }
+
+    fn release_held(&mut self, note: Note) {
+        self.voice.release_held(note);
+        if self.voice.held_note().is_none() {
+            self.envelope.release();
+        }
+    }
+
+    fn held_note(&self) -> Option<Note> {
+        self.voice.held_note()
+    }
+
+    fn set_pitch_bend(&mut self, bend: f32) {
+        self.voice.set_pitch_bend(bend);
+    }
+
+    fn amplitude(&self) -> f32 {
+        self.voice.amplitude()
+    }
+
+    fn tracks_amplitude(&self) -> bool {
+        self.voice.tracks_amplitude()
+    }
+
+    fn process(&mut self, audio_out: StereoBufferMut) -> bool {
+        if !self.enabled {
+            return self.voice.process(audio_out);
+        }
+
+        let StereoBufferMut { left, right } = audio_out;
+
+        let mut active = true;
+        for (out_left, out_right) in left.chunks_mut(BLOCK_SIZE).zip(right.chunks_mut(BLOCK_SIZE)) {
+            let len = out_left.len();
+            self.update_filters(len);
+
+            // Render the inner voice into the scratch buffers, since voices add to their output
+            let [scratch_left, scratch_right] = &mut self.scratch;
+            let (scratch_left, scratch_right) = (&mut scratch_left[..len], &mut scratch_right[..len]);
+            scratch_left.fill(0.0);
+            scratch_right.fill(0.0);
+            active = self.voice.process(StereoBufferMut::new(scratch_left, scratch_right));
+
+            let [filter_left, filter_right] = &mut self.filters;
+            for (out, &s) in out_left.iter_mut().zip(scratch_left.iter()) {
+                *out += filter_left.process_sample(s);
+            }
+            for (out, &s) in out_right.iter_mut().zip(scratch_right.iter()) {
+                *out += filter_right.process_sample(s);
+            }
+        }
+        active
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::voice::{
+        legato::Legato,
+        oscillator::{SimpleOscillator, Waveform},
+    };
+
+    /// Gets the energy of the first difference of the signal, which is dominated by its high frequency content.
+    fn high_frequency_energy(voice: &mut impl Voice) -> f32 {
+        voice.set_sample_rate(48000);
+        voice.trigger(Note::from_name("A2").unwrap(), 127);
+        let mut left = vec![0.0; 4800];
+        let mut right = vec![0.0; 4800];
+        voice.process(StereoBufferMut::new(&mut left, &mut right));
+        left[2400..].windows(2).map(|w| (w[1] - w[0]).powi(2)).sum()
+    }
+
+    #[test]
+    fn test_attenuates_harmonics() {
+        let mut osc = SimpleOscillator::new();
+        osc.set_waveform(Waveform::Sawtooth);
+        let unfiltered = high_frequency_energy(&mut osc.clone());
+
+        let mut voice = Filtered::new(osc);
+        voice.set_cutoff(Some(500.0));
+        let filtered = high_frequency_energy(&mut voice.clone());
+        assert!(filtered < 0.1 * unfiltered);
+
+        // Without a cutoff, the filter is bypassed
+        voice.set_cutoff(None);
+        assert_eq!(high_frequency_energy(&mut voice), unfiltered);
+    }
+
+    #[test]
+    fn test_legato_keeps_filter_open() {
+        let [a2, e3] = ["A2", "E3"].map(|n| Note::from_name(n).unwrap());
+        let mut voice = Filtered::new(Legato::new(SimpleOscillator::new()));
+        voice.set_sample_rate(48000);
+        voice.set_cutoff(Some(100.0));
+        voice.set_filter_env(6.0, 0.0, 0.0, 1.0, 0.01);
+        let mut left = vec![0.0; 4800];
+        let mut right = vec![0.0; 4800];
+
+        // Releasing the second note returns to the first, which is still held, so the filter stays open
+        voice.trigger(a2, 127);
+        voice.trigger(e3, 127);
+        voice.release();
+        voice.process(StereoBufferMut::new(&mut left, &mut right));
+        assert_eq!(voice.held_note(), Some(a2));
+        assert!(voice.envelope.amplitude() > 0.99);
+
+        // Releasing the first note closes it
+        voice.release();
+        voice.process(StereoBufferMut::new(&mut left, &mut right));
+        assert!(voice.envelope.amplitude() < 0.01);
+    }
+}
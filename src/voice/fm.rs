@@ -0,0 +1,162 @@
+use super::{envelope::AdsrEnvelope, Voice};
+use crate::{
+    audio::buffer::StereoBufferMut,
+    note::{Note, Tuning},
+};
+use std::f32::consts::PI;
+
+/// A two operator FM voice, where a sine modulator modulates the phase of a sine carrier.
+#[derive(Clone, Copy)]
+pub struct FmVoice {
+    inv_sample_rate: f32,
+    note: Note,
+    velocity: f32,
+    bend: f32,
+    tuning: Tuning,
+    /// The frequency ratio of the modulator to the carrier.
+    ratio: f32,
+    /// The peak phase deviation of the carrier in radians.
+    index: f32,
+    carrier_phase: f32,
+    modulator_phase: f32,
+    /// The envelope of the carrier, which controls the level of the voice.
+    carrier_env: AdsrEnvelope,
+    /// The envelope of the modulator, which controls the modulation depth.
+    modulator_env: AdsrEnvelope,
+}
+
+impl Default for FmVoice {
+    fn default() -> Self {
+        Self {
+            inv_sample_rate: 0.0,
+            note: Note::middle_c(),
+            velocity: 0.0,
+            bend: 1.0,
+            tuning: Tuning::default(),
+            ratio: 1.0,
+            index: 1.0,
+            carrier_phase: 0.0,
+            modulator_phase: 0.0,
+            carrier_env: AdsrEnvelope::new(),
+            modulator_env: AdsrEnvelope::new(),
+        }
+    }
+}
+
+impl FmVoice {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the frequency ratio of the modulator to the carrier.
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio.max(0.0);
+    }
+
+    /// Sets the modulation index, which is the peak phase deviation of the carrier in radians.
+    /// Higher values add more sidebands, spaced by the frequency of the modulator.
+    pub fn set_index(&mut self, index: f32) {
+        self.index = index.max(0.0);
+    }
+
+    /// Sets the tuning used to convert notes to frequencies.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+    }
+
+    /// Sets the envelope of the carrier. The times are in seconds, and the sustain level is between 0 and 1.
+    pub fn set_carrier_env(&mut self, attack: f32, decay: f32, sustain: f32, release: f32) {
+        self.carrier_env.set_adsr(attack, decay, sustain, release);
+    }
+
+    /// Sets the envelope of the modulator. The times are in seconds, and the sustain level is between 0 and 1.
+    pub fn set_modulator_env(&mut self, attack: f32, decay: f32, sustain: f32, release: f32) {
+        self.modulator_env.set_adsr(attack, decay, sustain, release);
+    }
+}
+
+impl Voice for FmVoice {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.inv_sample_rate = (sample_rate as f32).recip();
+        self.carrier_env.set_sample_rate(sample_rate);
+        self.modulator_env.set_sample_rate(sample_rate);
+    }
+
+    fn trigger(&mut self, note: Note, velocity: u8) {
+        self.note = note;
+        self.velocity = (velocity as f32) / 127.0;
+        self.carrier_env.trigger();
+        self.modulator_env.trigger();
+        // Reset the operators so that every note has the same timbre
+        self.carrier_phase = 0.0;
+        self.modulator_phase = 0.0;
+    }
+
+    fn release(&mut self) {
+        self.carrier_env.release();
+        self.modulator_env.release();
+    }
+
+    fn set_pitch_bend(&mut self, bend: f32) {
+        self.bend = bend;
+    }
+
+    fn amplitude(&self) -> f32 {
+        self.velocity * self.carrier_env.amplitude()
+    }
+
+    fn tracks_amplitude(&self) -> bool {
+        true
+    }
+
+    fn process(&mut self, audio_out: StereoBufferMut) -> bool {
+        let StereoBufferMut { left, right } = audio_out;
+
+        let omega = self.bend * self.tuning.frequency(self.note) * self.inv_sample_rate;
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            let modulator = self.index * self.modulator_env.process() * (2.0 * PI * self.modulator_phase).sin();
+            let carrier = (2.0 * PI * self.carrier_phase + modulator).sin();
+            let sample = self.carrier_env.process() * self.velocity * carrier;
+            *left += sample;
+            *right += sample;
+            self.carrier_phase = (self.carrier_phase + omega).fract();
+            self.modulator_phase = (self.modulator_phase + self.ratio * omega).fract();
+        }
+
+        self.carrier_env.active()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processor::magnitude_at;
+
+    /// Renders A4 with a modulator at twice the frequency of the carrier,
+    /// and returns the proportion of the energy outside the fundamental.
+    fn sideband_energy(index: f32) -> f32 {
+        // At this sample rate, each cycle of A4 is exactly 64 samples
+        let mut voice = FmVoice::new();
+        voice.set_sample_rate(28160);
+        voice.set_ratio(2.0);
+        voice.set_index(index);
+        let mut left = [0.0; 2048];
+        let mut right = [0.0; 2048];
+        voice.trigger(Note(69), 127);
+        voice.process(StereoBufferMut::new(&mut left, &mut right));
+
+        let output = &left[1024..];
+        let harmonic = |k: usize| magnitude_at(output, k as f32, 64.0).powi(2);
+        let total: f32 = (1..32).map(harmonic).sum();
+        1.0 - harmonic(1) / total
+    }
+
+    #[test]
+    fn test_modulation_index() {
+        // Without modulation the carrier is a pure sine, and more modulation adds more sidebands
+        let energies = [0.0, 0.5, 2.0].map(sideband_energy);
+        assert!(energies[0] < 1e-4);
+        assert!(energies[1] > 0.01);
+        assert!(energies[2] > energies[1]);
+    }
+}
@@ -0,0 +1,291 @@
+use super::Voice;
+use crate::{audio::buffer::StereoBufferMut, note::Note};
+
+/// Number of samples processed between updates of the glide.
+const GLIDE_BLOCK_SIZE: usize = 32;
+
+/// Wraps a voice to make it monophonic with last-note priority, gliding between overlapping notes.
+///
+/// Overlapping notes change the pitch of the inner voice without retriggering it,
+/// and releasing the most recent note returns to the previous note that is still held.
+pub struct Legato<V: Voice> {
+    /// The inner voice.
+    voice: V,
+    /// Duration of a sample in seconds.
+    inv_sample_rate: f32,
+    /// The held notes, with the most recently triggered note last.
+    notes: Vec<Note>,
+    /// The note that the inner voice was triggered with.
+    base: Note,
+    /// The current pitch in semitones, which glides towards the most recently triggered note.
+    pitch: f32,
+    /// Time constant of the glide in seconds.
+    glide_time: f32,
+    /// The pitch bend ratio.
+    bend: f32,
+    /// Whether overlapping notes are stacked and glided between, or each note retriggers the inner voice.
+    enabled: bool,
+}
+
+impl<V: Voice> Legato<V> {
+    pub fn new(voice: V) -> Self {
+        Self {
+            voice,
+            inv_sample_rate: 0.0,
+            notes: Vec::with_capacity(16),
+            base: Note::middle_c(),
+            pitch: Note::middle_c().0 as f32,
+            glide_time: 0.0,
+            bend: 1.0,
+            enabled: true,
+        }
+    }
+
+    /// Sets the time constant of the glide between overlapping notes in seconds, or `0.0` for no glide.
+    pub fn set_glide_time(&mut self, glide_time: f32) {
+        self.glide_time = glide_time.max(0.0);
+    }
+
+    /// Sets whether overlapping notes are stacked and glided between. When disabled, each note retriggers the
+    /// inner voice at its exact pitch, and releasing it releases the inner voice, as for a polyphonic voice.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn inner(&self) -> &V {
+        &self.voice
+    }
+
+    pub fn inner_mut(&mut self) -> &mut V {
+        &mut self.voice
+    }
+
+    /// Moves the pitch towards the most recent note over `num_samples`, and updates the inner voice's pitch bend.
+    fn update_pitch(&mut self, num_samples: usize) {
+        let Some(target) = self.notes.last().map(|n| n.0 as f32) else {
+            return;
+        };
+
+        let elapsed = num_samples as f32 * self.inv_sample_rate;
+        if self.glide_time > 0.0 && (target - self.pitch).abs() > 0.001 {
+            self.pitch += (target - self.pitch) * (1.0 - (-elapsed / self.glide_time).exp());
+        } else {
+            self.pitch = target;
+        }
+
+        let offset = self.pitch - self.base.0 as f32;
+        self.voice.set_pitch_bend(self.bend * 2f32.powf(offset / 12.0));
+    }
+}
+
+impl<V: Voice + Clone> Clone for Legato<V> {
+    fn clone(&self) -> Self {
+        Self {
+            voice: self.voice.clone(),
+            notes: self.notes.clone(),
+            ..*self
+        }
+    }
+
+    /// Copies the held notes into the existing buffer, so that refreshing a voice doesn't allocate.
+    fn clone_from(&mut self, source: &Self) {
+        self.voice.clone_from(&source.voice);
+        self.inv_sample_rate = source.inv_sample_rate;
+        self.notes.clear();
+        self.notes.extend_from_slice(&source.notes);
+        self.base = source.base;
+        self.pitch = source.pitch;
+        self.glide_time = source.glide_time;
+        self.bend = source.bend;
+        self.enabled = source.enabled;
+    }
+}
+
+impl<V: Voice> Voice for Legato<V> {
+    fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.inv_sample_rate = (sample_rate as f32).recip();
+        self.voice.set_sample_rate(sample_rate);
+    }
+
+    fn trigger(&mut self, note: Note, velocity: u8) {
+        if !self.enabled {
+            self.notes.clear();
+        }
+        self.notes.retain(|&n| n != note);
+        let legato = !self.notes.is_empty();
+        self.notes.push(note);
+
+        // Only retrigger the inner voice when no other notes are held
+        if !legato {
+            self.base = note;
+            self.pitch = note.0 as f32;
+            self.voice.trigger(note, velocity);
+            self.voice.set_pitch_bend(self.bend);
+        }
+    }
+
+    fn release(&mut self) {
+        if !self.enabled {
+            self.notes.clear();
+        }
+        self.notes.pop();
+        if self.notes.is_empty() {
+            self.voice.release();
+        }
+    }
+
+    fn release_held(&mut self, note: Note) {
+        if self.notes.last() == Some(&note) {
+            self.release();
+        } else {
+            self.notes.retain(|&n| n != note);
+        }
+    }
+
+    fn held_note(&self) -> Option<Note> {
+        self.notes.last().copied()
+    }
+
+    fn set_pitch_bend(&mut self, bend: f32) {
+        self.bend = bend;
+        if self.enabled {
+            self.update_pitch(0);
+        } else {
+            self.voice.set_pitch_bend(bend);
+        }
+    }
+
+    fn amplitude(&self) -> f32 {
+        self.voice.amplitude()
+    }
+
+    fn tracks_amplitude(&self) -> bool {
+        self.voice.tracks_amplitude()
+    }
+
+    fn process(&mut self, mut audio_out: StereoBufferMut) -> bool {
+        if !self.enabled {
+            return self.voice.process(audio_out);
+        }
+
+        let mut active = true;
+        let mut i = 0;
+        while i < audio_out.len() {
+            let j = (i + GLIDE_BLOCK_SIZE).min(audio_out.len());
+            self.update_pitch(j - i);
+            active = self.voice.process(audio_out.slice_mut(i..j));
+            i = j;
+        }
+        active
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A voice which records the calls made to it.
+    #[derive(Clone, Default)]
+    struct TestVoice {
+        note: Option<Note>,
+        triggers: usize,
+        released: bool,
+        bend: f32,
+    }
+
+    impl Voice for TestVoice {
+        fn set_sample_rate(&mut self, _sample_rate: u32) {}
+
+        fn trigger(&mut self, note: Note, _velocity: u8) {
+            self.note = Some(note);
+            self.triggers += 1;
+            self.released = false;
+        }
+
+        fn release(&mut self) {
+            self.released = true;
+        }
+
+        fn set_pitch_bend(&mut self, bend: f32) {
+            self.bend = bend;
+        }
+
+        fn process(&mut self, _audio_out: StereoBufferMut) -> bool {
+            true
+        }
+    }
+
+    fn process(voice: &mut Legato<TestVoice>) {
+        let mut left = [0.0; 64];
+        let mut right = [0.0; 64];
+        voice.process(StereoBufferMut::new(&mut left, &mut right));
+    }
+
+    #[test]
+    fn test_note_stack() {
+        let [c, e, g] = ["C4", "E4", "G4"].map(|n| Note::from_name(n).unwrap());
+        let mut voice = Legato::new(TestVoice::default());
+        voice.set_sample_rate(48000);
+
+        // Overlapping notes bend the inner voice rather than retriggering it
+        voice.trigger(c, 100);
+        voice.trigger(e, 100);
+        voice.trigger(g, 100);
+        process(&mut voice);
+        assert_eq!(voice.inner().triggers, 1);
+        assert_eq!(voice.inner().note, Some(c));
+        assert!((voice.inner().bend - 2f32.powf(7.0 / 12.0)).abs() < 1e-4);
+
+        // Releasing a note other than the most recent one doesn't change the pitch
+        voice.release_held(e);
+        process(&mut voice);
+        assert_eq!(voice.held_note(), Some(g));
+        assert!((voice.inner().bend - 2f32.powf(7.0 / 12.0)).abs() < 1e-4);
+
+        // Releasing the most recent note falls back to the previously held note
+        voice.release();
+        process(&mut voice);
+        assert_eq!(voice.held_note(), Some(c));
+        assert!(!voice.inner().released);
+        assert!((voice.inner().bend - 1.0).abs() < 1e-4);
+
+        // Releasing the last held note releases the inner voice
+        voice.release();
+        assert_eq!(voice.held_note(), None);
+        assert!(voice.inner().released);
+    }
+
+    #[test]
+    fn test_glide() {
+        let [c, g] = ["C4", "G4"].map(|n| Note::from_name(n).unwrap());
+        let mut voice = Legato::new(TestVoice::default());
+        voice.set_sample_rate(48000);
+        voice.set_glide_time(0.05);
+
+        voice.trigger(c, 100);
+        voice.trigger(g, 100);
+        process(&mut voice);
+        let bend = voice.inner().bend;
+        assert!(bend > 1.0 && bend < 2f32.powf(7.0 / 12.0));
+        for _ in 0..500 {
+            process(&mut voice);
+        }
+        assert!((voice.inner().bend - 2f32.powf(7.0 / 12.0)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_clone_from_reuses_notes() {
+        let c = Note::from_name("C4").unwrap();
+        let mut template = Legato::new(TestVoice::default());
+        template.set_glide_time(0.05);
+        let mut voice = Legato::new(TestVoice::default());
+        voice.trigger(c, 100);
+        let notes = voice.notes.as_ptr();
+
+        // The held notes are copied into the existing buffer, rather than replacing it
+        voice.clone_from(&template);
+        assert_eq!(voice.notes.as_ptr(), notes);
+        assert_eq!(voice.held_note(), None);
+        assert_eq!(voice.glide_time, 0.05);
+    }
+}
@@ -1,8 +1,40 @@
 use crate::{audio::buffer::StereoBufferMut, note::Note};
 
 mod envelope;
+pub mod filtered;
+pub mod fm;
+pub mod legato;
 pub mod oscillator;
 
+/// How the velocity of a note is mapped to the amplitude of a voice.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VelocityCurve {
+    /// The amplitude is proportional to the velocity.
+    #[default]
+    Linear,
+    /// The amplitude rises exponentially with the velocity, which makes the voice more dynamic,
+    /// as soft notes are much quieter than loud ones.
+    Exponential,
+    /// The amplitude is the square root of the velocity, which makes the voice less dynamic,
+    /// as soft notes are only a little quieter than loud ones.
+    SquareRoot,
+}
+
+impl VelocityCurve {
+    /// Gets the amplitude between 0 and 1 for a MIDI velocity.
+    pub fn amplitude(self, velocity: u8) -> f32 {
+        // The steepness of the exponential curve
+        const K: f32 = 4.0;
+        let velocity = velocity.min(127) as f32 / 127.0;
+        match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Exponential => (K * velocity).exp_m1() / K.exp_m1(),
+            VelocityCurve::SquareRoot => velocity.sqrt(),
+        }
+    }
+}
+
 /// A synthesiser or other instrument voice.
 pub trait Voice {
     /// Sets the sample rate.
@@ -14,9 +46,31 @@ pub trait Voice {
     /// Releases the note.
     fn release(&mut self);
 
+    /// Releases a held note other than the one most recently triggered.
+    /// Only voices which keep track of multiple held notes, such as [`legato::Legato`], need to implement this.
+    fn release_held(&mut self, _note: Note) {}
+
+    /// Gets the note which is still held after a call to `release`, if any.
+    /// Only voices which keep track of multiple held notes, such as [`legato::Legato`], need to implement this.
+    fn held_note(&self) -> Option<Note> {
+        None
+    }
+
     /// Sets the pitch bend, where `bend` is a ratio to be multiplied with the original frequency.
     fn set_pitch_bend(&mut self, bend: f32);
 
+    /// Gets the current envelope-scaled level of the voice, such as to display voice activity
+    /// or to steal the quietest voice. Voices which don't track their amplitude report silence.
+    fn amplitude(&self) -> f32 {
+        0.0
+    }
+
+    /// Whether `amplitude` tracks the level of the voice. Released voices which do are freed as soon as they fall
+    /// silent, while the others are only freed when `process` reports that they have finished.
+    fn tracks_amplitude(&self) -> bool {
+        false
+    }
+
     /// Synthesises audio into the provided stereo buffer.
     /// A return value of `false` indicates that the voice is off and
     /// will not produce any more sound until it is re-triggered.
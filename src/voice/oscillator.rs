@@ -1,38 +1,186 @@
-use crate::{audio::buffer::StereoBufferMut, note::Note};
+use crate::{
+    audio::buffer::StereoBufferMut,
+    note::{Note, Tuning},
+};
 use std::f32::consts::PI;
 
-use super::{envelope::AdsrEnvelope, Voice};
+use super::{envelope::AdsrEnvelope, VelocityCurve, Voice};
 #[derive(Clone, Copy)]
 pub struct SimpleOscillator {
     inv_sample_rate: f32,
-    wave: Waveform,
     note: Note,
+    /// The amplitude of the note, from its velocity.
     velocity: f32,
-    phase: f32,
+    /// The phase of each unison copy of the oscillator.
+    phases: [f32; MAX_UNISON],
     bend: f32,
+    /// The phase of the vibrato, between 0 and 1.
+    vibrato_phase: f32,
     envelope: AdsrEnvelope,
+    opts: VoiceOpts,
+    tuning: Tuning,
+    /// The phase of the slave oscillator of each unison copy, which is reset whenever its master oscillator
+    /// completes a cycle.
+    slave_phases: [f32; MAX_UNISON],
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// Maximum number of unison copies of the oscillator.
+pub const MAX_UNISON: usize = 8;
+
+/// Number of samples processed between updates of the pitch, for vibrato.
+const CONTROL_BLOCK: usize = 32;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Waveform {
     Sine,
     Triangle,
     Square,
     Sawtooth,
+    /// A pulse wave, where `width` is the fraction of each cycle spent high.
+    Pulse {
+        width: f32,
+    },
+}
+
+// Pulse widths are compared by their bits, so that every waveform is equal to itself
+impl PartialEq for Waveform {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Waveform::Pulse { width: a }, Waveform::Pulse { width: b }) => a.to_bits() == b.to_bits(),
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for Waveform {}
+
+impl Waveform {
+    /// Gets the value of the waveform at `phase`, which is between 0 and 1.
+    pub fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Sine => sine(phase),
+            Waveform::Triangle => triangle(phase),
+            Waveform::Square => square(phase),
+            Waveform::Sawtooth => sawtooth(phase),
+            Waveform::Pulse { width } => pulse(phase, width),
+        }
+    }
+}
+
+/// The settings of a [`SimpleOscillator`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VoiceOpts {
+    pub wave: Waveform,
+    /// Attack time in seconds.
+    pub attack: f32,
+    /// Decay time in seconds.
+    pub decay: f32,
+    /// Sustain level between 0 and 1.
+    pub sustain: f32,
+    /// Release time in seconds.
+    pub release: f32,
+    /// Number of detuned copies of the oscillator which are mixed together, from 1 to [`MAX_UNISON`].
+    pub unison: u8,
+    /// Spread in cents between the lowest and highest unison copies.
+    pub unison_detune: f32,
+    /// Vibrato rate in Hz.
+    pub vibrato_rate: f32,
+    /// Vibrato depth in cents either side of the note.
+    pub vibrato_depth: f32,
+    /// How the velocity of each note is mapped to its amplitude.
+    pub velocity_curve: VelocityCurve,
+    /// The phase between 0 and 1 that the oscillator is reset to when a note is triggered,
+    /// or `None` to let the oscillator run freely between notes.
+    pub retrigger_phase: Option<f32>,
+    /// The frequency ratio of the slave oscillator to the master oscillator when hard synced,
+    /// or `None` to disable hard sync.
+    pub sync_ratio: Option<f32>,
+}
+
+impl Default for VoiceOpts {
+    fn default() -> Self {
+        Self {
+            wave: Waveform::Sine,
+            attack: 0.001,
+            decay: 1.0,
+            sustain: 1.0,
+            release: 0.001,
+            unison: 1,
+            unison_detune: 0.0,
+            vibrato_rate: 5.0,
+            vibrato_depth: 0.0,
+            velocity_curve: VelocityCurve::Linear,
+            retrigger_phase: None,
+            sync_ratio: None,
+        }
+    }
 }
 
 impl SimpleOscillator {
     pub fn new() -> Self {
         Self {
             inv_sample_rate: 0.0,
-            wave: Waveform::Sine,
             velocity: 0.0,
             note: Note::middle_c(),
-            phase: 0.0,
+            phases: [0.0; MAX_UNISON],
             bend: 1.0,
+            vibrato_phase: 0.0,
             envelope: AdsrEnvelope::new(),
+            opts: VoiceOpts::default(),
+            tuning: Tuning::default(),
+            slave_phases: [0.0; MAX_UNISON],
         }
     }
+
+    pub fn set_waveform(&mut self, wave: Waveform) {
+        self.opts.wave = wave;
+    }
+
+    /// Sets the phase between 0 and 1 that the oscillator is reset to when a note is triggered,
+    /// or `None` to let the oscillator run freely between notes.
+    pub fn set_retrigger_phase(&mut self, phase: Option<f32>) {
+        self.opts.retrigger_phase = phase;
+    }
+
+    /// Sets how the velocity of each note is mapped to its amplitude, which takes effect from the next note.
+    pub fn set_velocity_curve(&mut self, curve: VelocityCurve) {
+        self.opts.velocity_curve = curve;
+    }
+
+    /// Sets the tuning used to convert notes to frequencies.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+    }
+
+    /// Enables or disables hard sync, where the output is taken from a slave oscillator running at `slave_ratio`
+    /// times the frequency of the note, and whose phase is reset whenever the master oscillator completes a cycle.
+    pub fn set_sync(&mut self, enabled: bool, slave_ratio: f32) {
+        self.opts.sync_ratio = enabled.then_some(slave_ratio);
+    }
+
+    pub fn opts(&self) -> VoiceOpts {
+        self.opts
+    }
+
+    pub fn set_opts(&mut self, opts: VoiceOpts) {
+        self.opts = opts;
+        self.envelope
+            .set_adsr(opts.attack, opts.decay, opts.sustain, opts.release);
+    }
+
+    /// Gets the frequency of the oscillator for the note, including the pitch bend and vibrato.
+    fn frequency(&self) -> f32 {
+        let vibrato = self.opts.vibrato_depth * sine(self.vibrato_phase);
+        self.bend * self.tuning.frequency(self.note) * 2f32.powf(vibrato / 1200.0)
+    }
+
+    /// Advances the vibrato by `num_samples`.
+    fn advance_vibrato(&mut self, num_samples: usize) {
+        let elapsed = num_samples as f32 * self.inv_sample_rate;
+        self.vibrato_phase = (self.vibrato_phase + elapsed * self.opts.vibrato_rate).fract();
+    }
 }
 
 impl Voice for SimpleOscillator {
@@ -43,8 +191,12 @@ impl Voice for SimpleOscillator {
 
     fn trigger(&mut self, note: Note, velocity: u8) {
         self.note = note;
-        self.velocity = (velocity as f32) / 127.0;
+        self.velocity = self.opts.velocity_curve.amplitude(velocity);
         self.envelope.trigger();
+        if let Some(phase) = self.opts.retrigger_phase {
+            self.phases = [phase.rem_euclid(1.0); MAX_UNISON];
+            self.slave_phases = [0.0; MAX_UNISON];
+        }
     }
 
     fn release(&mut self) {
@@ -55,24 +207,59 @@ impl Voice for SimpleOscillator {
         self.bend = bend;
     }
 
+    fn amplitude(&self) -> f32 {
+        self.velocity * self.envelope.amplitude()
+    }
+
+    fn tracks_amplitude(&self) -> bool {
+        true
+    }
+
     fn process(&mut self, audio_out: StereoBufferMut) -> bool {
         let StereoBufferMut { left, right } = audio_out;
 
-        let wave = match self.wave {
-            Waveform::Sine => sine,
-            Waveform::Triangle => triangle,
-            Waveform::Square => square,
-            Waveform::Sawtooth => sawtooth,
-        };
+        let wave = self.opts.wave;
+        let unison = (self.opts.unison as usize).clamp(1, MAX_UNISON);
+        let gain = (unison as f32).sqrt().recip();
+        let sync_ratio = self.opts.sync_ratio.map(|ratio| ratio.max(0.0));
+
+        // Spread the unison copies evenly across the detune
+        let mut detune = [1.0; MAX_UNISON];
+        if unison > 1 {
+            for (i, ratio) in detune[..unison].iter_mut().enumerate() {
+                let cents = self.opts.unison_detune * (i as f32 / (unison - 1) as f32 - 0.5);
+                *ratio = 2f32.powf(cents / 1200.0);
+            }
+        }
+
+        for (left, right) in left.chunks_mut(CONTROL_BLOCK).zip(right.chunks_mut(CONTROL_BLOCK)) {
+            let omega = self.frequency() * self.inv_sample_rate;
+            self.advance_vibrato(left.len());
 
-        let omega = self.bend * self.note.frequency() * self.inv_sample_rate;
-        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
-            let sample = self.envelope.process() * self.velocity * (wave)(self.phase);
-            *left += sample;
-            *right += sample;
-            self.phase += omega;
-            if self.phase >= 1.0 {
-                self.phase -= 1.0;
+            for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+                let mut sample = 0.0;
+                for ((phase, slave_phase), ratio) in self.phases[..unison]
+                    .iter_mut()
+                    .zip(self.slave_phases[..unison].iter_mut())
+                    .zip(detune)
+                {
+                    let omega = ratio * omega;
+                    sample += wave.sample(match sync_ratio {
+                        Some(_) => *slave_phase,
+                        None => *phase,
+                    });
+                    *phase += omega;
+                    if let Some(ratio) = sync_ratio {
+                        *slave_phase = (*slave_phase + ratio * omega).fract();
+                    }
+                    if *phase >= 1.0 {
+                        *phase -= 1.0;
+                        *slave_phase = 0.0;
+                    }
+                }
+                let sample = self.envelope.process() * self.velocity * gain * sample;
+                *left += sample;
+                *right += sample;
             }
         }
 
@@ -92,6 +279,14 @@ fn square(phase: f32) -> f32 {
     }
 }
 
+fn pulse(phase: f32, width: f32) -> f32 {
+    if phase > 1.0 - width {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
 fn triangle(phase: f32) -> f32 {
     (4.0 * phase - 2.0).abs() + 1.0
 }
@@ -99,3 +294,142 @@ fn triangle(phase: f32) -> f32 {
 fn sawtooth(phase: f32) -> f32 {
     2.0 * phase - 1.0
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processor::magnitude_at;
+
+    #[test]
+    fn test_pulse() {
+        let phases = (0..1000).map(|i| i as f32 / 1000.0);
+
+        // A narrow pulse is only high for a small part of the cycle
+        let narrow = Waveform::Pulse { width: 0.1 };
+        let high = phases.clone().filter(|&p| narrow.sample(p) > 0.0).count();
+        assert!((99..=101).contains(&high));
+
+        // A pulse with a width of a half is a square wave
+        let half = Waveform::Pulse { width: 0.5 };
+        assert!(phases.clone().all(|p| half.sample(p) == Waveform::Square.sample(p)));
+    }
+
+    #[test]
+    fn test_retrigger_phase() {
+        let mut osc = SimpleOscillator::new();
+        osc.set_sample_rate(48000);
+        osc.set_retrigger_phase(Some(0.25));
+
+        let mut play_note = || {
+            let mut left = [0.0; 256];
+            let mut right = [0.0; 256];
+            osc.trigger(Note::middle_c(), 100);
+            osc.process(StereoBufferMut::new(&mut left, &mut right));
+            osc.release();
+            osc.process(StereoBufferMut::new(&mut [0.0; 4800], &mut [0.0; 4800]));
+            left
+        };
+
+        assert_eq!(play_note(), play_note());
+    }
+
+    #[test]
+    fn test_velocity_curve() {
+        let peak = |curve: VelocityCurve, velocity: u8| {
+            let mut osc = SimpleOscillator::new();
+            osc.set_sample_rate(48000);
+            osc.set_velocity_curve(curve);
+            let mut left = [0.0; 1024];
+            let mut right = [0.0; 1024];
+            osc.trigger(Note(69), velocity);
+            osc.process(StereoBufferMut::new(&mut left, &mut right));
+            left.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+        };
+
+        // The curves differ in between, but all reach full scale at the maximum velocity
+        let linear = peak(VelocityCurve::Linear, 64);
+        assert!((linear - 64.0 / 127.0).abs() < 1e-3);
+        assert!(peak(VelocityCurve::Exponential, 64) < 0.5 * linear);
+        assert!(peak(VelocityCurve::SquareRoot, 64) > linear);
+        for curve in [
+            VelocityCurve::Linear,
+            VelocityCurve::Exponential,
+            VelocityCurve::SquareRoot,
+        ] {
+            assert!((peak(curve, 127) - 1.0).abs() < 1e-3);
+            assert_eq!(curve.amplitude(0), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_tuning() {
+        // Render a note with a detuned pitch class, and the same note pitch bent by the same amount
+        let play_note = |tuning: Tuning, bend: f32| {
+            let mut osc = SimpleOscillator::new();
+            osc.set_sample_rate(48000);
+            osc.set_waveform(Waveform::Sawtooth);
+            osc.set_tuning(tuning);
+            osc.set_pitch_bend(bend);
+            let mut left = [0.0; 1024];
+            let mut right = [0.0; 1024];
+            osc.trigger(Note(69), 127);
+            osc.process(StereoBufferMut::new(&mut left, &mut right));
+            left
+        };
+
+        let mut cents = [0.0; 12];
+        cents[9] = 50.0;
+        let tuned = play_note(Tuning::from_cents_table(cents), 1.0);
+        let bent = play_note(Tuning::default(), 2f32.powf(50.0 / 1200.0));
+        assert!(tuned.iter().zip(bent.iter()).all(|(a, b)| (a - b).abs() < 1e-3));
+        assert_ne!(tuned, play_note(Tuning::default(), 1.0));
+    }
+
+    #[test]
+    fn test_hard_sync() {
+        // At this sample rate, each cycle of A4 is exactly 64 samples
+        let mut osc = SimpleOscillator::new();
+        osc.set_sample_rate(28160);
+        osc.set_sync(true, 2.37);
+        let mut left = [0.0; 2048];
+        let mut right = [0.0; 2048];
+        osc.trigger(Note(69), 127);
+        osc.process(StereoBufferMut::new(&mut left, &mut right));
+
+        // Once the attack is over, the output repeats at the frequency of the master oscillator
+        let output = &left[1024..];
+        assert!(output.iter().zip(&output[64..]).all(|(a, b)| (a - b).abs() < 1e-3));
+
+        // Unlike the sine it is made from, the output has strong harmonics
+        let harmonic = |k: usize| magnitude_at(output, k as f32, 64.0) * 2.0 / output.len() as f32;
+        assert!((2..=4).all(|k| harmonic(k) > 0.1));
+    }
+
+    #[test]
+    fn test_unison() {
+        let render = |opts: VoiceOpts| {
+            let mut osc = SimpleOscillator::new();
+            osc.set_sample_rate(48000);
+            osc.set_opts(opts);
+            let mut left = vec![0.0; 4800];
+            let mut right = vec![0.0; 4800];
+            osc.trigger(Note::from_name("A2").unwrap(), 127);
+            osc.process(StereoBufferMut::new(&mut left, &mut right));
+            left
+        };
+        let saw = VoiceOpts {
+            wave: Waveform::Sawtooth,
+            ..Default::default()
+        };
+
+        // Detuned copies beat against each other, and are scaled so that they don't clip
+        let plain = render(saw);
+        let unison = render(VoiceOpts {
+            unison: 3,
+            unison_detune: 30.0,
+            ..saw
+        });
+        assert_ne!(plain, unison);
+        assert!(unison.iter().all(|s| s.abs() <= 3f32.sqrt()));
+    }
+}